@@ -0,0 +1,213 @@
+//! A/B comparison of two versions of a track, such as two masters. Both are
+//! decoded side by side so playback can switch between them at the same
+//! moment, and the louder is turned down to the other's loudness so the
+//! comparison isn't swayed by level alone. Playing A with B subtracted, a
+//! null test, leaves only what changed between them.
+
+use rodio::Source;
+use rodio::source::{SeekError, UniformSourceIterator};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::decoder::AudioSource;
+use crate::loudness;
+
+/// How long switching sides takes, to avoid a click.
+const SWITCH_RAMP: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    A,
+    B,
+    /// A with B phase-inverted and mixed in.
+    Diff,
+}
+
+impl Side {
+    /// Share of A and B in the output.
+    fn weights(self) -> [f32; 2] {
+        match self {
+            Self::A => [1.0, 0.0],
+            Self::B => [0.0, 1.0],
+            Self::Diff => [1.0, -1.0],
+        }
+    }
+}
+
+/// Which side is playing and the gain each gets, shared between the UI and
+/// the audio thread.
+pub struct AbSwitch {
+    /// 0 for A, 1 for B, 2 for the difference.
+    side: AtomicU8,
+    /// Gain applied to each side to match their loudness, as bits.
+    gains: [AtomicU32; 2],
+    matched: AtomicBool,
+}
+
+impl AbSwitch {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            side: AtomicU8::new(0),
+            gains: std::array::from_fn(|_| AtomicU32::new(1f32.to_bits())),
+            matched: AtomicBool::new(false),
+        })
+    }
+
+    pub fn side(&self) -> Side {
+        match self.side.load(Ordering::Relaxed) {
+            0 => Side::A,
+            1 => Side::B,
+            _ => Side::Diff,
+        }
+    }
+
+    pub fn set_side(&self, side: Side) {
+        self.side.store(side as u8, Ordering::Relaxed);
+    }
+
+    /// Switches between A and B, or back to A from the difference.
+    pub fn toggle(&self) {
+        self.set_side(match self.side() {
+            Side::A => Side::B,
+            Side::B | Side::Diff => Side::A,
+        });
+    }
+
+    /// Switches to the difference, or back to A from it.
+    pub fn toggle_diff(&self) {
+        self.set_side(match self.side() {
+            Side::Diff => Side::A,
+            Side::A | Side::B => Side::Diff,
+        });
+    }
+
+    /// Gain A or B is played at, as an amplitude.
+    pub fn gain(&self, side: Side) -> f32 {
+        let index = if side == Side::B { 1 } else { 0 };
+        f32::from_bits(self.gains[index].load(Ordering::Relaxed))
+    }
+
+    /// Whether the loudness of both sides has been measured and matched.
+    pub fn is_matched(&self) -> bool {
+        self.matched.load(Ordering::Relaxed)
+    }
+}
+
+/// Two files being compared, and the switch between them.
+#[derive(Clone)]
+pub struct Comparison {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    pub switch: Arc<AbSwitch>,
+}
+
+impl Comparison {
+    /// Starts comparing `a` with `b`, measuring their loudness in the
+    /// background. Until that's done both play at full level.
+    pub fn new(a: &Path, b: &Path) -> Self {
+        let comparison = Self {
+            a: a.to_path_buf(),
+            b: b.to_path_buf(),
+            switch: AbSwitch::new(),
+        };
+        let (a, b, switch) = (
+            comparison.a.clone(),
+            comparison.b.clone(),
+            Arc::clone(&comparison.switch),
+        );
+        thread::spawn(move || {
+            let loudness = |path: &Path| loudness::measure(path).ok()?.integrated();
+            let (Some(a), Some(b)) = (loudness(&a), loudness(&b)) else {
+                return;
+            };
+            // Only ever turn down, so neither side can clip.
+            let difference = (a - b) as f32;
+            let gains = [(-difference).min(0.0), difference.min(0.0)];
+            for (gain, db) in switch.gains.iter().zip(gains) {
+                gain.store(10f32.powf(db / 20.0).to_bits(), Ordering::Relaxed);
+            }
+            switch.matched.store(true, Ordering::Relaxed);
+        });
+        comparison
+    }
+}
+
+/// Plays whichever side of a [`Comparison`] is selected, reading both so
+/// they stay in step. B is converted to A's channels and sample rate.
+pub struct AbSource {
+    a: AudioSource,
+    b: UniformSourceIterator<AudioSource, i16>,
+    switch: Arc<AbSwitch>,
+    /// Share of A and B in the output, moving towards the selected side's.
+    weights: [f32; 2],
+    /// Change in the weights per sample while switching.
+    step: f32,
+}
+
+impl AbSource {
+    pub fn new(a: AudioSource, b: AudioSource, switch: Arc<AbSwitch>) -> Self {
+        let channels = a.channels();
+        let sample_rate = a.sample_rate();
+        let samples = SWITCH_RAMP.as_secs_f32() * sample_rate as f32 * channels as f32;
+        Self {
+            a,
+            b: UniformSourceIterator::new(b, channels, sample_rate),
+            weights: switch.side().weights(),
+            switch,
+            step: samples.max(1.0).recip(),
+        }
+    }
+}
+
+impl Iterator for AbSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let a = self.a.next();
+        let b = self.b.next();
+        if a.is_none() && b.is_none() {
+            return None;
+        }
+
+        let targets = self.switch.side().weights();
+        for (weight, target) in self.weights.iter_mut().zip(targets) {
+            if *weight < target {
+                *weight = (*weight + self.step).min(target);
+            } else if *weight > target {
+                *weight = (*weight - self.step).max(target);
+            }
+        }
+
+        let a = a.unwrap_or(0) as f32 * self.switch.gain(Side::A);
+        let b = b.unwrap_or(0) as f32 * self.switch.gain(Side::B);
+        let sample = a * self.weights[0] + b * self.weights[1];
+        Some(sample.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+    }
+}
+
+impl Source for AbSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.a.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.a.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.a.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let a = self.a.total_duration()?;
+        Some(self.b.total_duration().map_or(a, |b| a.max(b)))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.a.try_seek(pos)?;
+        self.b.try_seek(pos)
+    }
+}
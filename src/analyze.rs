@@ -0,0 +1,687 @@
+//! `apz analyze`: measures files without playing them and prints a report
+//! per file as JSON or CSV, for cataloging a library or a sample pack.
+
+use rodio::Source;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::decoder;
+use crate::directory::{self, ScanConfig};
+use crate::error::ApzError;
+use crate::json;
+use crate::loudness::{self, LoudnessMeter};
+
+pub const FORMATS: [&str; 2] = ["json", "csv"];
+
+/// Onsets and pitch are read from overlapping frames of this many samples.
+const FRAME: usize = 2048;
+const HOP: usize = 512;
+/// Tempos the beat tracker considers, and the one it leans towards when
+/// a track could be read at either half or double speed.
+const TEMPO_RANGE: Range<f32> = 60.0..200.0;
+const LIKELY_TEMPO: f32 = 120.0;
+/// How much alike the onsets one beat apart have to be, relative to how
+/// much they vary, for the track to count as having a beat at all.
+const MIN_REGULARITY: f32 = 0.1;
+/// How far either way the beat grid's period is nudged from the tempo
+/// found, and in how many steps each way, to keep it in line to the end of
+/// the track.
+const GRID_SLACK: f64 = 0.01;
+const GRID_STEPS: i32 = 20;
+/// Range of the spectrum used to tell the key.
+const PITCH_RANGE: Range<f32> = 100.0..4200.0;
+/// Silence is measured in windows this long.
+const SILENCE_WINDOW: Duration = Duration::from_millis(10);
+
+const NOTES: [&str; 12] = [
+    "C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+/// Krumhansl and Kessler's key profiles: how strongly each scale degree
+/// suggests a key.
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SilenceSettings {
+    /// Peak level in dBFS below which audio counts as silent.
+    pub threshold_db: f32,
+    /// Shorter gaps aren't reported.
+    pub min_length: Duration,
+}
+
+impl Default for SilenceSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: -50.0,
+            min_length: Duration::from_secs(2),
+        }
+    }
+}
+
+pub struct Report {
+    pub duration: Duration,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Integrated loudness in LUFS; `None` for silence.
+    pub loudness: Option<f64>,
+    /// True peak in dBTP.
+    pub true_peak: f32,
+    pub bpm: Option<f32>,
+    pub key: Option<String>,
+    pub silences: Vec<Range<Duration>>,
+    /// Mean of each channel, where full scale is 1.0.
+    pub dc_offset: Vec<f32>,
+}
+
+/// Where the beats fall in a track with a steady tempo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatGrid {
+    /// Time from one beat to the next.
+    pub beat: Duration,
+    /// The first beat, with the rest following every [`BeatGrid::beat`].
+    pub first_beat: Duration,
+}
+
+impl BeatGrid {
+    pub fn bpm(&self) -> f64 {
+        60.0 / self.beat.as_secs_f64()
+    }
+
+    /// The beat at or before `position`, or the first beat if that's still
+    /// to come.
+    pub fn beat_before(&self, position: Duration) -> Duration {
+        let since_first = position.saturating_sub(self.first_beat).as_secs_f64();
+        let beats = (since_first / self.beat.as_secs_f64()).floor();
+        self.first_beat + self.beat.mul_f64(beats)
+    }
+}
+
+/// What's known of the current track's beat, which is only looked for when
+/// something needs it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Beats {
+    #[default]
+    Unknown,
+    Finding,
+    Found(BeatGrid),
+    /// The track has no steady beat, or couldn't be decoded.
+    NotFound,
+}
+
+/// Finds the beat grid of `path` on another thread. Nothing is sent if
+/// there isn't one.
+pub fn find_beats(path: PathBuf) -> Receiver<BeatGrid> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let Ok(decoded) = decoder::open(&path) else {
+            return;
+        };
+        let mut source = decoded.source;
+        let channels = source.channels().max(1) as usize;
+        let mut spectrum = SpectralAnalysis::new(source.sample_rate().max(1));
+        let mut frame = Vec::with_capacity(channels);
+        for sample in source.by_ref() {
+            frame.push(sample as f32 / 32768.0);
+            if frame.len() == channels {
+                spectrum.push(frame.drain(..).sum::<f32>() / channels as f32);
+            }
+        }
+        if let Some(grid) = spectrum.beat_grid() {
+            sender.send(grid).ok();
+        }
+    });
+    receiver
+}
+
+/// Runs `apz analyze` with the arguments that follow it, returning the exit
+/// status: 1 if any file couldn't be analyzed.
+pub fn run(args: &[String]) -> i32 {
+    let mut format = Format::default();
+    let mut silence = SilenceSettings::default();
+    let mut scan = ScanConfig::default();
+    let mut paths = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(String::as_str);
+        match args[i].as_str() {
+            "-r" | "--recursive" => {
+                scan.recursive = true;
+                i += 1;
+            }
+            "--format" => {
+                format = value.and_then(Format::named).unwrap_or_else(|| {
+                    usage(&format!("--format must be one of: {}", FORMATS.join(", ")))
+                });
+                i += 2;
+            }
+            "--silence-level" => {
+                silence.threshold_db = value
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .unwrap_or_else(|| usage("--silence-level must be a level in dBFS, e.g. -50"))
+                    .min(0.0);
+                i += 2;
+            }
+            "--silence-min" => {
+                silence.min_length = value
+                    .and_then(|value| value.parse().ok())
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .unwrap_or_else(|| usage("--silence-min must be a length in seconds"));
+                i += 2;
+            }
+            "-h" | "--help" => usage(""),
+            arg if arg.starts_with('-') && arg != "-" => usage(&format!("Unknown option: {}", arg)),
+            arg => {
+                paths.push(PathBuf::from(arg));
+                i += 1;
+            }
+        }
+    }
+
+    let paths = directory::expand(&paths, &scan);
+    if paths.is_empty() {
+        usage("No audio files specified");
+    }
+
+    let results = map_parallel(&paths, |path| analyze(path, silence));
+    match format {
+        Format::Json => print_json(&paths, &results),
+        Format::Csv => print_csv(&paths, &results),
+    }
+
+    for (path, result) in paths.iter().zip(&results) {
+        if let Err(e) = result {
+            eprintln!("{}: {}", path.display(), e);
+        }
+    }
+    if results.iter().any(Result::is_err) {
+        1
+    } else {
+        0
+    }
+}
+
+fn usage(error: &str) -> ! {
+    if !error.is_empty() {
+        eprintln!("Error: {}", error);
+    }
+    eprintln!("Usage: apz analyze [OPTIONS] <audio_file|directory>...");
+    eprintln!(
+        "\nPrints the duration, integrated loudness, true peak, tempo, key, silences\nand DC offset of each file."
+    );
+    eprintln!("\nOptions:");
+    eprintln!("  -r, --recursive        Include files in subdirectories of a directory");
+    eprintln!("  --format <format>      json or csv (default: json)");
+    eprintln!("  --silence-level <dB>   Level below which audio is silent (default: -50)");
+    eprintln!("  --silence-min <secs>   Shortest silence to report (default: 2)");
+    process::exit(if error.is_empty() { 0 } else { 1 });
+}
+
+/// Runs `f` on each of `paths` on as many threads as there are cores,
+/// keeping the results in the order of `paths`.
+pub fn map_parallel<T: Send>(paths: &[PathBuf], f: impl Fn(&Path) -> T + Sync) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..paths.len()).map(|_| None).collect::<Vec<_>>());
+    let workers = thread::available_parallelism()
+        .map_or(1, |cores| cores.get())
+        .min(paths.len());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    let result = f(path);
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every file is done"))
+        .collect()
+}
+
+/// Decodes `path` once, feeding every measurement as it goes.
+pub fn analyze(path: &Path, silence: SilenceSettings) -> Result<Report, ApzError> {
+    let mut source = decoder::open(path)?.source;
+    let channels = source.channels().max(1);
+    let sample_rate = source.sample_rate().max(1);
+
+    let mut meter = LoudnessMeter::new(sample_rate, channels as usize);
+    let mut spectrum = SpectralAnalysis::new(sample_rate);
+    let mut silences = SilenceTracker::new(sample_rate, silence);
+    let mut sums = vec![0.0f64; channels as usize];
+    let mut frame = vec![0.0f32; channels as usize];
+    let mut frames = 0u64;
+
+    'decode: loop {
+        for sample in frame.iter_mut() {
+            let Some(next) = source.next() else {
+                break 'decode;
+            };
+            *sample = next as f32 / 32768.0;
+        }
+        meter.push(&frame);
+        for (sum, &sample) in sums.iter_mut().zip(&frame) {
+            *sum += sample as f64;
+        }
+        let peak = frame.iter().fold(0.0, |peak: f32, s| peak.max(s.abs()));
+        silences.push(peak);
+        spectrum.push(frame.iter().sum::<f32>() / channels as f32);
+        frames += 1;
+    }
+
+    Ok(Report {
+        duration: Duration::from_secs_f64(frames as f64 / sample_rate as f64),
+        sample_rate,
+        channels,
+        loudness: meter.integrated(),
+        true_peak: loudness::to_db(meter.true_peak()),
+        bpm: spectrum.tempo(),
+        key: spectrum.key(),
+        silences: silences.finish(),
+        dc_offset: sums
+            .iter()
+            .map(|sum| (sum / frames.max(1) as f64) as f32)
+            .collect(),
+    })
+}
+
+/// Finds stretches where every channel stays below the threshold.
+struct SilenceTracker {
+    window: u64,
+    threshold: f32,
+    min_length: Duration,
+    sample_rate: u32,
+    /// Frames seen so far.
+    position: u64,
+    window_peak: f32,
+    /// Frame the current silence started at.
+    silent_since: Option<u64>,
+    silences: Vec<Range<Duration>>,
+}
+
+impl SilenceTracker {
+    fn new(sample_rate: u32, config: SilenceSettings) -> Self {
+        Self {
+            window: (sample_rate as u64 * SILENCE_WINDOW.as_millis() as u64 / 1000).max(1),
+            threshold: 10f32.powf(config.threshold_db / 20.0),
+            min_length: config.min_length,
+            sample_rate,
+            position: 0,
+            window_peak: 0.0,
+            silent_since: None,
+            silences: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, peak: f32) {
+        self.window_peak = self.window_peak.max(peak);
+        self.position += 1;
+        if !self.position.is_multiple_of(self.window) {
+            return;
+        }
+
+        let start = self.position - self.window;
+        if std::mem::take(&mut self.window_peak) < self.threshold {
+            self.silent_since.get_or_insert(start);
+        } else if let Some(since) = self.silent_since.take() {
+            self.end_silence(since, start);
+        }
+    }
+
+    fn end_silence(&mut self, start: u64, end: u64) {
+        let time = |frame: u64| Duration::from_secs_f64(frame as f64 / self.sample_rate as f64);
+        let silence = time(start)..time(end);
+        if silence.end - silence.start >= self.min_length {
+            self.silences.push(silence);
+        }
+    }
+
+    fn finish(mut self) -> Vec<Range<Duration>> {
+        if let Some(since) = self.silent_since.take() {
+            self.end_silence(since, self.position);
+        }
+        self.silences
+    }
+}
+
+/// Tempo and key, from short-time spectra of the mono mix: onsets are where
+/// the spectrum suddenly gains energy, and the key is the one whose scale
+/// best fits the pitch classes heard over the whole track.
+struct SpectralAnalysis {
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    samples: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    previous: Vec<f32>,
+    /// Spectral flux of each frame.
+    onsets: Vec<f32>,
+    chroma: [f32; 12],
+}
+
+impl SpectralAnalysis {
+    fn new(sample_rate: u32) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FRAME);
+        Self {
+            sample_rate,
+            window: (0..FRAME)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME as f32).cos())
+                .collect(),
+            samples: Vec::with_capacity(FRAME),
+            buffer: vec![Complex::default(); FRAME],
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            previous: vec![0.0; FRAME / 2],
+            onsets: Vec::new(),
+            chroma: [0.0; 12],
+            fft,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.samples.push(sample);
+        if self.samples.len() == FRAME {
+            self.process_frame();
+            self.samples.drain(..HOP);
+        }
+    }
+
+    fn process_frame(&mut self) {
+        for ((bin, &sample), &window) in self.buffer.iter_mut().zip(&self.samples).zip(&self.window)
+        {
+            *bin = Complex::new(sample * window, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        let bin_width = self.sample_rate as f32 / FRAME as f32;
+        let mut flux = 0.0;
+        for (index, (bin, previous)) in self.buffer.iter().zip(&mut self.previous).enumerate() {
+            // Compressed, so quiet onsets count as well as loud ones.
+            let magnitude = (1.0 + 100.0 * bin.norm()).ln();
+            flux += (magnitude - *previous).max(0.0);
+            *previous = magnitude;
+
+            let frequency = index as f32 * bin_width;
+            if PITCH_RANGE.contains(&frequency) {
+                let note = 12.0 * (frequency / 440.0).log2() + 69.0;
+                self.chroma[note.round() as usize % 12] += bin.norm();
+            }
+        }
+        self.onsets.push(flux);
+    }
+
+    /// Tempo in beats per minute: the beat period at which the onsets line
+    /// up best with themselves, preferring tempos near [`LIKELY_TEMPO`].
+    fn tempo(&self) -> Option<f32> {
+        let frame_rate = self.sample_rate as f32 / HOP as f32;
+        if (self.onsets.len() as f32) < frame_rate * 5.0 {
+            return None;
+        }
+
+        let mean = self.onsets.iter().sum::<f32>() / self.onsets.len() as f32;
+        let onsets: Vec<f32> = self.onsets.iter().map(|onset| onset - mean).collect();
+        let correlation = |lag: usize| {
+            let pairs = onsets.len() - lag;
+            onsets
+                .iter()
+                .zip(&onsets[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / pairs as f32
+        };
+
+        let lag_of = |bpm: f32| (60.0 * frame_rate / bpm).round() as usize;
+        let lags = lag_of(TEMPO_RANGE.end)..=lag_of(TEMPO_RANGE.start);
+        let correlations: Vec<(usize, f32)> = lags.map(|lag| (lag, correlation(lag))).collect();
+        let (index, &(lag, peak)) = correlations.iter().enumerate().max_by(|(_, a), (_, b)| {
+            let score = |&(lag, correlation): &(usize, f32)| {
+                let octaves = (60.0 * frame_rate / lag as f32 / LIKELY_TEMPO).log2();
+                correlation * (-0.5 * octaves * octaves).exp()
+            };
+            score(a).total_cmp(&score(b))
+        })?;
+        // Steady sounds have onsets too, but nothing regular about them.
+        if peak <= MIN_REGULARITY * correlation(0) {
+            return None;
+        }
+
+        // A parabola through the peak and its neighbours places it between
+        // frames.
+        let mut lag = lag as f32;
+        if let (Some(before), Some(after)) = (
+            index.checked_sub(1).and_then(|i| correlations.get(i)),
+            correlations.get(index + 1),
+        ) {
+            let curvature = before.1 - 2.0 * peak + after.1;
+            if curvature < 0.0 {
+                lag += 0.5 * (before.1 - after.1) / curvature;
+            }
+        }
+        Some((600.0 * frame_rate / lag).round() / 10.0)
+    }
+
+    /// The tempo, and where its beats fall: the period close to it and the
+    /// offset into it at which the onsets are strongest on average.
+    fn beat_grid(&self) -> Option<BeatGrid> {
+        let frame_rate = self.sample_rate as f64 / HOP as f64;
+        let lag = 60.0 * frame_rate / self.tempo()? as f64;
+
+        let mut best = (f32::MIN, lag, 0);
+        for step in -GRID_STEPS..=GRID_STEPS {
+            let period = lag * (1.0 + GRID_SLACK * step as f64 / GRID_STEPS as f64);
+            for offset in 0..period as usize {
+                let (mut sum, mut count) = (0.0, 0);
+                let mut frame = offset as f64;
+                while let Some(&onset) = self.onsets.get(frame.round() as usize) {
+                    sum += onset;
+                    count += 1;
+                    frame += period;
+                }
+                let strength = sum / count.max(1) as f32;
+                if strength > best.0 {
+                    best = (strength, period, offset);
+                }
+            }
+        }
+
+        let (_, period, offset) = best;
+        let beat = period * HOP as f64 / self.sample_rate as f64;
+        // The flux jumps in the first frame an onset falls in, while it's
+        // still in that frame's last hop.
+        let first = (offset * HOP + FRAME - HOP) as f64 / self.sample_rate as f64;
+        Some(BeatGrid {
+            beat: Duration::from_secs_f64(beat),
+            first_beat: Duration::from_secs_f64(first % beat),
+        })
+    }
+
+    /// Key as in "A minor", by correlating the pitch classes with each key's
+    /// profile.
+    fn key(&self) -> Option<String> {
+        if self.chroma.iter().all(|&energy| energy == 0.0) {
+            return None;
+        }
+
+        let mut best = None;
+        for (profile, mode) in [(MAJOR_PROFILE, "major"), (MINOR_PROFILE, "minor")] {
+            for tonic in 0..12 {
+                let rotated: Vec<f32> = (0..12).map(|pc| profile[(pc + 12 - tonic) % 12]).collect();
+                let score = pearson(&self.chroma, &rotated);
+                if best.as_ref().is_none_or(|&(best, _, _)| score > best) {
+                    best = Some((score, tonic, mode));
+                }
+            }
+        }
+        best.map(|(_, tonic, mode)| format!("{} {}", NOTES[tonic], mode))
+    }
+}
+
+fn pearson(a: &[f32], b: &[f32]) -> f32 {
+    let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (a, b) in a.iter().zip(b) {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+    covariance / (variance_a * variance_b).sqrt().max(f32::EPSILON)
+}
+
+fn print_json(paths: &[PathBuf], results: &[Result<Report, ApzError>]) {
+    let number = |value: Option<f64>, decimals: usize| match value {
+        Some(value) if value.is_finite() => {
+            // Rounded first so that -0.001 prints as 0.0 rather than -0.0.
+            let scale = 10f64.powi(decimals as i32);
+            format!("{:.*}", decimals, (value * scale).round() / scale + 0.0)
+        }
+        _ => "null".to_string(),
+    };
+
+    println!("[");
+    for (index, (path, result)) in paths.iter().zip(results).enumerate() {
+        let path = json::string(&path.to_string_lossy());
+        let fields = match result {
+            Ok(report) => {
+                let silences: Vec<String> = report
+                    .silences
+                    .iter()
+                    .map(|silence| {
+                        format!(
+                            "{{\"start\": {:.3}, \"end\": {:.3}}}",
+                            silence.start.as_secs_f64(),
+                            silence.end.as_secs_f64()
+                        )
+                    })
+                    .collect();
+                let dc_offset: Vec<String> = report
+                    .dc_offset
+                    .iter()
+                    .map(|offset| format!("{:.6}", offset))
+                    .collect();
+                format!(
+                    "\"duration\": {:.3}, \"sample_rate\": {}, \"channels\": {}, \
+                     \"integrated_lufs\": {}, \"true_peak_dbtp\": {}, \"bpm\": {}, \
+                     \"key\": {}, \"silences\": [{}], \"dc_offset\": [{}]",
+                    report.duration.as_secs_f64(),
+                    report.sample_rate,
+                    report.channels,
+                    number(report.loudness, 1),
+                    number(Some(report.true_peak as f64), 1),
+                    number(report.bpm.map(f64::from), 1),
+                    report
+                        .key
+                        .as_deref()
+                        .map_or("null".to_string(), json::string),
+                    silences.join(", "),
+                    dc_offset.join(", ")
+                )
+            }
+            Err(e) => format!("\"error\": {}", json::string(&e.to_string())),
+        };
+        let separator = if index + 1 < results.len() { "," } else { "" };
+        println!("  {{\"path\": {}, {}}}{}", path, fields, separator);
+    }
+    println!("]");
+}
+
+/// One row per file. Silences are written as `start-end` pairs and DC
+/// offsets one per channel, both separated by semicolons.
+fn print_csv(paths: &[PathBuf], results: &[Result<Report, ApzError>]) {
+    println!(
+        "path,duration,sample_rate,channels,integrated_lufs,true_peak_dbtp,bpm,key,silences,dc_offset,error"
+    );
+    let number = |value: Option<f64>| match value {
+        Some(value) if value.is_finite() => format!("{:.1}", value),
+        _ => String::new(),
+    };
+
+    for (path, result) in paths.iter().zip(results) {
+        let path = csv_field(&path.to_string_lossy());
+        match result {
+            Ok(report) => {
+                let silences: Vec<String> = report
+                    .silences
+                    .iter()
+                    .map(|silence| {
+                        format!(
+                            "{:.3}-{:.3}",
+                            silence.start.as_secs_f64(),
+                            silence.end.as_secs_f64()
+                        )
+                    })
+                    .collect();
+                let dc_offset: Vec<String> = report
+                    .dc_offset
+                    .iter()
+                    .map(|offset| format!("{:.6}", offset))
+                    .collect();
+                println!(
+                    "{},{:.3},{},{},{},{},{},{},{},{},",
+                    path,
+                    report.duration.as_secs_f64(),
+                    report.sample_rate,
+                    report.channels,
+                    number(report.loudness),
+                    number(Some(report.true_peak as f64)),
+                    number(report.bpm.map(f64::from)),
+                    report.key.as_deref().unwrap_or_default(),
+                    silences.join(";"),
+                    dc_offset.join(";")
+                );
+            }
+            Err(e) => println!("{},,,,,,,,,,{}", path, csv_field(&e.to_string())),
+        }
+    }
+}
+
+/// Quotes a field if it has a comma, quote or line break in it.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
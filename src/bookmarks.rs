@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::paths;
+
+const BOOKMARKS_FILE: &str = "bookmarks.tsv";
+
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    pub label: String,
+    pub position: Duration,
+}
+
+/// Named markers for a single audio file, persisted across sessions in a
+/// shared `bookmarks.tsv` (`path<TAB>millis<TAB>label` per line).
+pub struct Bookmarks {
+    key: String,
+    entries: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn load<P: AsRef<Path>>(audio_path: P) -> Self {
+        let key = fs::canonicalize(&audio_path)
+            .unwrap_or_else(|_| audio_path.as_ref().to_path_buf())
+            .to_string_lossy()
+            .into_owned();
+
+        let mut entries: Vec<Bookmark> = read_store()
+            .lines()
+            .filter_map(parse_line)
+            .filter(|(path, _)| *path == key)
+            .map(|(_, bookmark)| bookmark)
+            .collect();
+        entries.sort_by_key(|b| b.position);
+
+        Self { key, entries }
+    }
+
+    pub fn entries(&self) -> &[Bookmark] {
+        &self.entries
+    }
+
+    pub fn add(&mut self, position: Duration, label: &str) {
+        let label = label.replace(['\t', '\n', '\r'], " ").trim().to_string();
+        let label = if label.is_empty() {
+            format!("Marker {}", self.entries.len() + 1)
+        } else {
+            label
+        };
+
+        self.entries.push(Bookmark { label, position });
+        self.entries.sort_by_key(|b| b.position);
+        self.save();
+    }
+
+    /// First bookmark more than a second past `position`, so repeated jumps
+    /// don't get stuck on the marker we just landed on.
+    pub fn next_after(&self, position: Duration) -> Option<&Bookmark> {
+        self.entries
+            .iter()
+            .find(|b| b.position > position + Duration::from_secs(1))
+    }
+
+    pub fn previous_before(&self, position: Duration) -> Option<&Bookmark> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|b| b.position + Duration::from_secs(1) < position)
+    }
+
+    fn save(&self) {
+        let Some(path) = store_path() else {
+            return;
+        };
+
+        let store = read_store();
+        let mut contents: String = store
+            .lines()
+            .filter(|line| parse_line(line).is_none_or(|(path, _)| path != self.key))
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        for bookmark in &self.entries {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\n",
+                self.key,
+                bookmark.position.as_millis(),
+                bookmark.label
+            ));
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        fs::write(path, contents).ok();
+    }
+}
+
+fn store_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(BOOKMARKS_FILE))
+}
+
+fn read_store() -> String {
+    store_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default()
+}
+
+fn parse_line(line: &str) -> Option<(&str, Bookmark)> {
+    let mut fields = line.splitn(3, '\t');
+    let path = fields.next()?;
+    let millis = fields.next()?.parse().ok()?;
+    let label = fields.next().unwrap_or_default().to_string();
+
+    Some((
+        path,
+        Bookmark {
+            label,
+            position: Duration::from_millis(millis),
+        },
+    ))
+}
@@ -0,0 +1,237 @@
+//! Buffering for streams played over the network. They're decoded ahead of
+//! playback on a thread of their own, so a slow connection runs the buffer
+//! down rather than stalling the audio output, and the player pauses until
+//! it has filled up again instead of stuttering.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::decoder::AudioSource;
+
+/// Frames decoded at a time.
+const CHUNK_FRAMES: usize = 1024;
+/// Room beyond the pre-buffer, so there's some slack once playing.
+const HEADROOM: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct Ring {
+    samples: VecDeque<i16>,
+    /// The stream has been decoded to its end.
+    ended: bool,
+    /// Nothing is reading any more.
+    closed: bool,
+    /// Counts seeks, so audio decoded from before one is thrown away.
+    generation: u64,
+    /// Where the decoding thread should seek to before decoding any more.
+    seek: Option<Duration>,
+}
+
+/// The decoded audio waiting to be played, shared by the decoding thread,
+/// the audio output and the player.
+pub struct Buffering {
+    ring: Mutex<Ring>,
+    changed: Condvar,
+    /// Most samples held at once.
+    capacity: usize,
+    /// Samples played per second, across all channels.
+    rate: f64,
+    /// Playback has caught up with decoding and is getting silence, until
+    /// more is decoded.
+    starved: AtomicBool,
+}
+
+/// How full a stream's buffer is, for the UI.
+#[derive(Debug, Clone, Copy)]
+pub struct BufferStatus {
+    /// From 0.0 to 1.0.
+    pub fill: f32,
+    /// Playback is paused until the buffer has filled up.
+    pub waiting: bool,
+}
+
+impl Buffering {
+    fn update(&self, change: impl FnOnce(&mut Ring)) {
+        change(&mut self.ring.lock().unwrap());
+        self.changed.notify_all();
+    }
+
+    /// Audio decoded and waiting to be played.
+    pub fn ahead(&self) -> Duration {
+        let samples = self.ring.lock().unwrap().samples.len();
+        Duration::from_secs_f64(samples as f64 / self.rate)
+    }
+
+    pub fn fill(&self) -> f32 {
+        let samples = self.ring.lock().unwrap().samples.len();
+        (samples as f32 / self.capacity as f32).min(1.0)
+    }
+
+    /// Whether the whole stream has been decoded, so there's no point
+    /// waiting for more.
+    pub fn is_ended(&self) -> bool {
+        self.ring.lock().unwrap().ended
+    }
+
+    pub fn is_starved(&self) -> bool {
+        self.starved.load(Ordering::Relaxed)
+    }
+}
+
+/// Plays a source decoded ahead into a [`Buffering`], and silence when
+/// that runs dry.
+pub struct BufferedSource {
+    buffering: Arc<Buffering>,
+    /// Samples taken from the ring a batch at a time, so it isn't locked
+    /// for every one.
+    batch: VecDeque<i16>,
+    /// Samples of silence left in the current frame.
+    silence: u16,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+}
+
+impl BufferedSource {
+    /// Starts decoding `source` ahead, keeping up to `prebuffer` and then
+    /// some.
+    pub fn new(source: AudioSource, prebuffer: Duration) -> Self {
+        let channels = source.channels().max(1);
+        let sample_rate = source.sample_rate();
+        let total_duration = source.total_duration();
+        let rate = sample_rate as f64 * channels as f64;
+        let buffering = Arc::new(Buffering {
+            ring: Mutex::new(Ring::default()),
+            changed: Condvar::new(),
+            capacity: ((prebuffer + HEADROOM).as_secs_f64() * rate) as usize,
+            rate,
+            starved: AtomicBool::new(false),
+        });
+
+        let filling = Arc::clone(&buffering);
+        thread::spawn(move || decode_ahead(source, filling, channels as usize));
+
+        Self {
+            buffering,
+            batch: VecDeque::new(),
+            silence: 0,
+            channels,
+            sample_rate,
+            total_duration,
+        }
+    }
+
+    pub fn buffering(&self) -> Arc<Buffering> {
+        Arc::clone(&self.buffering)
+    }
+}
+
+/// Seeking happens here too rather than in [`BufferedSource::try_seek`], as
+/// it can mean waiting on the network and that mustn't hold up the audio
+/// output.
+fn decode_ahead(mut source: AudioSource, buffering: Arc<Buffering>, channels: usize) {
+    let chunk_len = CHUNK_FRAMES * channels;
+    let mut chunk = Vec::with_capacity(chunk_len);
+    loop {
+        let (generation, seek) = {
+            let ring = buffering.ring.lock().unwrap();
+            let mut ring = buffering
+                .changed
+                .wait_while(ring, |ring| {
+                    !ring.closed
+                        && ring.seek.is_none()
+                        && (ring.ended || ring.samples.len() >= buffering.capacity)
+                })
+                .unwrap();
+            if ring.closed {
+                return;
+            }
+            (ring.generation, ring.seek.take())
+        };
+
+        if let Some(pos) = seek {
+            // A stream that can't seek carries on from where it was.
+            let _ = source.try_seek(pos);
+        }
+        chunk.extend(source.by_ref().take(chunk_len));
+        let ended = chunk.len() < chunk_len;
+        buffering.update(|ring| {
+            if ring.generation == generation {
+                ring.samples.extend(chunk.drain(..));
+                ring.ended = ended;
+                buffering.starved.store(false, Ordering::Relaxed);
+            }
+        });
+        chunk.clear();
+    }
+}
+
+impl Drop for BufferedSource {
+    fn drop(&mut self) {
+        self.buffering.update(|ring| ring.closed = true);
+    }
+}
+
+impl Iterator for BufferedSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Some(0);
+        }
+        if self.batch.is_empty() {
+            let mut ring = self.buffering.ring.lock().unwrap();
+            let take = ring
+                .samples
+                .len()
+                .min(CHUNK_FRAMES * self.channels as usize);
+            self.batch.extend(ring.samples.drain(..take));
+            let ended = ring.ended;
+            drop(ring);
+            self.buffering.changed.notify_all();
+
+            if self.batch.is_empty() && !ended {
+                self.buffering.starved.store(true, Ordering::Relaxed);
+                // A whole frame, so the channels stay in step.
+                self.silence = self.channels - 1;
+                return Some(0);
+            }
+        }
+        self.batch.pop_front()
+    }
+}
+
+impl Source for BufferedSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.batch.clear();
+        self.silence = 0;
+        self.buffering.update(|ring| {
+            ring.samples.clear();
+            ring.ended = false;
+            ring.generation += 1;
+            ring.seek = Some(pos);
+        });
+        Ok(())
+    }
+}
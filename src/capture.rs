@@ -0,0 +1,200 @@
+//! Audio coming in from an input device, such as a microphone, or whatever
+//! the system is playing.
+
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::cpal::{
+    self, FromSample, SampleFormat, SizedSample, Stream, StreamConfig, SupportedStreamConfig,
+};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+
+use crate::error::ApzError;
+
+/// Chunks of samples that can be waiting to be picked up. Past this the
+/// newest are dropped rather than holding up the audio thread.
+const QUEUED_CHUNKS: usize = 64;
+
+pub enum Source {
+    /// The default input device.
+    Default,
+    /// The first input device whose name contains this, ignoring case.
+    Named(String),
+    /// Everything the system is playing, as a loopback or monitor device.
+    Loopback,
+}
+
+pub enum Input {
+    /// Interleaved samples, in whatever sized chunks the device delivers.
+    Samples(Vec<f32>),
+    /// The stream stopped working, e.g. because the device was unplugged.
+    Error(String),
+}
+
+/// An open input stream. Capture stops when it's dropped.
+pub struct Capture {
+    _stream: Stream,
+    pub input: Receiver<Input>,
+    pub device_name: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl Capture {
+    /// Starts capturing. For [`Source::Loopback`] this may set environment
+    /// variables, so it has to be called before any other threads start.
+    pub fn start(source: &Source) -> Result<Self, ApzError> {
+        let host = cpal::default_host();
+        let (device, supported) = match source {
+            Source::Default => {
+                let device = host
+                    .default_input_device()
+                    .ok_or_else(|| ApzError::Device("no input device available".to_string()))?;
+                let config = device.default_input_config();
+                (device, config)
+            }
+            Source::Named(wanted) => {
+                let wanted = wanted.to_lowercase();
+                let device = host
+                    .input_devices()
+                    .map_err(|e| ApzError::Device(e.to_string()))?
+                    .find(|device| {
+                        device
+                            .name()
+                            .is_ok_and(|name| name.to_lowercase().contains(&wanted))
+                    })
+                    .ok_or_else(|| {
+                        ApzError::Device(format!("no input device matches '{}'", wanted))
+                    })?;
+                let config = device.default_input_config();
+                (device, config)
+            }
+            Source::Loopback => loopback_device(&host)?,
+        };
+        let supported = supported.map_err(|e| ApzError::Device(e.to_string()))?;
+
+        let config: StreamConfig = supported.config();
+        let (sender, input) = mpsc::sync_channel(QUEUED_CHUNKS);
+        let stream = match supported.sample_format() {
+            SampleFormat::I8 => build::<i8>(&device, &config, sender),
+            SampleFormat::I16 => build::<i16>(&device, &config, sender),
+            SampleFormat::I32 => build::<i32>(&device, &config, sender),
+            SampleFormat::U8 => build::<u8>(&device, &config, sender),
+            SampleFormat::U16 => build::<u16>(&device, &config, sender),
+            SampleFormat::U32 => build::<u32>(&device, &config, sender),
+            SampleFormat::F32 => build::<f32>(&device, &config, sender),
+            SampleFormat::F64 => build::<f64>(&device, &config, sender),
+            format => {
+                return Err(ApzError::Device(format!(
+                    "unsupported sample format {}",
+                    format
+                )));
+            }
+        }?;
+        stream.play().map_err(|e| ApzError::Device(e.to_string()))?;
+
+        Ok(Self {
+            _stream: stream,
+            input,
+            device_name: match source {
+                Source::Loopback => "System audio".to_string(),
+                _ => device.name().unwrap_or_else(|_| "input".to_string()),
+            },
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+        })
+    }
+}
+
+type DeviceConfig = (
+    cpal::Device,
+    Result<SupportedStreamConfig, cpal::DefaultStreamConfigError>,
+);
+
+/// WASAPI records what an output device plays when an input stream is opened
+/// on it.
+#[cfg(windows)]
+fn loopback_device(host: &cpal::Host) -> Result<DeviceConfig, ApzError> {
+    let device = host
+        .default_output_device()
+        .ok_or_else(|| ApzError::Device("no output device to capture".to_string()))?;
+    let config = device.default_output_config();
+    Ok((device, config))
+}
+
+/// PulseAudio, and PipeWire through its PulseAudio server, have a monitor
+/// source for each output. ALSA's `pulse` device records from the source
+/// named in `PULSE_SOURCE`, so unless one is already chosen that's pointed
+/// at the monitor of the default output.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn loopback_device(host: &cpal::Host) -> Result<DeviceConfig, ApzError> {
+    if std::env::var_os("PULSE_SOURCE").is_none() {
+        // SAFETY: `Capture::start` documents that it runs before any other
+        // threads that could be reading the environment.
+        unsafe { std::env::set_var("PULSE_SOURCE", "@DEFAULT_MONITOR@") };
+    }
+    let device = host
+        .input_devices()
+        .map_err(|e| ApzError::Device(e.to_string()))?
+        .find(|device| device.name().is_ok_and(|name| name == "pulse"))
+        .ok_or_else(|| {
+            ApzError::Device(
+                "system audio is captured through ALSA's pulse device, which needs \
+                 PulseAudio or PipeWire; otherwise pick a monitor device with --device"
+                    .to_string(),
+            )
+        })?;
+    let config = device.default_input_config();
+    Ok((device, config))
+}
+
+#[cfg(target_os = "macos")]
+fn loopback_device(_host: &cpal::Host) -> Result<DeviceConfig, ApzError> {
+    Err(ApzError::Device(
+        "macOS can't capture system audio by itself; install a loopback device \
+         such as BlackHole and pick it with --device"
+            .to_string(),
+    ))
+}
+
+fn build<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    sender: SyncSender<Input>,
+) -> Result<Stream, ApzError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let errors = sender.clone();
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _| {
+                let samples = data.iter().map(|&sample| sample.to_sample::<f32>());
+                sender.try_send(Input::Samples(samples.collect())).ok();
+            },
+            move |e| {
+                errors.try_send(Input::Error(e.to_string())).ok();
+            },
+            None,
+        )
+        .map_err(|e| ApzError::Device(e.to_string()))
+}
+
+/// Names of the input devices, default first.
+pub fn input_devices() -> Result<Vec<String>, ApzError> {
+    let host = cpal::default_host();
+    let default = host
+        .default_input_device()
+        .and_then(|device| device.name().ok());
+    let mut names: Vec<String> = host
+        .input_devices()
+        .map_err(|e| ApzError::Device(e.to_string()))?
+        .filter_map(|device| device.name().ok())
+        .collect();
+    if let Some(index) = default.and_then(|default| names.iter().position(|name| *name == default))
+    {
+        let name = names.remove(index);
+        names.insert(0, name);
+    }
+    Ok(names)
+}
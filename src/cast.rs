@@ -0,0 +1,917 @@
+//! Casting to a Chromecast, with apz as the remote. The device is found
+//! with mDNS and told to play the current file, which it fetches from a
+//! small HTTP server apz runs for it; seeking, pausing and volume are
+//! passed on as they happen. The connection is TLS, left to `openssl
+//! s_client` the way downloads are left to curl.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::metadata::TrackInfo;
+use crate::{download, hls, json};
+
+const SERVICE: &str = "_googlecast._tcp.local";
+const MDNS_GROUP: (Ipv4Addr, u16) = (Ipv4Addr::new(224, 0, 0, 251), 5353);
+/// How long to look for devices. The query is sent a few times in that
+/// time, in case one goes missing.
+const DISCOVERY_TIME: Duration = Duration::from_secs(3);
+const CAST_PORT: u16 = 8009;
+/// The receiver app every Chromecast has for playing a URL.
+const MEDIA_RECEIVER: &str = "CC1AD845";
+/// Apps can take a while to start on slower devices.
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(15);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+const CONNECTION: &str = "urn:x-cast:com.google.cast.tp.connection";
+const HEARTBEAT: &str = "urn:x-cast:com.google.cast.tp.heartbeat";
+const RECEIVER: &str = "urn:x-cast:com.google.cast.receiver";
+const MEDIA: &str = "urn:x-cast:com.google.cast.media";
+const SENDER: &str = "sender-0";
+const RECEIVER_ID: &str = "receiver-0";
+
+/// File types the media receiver plays, by extension.
+const CONTENT_TYPES: [(&str, &str); 11] = [
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/mp4"),
+    ("m4b", "audio/mp4"),
+    ("mp4", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("flac", "audio/flac"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/ogg"),
+    ("webm", "audio/webm"),
+];
+
+/// A Chromecast, or a speaker group, found on the network.
+#[derive(Debug, Clone)]
+struct Device {
+    name: String,
+    address: SocketAddr,
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name == self.address.to_string() {
+            write!(f, "{}", self.address)
+        } else {
+            write!(f, "{} ({})", self.name, self.address)
+        }
+    }
+}
+
+/// Asks the network for Chromecasts until `done` is happy with those found
+/// or the time's up.
+fn discover(done: impl Fn(&[Device]) -> bool) -> io::Result<Vec<Device>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    let deadline = Instant::now() + DISCOVERY_TIME;
+    let mut next_query = Instant::now();
+    let mut devices = Vec::new();
+    let mut packet = [0; 9000];
+
+    loop {
+        let now = Instant::now();
+        if now >= deadline || done(&devices) {
+            return Ok(devices);
+        }
+        if now >= next_query {
+            socket.send_to(&query(), MDNS_GROUP)?;
+            next_query = now + DISCOVERY_TIME / 3;
+        }
+        let wait = deadline.min(next_query) - now;
+        socket.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+        // Asked from a port other than mDNS's own, devices answer directly.
+        if let Ok((len, from)) = socket.recv_from(&mut packet) {
+            for device in parse_answer(&packet[..len], from.ip()).unwrap_or_default() {
+                if !devices
+                    .iter()
+                    .any(|known: &Device| known.address == device.address)
+                {
+                    devices.push(device);
+                }
+            }
+        }
+    }
+}
+
+/// A DNS query for the cast service, asking for the answer by unicast.
+fn query() -> Vec<u8> {
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in SERVICE.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    // PTR, class IN with the unicast-response bit.
+    packet.extend_from_slice(&[0, 0, 12, 0x80, 1]);
+    packet
+}
+
+/// Reads the devices out of an mDNS answer sent from `from`: the service
+/// instances it lists, their ports, and their friendly names.
+fn parse_answer(packet: &[u8], from: IpAddr) -> Option<Vec<Device>> {
+    let count = |at: usize| Some(u16::from_be_bytes([*packet.get(at)?, *packet.get(at + 1)?]));
+    let questions = count(4)?;
+    let records = count(6)? as usize + count(8)? as usize + count(10)? as usize;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(packet, pos)?.1 + 4;
+    }
+    let mut instances = Vec::new();
+    let mut ports = HashMap::new();
+    let mut names = HashMap::new();
+    for _ in 0..records {
+        let (name, after) = read_name(packet, pos)?;
+        let kind = count(after)?;
+        let len = count(after + 8)? as usize;
+        let data = after + 10;
+        let rdata = packet.get(data..data + len)?;
+        match kind {
+            12 if name.eq_ignore_ascii_case(SERVICE) => instances.push(read_name(packet, data)?.0),
+            33 if len >= 6 => {
+                ports.insert(name, u16::from_be_bytes([rdata[4], rdata[5]]));
+            }
+            16 => {
+                let mut text = rdata;
+                while let Some((&len, rest)) = text.split_first() {
+                    let entry = rest.get(..len as usize)?;
+                    if let Some(value) = entry.strip_prefix(b"fn=") {
+                        names.insert(name.clone(), String::from_utf8_lossy(value).into_owned());
+                    }
+                    text = &rest[len as usize..];
+                }
+            }
+            _ => {}
+        }
+        pos = data + len;
+    }
+
+    Some(
+        instances
+            .into_iter()
+            .map(|instance| Device {
+                name: names
+                    .get(&instance)
+                    .cloned()
+                    .unwrap_or_else(|| instance.split('.').next().unwrap_or_default().to_string()),
+                address: SocketAddr::new(from, ports.get(&instance).copied().unwrap_or(CAST_PORT)),
+            })
+            .collect(),
+    )
+}
+
+/// Reads the name at `pos`, following compression pointers. Returns it with
+/// the position just after it.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounded, so a pointer loop can't hang discovery.
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        }
+        if len & 0xc0 == 0xc0 {
+            end.get_or_insert(pos + 2);
+            pos = (len & 0x3f) << 8 | *packet.get(pos + 1)? as usize;
+            continue;
+        }
+        let label = packet.get(pos + 1..pos + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos += 1 + len;
+    }
+    None
+}
+
+/// Finds the device `wanted` names, by friendly name or the start of it,
+/// or by address.
+fn find(wanted: &str) -> Result<Device, String> {
+    if let Ok(address) = wanted.parse::<SocketAddr>() {
+        return Ok(Device {
+            name: wanted.to_string(),
+            address,
+        });
+    }
+    if let Ok(ip) = wanted.parse::<IpAddr>() {
+        return Ok(Device {
+            name: wanted.to_string(),
+            address: SocketAddr::new(ip, CAST_PORT),
+        });
+    }
+
+    let exact = |device: &Device| device.name.eq_ignore_ascii_case(wanted);
+    let devices = discover(|devices| devices.iter().any(exact))
+        .map_err(|e| format!("couldn't look for Chromecasts: {}", e))?;
+    let lower = wanted.to_lowercase();
+    let found = devices.iter().find(|device| exact(device)).or_else(|| {
+        devices
+            .iter()
+            .find(|device| device.name.to_lowercase().starts_with(&lower))
+    });
+    match found {
+        Some(device) => Ok(device.clone()),
+        None if devices.is_empty() => Err("no Chromecasts found on the network".to_string()),
+        None => {
+            let names: Vec<String> = devices.iter().map(Device::to_string).collect();
+            Err(format!(
+                "no Chromecast called \"{}\"; found {}",
+                wanted,
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+/// A message between apps, the protobuf `CastMessage` with its payload as
+/// JSON.
+struct Message {
+    source: String,
+    namespace: String,
+    payload: String,
+}
+
+impl Message {
+    fn kind(&self) -> String {
+        json::field(&self.payload, "type").unwrap_or_default()
+    }
+}
+
+fn encode(destination: &str, namespace: &str, payload: &str) -> Vec<u8> {
+    // Protocol version and payload type are both 0: CASTV2_1_0 and STRING.
+    let mut message = vec![0x08, 0];
+    push_field(&mut message, 0x12, SENDER.as_bytes());
+    push_field(&mut message, 0x1a, destination.as_bytes());
+    push_field(&mut message, 0x22, namespace.as_bytes());
+    message.extend_from_slice(&[0x28, 0]);
+    push_field(&mut message, 0x32, payload.as_bytes());
+
+    let mut frame = (message.len() as u32).to_be_bytes().to_vec();
+    frame.extend(message);
+    frame
+}
+
+fn push_field(message: &mut Vec<u8>, key: u8, value: &[u8]) {
+    message.push(key);
+    let mut len = value.len();
+    while len >= 0x80 {
+        message.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    message.push(len as u8);
+    message.extend_from_slice(value);
+}
+
+fn decode(bytes: &[u8]) -> Option<Message> {
+    fn varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *bytes.get(*pos)?;
+            *pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    let mut message = Message {
+        source: String::new(),
+        namespace: String::new(),
+        payload: String::new(),
+    };
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let key = varint(bytes, &mut pos)?;
+        match key & 7 {
+            0 => {
+                varint(bytes, &mut pos)?;
+            }
+            2 => {
+                let len = varint(bytes, &mut pos)? as usize;
+                let value = bytes.get(pos..pos.checked_add(len)?)?;
+                pos += len;
+                let value = String::from_utf8_lossy(value).into_owned();
+                match key >> 3 {
+                    2 => message.source = value,
+                    4 => message.namespace = value,
+                    6 => message.payload = value,
+                    _ => {}
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(message)
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut header = [0; 4];
+    reader.read_exact(&mut header)?;
+    let len = u32::from_be_bytes(header) as usize;
+    if len > 1 << 20 {
+        return Err(io::Error::other("message too long"));
+    }
+    let mut frame = vec![0; len];
+    reader.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+/// Serves the file being cast, and nothing else, at a URL that changes
+/// with every track so the device never mixes them up.
+struct FileServer {
+    address: SocketAddr,
+    served: Arc<Mutex<Option<(String, PathBuf)>>>,
+    count: u32,
+}
+
+impl FileServer {
+    fn start(ip: IpAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind((ip, 0))?;
+        let address = listener.local_addr()?;
+        let served = Arc::new(Mutex::new(None));
+
+        let serving = Arc::clone(&served);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let serving = Arc::clone(&serving);
+                thread::spawn(move || {
+                    let served = serving.lock().unwrap().clone();
+                    serve_file(stream, served).ok();
+                });
+            }
+        });
+        Ok(Self {
+            address,
+            served,
+            count: 0,
+        })
+    }
+
+    /// Starts serving `path`, and returns its URL.
+    fn serve(&mut self, path: &Path, extension: &str) -> String {
+        self.count += 1;
+        let route = format!("/{}.{}", self.count, extension);
+        let url = format!("http://{}{}", self.address, route);
+        *self.served.lock().unwrap() = Some((route, path.to_path_buf()));
+        url
+    }
+}
+
+/// Answers a GET or HEAD for the served file, with byte ranges for
+/// seeking.
+fn serve_file(stream: TcpStream, served: Option<(String, PathBuf)>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    );
+
+    let mut range = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("range")
+        {
+            range = value.trim().strip_prefix("bytes=").and_then(|range| {
+                let (start, end) = range.split_once('-')?;
+                Some((start.parse::<u64>().ok()?, end.parse::<u64>().ok()))
+            });
+        }
+    }
+
+    let mut stream = stream;
+    let file = served
+        .filter(|(route, _)| route == target)
+        .and_then(|(_, path)| File::open(path).ok());
+    let Some(mut file) = file.filter(|_| method == "GET" || method == "HEAD") else {
+        return write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    };
+    let size = file.metadata()?.len();
+    let content_type = content_type(target).unwrap_or("application/octet-stream");
+
+    let (status, start, end) = match range {
+        Some((start, end)) => {
+            let end = end.unwrap_or(u64::MAX).min(size.saturating_sub(1));
+            if start >= size || end < start {
+                return write!(
+                    stream,
+                    "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\n\
+                     Content-Length: 0\r\nConnection: close\r\n\r\n",
+                    size
+                );
+            }
+            ("206 Partial Content", start, end)
+        }
+        None => ("200 OK", 0, size.saturating_sub(1)),
+    };
+    let len = if size == 0 { 0 } else { end - start + 1 };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n",
+        status, content_type, len
+    )?;
+    if status.starts_with("206") {
+        write!(
+            stream,
+            "Content-Range: bytes {}-{}/{}\r\n",
+            start, end, size
+        )?;
+    }
+    write!(
+        stream,
+        "Access-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n"
+    )?;
+    if method == "GET" {
+        file.seek(SeekFrom::Start(start))?;
+        io::copy(&mut file.take(len), &mut stream)?;
+    }
+    stream.flush()
+}
+
+fn content_type(path: &str) -> Option<&'static str> {
+    let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+    CONTENT_TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, content_type)| *content_type)
+}
+
+/// Something that happened on the device that apz didn't ask for, such as
+/// being paused from a phone, or going wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Played,
+    Paused,
+    Volume(f32),
+    Failed(String),
+    /// The connection was lost or the device stopped casting, so nothing
+    /// more will play on it.
+    Lost(String),
+}
+
+/// The device's playhead, as last reported.
+struct Remote {
+    state: String,
+    position: Duration,
+    at: Instant,
+}
+
+struct Session {
+    messages: Receiver<Message>,
+    /// The media receiver's session and where to send it messages.
+    session_id: String,
+    transport: String,
+    media_session: Option<u64>,
+    next_request: u64,
+    /// The LOAD still waiting for an answer.
+    loading: Option<u64>,
+    /// A PLAY, PAUSE or SEEK still waiting for an answer.
+    awaiting: Option<u64>,
+    /// Whether apz wants it playing, once loaded.
+    playing: bool,
+    /// Where to seek once loaded.
+    seek: Option<Duration>,
+    remote: Option<Remote>,
+    volume: Option<f32>,
+    changes: Vec<Change>,
+    last_ping: Instant,
+    last_status: Instant,
+    closed: bool,
+    server: FileServer,
+}
+
+/// A connection to a Chromecast playing what apz plays.
+pub struct Cast {
+    name: String,
+    child: Child,
+    writer: Arc<Mutex<ChildStdin>>,
+    session: Mutex<Session>,
+}
+
+impl Cast {
+    /// Finds `device` and starts the media receiver on it.
+    pub fn connect(device: &str) -> Result<Self, String> {
+        let device = find(device)?;
+        let name = device.name.clone();
+        let mut child = Command::new("openssl")
+            .args(["s_client", "-quiet", "-connect"])
+            .arg(device.address.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => "openssl isn't installed".to_string(),
+                _ => format!("couldn't run openssl: {}", e),
+            })?;
+        let writer = Arc::new(Mutex::new(child.stdin.take().unwrap()));
+        let mut stdout = child.stdout.take().unwrap();
+
+        let (sender, messages) = mpsc::channel();
+        let ponger = Arc::clone(&writer);
+        thread::spawn(move || {
+            while let Some(message) = read_frame(&mut stdout)
+                .ok()
+                .and_then(|frame| decode(&frame))
+            {
+                if message.namespace == HEARTBEAT && message.kind() == "PING" {
+                    let pong = encode(&message.source, HEARTBEAT, r#"{"type":"PONG"}"#);
+                    ponger.lock().unwrap().write_all(&pong).ok();
+                } else if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let send = |destination: &str, namespace: &str, payload: &str| {
+            let frame = encode(destination, namespace, payload);
+            writer.lock().unwrap().write_all(&frame)
+        };
+        let launch = format!(
+            r#"{{"type":"LAUNCH","requestId":1,"appId":"{}"}}"#,
+            MEDIA_RECEIVER
+        );
+        send(RECEIVER_ID, CONNECTION, r#"{"type":"CONNECT"}"#)
+            .and_then(|_| send(RECEIVER_ID, RECEIVER, &launch))
+            .map_err(|_| format!("couldn't connect to {}", device))?;
+
+        let deadline = Instant::now() + LAUNCH_TIMEOUT;
+        let (session_id, transport, volume) = loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let message = match messages.recv_timeout(wait) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => return Err(format!("{} didn't answer", name)),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(format!("couldn't connect to {}", device));
+                }
+            };
+            let field = |key| json::field(&message.payload, key);
+            if message.kind() == "RECEIVER_STATUS"
+                && field("appId").as_deref() == Some(MEDIA_RECEIVER)
+                && let (Some(session_id), Some(transport)) =
+                    (field("sessionId"), field("transportId"))
+            {
+                let volume = field("level").and_then(|level| level.parse().ok());
+                break (session_id, transport, volume);
+            }
+            if message.kind() == "LAUNCH_ERROR" {
+                return Err(format!("{} couldn't start playing", name));
+            }
+        };
+        send(&transport, CONNECTION, r#"{"type":"CONNECT"}"#)
+            .map_err(|_| format!("lost the connection to {}", name))?;
+
+        // Served from whichever address the device can reach apz on.
+        let local = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .and_then(|socket| {
+                socket.connect(device.address)?;
+                socket.local_addr()
+            })
+            .map_err(|e| format!("can't reach {}: {}", device, e))?;
+        let server = FileServer::start(local.ip())
+            .map_err(|e| format!("couldn't serve files to {}: {}", name, e))?;
+
+        Ok(Self {
+            name,
+            child,
+            writer,
+            session: Mutex::new(Session {
+                messages,
+                session_id,
+                transport,
+                media_session: None,
+                next_request: 2,
+                loading: None,
+                awaiting: None,
+                playing: false,
+                seek: None,
+                remote: None,
+                volume,
+                changes: Vec::new(),
+                last_ping: Instant::now(),
+                last_status: Instant::now(),
+                closed: false,
+                server,
+            }),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The device's volume when casting started.
+    pub fn volume(&self) -> Option<f32> {
+        self.session.lock().unwrap().volume
+    }
+
+    fn send(&self, destination: &str, namespace: &str, payload: &str) {
+        let frame = encode(destination, namespace, payload);
+        // A lost connection shows up on the reading side.
+        self.writer.lock().unwrap().write_all(&frame).ok();
+    }
+
+    /// Sends a request of `kind` with `fields` to the media session, if
+    /// there is one. Returns its ID.
+    fn media_request(&self, session: &mut Session, kind: &str, fields: &str) -> Option<u64> {
+        let media_session = session.media_session.filter(|_| !session.closed)?;
+        let id = session.next_request;
+        session.next_request += 1;
+        let payload = format!(
+            r#"{{"type":"{}","requestId":{},"mediaSessionId":{}{}}}"#,
+            kind, id, media_session, fields
+        );
+        self.send(&session.transport, MEDIA, &payload);
+        Some(id)
+    }
+
+    /// Has the device play `path` from `start`, paused until
+    /// [`Cast::play`]. Files it can't play are reported by the next
+    /// [`Cast::poll`].
+    pub fn load(&self, path: &Path, info: &TrackInfo, start: Duration, live: bool) {
+        let mut session = self.session.lock().unwrap();
+        if session.closed {
+            return;
+        }
+        session.media_session = None;
+        session.remote = None;
+        session.awaiting = None;
+        session.seek = None;
+        session.loading = None;
+
+        let file_name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        // Streams go to the device straight from the source, rather than
+        // through apz.
+        let media = if hls::is_playlist(path) {
+            Some((path.to_string_lossy().into_owned(), "application/x-mpegurl"))
+        } else {
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+            content_type(&file_name).map(|content_type| match download::active(path) {
+                Some(download) => (download.url.clone(), content_type),
+                None => (
+                    session.server.serve(path, &extension.to_lowercase()),
+                    content_type,
+                ),
+            })
+        };
+        let Some((url, content_type)) = media else {
+            session.changes.push(Change::Failed(format!(
+                "{} can't play {}",
+                self.name, file_name
+            )));
+            return;
+        };
+
+        let optional = |key: &str, value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|value| format!(r#","{}":{}"#, key, json::string(value)))
+                .unwrap_or_default()
+        };
+        let title = info.title.clone().or_else(|| Some(file_name.into_owned()));
+        let id = session.next_request;
+        session.next_request += 1;
+        let payload = format!(
+            r#"{{"type":"LOAD","requestId":{},"sessionId":{},"media":{{"contentId":{},"contentType":"{}","streamType":"{}","metadata":{{"metadataType":3{}{}{}}}}},"autoplay":false,"currentTime":{}}}"#,
+            id,
+            json::string(&session.session_id),
+            json::string(&url),
+            content_type,
+            if live { "LIVE" } else { "BUFFERED" },
+            optional("title", &title),
+            optional("artist", &info.artist),
+            optional("albumName", &info.album),
+            start.as_secs_f64()
+        );
+        self.send(&session.transport, MEDIA, &payload);
+        session.loading = Some(id);
+    }
+
+    pub fn play(&self) {
+        let mut session = self.session.lock().unwrap();
+        session.playing = true;
+        session.awaiting = self.media_request(&mut session, "PLAY", "");
+    }
+
+    pub fn pause(&self) {
+        let mut session = self.session.lock().unwrap();
+        session.playing = false;
+        session.awaiting = self.media_request(&mut session, "PAUSE", "");
+    }
+
+    pub fn seek(&self, position: Duration) {
+        let mut session = self.session.lock().unwrap();
+        if session.loading.is_some() {
+            session.seek = Some(position);
+            return;
+        }
+        let fields = format!(r#","currentTime":{}"#, position.as_secs_f64());
+        session.awaiting = self.media_request(&mut session, "SEEK", &fields);
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        let mut session = self.session.lock().unwrap();
+        session.volume = Some(volume);
+        let id = session.next_request;
+        session.next_request += 1;
+        let payload = format!(
+            r#"{{"type":"SET_VOLUME","requestId":{},"volume":{{"level":{}}}}}"#,
+            id, volume
+        );
+        self.send(RECEIVER_ID, RECEIVER, &payload);
+    }
+
+    /// Whether playback on the device has yet to catch up with what it was
+    /// last asked to do, so apz should hold on.
+    pub fn is_pending(&self) -> bool {
+        let session = self.session.lock().unwrap();
+        !session.closed
+            && (session.loading.is_some()
+                || session.awaiting.is_some()
+                || session
+                    .remote
+                    .as_ref()
+                    .is_some_and(|remote| remote.state == "BUFFERING"))
+    }
+
+    /// Where the device is playing, while it's playing.
+    pub fn position(&self) -> Option<Duration> {
+        let session = self.session.lock().unwrap();
+        let remote = session.remote.as_ref()?;
+        (remote.state == "PLAYING").then(|| remote.position + remote.at.elapsed())
+    }
+
+    /// Handles what the device has said since last time, keeping the
+    /// connection alive, and returns any changes made on it.
+    pub fn poll(&self) -> Vec<Change> {
+        let mut session = self.session.lock().unwrap();
+        loop {
+            match session.messages.try_recv() {
+                Ok(message) => self.handle(&mut session, &message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !session.closed {
+                        session.closed = true;
+                        let message = format!("Lost the connection to {}", self.name);
+                        session.changes.push(Change::Lost(message));
+                    }
+                    break;
+                }
+            }
+        }
+
+        if !session.closed && session.last_ping.elapsed() >= HEARTBEAT_INTERVAL {
+            session.last_ping = Instant::now();
+            self.send(RECEIVER_ID, HEARTBEAT, r#"{"type":"PING"}"#);
+        }
+        if session.last_status.elapsed() >= STATUS_INTERVAL {
+            session.last_status = Instant::now();
+            self.media_request(&mut session, "GET_STATUS", "");
+        }
+        std::mem::take(&mut session.changes)
+    }
+
+    fn handle(&self, session: &mut Session, message: &Message) {
+        let field = |key| json::field(&message.payload, key);
+        let request = field("requestId")
+            .and_then(|id| id.parse::<u64>().ok())
+            .filter(|&id| id != 0);
+        if request.is_some() && request == session.awaiting {
+            session.awaiting = None;
+        }
+
+        match message.kind().as_str() {
+            "CLOSE" if message.namespace == CONNECTION => self.stopped(session),
+            "RECEIVER_STATUS" => {
+                if field("sessionId").as_deref() != Some(&session.session_id) {
+                    self.stopped(session);
+                    return;
+                }
+                let level = field("level").and_then(|level| level.parse::<f32>().ok());
+                if let Some(level) = level
+                    && request.is_none()
+                    && session
+                        .volume
+                        .is_none_or(|volume| (volume - level).abs() > 0.005)
+                {
+                    session.volume = Some(level);
+                    session.changes.push(Change::Volume(level));
+                }
+            }
+            "LOAD_FAILED" if request.is_some() && request == session.loading => {
+                session.loading = None;
+                session.changes.push(Change::Failed(format!(
+                    "{} couldn't play the track",
+                    self.name
+                )));
+            }
+            "MEDIA_STATUS" => {
+                let Some(media_session) = field("mediaSessionId").and_then(|id| id.parse().ok())
+                else {
+                    return;
+                };
+                if request.is_some() && request == session.loading {
+                    session.loading = None;
+                    session.media_session = Some(media_session);
+                    if let Some(position) = session.seek.take() {
+                        let fields = format!(r#","currentTime":{}"#, position.as_secs_f64());
+                        session.awaiting = self.media_request(session, "SEEK", &fields);
+                    }
+                    if session.playing {
+                        session.awaiting = self.media_request(session, "PLAY", "");
+                    }
+                }
+                // Anything else is about a track that's been replaced.
+                if session.media_session != Some(media_session) {
+                    return;
+                }
+
+                let state = field("playerState").unwrap_or_default();
+                let position = field("currentTime")
+                    .and_then(|time| time.parse::<f64>().ok())
+                    .filter(|time| time.is_finite() && *time >= 0.0)
+                    .map(Duration::from_secs_f64);
+                if request.is_none() && session.awaiting.is_none() {
+                    if state == "PLAYING" && !session.playing {
+                        session.playing = true;
+                        session.changes.push(Change::Played);
+                    } else if state == "PAUSED" && session.playing {
+                        session.playing = false;
+                        session.changes.push(Change::Paused);
+                    }
+                }
+                if state == "IDLE" && field("idleReason").as_deref() == Some("ERROR") {
+                    session.changes.push(Change::Failed(format!(
+                        "{} couldn't play the track",
+                        self.name
+                    )));
+                }
+                session.remote = Some(Remote {
+                    position: position
+                        .or(session.remote.as_ref().map(|remote| remote.position))
+                        .unwrap_or_default(),
+                    state,
+                    at: Instant::now(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// The media receiver was closed, or something else was cast in its
+    /// place.
+    fn stopped(&self, session: &mut Session) {
+        if !session.closed {
+            session.closed = true;
+            session
+                .changes
+                .push(Change::Lost(format!("{} stopped casting", self.name)));
+        }
+    }
+}
+
+impl Drop for Cast {
+    /// Stops the device playing, since nothing is left to tell it what's
+    /// next.
+    fn drop(&mut self) {
+        let session = self.session.get_mut().unwrap();
+        if !session.closed {
+            let payload = format!(
+                r#"{{"type":"STOP","requestId":{},"sessionId":{}}}"#,
+                session.next_request,
+                json::string(&session.session_id)
+            );
+            let frame = encode(RECEIVER_ID, RECEIVER, &payload);
+            self.writer.lock().unwrap().write_all(&frame).ok();
+            // Give openssl a moment to pass it on.
+            thread::sleep(Duration::from_millis(200));
+        }
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
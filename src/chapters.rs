@@ -0,0 +1,289 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+}
+
+/// Loads chapters for `path`, preferring a sidecar `.cue` sheet over
+/// chapters embedded in the file itself (ID3v2 `CHAP` or MP4 `chpl`).
+pub fn load_chapters<P: AsRef<Path>>(path: P) -> Vec<Chapter> {
+    let path = path.as_ref();
+
+    let mut chapters = find_cue_sheet(path)
+        .and_then(|cue| fs::read_to_string(cue).ok())
+        .map(|contents| parse_cue_sheet(&contents))
+        .filter(|chapters| !chapters.is_empty())
+        .or_else(|| read_id3_chapters(path).ok().filter(|c| !c.is_empty()))
+        .or_else(|| read_mp4_chapters(path).ok().filter(|c| !c.is_empty()))
+        .unwrap_or_default();
+
+    chapters.sort_by_key(|c| c.start);
+    chapters
+}
+
+/// Returns the index of the chapter containing `position`.
+pub fn chapter_at(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    chapters.iter().rposition(|c| c.start <= position)
+}
+
+fn find_cue_sheet(path: &Path) -> Option<PathBuf> {
+    let mut appended = path.as_os_str().to_owned();
+    appended.push(".cue");
+
+    [path.with_extension("cue"), PathBuf::from(appended)]
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+}
+
+fn parse_cue_sheet(contents: &str) -> Vec<Chapter> {
+    let mut chapters = Vec::new();
+    let mut in_track = false;
+    let mut title = String::new();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        match command {
+            "TRACK" => {
+                in_track = true;
+                title = format!("Track {}", rest.split_whitespace().next().unwrap_or("?"));
+            }
+            "TITLE" if in_track => {
+                title = rest.trim().trim_matches('"').to_string();
+            }
+            "INDEX" if in_track => {
+                let mut parts = rest.split_whitespace();
+                if parts.next() == Some("01")
+                    && let Some(start) = parts.next().and_then(parse_cue_time)
+                {
+                    chapters.push(Chapter {
+                        title: title.clone(),
+                        start,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    chapters
+}
+
+/// Parses a cue sheet `mm:ss:ff` timestamp (75 frames per second).
+fn parse_cue_time(time: &str) -> Option<Duration> {
+    let mut parts = time.split(':').map(|p| p.parse::<u64>().ok());
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+    let frames = parts.next()??;
+    let millis = minutes
+        .checked_mul(60)?
+        .checked_add(seconds)?
+        .checked_mul(1000)?
+        .checked_add(frames.checked_mul(1000)? / 75)?;
+    Some(Duration::from_millis(millis))
+}
+
+fn read_id3_chapters(path: &Path) -> io::Result<Vec<Chapter>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header)?;
+
+    if &header[..3] != b"ID3" {
+        return Ok(Vec::new());
+    }
+
+    let version = header[3];
+    let flags = header[5];
+    let tag_size = syncsafe(&header[6..10]);
+    let mut tag = vec![0u8; tag_size];
+    file.read_exact(&mut tag)?;
+
+    let mut offset = 0;
+    if flags & 0x40 != 0 && tag.len() >= 4 {
+        // Extended header: v2.4 includes its own size field, v2.3 does not.
+        offset = if version >= 4 {
+            syncsafe(&tag[..4])
+        } else {
+            4 + u32::from_be_bytes([tag[0], tag[1], tag[2], tag[3]]) as usize
+        };
+    }
+
+    let mut chapters = Vec::new();
+    for (id, body) in id3_frames(&tag[offset.min(tag.len())..], version) {
+        if id == b"CHAP"
+            && let Some(chapter) = parse_chap_frame(body, version, chapters.len())
+        {
+            chapters.push(chapter);
+        }
+    }
+
+    Ok(chapters)
+}
+
+fn id3_frames(mut data: &[u8], version: u8) -> Vec<(&[u8], &[u8])> {
+    let mut frames = Vec::new();
+
+    while data.len() >= 10 && data[0] != 0 {
+        let id = &data[..4];
+        let size = if version >= 4 {
+            syncsafe(&data[4..8])
+        } else {
+            u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize
+        };
+
+        let end = (10 + size).min(data.len());
+        frames.push((id, &data[10..end]));
+        data = &data[end..];
+    }
+
+    frames
+}
+
+fn parse_chap_frame(body: &[u8], version: u8, index: usize) -> Option<Chapter> {
+    let id_end = body.iter().position(|&b| b == 0)?;
+    let times = body.get(id_end + 1..id_end + 17)?;
+    let start_ms = u32::from_be_bytes([times[0], times[1], times[2], times[3]]);
+
+    let title = id3_frames(&body[id_end + 17..], version)
+        .into_iter()
+        .find(|(id, _)| *id == b"TIT2")
+        .and_then(|(_, text)| decode_id3_text(text))
+        .unwrap_or_else(|| format!("Chapter {}", index + 1));
+
+    Some(Chapter {
+        title,
+        start: Duration::from_millis(start_ms as u64),
+    })
+}
+
+fn decode_id3_text(text: &[u8]) -> Option<String> {
+    let (&encoding, bytes) = text.split_first()?;
+
+    let decoded = match encoding {
+        1 | 2 => {
+            let mut units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            match units.first() {
+                Some(0xFFFE) => {
+                    units = units.iter().skip(1).map(|u| u.swap_bytes()).collect();
+                }
+                Some(0xFEFF) => {
+                    units.remove(0);
+                }
+                _ => {}
+            }
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(bytes).into_owned(),
+        _ => bytes.iter().map(|&b| b as char).collect(),
+    };
+
+    let decoded = decoded.trim_end_matches('\0').trim().to_string();
+    (!decoded.is_empty()).then_some(decoded)
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0usize, |acc, &b| (acc << 7) | (b & 0x7F) as usize)
+}
+
+fn read_mp4_chapters(path: &Path) -> io::Result<Vec<Chapter>> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+
+    let Some((moov_start, moov_len)) = find_atom(&mut file, 0, file_len, b"moov")? else {
+        return Ok(Vec::new());
+    };
+    let Some((udta_start, udta_len)) = find_atom(&mut file, moov_start, moov_len, b"udta")? else {
+        return Ok(Vec::new());
+    };
+    let Some((chpl_start, chpl_len)) = find_atom(&mut file, udta_start, udta_len, b"chpl")? else {
+        return Ok(Vec::new());
+    };
+
+    // The atom's size comes from the file, so don't trust it beyond the
+    // file's end.
+    let chpl_len = chpl_len.min(file_len.saturating_sub(chpl_start));
+    let mut chpl = vec![0u8; chpl_len as usize];
+    file.seek(SeekFrom::Start(chpl_start))?;
+    file.read_exact(&mut chpl)?;
+
+    Ok(parse_chpl(&chpl).unwrap_or_default())
+}
+
+/// Scans the atoms in `[start, start + len)` for `name`, returning the
+/// offset and length of its payload.
+fn find_atom(
+    file: &mut File,
+    start: u64,
+    len: u64,
+    name: &[u8; 4],
+) -> io::Result<Option<(u64, u64)>> {
+    let end = start.saturating_add(len);
+    let mut offset = start;
+
+    while end.saturating_sub(offset) >= 8 {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - offset;
+        }
+
+        if size < header_len {
+            break;
+        }
+        if &header[4..8] == name {
+            return Ok(Some((offset + header_len, size - header_len)));
+        }
+        let Some(next) = offset.checked_add(size) else {
+            break;
+        };
+        offset = next;
+    }
+
+    Ok(None)
+}
+
+fn parse_chpl(data: &[u8]) -> Option<Vec<Chapter>> {
+    let version = *data.first()?;
+    let mut offset = if version > 0 { 8 } else { 4 };
+    let count = *data.get(offset)? as usize;
+    offset += 1;
+
+    let mut chapters = Vec::with_capacity(count);
+    for _ in 0..count {
+        let start = u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?);
+        let title_len = *data.get(offset + 8)? as usize;
+        let title = data.get(offset + 9..offset + 9 + title_len)?;
+        offset += 9 + title_len;
+
+        // chpl timestamps are in 100ns units
+        let Some(start) = start.checked_mul(100) else {
+            continue;
+        };
+        chapters.push(Chapter {
+            title: String::from_utf8_lossy(title).into_owned(),
+            start: Duration::from_nanos(start),
+        });
+    }
+
+    Some(chapters)
+}
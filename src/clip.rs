@@ -0,0 +1,144 @@
+//! Saves part of a track, between the in and out points marked while
+//! listening, as WAV or FLAC.
+
+use rodio::Source;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use crate::decoder;
+use crate::error::ApzError;
+use crate::flac::FlacWriter;
+use crate::raw;
+use crate::wav::WavWriter;
+
+enum Writer {
+    Wav(WavWriter),
+    Flac(FlacWriter),
+}
+
+impl Writer {
+    /// Picks the format from the extension of `path`, and creates the file
+    /// as [`create_new`] does. Returns the writer and where it's writing.
+    fn create(path: &Path, sample_rate: u32, channels: u16) -> io::Result<(Self, PathBuf)> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if extension != "wav" && extension != "flac" {
+            return Err(io::Error::other("clips are saved as .wav or .flac"));
+        }
+        let (file, path) = create_new(path)?;
+        let writer = if extension == "wav" {
+            Self::Wav(WavWriter::new(file, sample_rate, channels)?)
+        } else {
+            Self::Flac(FlacWriter::new(file, sample_rate, channels)?)
+        };
+        Ok((writer, path))
+    }
+
+    fn write(&mut self, sample: i16) -> io::Result<()> {
+        match self {
+            Self::Wav(writer) => writer.write(sample),
+            Self::Flac(writer) => writer.write(sample),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Wav(writer) => writer.finish(),
+            Self::Flac(writer) => writer.finish(),
+        }
+    }
+}
+
+/// Exports on a background thread, since decoding up to the in point of a
+/// long track takes a while. Sends back where the clip was saved.
+pub fn export_in_background(
+    path: PathBuf,
+    range: Range<Duration>,
+    output: PathBuf,
+) -> Receiver<Result<PathBuf, ApzError>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = export(&path, range, &output);
+        sender.send(result).ok();
+    });
+    receiver
+}
+
+/// Saves the clip to `output`, or beside it under a numbered name if
+/// something's already there. Returns where it was saved.
+pub fn export(path: &Path, range: Range<Duration>, output: &Path) -> Result<PathBuf, ApzError> {
+    if raw::is_stdin(path) {
+        return Err(io::Error::other("can't export from stdin").into());
+    }
+
+    let mut source = decoder::open(path)?.source;
+    let channels = source.channels().max(1);
+    let sample_rate = source.sample_rate().max(1);
+    let frame_at = |time: Duration| (time.as_secs_f64() * sample_rate as f64) as u64;
+
+    // Seeking is much quicker where the decoder supports it; otherwise the
+    // audio before the in point is decoded and thrown away.
+    let skip = if source.try_seek(range.start).is_ok() {
+        0
+    } else {
+        frame_at(range.start) * channels as u64
+    };
+    let length = frame_at(range.end).saturating_sub(frame_at(range.start)) * channels as u64;
+
+    let (mut writer, output) = Writer::create(output, sample_rate, channels)?;
+    for sample in source.skip(skip as usize).take(length as usize) {
+        writer.write(sample)?;
+    }
+    writer.finish()?;
+    Ok(output)
+}
+
+/// Creates `path`, never opening a file that's already there: one that is
+/// is left alone and ` (2)`, ` (3)` and so on tried after the name instead.
+fn create_new(path: &Path) -> io::Result<(File, PathBuf)> {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    for n in 1..1000 {
+        let candidate = if n == 1 {
+            path.to_path_buf()
+        } else {
+            path.with_file_name(format!("{} ({}).{}", stem, n, extension))
+        };
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok((file, candidate)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} and the names after it are all taken", path.display()),
+    ))
+}
+
+/// A name for a clip of `path`, beside it, like `talk 01m05s-01m30s.wav`.
+pub fn default_name(path: &Path, range: &Range<Duration>) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let time = |time: Duration| {
+        let seconds = time.as_secs();
+        format!("{:02}m{:02}s", seconds / 60, seconds % 60)
+    };
+    path.with_file_name(format!(
+        "{} {}-{}.wav",
+        stem,
+        time(range.start),
+        time(range.end)
+    ))
+}
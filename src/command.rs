@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Actions that can reach the player from outside the keyboard handler, such
+/// as another apz process talking to the control socket or an HTTP client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Enqueue(PathBuf),
+    /// Enqueue and start playing right away.
+    Open(PathBuf),
+    /// Play this right after the current track.
+    InsertNext(PathBuf),
+    RemoveFromQueue(usize),
+    /// Remove several tracks at once, by index.
+    RemoveTracks(Vec<usize>),
+    /// Move the track at the first index to the second.
+    MoveInQueue(usize, usize),
+    /// Move these tracks to the top of the queue, in the order they're in.
+    MoveToTop(Vec<usize>),
+    /// Move these tracks to play right after the current one.
+    PlayNext(Vec<usize>),
+    /// Remove everything but the current track.
+    ClearQueue,
+    /// Swap in a new list of tracks, carrying on with the current one.
+    ReplaceQueue(Vec<PathBuf>),
+    PlayIndex(usize),
+    Play,
+    Pause,
+    TogglePause,
+    /// Pause and go back to the start of the track.
+    Stop,
+    NextTrack,
+    PreviousTrack,
+    Seek(i64),
+    SeekTo(Duration),
+    SetVolume(f32),
+    /// Show a message, or an error, in the UI.
+    Toast(String),
+    Error(String),
+    Quit,
+}
+
+impl Command {
+    /// Parses one line of the control socket's text protocol, e.g.
+    /// `enqueue /music/song.flac` or `next`.
+    #[cfg(unix)]
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        match name {
+            "enqueue" if !argument.is_empty() => Some(Command::Enqueue(PathBuf::from(argument))),
+            "toggle" => Some(Command::TogglePause),
+            "next" => Some(Command::NextTrack),
+            "previous" | "prev" => Some(Command::PreviousTrack),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! A dynamic range compressor, for evening out recordings whose level jumps
+//! around, such as speech from several microphones.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompressorConfig {
+    /// Level in dBFS above which the gain is turned down.
+    pub threshold_db: f32,
+    /// How many dB over the threshold it takes to raise the output by one.
+    pub ratio: f32,
+    /// How quickly the gain comes down once the level is over the threshold.
+    pub attack: Duration,
+    /// How quickly it recovers once the level falls back.
+    pub release: Duration,
+    /// Gain applied afterwards, to make up for what was taken off the peaks.
+    pub makeup_db: f32,
+}
+
+impl Default for CompressorConfig {
+    fn default() -> Self {
+        Self {
+            threshold_db: -24.0,
+            ratio: 4.0,
+            attack: Duration::from_millis(10),
+            release: Duration::from_millis(200),
+            makeup_db: 0.0,
+        }
+    }
+}
+
+/// Settings for the compressor, set from the UI, and how far it's turning
+/// the gain down, set from the audio thread for metering.
+pub struct Compressor {
+    enabled: AtomicBool,
+    /// Threshold, ratio, attack and release in seconds, and makeup gain, as
+    /// bits.
+    settings: [AtomicU32; 5],
+    /// Gain reduction in dB, as bits.
+    reduction: AtomicU32,
+}
+
+impl Compressor {
+    pub fn new() -> Arc<Self> {
+        let compressor = Self {
+            enabled: AtomicBool::new(false),
+            settings: std::array::from_fn(|_| AtomicU32::new(0)),
+            reduction: AtomicU32::new(0),
+        };
+        compressor.configure(CompressorConfig::default());
+        Arc::new(compressor)
+    }
+
+    pub fn config(&self) -> CompressorConfig {
+        let [threshold_db, ratio, attack, release, makeup_db] = self
+            .settings
+            .each_ref()
+            .map(|setting| f32::from_bits(setting.load(Ordering::Relaxed)));
+        CompressorConfig {
+            threshold_db,
+            ratio,
+            attack: Duration::from_secs_f32(attack),
+            release: Duration::from_secs_f32(release),
+            makeup_db,
+        }
+    }
+
+    pub fn configure(&self, config: CompressorConfig) {
+        let values = [
+            config.threshold_db,
+            config.ratio.max(1.0),
+            config.attack.as_secs_f32(),
+            config.release.as_secs_f32(),
+            config.makeup_db,
+        ];
+        for (setting, value) in self.settings.iter().zip(values) {
+            setting.store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// How many dB the gain is currently turned down by.
+    pub fn reduction_db(&self) -> f32 {
+        f32::from_bits(self.reduction.load(Ordering::Relaxed))
+    }
+}
+
+/// Applies a [`Compressor`] a frame at a time, so every channel gets the
+/// same gain and the stereo image doesn't shift.
+pub struct CompressorSource<I> {
+    input: I,
+    compressor: Arc<Compressor>,
+    frame: Vec<f32>,
+    /// Next sample of `frame` to hand out.
+    position: usize,
+    /// Gain reduction in dB, following the level with the attack and
+    /// release times.
+    reduction: f32,
+    /// Share of the old reduction kept each frame while it's rising and
+    /// falling.
+    attack: f32,
+    release: f32,
+    /// Attack and release times and sample rate `attack` and `release` were
+    /// worked out for.
+    timing: Option<(Duration, Duration, u32)>,
+}
+
+impl<I> CompressorSource<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(input: I, compressor: Arc<Compressor>) -> Self {
+        Self {
+            input,
+            compressor,
+            frame: Vec::new(),
+            position: 0,
+            reduction: 0.0,
+            attack: 0.0,
+            release: 0.0,
+            timing: None,
+        }
+    }
+
+    /// Reads the next frame and applies the gain to it. Returns false at
+    /// the end of the input.
+    fn next_frame(&mut self) -> bool {
+        let channels = self.input.channels().max(1) as usize;
+        let sample_rate = self.input.sample_rate().max(1);
+        self.frame.clear();
+        self.frame.extend(self.input.by_ref().take(channels));
+        self.position = 0;
+        if self.frame.is_empty() {
+            return false;
+        }
+
+        if !self.compressor.is_enabled() {
+            if self.reduction != 0.0 {
+                self.reduction = 0.0;
+                self.compressor.reduction.store(0, Ordering::Relaxed);
+            }
+            return true;
+        }
+
+        let config = self.compressor.config();
+        let timing = (config.attack, config.release, sample_rate);
+        if self.timing != Some(timing) {
+            self.timing = Some(timing);
+            let coefficient =
+                |time: Duration| (-1.0 / (time.as_secs_f32() * sample_rate as f32).max(1.0)).exp();
+            self.attack = coefficient(config.attack);
+            self.release = coefficient(config.release);
+        }
+
+        let peak = self.frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let level_db = 20.0 * peak.max(1e-9).log10();
+        let over = (level_db - config.threshold_db).max(0.0);
+        let target = over * (1.0 - 1.0 / config.ratio);
+        let kept = if target > self.reduction {
+            self.attack
+        } else {
+            self.release
+        };
+        self.reduction = target + kept * (self.reduction - target);
+        self.compressor
+            .reduction
+            .store(self.reduction.to_bits(), Ordering::Relaxed);
+
+        let gain = 10f32.powf((config.makeup_db - self.reduction) / 20.0);
+        for sample in &mut self.frame {
+            *sample *= gain;
+        }
+        true
+    }
+}
+
+impl<I> Iterator for CompressorSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.frame.len() && !self.next_frame() {
+            return None;
+        }
+        let sample = self.frame[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl<I> Source for CompressorSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // Samples of the frame read ahead haven't been handed out yet.
+        let pending = self.frame.len() - self.position;
+        self.input.current_frame_len().map(|len| len + pending)
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.frame.clear();
+        self.position = 0;
+        Ok(())
+    }
+}
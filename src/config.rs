@@ -1,41 +1,365 @@
+use std::fs;
+use std::path::PathBuf;
 use std::process;
+use std::time::Duration;
+
+use crate::compressor::CompressorConfig;
+use crate::cover;
+use crate::directory::{self, SortOrder};
+use crate::download;
+use crate::eq;
+use crate::hooks::HookConfig;
+use crate::library;
+use crate::network_output::Target;
+use crate::paths;
+use crate::raw::{self, RawFormat, SampleFormat};
+use crate::resample::{self, Quality};
+use crate::shuffle::{self, ShuffleMode};
+use crate::stereo::{self, Channel};
+use crate::theme::{self, Theme};
+use crate::ui::{self, Panel, SpectrumLayout};
+use crate::visualizer;
+use crate::waveform::{self, Reduction};
 
 pub struct Config {
-    pub audio_path: String,
+    pub audio_paths: Vec<PathBuf>,
+    pub recursive: bool,
+    pub sort_order: SortOrder,
+    pub min_rating: Option<u8>,
+    pub shuffle: Option<ShuffleMode>,
+    pub auto_advance: bool,
+    pub watch: Option<PathBuf>,
+    pub keep_dir: Option<PathBuf>,
+    pub reject_dir: Option<PathBuf>,
     pub use_visualizer: bool,
+    pub enhanced_waveform: bool,
+    pub stereo_waveform: bool,
+    pub waveform_db: bool,
+    pub waveform_resolution: Option<usize>,
+    pub waveform_reduction: Option<Reduction>,
     pub num_bars: usize,
     pub smoothing: f32,
     pub bass_boost: f32,
+    pub min_freq: f32,
+    pub max_freq: f32,
+    pub freq_curve: f32,
+    pub fps: u32,
+    pub beat_pulse: bool,
+    pub spectrum_layout: SpectrumLayout,
+    /// Columns the cover may take beside the visualizer, if it's shown.
+    pub cover_width: Option<u16>,
+    /// Name of the visualization to start with.
+    pub visualization: Option<String>,
+    pub eq: [eq::Band; eq::BANDS],
+    pub compress: bool,
+    pub compressor: CompressorConfig,
+    pub stereo_width: f32,
+    pub mono: bool,
+    pub solo: Option<Channel>,
+    pub resampler: Quality,
+    /// Semitones to play tracks up or down.
+    pub transpose: i32,
+    /// Send the audio here instead of to the sound card.
+    pub output: Option<Target>,
+    /// Chromecast to play on, by name or address.
+    pub cast: Option<String>,
+    /// Compare the two files given instead of queueing them.
+    pub ab: bool,
+    /// RSS feed whose episodes are listed to pick from.
+    pub podcast: Option<String>,
+    /// Audio a network stream buffers before playing.
+    pub prebuffer: Duration,
+    pub low_power: bool,
     pub volume_step: f32,
     pub seek_step: i64,
+    /// Tempo added after each clean repetition of a practice loop.
+    pub practice_step: f32,
+    pub metronome: bool,
+    pub fade: Duration,
+    pub skip_silence: bool,
+    pub trim_silence: bool,
+    pub silence_threshold: f32,
+    pub silence_min_length: Duration,
+    pub discord_client_id: Option<String>,
+    pub set_title: bool,
+    pub status_file: Option<PathBuf>,
+    /// Shell commands run on playback events.
+    pub hooks: HookConfig,
+    pub scripts: Vec<PathBuf>,
+    pub soundfont: Option<PathBuf>,
+    pub raw_format: RawFormat,
+    pub new_instance: bool,
+    pub serve_addr: Option<String>,
+    pub mpd_addr: Option<String>,
+    pub media_keys: bool,
+    pub mouse: bool,
+    pub precise_time: bool,
+    pub hidden_panels: Vec<Panel>,
+    pub theme: Theme,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            audio_path: String::new(),
+            audio_paths: Vec::new(),
+            recursive: false,
+            sort_order: SortOrder::default(),
+            min_rating: None,
+            shuffle: None,
+            auto_advance: true,
+            watch: None,
+            keep_dir: None,
+            reject_dir: None,
             use_visualizer: false,
+            enhanced_waveform: false,
+            stereo_waveform: false,
+            waveform_db: false,
+            waveform_resolution: None,
+            waveform_reduction: None,
             num_bars: 100,
             smoothing: 0.7,
             bass_boost: 1.5,
+            min_freq: 20.0,
+            max_freq: 20000.0,
+            freq_curve: 1.0,
+            fps: 30,
+            beat_pulse: false,
+            spectrum_layout: SpectrumLayout::default(),
+            cover_width: None,
+            visualization: None,
+            eq: eq::DEFAULT_BANDS,
+            compress: false,
+            compressor: CompressorConfig::default(),
+            stereo_width: 1.0,
+            mono: false,
+            solo: None,
+            resampler: Quality::default(),
+            transpose: 0,
+            output: None,
+            cast: None,
+            ab: false,
+            podcast: None,
+            prebuffer: Duration::from_secs(3),
+            low_power: false,
             volume_step: 0.05,
             seek_step: 5,
+            practice_step: 0.05,
+            metronome: false,
+            fade: Duration::from_millis(100),
+            skip_silence: false,
+            trim_silence: false,
+            silence_threshold: -50.0,
+            silence_min_length: Duration::from_secs(2),
+            discord_client_id: None,
+            set_title: true,
+            status_file: None,
+            hooks: HookConfig::default(),
+            scripts: Vec::new(),
+            soundfont: None,
+            raw_format: RawFormat::default(),
+            new_instance: false,
+            serve_addr: None,
+            mpd_addr: None,
+            media_keys: true,
+            mouse: false,
+            precise_time: false,
+            hidden_panels: Vec::new(),
+            theme: Theme::default(),
         }
     }
 }
 
 impl Config {
     pub fn from_args() -> Self {
-        let args: Vec<String> = std::env::args().collect();
+        let mut args: Vec<String> = std::env::args().take(1).collect();
+        args.extend(Self::config_file_args());
+        args.extend(std::env::args().skip(1));
+
         let mut config = Config::default();
+        // Individual colors apply on top of whichever preset is chosen, in
+        // whatever order the two appear.
+        let mut colors = Vec::new();
         let mut i = 1;
 
         while i < args.len() {
             match args[i].as_str() {
+                "-r" | "--recursive" => {
+                    config.recursive = true;
+                    i += 1;
+                }
+                "--sort" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --sort requires an order");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.sort_order = SortOrder::named(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!(
+                            "Error: --sort must be one of: {}",
+                            directory::SORT_ORDERS.join(", ")
+                        );
+                        Self::print_usage(&args[0]);
+                    });
+                    i += 2;
+                }
+                "--min-rating" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --min-rating requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    let stars = args[i + 1]
+                        .parse()
+                        .ok()
+                        .filter(|stars| (1..=library::MAX_RATING).contains(stars))
+                        .unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --min-rating must be from 1 to {}",
+                                library::MAX_RATING
+                            );
+                            Self::print_usage(&args[0]);
+                        });
+                    config.min_rating = Some(stars);
+                    i += 2;
+                }
+                "--shuffle" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --shuffle requires a mode");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.shuffle = Some(ShuffleMode::named(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!(
+                            "Error: --shuffle must be one of: {}",
+                            shuffle::SHUFFLE_MODES.join(", ")
+                        );
+                        Self::print_usage(&args[0]);
+                    }));
+                    i += 2;
+                }
+                "--watch" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --watch requires a playlist");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.watch = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                }
+                "--keep-dir" | "--reject-dir" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: {} requires a directory", args[i]);
+                        Self::print_usage(&args[0]);
+                    }
+                    let dir = Some(paths::expand_home(&args[i + 1]));
+                    if args[i] == "--keep-dir" {
+                        config.keep_dir = dir;
+                    } else {
+                        config.reject_dir = dir;
+                    }
+                    i += 2;
+                }
                 "--visualizer" => {
                     config.use_visualizer = true;
                     i += 1;
                 }
+                "--beat-pulse" => {
+                    config.use_visualizer = true;
+                    config.beat_pulse = true;
+                    i += 1;
+                }
+                "--spectrum-layout" => {
+                    config.spectrum_layout = args
+                        .get(i + 1)
+                        .and_then(|name| SpectrumLayout::named(name))
+                        .unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --spectrum-layout must be one of: {}",
+                                ui::SPECTRUM_LAYOUTS.join(", ")
+                            );
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--cover" => {
+                    config.cover_width.get_or_insert(cover::DEFAULT_WIDTH);
+                    i += 1;
+                }
+                "--cover-width" => {
+                    config.cover_width = Some(
+                        args.get(i + 1)
+                            .and_then(|width| width.parse().ok())
+                            .filter(|&width| width > 0)
+                            .unwrap_or_else(|| {
+                                eprintln!("Error: --cover-width requires a number of columns");
+                                Self::print_usage(&args[0]);
+                            }),
+                    );
+                    i += 2;
+                }
+                "--visualization" => {
+                    let name = args
+                        .get(i + 1)
+                        .filter(|name| visualizer::named(name).is_some())
+                        .unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --visualization must be one of: {}",
+                                visualizer::names().join(", ")
+                            );
+                            Self::print_usage(&args[0]);
+                        });
+                    config.visualization = Some(name.clone());
+                    i += 2;
+                }
+                "--eq" => {
+                    config.eq = args
+                        .get(i + 1)
+                        .and_then(|value| eq::parse(value))
+                        .unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --eq takes up to {} bands as freq:gain[:q], e.g. 100:+3,8000:-2:0.7",
+                                eq::BANDS
+                            );
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--enhanced-waveform" => {
+                    config.enhanced_waveform = true;
+                    i += 1;
+                }
+                "--stereo-waveform" => {
+                    config.enhanced_waveform = true;
+                    config.stereo_waveform = true;
+                    i += 1;
+                }
+                "--waveform-db" => {
+                    config.waveform_db = true;
+                    i += 1;
+                }
+                "--waveform-buckets" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --waveform-buckets requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    let buckets: usize = args[i + 1].parse().unwrap_or_else(|_| {
+                        eprintln!("Error: --waveform-buckets must be a positive integer");
+                        Self::print_usage(&args[0]);
+                    });
+                    config.waveform_resolution = Some(buckets.clamp(1, 100_000));
+                    i += 2;
+                }
+                "--waveform-reduce" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --waveform-reduce requires a method");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.waveform_reduction =
+                        Some(Reduction::named(&args[i + 1]).unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --waveform-reduce must be one of: {}",
+                                waveform::REDUCTIONS.join(", ")
+                            );
+                            Self::print_usage(&args[0]);
+                        }));
+                    i += 2;
+                }
                 "--bars" => {
                     if i + 1 >= args.len() {
                         eprintln!("Error: --bars requires a value");
@@ -70,6 +394,67 @@ impl Config {
                     });
                     i += 2;
                 }
+                "--min-freq" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --min-freq requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.min_freq = args[i + 1]
+                        .parse()
+                        .ok()
+                        .filter(|v: &f32| v.is_finite())
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --min-freq must be a frequency in Hz");
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--max-freq" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --max-freq requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.max_freq = args[i + 1]
+                        .parse()
+                        .ok()
+                        .filter(|v: &f32| v.is_finite())
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --max-freq must be a frequency in Hz");
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--freq-curve" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --freq-curve requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.freq_curve = args[i + 1]
+                        .parse()
+                        .ok()
+                        .filter(|v: &f32| v.is_finite())
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --freq-curve must be a positive float");
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--fps" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --fps requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.fps = args[i + 1].parse().unwrap_or_else(|_| {
+                        eprintln!("Error: --fps must be a positive integer");
+                        Self::print_usage(&args[0]);
+                    });
+                    config.fps = config.fps.clamp(1, 240);
+                    i += 2;
+                }
+                "--low-power" => {
+                    config.low_power = true;
+                    i += 1;
+                }
                 "--volume-step" => {
                     if i + 1 >= args.len() {
                         eprintln!("Error: --volume-step requires a value");
@@ -93,16 +478,409 @@ impl Config {
                     });
                     i += 2;
                 }
-                "--help" | "-h" => {
-                    Self::print_usage(&args[0]);
+                "--practice-step" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --practice-step requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    let percent: f32 = args[i + 1]
+                        .parse()
+                        .ok()
+                        .filter(|percent| (1.0..=30.0).contains(percent))
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --practice-step must be a percentage from 1 to 30");
+                            Self::print_usage(&args[0]);
+                        });
+                    config.practice_step = percent / 100.0;
+                    i += 2;
+                }
+                "--fade" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --fade requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    let millis: u64 = args[i + 1].parse().unwrap_or_else(|_| {
+                        eprintln!("Error: --fade must be a length in milliseconds");
+                        Self::print_usage(&args[0]);
+                    });
+                    config.fade = Duration::from_millis(millis.min(1000));
+                    i += 2;
                 }
-                arg if !arg.starts_with('-') => {
-                    if config.audio_path.is_empty() {
-                        config.audio_path = arg.to_string();
+                "--width" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --width requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    let percent = args[i + 1]
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|percent| percent.is_finite())
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --width must be a percentage, e.g. 150");
+                            Self::print_usage(&args[0]);
+                        });
+                    config.stereo_width = (percent / 100.0).clamp(0.0, stereo::MAX_WIDTH);
+                    i += 2;
+                }
+                "--metronome" => {
+                    config.metronome = true;
+                    i += 1;
+                }
+                "--mono" => {
+                    config.mono = true;
+                    i += 1;
+                }
+                "--solo" => {
+                    let channel = args
+                        .get(i + 1)
+                        .and_then(|name| Channel::named(name))
+                        .unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --solo must be one of: {}",
+                                stereo::CHANNELS.join(", ")
+                            );
+                            Self::print_usage(&args[0]);
+                        });
+                    config.solo = Some(channel);
+                    i += 2;
+                }
+                "--transpose" => {
+                    config.transpose = args
+                        .get(i + 1)
+                        .and_then(|semitones| semitones.parse().ok())
+                        .filter(|semitones: &i32| semitones.abs() <= resample::MAX_TRANSPOSE)
+                        .unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --transpose must be a number of semitones from -{0} to +{0}",
+                                resample::MAX_TRANSPOSE
+                            );
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--resampler" => {
+                    config.resampler = args
+                        .get(i + 1)
+                        .and_then(|name| Quality::named(name))
+                        .unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --resampler must be one of: {}",
+                                resample::QUALITIES.join(", ")
+                            );
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--ab" => {
+                    config.ab = true;
+                    i += 1;
+                }
+                "--podcast" => {
+                    let url = args
+                        .get(i + 1)
+                        .filter(|url| download::is_url(url))
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --podcast requires a feed URL");
+                            Self::print_usage(&args[0]);
+                        });
+                    config.podcast = Some(url.clone());
+                    i += 2;
+                }
+                "--prebuffer" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --prebuffer requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    let secs = args[i + 1]
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|secs| secs.is_finite())
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --prebuffer must be a length in seconds");
+                            Self::print_usage(&args[0]);
+                        });
+                    config.prebuffer = Duration::from_secs_f32(secs.clamp(0.0, 60.0));
+                    i += 2;
+                }
+                "--output" => {
+                    config.output = Some(
+                        args.get(i + 1)
+                            .and_then(|url| Target::parse(url))
+                            .unwrap_or_else(|| {
+                                eprintln!(
+                                    "Error: --output requires tcp://host:port or udp://host:port"
+                                );
+                                Self::print_usage(&args[0]);
+                            }),
+                    );
+                    i += 2;
+                }
+                "--cast" => {
+                    let device = args.get(i + 1).unwrap_or_else(|| {
+                        eprintln!("Error: --cast requires a device name or address");
+                        Self::print_usage(&args[0]);
+                    });
+                    config.cast = Some(device.clone());
+                    i += 2;
+                }
+                "--compress" => {
+                    config.compress = true;
+                    i += 1;
+                }
+                "--compress-threshold" | "--compress-ratio" | "--compress-gain" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: {} requires a value", args[i]);
+                        Self::print_usage(&args[0]);
+                    }
+                    let value = args[i + 1]
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|value| value.is_finite())
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: {} must be a number", args[i]);
+                            Self::print_usage(&args[0]);
+                        });
+                    let compressor = &mut config.compressor;
+                    match args[i].as_str() {
+                        "--compress-threshold" => compressor.threshold_db = value.min(0.0),
+                        "--compress-ratio" => compressor.ratio = value.max(1.0),
+                        _ => compressor.makeup_db = value.clamp(-24.0, 24.0),
+                    }
+                    i += 2;
+                }
+                "--compress-attack" | "--compress-release" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: {} requires a value", args[i]);
+                        Self::print_usage(&args[0]);
+                    }
+                    let millis: u64 = args[i + 1].parse().unwrap_or_else(|_| {
+                        eprintln!("Error: {} must be a length in milliseconds", args[i]);
+                        Self::print_usage(&args[0]);
+                    });
+                    let time = Duration::from_millis(millis.min(5000));
+                    if args[i] == "--compress-attack" {
+                        config.compressor.attack = time;
                     } else {
-                        eprintln!("Error: Multiple audio files specified");
+                        config.compressor.release = time;
+                    }
+                    i += 2;
+                }
+                "--skip-silence" => {
+                    config.skip_silence = true;
+                    i += 1;
+                }
+                "--trim-silence" => {
+                    config.trim_silence = true;
+                    i += 1;
+                }
+                "--silence-level" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --silence-level requires a value");
                         Self::print_usage(&args[0]);
                     }
+                    config.silence_threshold = args[i + 1].parse().unwrap_or_else(|_| {
+                        eprintln!("Error: --silence-level must be a level in dBFS, e.g. -50");
+                        Self::print_usage(&args[0]);
+                    });
+                    config.silence_threshold = config.silence_threshold.min(0.0);
+                    i += 2;
+                }
+                "--silence-min" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --silence-min requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.silence_min_length = args[i + 1]
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|secs| secs.is_finite())
+                        .and_then(|secs| Duration::try_from_secs_f32(secs.max(0.0)).ok())
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --silence-min must be a length in seconds");
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--discord" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --discord requires a client ID");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.discord_client_id = Some(args[i + 1].clone());
+                    i += 2;
+                }
+                "--no-title" => {
+                    config.set_title = false;
+                    i += 1;
+                }
+                "--no-auto-advance" => {
+                    config.auto_advance = false;
+                    i += 1;
+                }
+                "--no-media-keys" => {
+                    config.media_keys = false;
+                    i += 1;
+                }
+                "--mouse" => {
+                    config.mouse = true;
+                    i += 1;
+                }
+                "--precise-time" => {
+                    config.precise_time = true;
+                    i += 1;
+                }
+                "--hide" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --hide requires a list of panels");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.hidden_panels = args[i + 1]
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| {
+                            Panel::named(name).unwrap_or_else(|| {
+                                eprintln!(
+                                    "Error: --hide takes panels from: {}",
+                                    ui::PANELS.join(", ")
+                                );
+                                Self::print_usage(&args[0]);
+                            })
+                        })
+                        .collect();
+                    i += 2;
+                }
+                "--status-file" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --status-file requires a path");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.status_file = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                }
+                "--on-start" | "--on-end" | "--on-pause" | "--on-resume" => {
+                    let Some(command) = args.get(i + 1) else {
+                        eprintln!("Error: {} requires a command", args[i]);
+                        Self::print_usage(&args[0]);
+                    };
+                    let hook = match args[i].as_str() {
+                        "--on-start" => &mut config.hooks.on_start,
+                        "--on-end" => &mut config.hooks.on_end,
+                        "--on-pause" => &mut config.hooks.on_pause,
+                        _ => &mut config.hooks.on_resume,
+                    };
+                    *hook = Some(command.clone());
+                    i += 2;
+                }
+                "--script" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --script requires a path");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.scripts.push(paths::expand_home(&args[i + 1]));
+                    i += 2;
+                }
+                "--soundfont" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --soundfont requires a path");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.soundfont = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                }
+                "--format" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --format requires a sample format");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.raw_format.sample =
+                        SampleFormat::named(&args[i + 1]).unwrap_or_else(|| {
+                            eprintln!(
+                                "Error: --format must be one of: {}",
+                                raw::SAMPLE_FORMATS.join(", ")
+                            );
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--rate" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --rate requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.raw_format.rate = args[i + 1]
+                        .parse()
+                        .ok()
+                        .filter(|&rate| rate > 0)
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --rate must be a sample rate in Hz");
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--channels" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --channels requires a value");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.raw_format.channels = args[i + 1]
+                        .parse()
+                        .ok()
+                        .filter(|&channels| channels > 0)
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --channels must be a positive integer");
+                            Self::print_usage(&args[0]);
+                        });
+                    i += 2;
+                }
+                "--new-instance" => {
+                    config.new_instance = true;
+                    i += 1;
+                }
+                "--serve" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --serve requires an address (e.g. 127.0.0.1:8090)");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.serve_addr = Some(args[i + 1].clone());
+                    i += 2;
+                }
+                "--mpd" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --mpd requires an address (e.g. 127.0.0.1:6600)");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.mpd_addr = Some(args[i + 1].clone());
+                    i += 2;
+                }
+                "--theme" => {
+                    if i + 1 >= args.len() {
+                        eprintln!("Error: --theme requires a name");
+                        Self::print_usage(&args[0]);
+                    }
+                    config.theme = Theme::named(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!(
+                            "Error: --theme must be one of: {}",
+                            theme::PRESETS.join(", ")
+                        );
+                        Self::print_usage(&args[0]);
+                    });
+                    i += 2;
+                }
+                "--color" => {
+                    let Some((role, color)) = args.get(i + 1).and_then(|arg| arg.split_once('='))
+                    else {
+                        eprintln!("Error: --color requires <role>=<color>");
+                        Self::print_usage(&args[0]);
+                    };
+                    colors.push((role.trim().to_string(), color.trim().to_string()));
+                    i += 2;
+                }
+                "--help" | "-h" => {
+                    Self::print_usage(&args[0]);
+                }
+                arg if arg == raw::STDIN || !arg.starts_with('-') => {
+                    config.audio_paths.push(PathBuf::from(arg));
                     i += 1;
                 }
                 _ => {
@@ -112,31 +890,284 @@ impl Config {
             }
         }
 
-        if config.audio_path.is_empty() {
+        if config.min_freq < 0.0 || config.min_freq >= config.max_freq {
+            eprintln!("Error: --min-freq must be below --max-freq");
+            Self::print_usage(&args[0]);
+        }
+        if config.freq_curve <= 0.0 {
+            eprintln!("Error: --freq-curve must be a positive float");
+            Self::print_usage(&args[0]);
+        }
+
+        if config.audio_paths.is_empty() && config.watch.is_none() && config.podcast.is_none() {
             eprintln!("Error: No audio file specified");
             Self::print_usage(&args[0]);
         }
+        if config.ab
+            && (config.audio_paths.len() != 2
+                || config.audio_paths.iter().any(|path| raw::is_stdin(path)))
+        {
+            eprintln!("Error: --ab takes exactly two files");
+            Self::print_usage(&args[0]);
+        }
+
+        for (role, color) in &colors {
+            if let Err(e) = config.theme.set(role, color) {
+                eprintln!("Error: --color {}", e);
+                Self::print_usage(&args[0]);
+            }
+        }
+        config.theme = config.theme.for_terminal();
 
         config
     }
 
+    /// The value `key` is set to in the config file, if any.
+    pub fn saved_option(key: &str) -> Option<String> {
+        let args = Self::config_file_args();
+        let flag = format!("--{}", key);
+        let index = args.iter().rposition(|arg| *arg == flag)?;
+        args.get(index + 1)
+            .filter(|value| !value.starts_with("--"))
+            .cloned()
+    }
+
+    /// Translates `key = value` lines from the config file into the matching
+    /// command-line options, so the file goes through the same parser and
+    /// anything passed on the command line is applied afterwards.
+    fn config_file_args() -> Vec<String> {
+        let Some(contents) = config_path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return Vec::new();
+        };
+
+        let mut args = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Only one pair of quotes around the value is taken off, so it
+            // can end in a quote of its own, as a shell command might.
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| {
+                    let value = value.trim();
+                    let unquoted = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'));
+                    (key.trim(), unquoted.unwrap_or(value))
+                })
+                .unwrap_or((line, "true"));
+
+            match value {
+                "true" => args.push(format!("--{}", key)),
+                "false" => {}
+                _ => {
+                    args.push(format!("--{}", key));
+                    args.push(value.to_string());
+                }
+            }
+        }
+
+        args
+    }
+
     fn print_usage(program: &str) -> ! {
-        eprintln!("Usage: {} [OPTIONS] <audio_file>", program);
-        eprintln!("\nSupported formats: MP3, WAV, FLAC, OGG, AAC/M4A");
+        eprintln!("Usage: {} [OPTIONS] <audio_file|directory>...", program);
+        eprintln!(
+            "       {} [OPTIONS] - [--format <fmt>] [--rate <hz>] [--channels <n>]",
+            program
+        );
+        eprintln!(
+            "       {} analyze [OPTIONS] <audio_file|directory>...",
+            program
+        );
+        eprintln!(
+            "       {} normalize [OPTIONS] <audio_file|directory>...",
+            program
+        );
+        eprintln!(
+            "       {} record [--device <name>] [--out <file.wav>]",
+            program
+        );
+        eprintln!("       {} visualize [--out <file.wav>]", program);
+        eprintln!(
+            "       {} render [OPTIONS] <audio_file> --out <file.wav>",
+            program
+        );
+        eprintln!(
+            "\nSupported formats: MP3, WAV, FLAC, OGG Vorbis, Opus, AAC/M4A, ALAC,\n                   MOD, XM, S3M, IT, MIDI"
+        );
         eprintln!("\nOptions:");
+        eprintln!("  -r, --recursive        Include files in subdirectories of a directory");
+        eprintln!(
+            "  --sort <order>         Directory order: track, name, mtime, rating, plays\n                         (default: track)"
+        );
+        eprintln!(
+            "  --min-rating <n>       Leave out unrated files and those under n stars\n                         found in directories or by patterns"
+        );
+        eprintln!(
+            "  --shuffle <mode>       Shuffle the queue: random, or smart to favor highly\n                         rated tracks not played lately"
+        );
+        eprintln!("  --no-auto-advance      Cue up the next track paused instead of playing it");
+        eprintln!("  --watch <playlist>     Play an .m3u playlist and follow changes to it");
+        eprintln!("  --keep-dir <dir>       Where K moves the current track");
+        eprintln!("  --reject-dir <dir>     Where X moves the current track");
         eprintln!("  --visualizer           Enable live spectrum analyzer");
+        eprintln!("  --enhanced-waveform    Taller, high-resolution waveform with a playhead");
+        eprintln!("  --stereo-waveform      Enhanced waveform with left above and right below");
+        eprintln!("  --waveform-db          Draw the waveform on a dB scale");
+        eprintln!("  --waveform-buckets <n> Waveform resolution (default: 100, enhanced: 4000)");
+        eprintln!(
+            "  --waveform-reduce <m>  Waveform level per bucket: peak, mean, rms\n                         (default: mean, enhanced: peak)"
+        );
         eprintln!("  --bars <n>             Number of frequency bars (default: 100)");
         eprintln!("  --smoothing <f>        Smoothing factor 0.0-1.0 (default: 0.7)");
         eprintln!("  --bass-boost <f>       Bass boost multiplier (default: 1.5)");
+        eprintln!("  --min-freq <hz>        Lowest frequency shown (default: 20)");
+        eprintln!("  --max-freq <hz>        Highest frequency shown (default: 20000)");
+        eprintln!("  --freq-curve <f>       Bar spacing exponent, >1 favors bass (default: 1.0)");
+        eprintln!("  --fps <n>              Visualizer frame rate (default: 30)");
+        eprintln!("  --beat-pulse           Pulse the visualizer on each beat and show the tempo");
+        eprintln!("  --spectrum-layout <l>  Spectrum bars: bars, or mirror around the middle");
+        eprintln!("  --cover                Show the album cover beside the visualizer");
+        eprintln!("  --cover-width <n>      Most columns the cover takes (default: 24)");
+        eprintln!(
+            "  --visualization <name> What the visualizer shows: spectrum, scope, rain, fire"
+        );
+        eprintln!("  --low-power            Save battery even when on mains power");
+        eprintln!("  --eq <bands>           Equalizer bands as freq:gain[:q], e.g. 100:+3,8000:-2");
         eprintln!("  --volume-step <f>      Volume adjustment step (default: 0.05)");
         eprintln!("  --seek-step <n>        Seek step in seconds (default: 5)");
+        eprintln!(
+            "  --practice-step <pct>  Tempo a practice loop gains per clean repetition (default: 5)"
+        );
+        eprintln!("  --metronome            Click along to the beat");
+        eprintln!(
+            "  --fade <ms>            Fade on pause, resume and seek, 0 for none (default: 100)"
+        );
+        eprintln!("  --width <percent>      Stereo width, 0 to 200 (default: 100)");
+        eprintln!("  --mono                 Play in mono");
+        eprintln!("  --solo <channel>       Play only the left or right channel");
+        eprintln!(
+            "  --resampler <quality>  Sample rate conversion: linear, sinc (default: linear)"
+        );
+        eprintln!("  --transpose <n>        Play n semitones up or down, -12 to +12");
+        eprintln!("  --ab                   Compare two files, switching between them with A");
+        eprintln!("  --podcast <url>        Pick episodes to play from a podcast feed");
+        eprintln!(
+            "  --prebuffer <secs>     Audio buffered before a stream plays, and after it runs\n                         out (default: 3)"
+        );
+        eprintln!(
+            "  --output <url>         Stream 48 kHz 16-bit stereo PCM to tcp://host:port or\n                         udp://host:port instead of the sound card"
+        );
+        eprintln!(
+            "  --cast <device>        Play on a Chromecast, found by name or address, with\n                         apz as the remote"
+        );
+        eprintln!("  --compress             Even out the level with a compressor");
+        eprintln!("  --compress-threshold <db>");
+        eprintln!("                         Level the compressor works above (default: -24)");
+        eprintln!("  --compress-ratio <n>   Compression ratio (default: 4)");
+        eprintln!("  --compress-attack <ms> How fast the compressor reacts (default: 10)");
+        eprintln!("  --compress-release <ms>");
+        eprintln!("                         How fast the compressor lets go (default: 200)");
+        eprintln!("  --compress-gain <db>   Makeup gain after compressing (default: 0)");
+        eprintln!("  --skip-silence         Skip over silent stretches while playing");
+        eprintln!("  --trim-silence         Start at the first sound and stop after the last");
+        eprintln!("  --silence-level <db>   Level counted as silence (default: -50)");
+        eprintln!("  --silence-min <secs>   Shortest silence that is skipped (default: 2)");
+        eprintln!("  --discord <client-id>  Publish now playing to Discord Rich Presence");
+        eprintln!("  --no-title             Don't set the terminal title to the playing track");
+        eprintln!("  --no-media-keys        Don't register with the OS media controls");
+        eprintln!("  --mouse                Click the waveform to seek, hover to preview");
+        eprintln!("  --precise-time         Show positions as mm:ss.mmm");
+        eprintln!("  --hide <panels>        Hide title, visualizer, volume, controls, status");
+        eprintln!("  --status-file <path>   Keep a now-playing JSON file updated at <path>");
+        eprintln!("  --on-start <command>   Run a shell command when a track starts playing");
+        eprintln!("  --on-end <command>     Run a shell command when a track ends or is left");
+        eprintln!("  --on-pause <command>   Run a shell command on pausing");
+        eprintln!("  --on-resume <command>  Run a shell command on resuming");
+        eprintln!("  --script <path>        Load a Rhai script, as well as those in scripts/");
+        eprintln!("  --soundfont <path>     SoundFont (.sf2) used to play MIDI files");
+        eprintln!(
+            "  --format <fmt>         Raw PCM on stdin: s16le, s16be, s24le, s32le, f32le, u8"
+        );
+        eprintln!("  --rate <hz>            Raw PCM sample rate (default: 44100)");
+        eprintln!("  --channels <n>         Raw PCM channel count (default: 2)");
+        eprintln!(
+            "  --new-instance         Start a new player instead of queueing in a running one"
+        );
+        eprintln!("  --serve <addr>         Serve the HTTP remote-control API on <addr>");
+        eprintln!("  --mpd <addr>           Accept MPD clients on <addr>");
+        eprintln!(
+            "  --theme <name>         Color theme: default, nord, gruvbox, dracula, monochrome"
+        );
+        eprintln!("  --color <role>=<color> Override one theme color, e.g. track=#88c0d0");
         eprintln!("  -h, --help             Show this help message");
+        eprintln!("\nOptions can also be set in ~/.config/apz/config as `key = value` lines.");
         eprintln!("\nControls:");
         eprintln!("  Space    - Play/pause");
         eprintln!("  Q/Esc    - Quit");
         eprintln!("  ←/→      - Seek backward/forward");
         eprintln!("  ↑/↓      - Volume up/down");
         eprintln!("  R        - Restart");
+        eprintln!("  n/p      - Next/previous track");
+        eprintln!("  s        - Stop after the current track");
+        eprintln!("  a        - Toggle playing the next track automatically");
+        eprintln!("  t        - Toggle elapsed/remaining time");
+        eprintln!("  i/v/V/?  - Show/hide the title, visualizer, volume, controls");
+        eprintln!("  :        - Command line, e.g. :seek 2:00, :vol 40, :theme nord");
+        eprintln!("  o        - Open a file, with Tab completion");
+        eprintln!("  h        - Recently played tracks");
+        eprintln!("  P        - Episodes of the podcast feed (with --podcast)");
+        eprintln!("  l        - Queue: mark, move, remove, play next, add next");
+        eprintln!("  Tab      - Switch keys between the queue and the player");
+        eprintln!("  e        - Edit the track's tags");
+        eprintln!("  1-5/0    - Rate the track, or clear its rating");
+        eprintln!("  [/]      - Previous/next chapter");
+        eprintln!("  m        - Add a named bookmark at the current position");
+        eprintln!("  b/B      - Jump to next/previous bookmark");
+        eprintln!("  I/O      - Mark the in/out point of a clip");
+        eprintln!("  x        - Save the marked clip as WAV or FLAC");
+        eprintln!("  z/Z      - Zoom the enhanced waveform in/out");
+        eprintln!("  Shift+←/→ - Scroll the zoomed waveform");
+        eprintln!("  ,/.      - Fewer/more spectrum bars");
+        eprintln!("  {{/}}      - Less/more spectrum bass boost");
+        eprintln!("  M        - Switch the spectrum between bars and mirrored");
+        eprintln!("  W        - Switch to the next visualization");
+        eprintln!("  E        - Adjust the equalizer");
+        eprintln!("  C        - Turn the compressor on/off");
+        eprintln!("  w        - Switch between mono and stereo");
+        eprintln!("  </>      - Narrower/wider stereo");
+        eprintln!("  A        - Switch between the files being compared (with --ab)");
+        eprintln!("  S        - Play the difference between the compared files");
+        eprintln!("  L        - Solo the left channel, the right, then neither");
         process::exit(1);
     }
 }
+
+fn config_path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("config"))
+}
+
+/// Sets `key = value` in the config file, replacing any earlier setting, or
+/// removes the setting if `value` is `None`. Used to remember choices made
+/// while running.
+pub fn save_option(key: &str, value: Option<&str>) {
+    let Some(path) = config_path() else {
+        return;
+    };
+
+    let mut contents: String = fs::read_to_string(&path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.split('=').next().is_none_or(|k| k.trim() != key))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    if let Some(value) = value {
+        contents.push_str(&format!("{} = {}\n", key, value));
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    fs::write(path, contents).ok();
+}
@@ -1,17 +1,128 @@
-use crossterm::event::{self, Event, KeyCode, KeyEvent};
-use std::time::Duration;
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use crossterm::terminal;
+use ratatui::layout::Rect;
+use std::path::PathBuf;
 
+use crate::clip;
+use crate::command::Command;
+use crate::config;
+use crate::cues;
+use crate::eq;
+use crate::error::ApzError;
+use crate::files::FileAction;
+use crate::metadata::TrackInfo;
+use crate::palette::{self, Action};
+use crate::paths;
 use crate::player::Player;
+use crate::stereo::Channel;
+use crate::tags;
+use crate::ui::{
+    EqEditor, Panel, Prompt, PromptKind, QueuePanel, RecentList, TAG_FIELDS, TagEditor, UIState,
+};
+use crate::visualizer;
+
+const BAR_STEP: usize = 8;
+const BASS_BOOST_STEP: f32 = 0.25;
+/// A sixth of an octave.
+const EQ_FREQUENCY_STEP: f32 = 1.122_462;
+const EQ_GAIN_STEP: f32 = 0.5;
+const EQ_Q_STEP: f32 = 1.25;
+const WIDTH_STEP: f32 = 0.1;
+const TRACK_GAIN_STEP: f32 = 1.0;
+const PITCH_STEP: f32 = 0.001;
+const COARSE_PITCH_STEP: f32 = 0.01;
+/// What Shift turns 1 to 4 into on a US keyboard, for setting hot cues.
+const SHIFTED_DIGITS: [char; cues::SLOTS] = ['!', '@', '#', '$'];
+/// Loop roll lengths in beats, on 5 to 8 in deck mode.
+const ROLL_BEATS: [u32; 4] = [1, 2, 4, 8];
 
 pub enum ControlAction {
     Quit,
+    /// An event was handled and the screen may need redrawing.
     Continue,
+    /// The event didn't change anything.
+    Idle,
+    Command(Command),
+    /// Move the current track out of the queue and into the trash or a
+    /// triage directory. Only ever sent from the keyboard, once confirmed.
+    File(FileAction),
+    /// Save the marked clip of the current track to this file.
+    ExportClip(PathBuf),
+    /// Start practising along to the marked clip, or stop.
+    TogglePractice,
+    /// Don't speed the practice loop up after this time through.
+    Flub,
+    /// Put a beat at the current position, for the metronome.
+    Tap,
 }
 
-pub fn handle_input(player: &Player) -> Result<ControlAction, Box<dyn std::error::Error>> {
-    if event::poll(Duration::from_millis(100))?
-        && let Event::Key(KeyEvent { code, .. }) = event::read()?
+pub fn handle_input(
+    event: Event,
+    player: &Player,
+    ui_state: &mut UIState,
+) -> Result<ControlAction, ApzError> {
+    if let Event::Mouse(mouse) = event {
+        return handle_mouse(mouse, player, ui_state);
+    }
+
+    if let Event::Key(KeyEvent {
+        code, modifiers, ..
+    }) = event
     {
+        // Raw mode swallows Ctrl+C, so treat it as quit like a terminal would.
+        if code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL) {
+            return Ok(ControlAction::Quit);
+        }
+
+        if ui_state.prompt.is_some() {
+            return Ok(handle_prompt_input(code, player, ui_state));
+        }
+
+        if let Some(action) = ui_state.file_action.take() {
+            return Ok(match code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => ControlAction::File(action),
+                _ => ControlAction::Continue,
+            });
+        }
+
+        if ui_state.tag_editor.is_some() {
+            return Ok(handle_tag_input(code, ui_state));
+        }
+
+        if ui_state.recent.is_some() {
+            return Ok(handle_recent_input(code, ui_state));
+        }
+
+        if ui_state.podcasts.is_some() {
+            return Ok(handle_podcast_input(code, ui_state));
+        }
+
+        if ui_state
+            .queue_panel
+            .as_ref()
+            .is_some_and(|panel| panel.focused)
+        {
+            return Ok(handle_queue_input(code, modifiers, ui_state));
+        }
+
+        if ui_state.eq_editor.is_some() {
+            return Ok(handle_eq_input(code, ui_state));
+        }
+
+        if code == KeyCode::Esc
+            && (ui_state.error.take().is_some() || ui_state.queue_panel.take().is_some())
+        {
+            return Ok(ControlAction::Continue);
+        }
+
+        if ui_state.deck
+            && let Some(action) = handle_deck_input(code, player, ui_state)
+        {
+            return Ok(action);
+        }
+
         match code {
             KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => {
                 return Ok(ControlAction::Quit);
@@ -19,26 +130,794 @@ pub fn handle_input(player: &Player) -> Result<ControlAction, Box<dyn std::error
             KeyCode::Char(' ') => {
                 player.toggle_play_pause();
             }
+            KeyCode::Left | KeyCode::Right if modifiers.contains(KeyModifiers::SHIFT) => {
+                let forward = code == KeyCode::Right;
+                ui_state
+                    .waveform_view
+                    .scroll(forward, player.position(), player.duration());
+            }
             KeyCode::Left => {
-                player.seek(-player.seek_step);
+                if let Some(position) = player.seek(-player.seek_step) {
+                    ui_state.toast_seek(position);
+                }
             }
             KeyCode::Right => {
-                player.seek(player.seek_step);
+                if let Some(position) = player.seek(player.seek_step) {
+                    ui_state.toast_seek(position);
+                }
+            }
+            KeyCode::Up | KeyCode::Down if modifiers.contains(KeyModifiers::SHIFT) => {
+                let step = if code == KeyCode::Up {
+                    TRACK_GAIN_STEP
+                } else {
+                    -TRACK_GAIN_STEP
+                };
+                ui_state.set_track_gain(ui_state.stats.gain_db + step);
+                ui_state.toast(format!("Track gain {:+.1} dB", ui_state.stats.gain_db));
             }
             KeyCode::Up => {
                 let new_volume = (player.volume() + player.volume_step).min(1.0);
                 player.set_volume(new_volume);
+                ui_state.toast_volume(new_volume);
             }
             KeyCode::Down => {
                 let new_volume = (player.volume() - player.volume_step).max(0.0);
                 player.set_volume(new_volume);
+                ui_state.toast_volume(new_volume);
             }
             KeyCode::Char('r') | KeyCode::Char('R') => {
                 player.restart();
             }
+            KeyCode::Char('n') => {
+                return Ok(ControlAction::Command(Command::NextTrack));
+            }
+            KeyCode::Char('p') => {
+                return Ok(ControlAction::Command(Command::PreviousTrack));
+            }
+            KeyCode::Char('s') => {
+                ui_state.stop_after_track = !ui_state.stop_after_track;
+                ui_state.toast(if ui_state.stop_after_track {
+                    "Stopping after this track"
+                } else {
+                    "Carrying on after this track"
+                });
+            }
+            KeyCode::Char('a') => {
+                ui_state.auto_advance = !ui_state.auto_advance;
+                ui_state.toast(if ui_state.auto_advance {
+                    "Auto-advance on"
+                } else {
+                    "Auto-advance off"
+                });
+            }
+            KeyCode::Char('t') => {
+                ui_state.show_remaining = !ui_state.show_remaining;
+            }
+            KeyCode::Char('i') => {
+                ui_state.toggle_panel(Panel::Title);
+            }
+            KeyCode::Char('v') => {
+                ui_state.toggle_panel(Panel::Visualizer);
+            }
+            KeyCode::Char('V') => {
+                ui_state.toggle_panel(Panel::Volume);
+            }
+            KeyCode::Char('?') => {
+                ui_state.toggle_panel(Panel::Controls);
+            }
+            KeyCode::Char(']') => {
+                player.next_chapter();
+            }
+            KeyCode::Char('[') => {
+                player.previous_chapter();
+            }
+            KeyCode::Char(':') => {
+                ui_state.prompt = Some(Prompt::new(PromptKind::Command));
+            }
+            KeyCode::Char('o') => {
+                ui_state.prompt = Some(Prompt::new(PromptKind::OpenFile));
+            }
+            KeyCode::Char('h') => {
+                ui_state.recent = Some(RecentList::load());
+            }
+            KeyCode::Char('P') => {
+                if let Some(url) = ui_state.podcast_feed.clone() {
+                    ui_state.open_podcasts(&url);
+                }
+            }
+            KeyCode::Char('e') => {
+                ui_state.tag_editor = Some(TagEditor::new(&ui_state.track));
+            }
+            KeyCode::Char('A') => {
+                if let Some(comparison) = &ui_state.comparison {
+                    comparison.switch.toggle();
+                }
+            }
+            KeyCode::Char('S') => {
+                if let Some(comparison) = &ui_state.comparison {
+                    comparison.switch.toggle_diff();
+                }
+            }
+            KeyCode::Char('w') => {
+                let stereo = &ui_state.stereo;
+                let mono = !stereo.is_mono();
+                stereo.set_mono(mono);
+                ui_state.toast(if mono { "Mono" } else { "Stereo" });
+            }
+            KeyCode::Char('L') => {
+                let stereo = &ui_state.stereo;
+                let solo = match stereo.solo() {
+                    None => Some(Channel::Left),
+                    Some(Channel::Left) => Some(Channel::Right),
+                    Some(Channel::Right) => None,
+                };
+                stereo.set_solo(solo);
+                ui_state.toast(match solo {
+                    Some(Channel::Left) => "Left channel only",
+                    Some(Channel::Right) => "Right channel only",
+                    None => "Both channels",
+                });
+            }
+            KeyCode::Char('<') | KeyCode::Char('>') => {
+                let step = if code == KeyCode::Char('>') {
+                    WIDTH_STEP
+                } else {
+                    -WIDTH_STEP
+                };
+                let stereo = &ui_state.stereo;
+                stereo.set_width(((stereo.width() + step) * 10.0).round() / 10.0);
+                ui_state.toast(format!("Stereo width {:.0}%", stereo.width() * 100.0));
+            }
+            KeyCode::Char('(') | KeyCode::Char(')') => {
+                let step = if code == KeyCode::Char(')') { 1 } else { -1 };
+                let resampler = &ui_state.resampler;
+                resampler.set_transpose(resampler.transpose() + step);
+                ui_state.toast(format!("Transposed {:+} semitones", resampler.transpose()));
+            }
+            KeyCode::Char('g') => {
+                let metronome = &ui_state.metronome;
+                let enabled = !metronome.is_enabled();
+                metronome.set_enabled(enabled);
+                ui_state.toast(if enabled {
+                    "Metronome on"
+                } else {
+                    "Metronome off"
+                });
+            }
+            KeyCode::Char(';') => {
+                return Ok(ControlAction::Tap);
+            }
+            KeyCode::Char('C') => {
+                let compressor = &ui_state.compressor;
+                let enabled = !compressor.is_enabled();
+                compressor.set_enabled(enabled);
+                ui_state.toast(if enabled {
+                    "Compressor on"
+                } else {
+                    "Compressor off"
+                });
+            }
+            KeyCode::Char('E') => {
+                ui_state.eq_editor = Some(EqEditor { selected: 0 });
+            }
+            KeyCode::Char('d') => {
+                ui_state.deck = true;
+            }
+            KeyCode::Char('D') => {
+                ui_state.file_action = Some(FileAction::Trash);
+            }
+            KeyCode::Char('K') | KeyCode::Char('X') => {
+                let (dir, flag) = if code == KeyCode::Char('K') {
+                    (&ui_state.keep_dir, "--keep-dir")
+                } else {
+                    (&ui_state.reject_dir, "--reject-dir")
+                };
+                match dir {
+                    Some(dir) => ui_state.file_action = Some(FileAction::MoveTo(dir.clone())),
+                    None => ui_state.error = Some(format!("No directory set, see {}", flag)),
+                }
+            }
+            KeyCode::Char('I') => {
+                ui_state.mark_in(player.position());
+            }
+            KeyCode::Char('O') => {
+                ui_state.mark_out(player.position());
+            }
+            KeyCode::Char('x') => {
+                prompt_for_clip(ui_state);
+            }
+            KeyCode::Char('T') => {
+                return Ok(ControlAction::TogglePractice);
+            }
+            KeyCode::Char('F') => {
+                return Ok(ControlAction::Flub);
+            }
+            KeyCode::Char('l') => {
+                ui_state.queue_panel = Some(QueuePanel::new(ui_state.queue_index));
+            }
+            KeyCode::Tab => match ui_state.queue_panel.as_mut() {
+                Some(panel) => panel.focused = true,
+                None => ui_state.queue_panel = Some(QueuePanel::new(ui_state.queue_index)),
+            },
+            KeyCode::Char(digit @ '0'..='5') => {
+                let stars = digit as u8 - b'0';
+                ui_state.rate((stars > 0).then_some(stars));
+                ui_state.toast(match stars {
+                    0 => "Rating cleared".to_string(),
+                    1 => "Rated 1 star".to_string(),
+                    stars => format!("Rated {} stars", stars),
+                });
+            }
+            KeyCode::Char('m') => {
+                ui_state.prompt = Some(Prompt::new(PromptKind::BookmarkLabel(player.position())));
+            }
+            KeyCode::Char('G') => {
+                ui_state.prompt = Some(Prompt::new(PromptKind::Gain(player.position())));
+            }
+            KeyCode::Char('b') => {
+                if let Some(bookmark) = ui_state.bookmarks.next_after(player.position()) {
+                    player.seek_to(bookmark.position);
+                }
+            }
+            KeyCode::Char('B') => {
+                if let Some(bookmark) = ui_state.bookmarks.previous_before(player.position()) {
+                    player.seek_to(bookmark.position);
+                }
+            }
+            KeyCode::Char('z') => {
+                ui_state.waveform_view.zoom_in();
+            }
+            KeyCode::Char('Z') => {
+                ui_state.waveform_view.zoom_out();
+            }
+            KeyCode::Char(',') | KeyCode::Char('.') => {
+                if let Some(spectrum) = player.spectrum() {
+                    let num_bars = if code == KeyCode::Char('.') {
+                        spectrum.num_bars() + BAR_STEP
+                    } else {
+                        spectrum.num_bars().saturating_sub(BAR_STEP)
+                    };
+                    spectrum.set_num_bars(num_bars);
+                }
+            }
+            KeyCode::Char('{') | KeyCode::Char('}') => {
+                if let Some(spectrum) = player.spectrum() {
+                    let step = if code == KeyCode::Char('}') {
+                        BASS_BOOST_STEP
+                    } else {
+                        -BASS_BOOST_STEP
+                    };
+                    let bass_boost = spectrum.bass_boost() + step;
+                    spectrum.set_bass_boost(bass_boost);
+                }
+            }
+            KeyCode::Char('M') => {
+                ui_state.set_spectrum_layout(ui_state.spectrum_layout.next());
+            }
+            KeyCode::Char('W') => {
+                let visualizer = visualizer::next(ui_state.visualizer.borrow().name());
+                ui_state.set_visualizer(visualizer);
+            }
             _ => {}
         }
     }
 
     Ok(ControlAction::Continue)
 }
+
+/// Hovering over the waveform previews a seek, clicking performs it, as
+/// does clicking a tick under the progress bar.
+fn handle_mouse(
+    mouse: MouseEvent,
+    player: &Player,
+    ui_state: &mut UIState,
+) -> Result<ControlAction, ApzError> {
+    let (width, height) = terminal::size()?;
+    let screen = Rect::new(0, 0, width, height);
+    let position = ui_state.waveform_position(screen, mouse.column, mouse.row);
+
+    match mouse.kind {
+        MouseEventKind::Moved | MouseEventKind::Drag(MouseButton::Left) => {
+            let hover = position.map(|_| (mouse.column, mouse.row));
+            if hover == ui_state.hover {
+                return Ok(ControlAction::Idle);
+            }
+            ui_state.hover = hover;
+        }
+        MouseEventKind::Down(MouseButton::Left) => {
+            let mark = ui_state.progress_mark_at(screen, mouse.column, mouse.row);
+            if let Some(position) = position.or(mark).and_then(|p| player.seek_to(p)) {
+                ui_state.toast_seek(position);
+            }
+        }
+        _ => return Ok(ControlAction::Idle),
+    }
+
+    Ok(ControlAction::Continue)
+}
+
+fn handle_recent_input(code: KeyCode, ui_state: &mut UIState) -> ControlAction {
+    let Some(recent) = ui_state.recent.as_mut() else {
+        return ControlAction::Continue;
+    };
+
+    match code {
+        KeyCode::Esc | KeyCode::Char('h') | KeyCode::Char('q') => {
+            ui_state.recent = None;
+        }
+        KeyCode::Up | KeyCode::Char('k') => recent.select_previous(),
+        KeyCode::Down | KeyCode::Char('j') => recent.select_next(),
+        KeyCode::Enter => {
+            let play = recent.selected().map(|play| play.path.clone());
+            ui_state.recent = None;
+            if let Some(path) = play {
+                return ControlAction::Command(Command::Open(path));
+            }
+        }
+        _ => return ControlAction::Idle,
+    }
+
+    ControlAction::Continue
+}
+
+fn handle_podcast_input(code: KeyCode, ui_state: &mut UIState) -> ControlAction {
+    let Some(podcasts) = ui_state.podcasts.as_mut() else {
+        return ControlAction::Continue;
+    };
+
+    match code {
+        KeyCode::Esc | KeyCode::Char('P') | KeyCode::Char('q') => {
+            ui_state.podcasts = None;
+        }
+        KeyCode::Up | KeyCode::Char('k') => podcasts.select_previous(),
+        KeyCode::Down | KeyCode::Char('j') => podcasts.select_next(),
+        KeyCode::Enter => {
+            let download = podcasts.selected().map(|episode| episode.download());
+            ui_state.podcasts = None;
+            match download {
+                Some(Ok(path)) => return ControlAction::Command(Command::Open(path)),
+                Some(Err(e)) => ui_state.error = Some(format!("Couldn't download: {}", e)),
+                None => {}
+            }
+        }
+        _ => return ControlAction::Idle,
+    }
+
+    ControlAction::Continue
+}
+
+/// Typing edits the focused field; Enter asks for confirmation, and `y`
+/// then writes the tags to the file.
+fn handle_tag_input(code: KeyCode, ui_state: &mut UIState) -> ControlAction {
+    let Some(editor) = ui_state.tag_editor.as_mut() else {
+        return ControlAction::Continue;
+    };
+
+    if editor.confirming {
+        match code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                let result = editor
+                    .track_info()
+                    .and_then(|info| tags::write(&ui_state.path, &info).map_err(|e| e.to_string()));
+                ui_state.tag_editor = None;
+                match result {
+                    Ok(()) => ui_state.track = TrackInfo::read(&ui_state.path),
+                    Err(e) => {
+                        ui_state.error = Some(format!("Couldn't write tags: {}", e));
+                    }
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => editor.confirming = false,
+            _ => return ControlAction::Idle,
+        }
+        return ControlAction::Continue;
+    }
+
+    match code {
+        KeyCode::Esc => ui_state.tag_editor = None,
+        KeyCode::Enter => match editor.track_info() {
+            Ok(_) => editor.confirming = true,
+            Err(e) => ui_state.error = Some(e),
+        },
+        KeyCode::Tab | KeyCode::Down => editor.focused = (editor.focused + 1) % TAG_FIELDS.len(),
+        KeyCode::BackTab | KeyCode::Up => {
+            editor.focused = (editor.focused + TAG_FIELDS.len() - 1) % TAG_FIELDS.len()
+        }
+        KeyCode::Backspace => {
+            editor.values[editor.focused].pop();
+        }
+        KeyCode::Char(c) => editor.values[editor.focused].push(c),
+        _ => return ControlAction::Idle,
+    }
+
+    ControlAction::Continue
+}
+
+fn handle_queue_input(
+    code: KeyCode,
+    modifiers: KeyModifiers,
+    ui_state: &mut UIState,
+) -> ControlAction {
+    let len = ui_state.queue.len();
+    let Some(panel) = ui_state.queue_panel.as_mut() else {
+        return ControlAction::Continue;
+    };
+    let selected = panel.selected(len);
+    let shift = modifiers.contains(KeyModifiers::SHIFT);
+
+    match code {
+        KeyCode::Esc | KeyCode::Char('l') | KeyCode::Char('q') => {
+            ui_state.queue_panel = None;
+        }
+        KeyCode::Tab => panel.focused = false,
+        KeyCode::Up if shift => return move_selected(panel, selected, selected.checked_sub(1)),
+        KeyCode::Char('K') => return move_selected(panel, selected, selected.checked_sub(1)),
+        KeyCode::Down if shift => {
+            return move_selected(panel, selected, Some(selected + 1).filter(|&to| to < len));
+        }
+        KeyCode::Char('J') => {
+            return move_selected(panel, selected, Some(selected + 1).filter(|&to| to < len));
+        }
+        KeyCode::Up | KeyCode::Char('k') => panel.selected = selected.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => {
+            panel.selected = (selected + 1).min(len.saturating_sub(1))
+        }
+        KeyCode::Char(' ') if len > 0 => {
+            if !panel.marked.remove(&selected) {
+                panel.marked.insert(selected);
+            }
+            panel.selected = (selected + 1).min(len - 1);
+        }
+        KeyCode::Enter if len > 0 => {
+            return ControlAction::Command(Command::PlayIndex(selected));
+        }
+        KeyCode::Char('d') | KeyCode::Delete if len > 0 => {
+            let targets = panel.targets(len);
+            panel.marked.clear();
+            return ControlAction::Command(Command::RemoveTracks(targets));
+        }
+        KeyCode::Char('t') if len > 0 => {
+            let targets = panel.targets(len);
+            panel.marked.clear();
+            panel.selected = 0;
+            ui_state.toast(tracks_toast("Moved to the top:", targets.len()));
+            return ControlAction::Command(Command::MoveToTop(targets));
+        }
+        KeyCode::Char('n') if len > 0 => {
+            let current = ui_state.queue_index;
+            let targets: Vec<usize> = panel
+                .targets(len)
+                .into_iter()
+                .filter(|&index| index != current)
+                .collect();
+            panel.marked.clear();
+            if targets.is_empty() {
+                return ControlAction::Continue;
+            }
+            // The current track moves up past any taken from above it.
+            let before = targets.iter().filter(|&&index| index < current).count();
+            panel.selected = current - before + 1;
+            ui_state.toast(tracks_toast("Playing next:", targets.len()));
+            return ControlAction::Command(Command::PlayNext(targets));
+        }
+        KeyCode::Char('C') => return ControlAction::Command(Command::ClearQueue),
+        KeyCode::Char('a') => {
+            ui_state.prompt = Some(Prompt::new(PromptKind::InsertFile));
+        }
+        _ => return ControlAction::Idle,
+    }
+
+    ControlAction::Continue
+}
+
+/// Arrows move the selected band and change its gain, `-`/`+` its width.
+fn handle_eq_input(code: KeyCode, ui_state: &mut UIState) -> ControlAction {
+    let Some(editor) = ui_state.eq_editor.as_mut() else {
+        return ControlAction::Continue;
+    };
+    let index = editor.selected;
+    let mut band = ui_state.equalizer.bands()[index];
+
+    match code {
+        KeyCode::Esc | KeyCode::Char('E') | KeyCode::Char('q') => {
+            ui_state.close_eq_editor();
+            return ControlAction::Continue;
+        }
+        KeyCode::Tab => editor.selected = (index + 1) % eq::BANDS,
+        KeyCode::BackTab => editor.selected = (index + eq::BANDS - 1) % eq::BANDS,
+        KeyCode::Char(digit)
+            if digit
+                .to_digit(10)
+                .is_some_and(|n| (1..=eq::BANDS as u32).contains(&n)) =>
+        {
+            editor.selected = digit as usize - '1' as usize;
+        }
+        KeyCode::Left => band.frequency /= EQ_FREQUENCY_STEP,
+        KeyCode::Right => band.frequency *= EQ_FREQUENCY_STEP,
+        KeyCode::Up => band.gain_db += EQ_GAIN_STEP,
+        KeyCode::Down => band.gain_db -= EQ_GAIN_STEP,
+        KeyCode::Char('-') => band.q /= EQ_Q_STEP,
+        KeyCode::Char('+') | KeyCode::Char('=') => band.q *= EQ_Q_STEP,
+        KeyCode::Char('0') => band.gain_db = 0.0,
+        _ => return ControlAction::Idle,
+    }
+
+    ui_state.equalizer.set_band(index, band);
+    ControlAction::Continue
+}
+
+/// Keys that mean something else in deck mode. Anything else is left to the
+/// usual handling, returning `None`.
+fn handle_deck_input(
+    code: KeyCode,
+    player: &Player,
+    ui_state: &mut UIState,
+) -> Option<ControlAction> {
+    let resampler = &ui_state.resampler;
+    let step = match code {
+        KeyCode::Esc | KeyCode::Char('d') => {
+            player.end_roll();
+            ui_state.close_deck();
+            return Some(ControlAction::Continue);
+        }
+        KeyCode::Char(digit @ '1'..='4') => {
+            let slot = digit as usize - '1' as usize;
+            if let Some(position) = ui_state.cues.get(slot) {
+                player.seek_to(position);
+            }
+            return Some(ControlAction::Continue);
+        }
+        KeyCode::Char(c) if SHIFTED_DIGITS.contains(&c) => {
+            let slot = SHIFTED_DIGITS.iter().position(|&d| d == c).unwrap_or(0);
+            ui_state.cues.set(slot, player.position());
+            return Some(ControlAction::Continue);
+        }
+        KeyCode::Char(digit @ '5'..='8') => {
+            let beats = ROLL_BEATS[digit as usize - '5' as usize];
+            if !player.roll(beats) {
+                ui_state.error = Some("No beat to loop to yet".to_string());
+            }
+            return Some(ControlAction::Continue);
+        }
+        // Digits rate tracks otherwise, which is easy to do by mistake here.
+        KeyCode::Char('9') => return Some(ControlAction::Idle),
+        KeyCode::Char('0') => {
+            resampler.set_pitch(1.0);
+            return Some(ControlAction::Continue);
+        }
+        KeyCode::Char('-') => -PITCH_STEP,
+        KeyCode::Char('=') => PITCH_STEP,
+        KeyCode::Char('_') => -COARSE_PITCH_STEP,
+        KeyCode::Char('+') => COARSE_PITCH_STEP,
+        _ => return None,
+    };
+    // Kept to whole steps, so stepping back lands on normal speed exactly.
+    let pitch = ((resampler.pitch() + step) / PITCH_STEP).round() * PITCH_STEP;
+    resampler.set_pitch(pitch);
+    Some(ControlAction::Continue)
+}
+
+/// Moves the selected queue entry to `to`, keeping it selected.
+fn move_selected(panel: &mut QueuePanel, from: usize, to: Option<usize>) -> ControlAction {
+    let Some(to) = to else {
+        return ControlAction::Idle;
+    };
+    panel.selected = to;
+    // The entries swap places, and their marks with them.
+    let (from_marked, to_marked) = (panel.marked.remove(&from), panel.marked.remove(&to));
+    if from_marked {
+        panel.marked.insert(to);
+    }
+    if to_marked {
+        panel.marked.insert(from);
+    }
+    ControlAction::Command(Command::MoveInQueue(from, to))
+}
+
+/// E.g. "Removed 3 tracks".
+pub fn tracks_toast(action: &str, count: usize) -> String {
+    if count == 1 {
+        format!("{} 1 track", action)
+    } else {
+        format!("{} {} tracks", action, count)
+    }
+}
+
+fn handle_prompt_input(code: KeyCode, player: &Player, ui_state: &mut UIState) -> ControlAction {
+    let Some(prompt) = ui_state.prompt.as_mut() else {
+        return ControlAction::Continue;
+    };
+
+    match code {
+        KeyCode::Esc => {
+            ui_state.prompt = None;
+        }
+        KeyCode::Enter => {
+            if let Some(prompt) = ui_state.prompt.take() {
+                match prompt.kind {
+                    PromptKind::BookmarkLabel(position) => {
+                        ui_state.bookmarks.add(position, &prompt.input);
+                    }
+                    PromptKind::Command => match palette::parse(&prompt.input) {
+                        Ok(action) => return run_action(action, player, ui_state),
+                        Err(e) => ui_state.error = Some(e),
+                    },
+                    PromptKind::OpenFile if !prompt.input.trim().is_empty() => {
+                        let path = paths::expand_home(prompt.input.trim());
+                        return ControlAction::Command(Command::Open(path));
+                    }
+                    PromptKind::InsertFile if !prompt.input.trim().is_empty() => {
+                        let path = paths::expand_home(prompt.input.trim());
+                        return ControlAction::Command(Command::InsertNext(path));
+                    }
+                    PromptKind::ExportClip if !prompt.input.trim().is_empty() => {
+                        let path = paths::expand_home(prompt.input.trim());
+                        return ControlAction::ExportClip(path);
+                    }
+                    PromptKind::Gain(position) if prompt.input.trim().is_empty() => {
+                        ui_state.envelope.remove_near(position);
+                    }
+                    PromptKind::Gain(position) => match palette::parse_gain(&prompt.input) {
+                        Ok(gain_db) => ui_state.envelope.set(position, gain_db),
+                        Err(e) => ui_state.error = Some(e),
+                    },
+                    PromptKind::OpenFile | PromptKind::InsertFile | PromptKind::ExportClip => {}
+                }
+            }
+        }
+        KeyCode::Tab => {
+            prompt.input = match prompt.kind {
+                PromptKind::OpenFile | PromptKind::InsertFile | PromptKind::ExportClip => {
+                    paths::complete(&prompt.input)
+                }
+                // Paths given to `:open`, `:enqueue`, `:insert` and `:clip`
+                // complete too.
+                PromptKind::Command => match prompt.input.split_once(' ') {
+                    Some((name @ ("open" | "enqueue" | "insert" | "clip"), path)) => {
+                        format!("{} {}", name, paths::complete(path.trim_start()))
+                    }
+                    _ => return ControlAction::Idle,
+                },
+                PromptKind::BookmarkLabel(_) | PromptKind::Gain(_) => return ControlAction::Idle,
+            };
+        }
+        KeyCode::Backspace => {
+            prompt.input.pop();
+        }
+        KeyCode::Char(c) => {
+            prompt.input.push(c);
+        }
+        _ => {}
+    }
+
+    ControlAction::Continue
+}
+
+const NO_CLIP: &str = "No clip marked, set an in point with I or an out point with O";
+
+/// Asks where to save the marked clip, suggesting a name beside the track.
+fn prompt_for_clip(ui_state: &mut UIState) {
+    let Some(clip) = ui_state.clip() else {
+        ui_state.error = Some(NO_CLIP.to_string());
+        return;
+    };
+    let mut prompt = Prompt::new(PromptKind::ExportClip);
+    prompt.input = clip::default_name(&ui_state.path, &clip)
+        .to_string_lossy()
+        .into_owned();
+    ui_state.prompt = Some(prompt);
+}
+
+fn run_action(action: Action, player: &Player, ui_state: &mut UIState) -> ControlAction {
+    match action {
+        Action::Command(command) => return ControlAction::Command(command),
+        Action::VolumeBy(points) => {
+            let volume = (player.volume() + points / 100.0).clamp(0.0, 1.0);
+            player.set_volume(volume);
+            ui_state.toast_volume(volume);
+        }
+        Action::Restart => player.restart(),
+        Action::Theme(theme) => ui_state.theme = theme.for_terminal(),
+        Action::Hide(panel) => {
+            if ui_state.is_shown(panel) {
+                ui_state.toggle_panel(panel);
+            }
+        }
+        Action::Show(panel) => {
+            if !ui_state.is_shown(panel) {
+                ui_state.toggle_panel(panel);
+            }
+        }
+        Action::SpectrumLayout(layout) => {
+            let layout = layout.unwrap_or(ui_state.spectrum_layout.next());
+            ui_state.set_spectrum_layout(layout);
+        }
+        Action::Visualizer(visualizer) => {
+            let visualizer =
+                visualizer.unwrap_or_else(|| visualizer::next(ui_state.visualizer.borrow().name()));
+            ui_state.set_visualizer(visualizer);
+        }
+        Action::SpectrumRange(min_freq, max_freq) => {
+            if let Some(spectrum) = player.spectrum() {
+                spectrum.set_range(min_freq, max_freq);
+            }
+        }
+        Action::SpectrumCurve(curve) => {
+            if let Some(spectrum) = player.spectrum() {
+                spectrum.set_curve(curve);
+            }
+        }
+        Action::ToggleRemaining => ui_state.show_remaining = !ui_state.show_remaining,
+        Action::StopAfterTrack => ui_state.stop_after_track = !ui_state.stop_after_track,
+        Action::AutoAdvance => ui_state.auto_advance = !ui_state.auto_advance,
+        Action::Bookmark(label) => ui_state.bookmarks.add(player.position(), &label),
+        Action::Recent => ui_state.recent = Some(RecentList::load()),
+        Action::Podcast(url) => match url.or_else(|| ui_state.podcast_feed.clone()) {
+            Some(url) => ui_state.open_podcasts(&url),
+            None => ui_state.error = Some("podcast requires a feed URL".to_string()),
+        },
+        Action::Ab => {
+            if let Some(comparison) = &ui_state.comparison {
+                comparison.switch.toggle();
+            }
+        }
+        Action::Diff => {
+            if let Some(comparison) = &ui_state.comparison {
+                comparison.switch.toggle_diff();
+            }
+        }
+        Action::Mono => {
+            let stereo = &ui_state.stereo;
+            stereo.set_mono(!stereo.is_mono());
+        }
+        Action::Resampler(quality) => {
+            ui_state.resampler.set_quality(quality);
+            config::save_option("resampler", Some(quality.name()));
+        }
+        Action::Solo(solo) => ui_state.stereo.set_solo(solo),
+        Action::Width(width) => ui_state.stereo.set_width(width),
+        Action::Transpose(semitones) => ui_state.resampler.set_transpose(semitones),
+        Action::Compressor => {
+            let compressor = &ui_state.compressor;
+            compressor.set_enabled(!compressor.is_enabled());
+        }
+        Action::Equalizer => ui_state.eq_editor = Some(EqEditor { selected: 0 }),
+        Action::Queue => ui_state.queue_panel = Some(QueuePanel::new(ui_state.queue_index)),
+        Action::Rate(rating) => ui_state.rate(rating),
+        Action::MarkIn(time) => ui_state.mark_in(time.unwrap_or_else(|| player.position())),
+        Action::MarkOut(time) => ui_state.mark_out(time.unwrap_or_else(|| player.position())),
+        Action::Deck => ui_state.deck = true,
+        Action::Practice => return ControlAction::TogglePractice,
+        Action::Metronome(enabled) => {
+            let metronome = &ui_state.metronome;
+            metronome.set_enabled(enabled.unwrap_or(!metronome.is_enabled()));
+        }
+        Action::Tap => return ControlAction::Tap,
+        Action::SetGain(gain_db, time) => {
+            let position = time.unwrap_or_else(|| player.position());
+            ui_state.envelope.set(position, gain_db);
+        }
+        Action::RemoveGain(time) => {
+            let position = time.unwrap_or_else(|| player.position());
+            if !ui_state.envelope.remove_near(position) {
+                ui_state.error = Some("No volume keyframe within a second of there".to_string());
+            }
+        }
+        Action::ClearGain => ui_state.envelope.clear(),
+        Action::TrackGain(gain_db) => ui_state.set_track_gain(gain_db),
+        Action::SetCue(slot, time) => {
+            let position = time.unwrap_or_else(|| player.position());
+            ui_state.cues.set(slot, position);
+        }
+        Action::ClearCue(slot) => ui_state.cues.clear(slot),
+        Action::ExportClip(None) => prompt_for_clip(ui_state),
+        Action::ExportClip(Some(path)) => {
+            if ui_state.clip().is_some() {
+                return ControlAction::ExportClip(path);
+            }
+            ui_state.error = Some(NO_CLIP.to_string());
+        }
+        Action::Quit => return ControlAction::Quit,
+    }
+    ControlAction::Continue
+}
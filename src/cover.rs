@@ -0,0 +1,146 @@
+//! The album cover embedded in a track, drawn beside the visualizer in
+//! colored half blocks, which work in any terminal without a graphics
+//! protocol: each cell is two pixels, the top one in the foreground color
+//! and the bottom in the background.
+
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Color,
+    widgets::{Block, Borders},
+};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use symphonia::core::meta::{MetadataRevision, StandardVisualKey};
+
+use crate::metadata;
+
+/// Columns the cover takes, inside its border, unless `--cover-width` says
+/// otherwise.
+pub const DEFAULT_WIDTH: u16 = 24;
+/// Covers are shrunk to this many pixels across on loading, far more than
+/// a panel can show, so drawing never has to scale down a huge image.
+const MAX_PIXELS: u32 = 256;
+
+/// The current track's cover, decoded in the background.
+pub struct Cover {
+    image: Mutex<Option<RgbImage>>,
+    /// The image scaled to fit the panel, kept until the panel's size
+    /// changes.
+    scaled: Mutex<Option<RgbImage>>,
+}
+
+impl Cover {
+    /// Starts reading the cover of `path`. There's nothing to draw until
+    /// it's decoded, or ever if the track has none.
+    pub fn load(path: &Path) -> Arc<Self> {
+        let cover = Arc::new(Self {
+            image: Mutex::new(None),
+            scaled: Mutex::new(None),
+        });
+        let loading = Arc::clone(&cover);
+        let path = path.to_path_buf();
+        thread::Builder::new()
+            .name("cover".to_string())
+            .spawn(move || {
+                if let Some(image) = read(&path) {
+                    *loading.image.lock().unwrap() = Some(image);
+                }
+            })
+            .ok();
+        cover
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.image.lock().unwrap().is_some()
+    }
+
+    /// Draws the cover in a bordered block filling `area`, as large as fits
+    /// and centered. Without `truecolor`, colors are the nearest of the 256
+    /// most terminals have.
+    pub fn render(&self, frame: &mut Frame, area: Rect, truecolor: bool) {
+        let block = Block::default().borders(Borders::ALL).title("Cover");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let image = self.image.lock().unwrap();
+        let Some(image) = image.as_ref() else {
+            return;
+        };
+        let (width, height) = (inner.width as u32, inner.height as u32 * 2);
+        if width == 0 || height == 0 {
+            return;
+        }
+        let scale = f32::min(
+            width as f32 / image.width() as f32,
+            height as f32 / image.height() as f32,
+        );
+        let size = (
+            ((image.width() as f32 * scale) as u32).clamp(1, width),
+            ((image.height() as f32 * scale) as u32).clamp(1, height),
+        );
+        let mut scaled = self.scaled.lock().unwrap();
+        if scaled
+            .as_ref()
+            .is_some_and(|scaled| scaled.dimensions() != size)
+        {
+            *scaled = None;
+        }
+        let scaled = scaled
+            .get_or_insert_with(|| imageops::resize(image, size.0, size.1, FilterType::Triangle));
+
+        let left = inner.x + ((width - size.0) / 2) as u16;
+        // Whole cells above, so the picture starts on a cell's top half.
+        let top = inner.y + ((height - size.1) / 4) as u16;
+        let color = |pixel: &Rgb<u8>| {
+            let [r, g, b] = pixel.0;
+            if truecolor {
+                Color::Rgb(r, g, b)
+            } else {
+                let level = |value: u8| ((value as u16 * 5 + 127) / 255) as u8;
+                Color::Indexed(16 + 36 * level(r) + 6 * level(g) + level(b))
+            }
+        };
+        for y in (0..size.1).step_by(2) {
+            for x in 0..size.0 {
+                let cell = &mut frame.buffer_mut()[(left + x as u16, top + (y / 2) as u16)];
+                cell.set_symbol("▀");
+                cell.set_fg(color(scaled.get_pixel(x, y)));
+                if y + 1 < size.1 {
+                    cell.set_bg(color(scaled.get_pixel(x, y + 1)));
+                }
+            }
+        }
+    }
+}
+
+/// The front cover of the track at `path`, or whatever picture it has
+/// otherwise.
+fn read(path: &Path) -> Option<RgbImage> {
+    let mut probed = metadata::probe(path)?;
+    let pick = |revision: &MetadataRevision| {
+        let visuals = revision.visuals();
+        visuals
+            .iter()
+            .find(|visual| visual.usage == Some(StandardVisualKey::FrontCover))
+            .or(visuals.first())
+            .map(|visual| visual.data.clone())
+    };
+    // Like tags, pictures are in the container or a tag block ahead of it.
+    let data = probed
+        .format
+        .metadata()
+        .current()
+        .and_then(pick)
+        .or_else(|| {
+            probed
+                .metadata
+                .get()
+                .and_then(|metadata| metadata.current().and_then(pick))
+        })?;
+    let image = image::load_from_memory(&data).ok()?;
+    Some(image.thumbnail(MAX_PIXELS, MAX_PIXELS).to_rgb8())
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::paths;
+
+const CUES_FILE: &str = "cues.tsv";
+
+pub const SLOTS: usize = 4;
+
+/// Hot cues for a single audio file: a few numbered positions to jump
+/// straight to, persisted across sessions in a shared `cues.tsv`
+/// (`path<TAB>slot<TAB>millis` per line).
+pub struct HotCues {
+    key: String,
+    slots: [Option<Duration>; SLOTS],
+}
+
+impl HotCues {
+    pub fn load<P: AsRef<Path>>(audio_path: P) -> Self {
+        let key = fs::canonicalize(&audio_path)
+            .unwrap_or_else(|_| audio_path.as_ref().to_path_buf())
+            .to_string_lossy()
+            .into_owned();
+
+        let mut slots = [None; SLOTS];
+        for (path, slot, position) in read_store().lines().filter_map(parse_line) {
+            if path == key {
+                slots[slot] = Some(position);
+            }
+        }
+
+        Self { key, slots }
+    }
+
+    pub fn slots(&self) -> &[Option<Duration>; SLOTS] {
+        &self.slots
+    }
+
+    pub fn get(&self, slot: usize) -> Option<Duration> {
+        self.slots.get(slot).copied().flatten()
+    }
+
+    pub fn set(&mut self, slot: usize, position: Duration) {
+        if slot < SLOTS {
+            self.slots[slot] = Some(position);
+            self.save();
+        }
+    }
+
+    pub fn clear(&mut self, slot: usize) {
+        if self.get(slot).is_some() {
+            self.slots[slot] = None;
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = store_path() else {
+            return;
+        };
+
+        let store = read_store();
+        let mut contents: String = store
+            .lines()
+            .filter(|line| parse_line(line).is_none_or(|(path, _, _)| path != self.key))
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        for (slot, position) in self.slots.iter().enumerate() {
+            if let Some(position) = position {
+                contents.push_str(&format!(
+                    "{}\t{}\t{}\n",
+                    self.key,
+                    slot + 1,
+                    position.as_millis()
+                ));
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        fs::write(path, contents).ok();
+    }
+}
+
+fn store_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(CUES_FILE))
+}
+
+fn read_store() -> String {
+    store_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default()
+}
+
+/// Slots are numbered from 1 in the file, as on the keyboard.
+fn parse_line(line: &str) -> Option<(&str, usize, Duration)> {
+    let mut fields = line.splitn(3, '\t');
+    let path = fields.next()?;
+    let slot = fields
+        .next()?
+        .parse::<usize>()
+        .ok()
+        .filter(|slot| (1..=SLOTS).contains(slot))?;
+    let millis = fields.next()?.trim().parse().ok()?;
+    Some((path, slot - 1, Duration::from_millis(millis)))
+}
@@ -0,0 +1,129 @@
+use rodio::decoder::DecoderError;
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::download::{self, DownloadReader};
+use crate::error::ApzError;
+use crate::hls;
+use crate::midi::{self, MidiSource};
+use crate::opus::OpusSource;
+use crate::raw::{self, RawSource};
+use crate::tracker::{self, SharedPosition, TrackerSource};
+
+pub type AudioSource = Box<dyn Source<Item = i16> + Send>;
+
+/// Extensions of the formats rodio's decoder and [`OpusSource`] handle.
+const EXTENSIONS: [&str; 11] = [
+    "mp3", "wav", "flac", "ogg", "oga", "opus", "m4a", "m4b", "mp4", "aac", "caf",
+];
+
+pub struct Decoded {
+    pub source: AudioSource,
+    /// Live pattern/row position, for tracker modules.
+    pub tracker: Option<SharedPosition>,
+}
+
+/// Whether `path` looks like something [`open`] can play, judging by its
+/// extension. Used to pick the audio files out of a directory.
+pub fn is_supported(path: &Path) -> bool {
+    let known = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        });
+    known || tracker::is_module(path) || midi::is_midi(path)
+}
+
+/// Opens `path` with whichever decoder handles it: tracker modules go to
+/// [`TrackerSource`], MIDI files to [`MidiSource`], Ogg Opus to
+/// [`OpusSource`], everything else to rodio's symphonia-backed decoder.
+/// The path `-` reads raw PCM from stdin through [`RawSource`], an HLS
+/// playlist URL streams through [`hls`], and a file still being downloaded
+/// is read as it arrives.
+pub fn open(path: &Path) -> Result<Decoded, ApzError> {
+    if raw::is_stdin(path) {
+        return Ok(Decoded {
+            source: Box::new(RawSource::stdin()),
+            tracker: None,
+        });
+    }
+
+    if hls::is_playlist(path) {
+        return Ok(Decoded {
+            source: hls::open(path)?,
+            tracker: None,
+        });
+    }
+
+    if let Some(download) = download::active(path) {
+        let reader = DownloadReader::open(download).map_err(|e| ApzError::open(path, e))?;
+        return open_encoded(path, BufReader::new(reader));
+    }
+
+    if tracker::is_module(path) {
+        let module = tracker::load(path)
+            .map_err(|e| ApzError::open(path, e))?
+            .ok_or_else(|| ApzError::decode(path, DecoderError::UnrecognizedFormat))?;
+        let source = TrackerSource::new(module);
+        return Ok(Decoded {
+            tracker: Some(source.position()),
+            source: Box::new(source),
+        });
+    }
+
+    let file = File::open(path).map_err(|e| ApzError::open(path, e))?;
+    let mut reader = BufReader::new(file);
+
+    if midi::is_midi(path) {
+        let midi_error = |e| ApzError::decode(path, DecoderError::DecodeError(e));
+        let soundfont = midi::soundfont().map_err(midi_error)?;
+        let source = MidiSource::new(&mut reader, &soundfont).map_err(midi_error)?;
+        return Ok(Decoded {
+            source: Box::new(source.convert_samples()),
+            tracker: None,
+        });
+    }
+
+    open_encoded(path, reader)
+}
+
+/// Opens an Ogg Opus stream with [`OpusSource`] and anything else with
+/// rodio's decoder.
+fn open_encoded<R>(path: &Path, mut reader: R) -> Result<Decoded, ApzError>
+where
+    R: Read + Seek + Send + Sync + 'static,
+{
+    if is_opus(&mut reader).map_err(|e| ApzError::open(path, e))? {
+        let source = OpusSource::new(reader).map_err(|e| ApzError::decode(path, e))?;
+        return Ok(Decoded {
+            source: Box::new(source),
+            tracker: None,
+        });
+    }
+
+    let source = Decoder::new(reader).map_err(|e| ApzError::decode(path, e))?;
+    Ok(Decoded {
+        source: Box::new(source),
+        tracker: None,
+    })
+}
+
+/// Whether `path` can only be played as it arrives, not read ahead of time
+/// for a waveform or silences: stdin and HLS streams.
+pub fn is_stream(path: &Path) -> bool {
+    raw::is_stdin(path) || hls::is_playlist(path)
+}
+
+/// The first Ogg page of an Opus stream holds just the `OpusHead` packet,
+/// right after the 27-byte page header and a one-entry segment table.
+fn is_opus<R: Read + Seek>(reader: &mut R) -> std::io::Result<bool> {
+    let mut start = [0; 36];
+    let read = reader.read(&mut start)?;
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(read == start.len() && start.starts_with(b"OggS") && &start[28..] == b"OpusHead")
+}
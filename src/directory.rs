@@ -0,0 +1,205 @@
+//! Turns directories and glob patterns given on the command line into the
+//! audio files they cover, so `apz ~/Music/Album/` plays the album in order
+//! and `apz "*.flac"` works in shells that don't expand globs themselves.
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::decoder;
+use crate::download;
+use crate::library::Library;
+use crate::metadata::TrackInfo;
+
+pub const SORT_ORDERS: [&str; 5] = ["track", "name", "mtime", "rating", "plays"];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanConfig {
+    /// Include the files in subdirectories too.
+    pub recursive: bool,
+    pub order: SortOrder,
+    /// Leave out files rated lower than this, and unrated ones.
+    pub min_rating: Option<u8>,
+}
+
+/// Order of the files found in a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SortOrder {
+    /// By track-number tag, with untagged files after the tagged ones and
+    /// ties broken by name.
+    #[default]
+    Track,
+    Name,
+    /// Oldest modification time first.
+    Modified,
+    /// Highest rated first, unrated last.
+    Rating,
+    /// Most played first.
+    Plays,
+}
+
+impl SortOrder {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "track" => Some(Self::Track),
+            "name" => Some(Self::Name),
+            "mtime" => Some(Self::Modified),
+            "rating" => Some(Self::Rating),
+            "plays" => Some(Self::Plays),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces each directory in `paths` with the supported files in it, and
+/// each glob pattern with its matches, leaving other paths alone.
+/// Subdirectories are only descended into when `recursive` is set, each one
+/// after the files beside it.
+pub fn expand(paths: &[PathBuf], config: &ScanConfig) -> Vec<PathBuf> {
+    let library = Library::load();
+    let mut expanded = Vec::new();
+    for path in paths {
+        // A file that really is called `*.flac` is played as it is, and a
+        // URL's `?` starts its query.
+        let pattern =
+            is_pattern(path) && !path.exists() && !download::is_url(&path.to_string_lossy());
+        let matches = if pattern {
+            glob(path)
+        } else {
+            vec![path.clone()]
+        };
+        for path in matches {
+            if path.is_dir() {
+                collect(&path, config, &library, &mut expanded);
+            } else if !pattern || is_wanted(&path, config, &library) {
+                expanded.push(path);
+            }
+        }
+    }
+    expanded
+}
+
+fn is_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?'])
+}
+
+/// Paths matching `pattern`, in which `*` stands for any run of characters
+/// and `?` for any one character, within a single path component. As in a
+/// shell, wildcards don't match hidden files unless the pattern component
+/// starts with a dot.
+fn glob(pattern: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let Component::Normal(part) = component else {
+            for candidate in &mut candidates {
+                candidate.push(component);
+            }
+            continue;
+        };
+        let part = part.to_string_lossy();
+        if !part.contains(['*', '?']) {
+            for candidate in &mut candidates {
+                candidate.push(&*part);
+            }
+            continue;
+        }
+
+        let wanted: Vec<char> = part.chars().collect();
+        let mut matches = Vec::new();
+        for dir in &candidates {
+            let listed = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let Ok(entries) = fs::read_dir(listed) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') && !part.starts_with('.') {
+                    continue;
+                }
+                let name: Vec<char> = name.chars().collect();
+                if wildcard_match(&wanted, &name) {
+                    matches.push(dir.join(entry.file_name()));
+                }
+            }
+        }
+        matches.sort();
+        candidates = matches;
+    }
+    candidates.retain(|path| path.exists());
+    candidates
+}
+
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| wildcard_match(rest, &name[skip..])),
+        Some((&wanted, rest)) => name.split_first().is_some_and(|(&actual, name)| {
+            let same = if cfg!(target_os = "windows") {
+                actual.eq_ignore_ascii_case(&wanted)
+            } else {
+                actual == wanted
+            };
+            (wanted == '?' || same) && wildcard_match(rest, name)
+        }),
+    }
+}
+
+/// Whether a file found by a pattern or in a directory is rated highly
+/// enough. Files named on their own are always played.
+fn is_wanted(path: &Path, config: &ScanConfig, library: &Library) -> bool {
+    config
+        .min_rating
+        .is_none_or(|min| library.get(path).rating.is_some_and(|rating| rating >= min))
+}
+
+fn collect(dir: &Path, config: &ScanConfig, library: &Library, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            subdirs.push(path);
+        } else if decoder::is_supported(&path) && is_wanted(&path, config, library) {
+            files.push(path);
+        }
+    }
+
+    sort(&mut files, config.order, library);
+    found.append(&mut files);
+
+    if config.recursive {
+        subdirs.sort();
+        for subdir in subdirs {
+            collect(&subdir, config, library, found);
+        }
+    }
+}
+
+fn sort(files: &mut [PathBuf], order: SortOrder, library: &Library) {
+    files.sort();
+    match order {
+        SortOrder::Name => {}
+        SortOrder::Track => {
+            files.sort_by_cached_key(|path| TrackInfo::read(path).track_number.unwrap_or(u32::MAX))
+        }
+        SortOrder::Modified => files.sort_by_cached_key(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        }),
+        SortOrder::Rating => files.sort_by_cached_key(|path| Reverse(library.get(path).rating)),
+        SortOrder::Plays => files.sort_by_cached_key(|path| Reverse(library.get(path).plays)),
+    }
+}
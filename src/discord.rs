@@ -0,0 +1,210 @@
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::json;
+use crate::player::PlaybackState;
+
+const OP_HANDSHAKE: u32 = 0;
+const OP_FRAME: u32 = 1;
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Activity {
+    details: String,
+    state: PlaybackState,
+    /// Unix timestamp the track would have started at, for Discord's
+    /// elapsed-time counter.
+    started_at: u64,
+}
+
+/// Publishes the current track to Discord Rich Presence over Discord's local
+/// IPC socket. All I/O happens on a background thread so a missing or slow
+/// Discord client never stalls the UI.
+pub struct DiscordPresence {
+    sender: Sender<Option<Activity>>,
+    last: Option<Activity>,
+}
+
+impl DiscordPresence {
+    pub fn start(client_id: String) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run(client_id, receiver));
+        Self { sender, last: None }
+    }
+
+    pub fn update(&mut self, title: &str, state: PlaybackState, position: Duration) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let activity = Activity {
+            details: title.chars().take(128).collect(),
+            state,
+            started_at: now.saturating_sub(position.as_secs()),
+        };
+
+        // Position and wall clock tick at slightly different moments, so
+        // allow a little drift before re-sending an otherwise identical update.
+        if let Some(last) = &self.last
+            && last.details == activity.details
+            && last.state == activity.state
+            && last.started_at.abs_diff(activity.started_at) <= 2
+        {
+            return;
+        }
+
+        self.sender.send(Some(activity.clone())).ok();
+        self.last = Some(activity);
+    }
+}
+
+impl Drop for DiscordPresence {
+    fn drop(&mut self) {
+        self.sender.send(None).ok();
+        // Give the worker a moment to clear the presence before the process exits.
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+trait IpcStream: Read + Write + Send {}
+impl<T: Read + Write + Send> IpcStream for T {}
+
+fn run(client_id: String, receiver: Receiver<Option<Activity>>) {
+    let mut connection: Option<Box<dyn IpcStream>> = None;
+    let mut last_attempt: Option<Instant> = None;
+    let mut pending: Option<Activity> = None;
+    let mut nonce = 0u64;
+
+    loop {
+        match receiver.recv_timeout(RECONNECT_INTERVAL) {
+            Ok(Some(activity)) => pending = Some(activity),
+            Ok(None) | Err(RecvTimeoutError::Disconnected) => {
+                if let Some(stream) = connection.as_mut() {
+                    send_activity(stream, None, &mut nonce).ok();
+                }
+                return;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        if connection.is_none() && last_attempt.is_none_or(|t| t.elapsed() >= RECONNECT_INTERVAL) {
+            last_attempt = Some(Instant::now());
+            connection = connect(&client_id);
+        }
+
+        if let (Some(stream), Some(activity)) = (connection.as_mut(), pending.as_ref()) {
+            if send_activity(stream, Some(activity), &mut nonce).is_ok() {
+                pending = None;
+            } else {
+                connection = None;
+            }
+        }
+    }
+}
+
+fn connect(client_id: &str) -> Option<Box<dyn IpcStream>> {
+    let mut stream = open_socket()?;
+    let handshake = format!(r#"{{"v":1,"client_id":{}}}"#, json::string(client_id));
+    write_frame(&mut stream, OP_HANDSHAKE, &handshake).ok()?;
+    read_frame(&mut stream).ok()?;
+    Some(stream)
+}
+
+fn send_activity(
+    stream: &mut Box<dyn IpcStream>,
+    activity: Option<&Activity>,
+    nonce: &mut u64,
+) -> std::io::Result<()> {
+    let activity = match activity {
+        Some(activity) => {
+            let (state, timestamps) = match activity.state {
+                PlaybackState::Playing => (
+                    "Playing",
+                    format!(r#","timestamps":{{"start":{}}}"#, activity.started_at),
+                ),
+                PlaybackState::Paused => ("Paused", String::new()),
+                PlaybackState::Stopped => ("Stopped", String::new()),
+            };
+            format!(
+                r#"{{"type":2,"details":{},"state":"{}"{}}}"#,
+                json::string(&activity.details),
+                state,
+                timestamps
+            )
+        }
+        None => "null".to_string(),
+    };
+
+    *nonce += 1;
+    let payload = format!(
+        r#"{{"cmd":"SET_ACTIVITY","args":{{"pid":{},"activity":{}}},"nonce":"{}"}}"#,
+        std::process::id(),
+        activity,
+        nonce
+    );
+    write_frame(stream, OP_FRAME, &payload)?;
+    read_frame(stream)
+}
+
+fn write_frame(stream: &mut impl Write, op: u32, payload: &str) -> std::io::Result<()> {
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload.as_bytes());
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+/// Reads and discards one reply frame; Discord answers every request and
+/// expects clients to drain the responses.
+fn read_frame(stream: &mut impl Read) -> std::io::Result<()> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header)?;
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body)
+}
+
+#[cfg(unix)]
+fn open_socket() -> Option<Box<dyn IpcStream>> {
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+
+    let base = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .find_map(std::env::var_os)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+
+    // Flatpak and Snap builds of Discord put their socket in a subdirectory.
+    let dirs = [
+        base.clone(),
+        base.join("app/com.discordapp.Discord"),
+        base.join("snap.discord"),
+    ];
+
+    dirs.iter()
+        .flat_map(|dir| (0..10).map(move |i| dir.join(format!("discord-ipc-{}", i))))
+        .find_map(|path| UnixStream::connect(path).ok())
+        .map(|stream| {
+            stream.set_read_timeout(Some(Duration::from_secs(5))).ok();
+            Box::new(stream) as Box<dyn IpcStream>
+        })
+}
+
+#[cfg(windows)]
+fn open_socket() -> Option<Box<dyn IpcStream>> {
+    use std::fs::OpenOptions;
+
+    (0..10)
+        .find_map(|i| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(format!(r"\\?\pipe\discord-ipc-{}", i))
+                .ok()
+        })
+        .map(|pipe| Box::new(pipe) as Box<dyn IpcStream>)
+}
@@ -0,0 +1,264 @@
+//! Downloads over HTTP(S) into a local file that can be played while it's
+//! still arriving. The transfer itself is left to curl, run in the
+//! background, so apz doesn't need a TLS stack of its own.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::paths;
+
+/// Characters of a title kept in a file name.
+const MAX_NAME_LEN: usize = 60;
+/// How often a reader that has caught up with the download checks for more.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Downloads started this session, finished or not.
+static DOWNLOADS: Mutex<Vec<Arc<Download>>> = Mutex::new(Vec::new());
+
+pub fn is_url(text: &str) -> bool {
+    text.starts_with("http://") || text.starts_with("https://")
+}
+
+/// Fetches `url` whole, for small things like feeds and playlists.
+pub fn fetch(url: &str) -> Result<String, String> {
+    fetch_bytes(url).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub fn fetch_bytes(url: &str) -> Result<Vec<u8>, String> {
+    let output = curl("-fsSL", url)
+        .map_err(|e| e.to_string())?
+        .output()
+        .map_err(|e| format!("couldn't run curl: {}", e))?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(message.trim().trim_start_matches("curl: ").to_string());
+    }
+    Ok(output.stdout)
+}
+
+/// curl with `flags`, set up to fetch `url` and nothing else. URLs come from
+/// feeds and pages, so only http(s) is allowed, redirects included, and the
+/// URL can't be taken for an option.
+fn curl(flags: &str, url: &str) -> io::Result<Command> {
+    if !is_url(url) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("not an http(s) URL: {}", url),
+        ));
+    }
+    let mut command = Command::new("curl");
+    command.args([
+        flags,
+        "--proto",
+        "=http,https",
+        "--proto-redir",
+        "=http,https",
+        "--",
+        url,
+    ]);
+    Ok(command)
+}
+
+/// Where in the cache's `dir` to download `url` to: a file named after
+/// its `title`, so it reads well in the history, made unique by a hash of
+/// the URL.
+pub fn cache_path(dir: &str, title: &str, url: &str, extension: &str) -> Option<PathBuf> {
+    let name: String = title
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || " -_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_NAME_LEN)
+        .collect();
+    let file = format!("{}-{:08x}.{}", name.trim(), hash(url), extension);
+    paths::cache_dir().map(|cache| cache.join(dir).join(file))
+}
+
+/// FNV-1a, for file names that stay the same from one build to the next.
+fn hash(text: &str) -> u32 {
+    text.bytes().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// A file being downloaded to `path`.
+pub struct Download {
+    pub url: String,
+    pub path: PathBuf,
+    /// Size of the whole file, or 0 until the server has said.
+    total: AtomicU64,
+    finished: AtomicBool,
+    failed: AtomicBool,
+}
+
+impl Download {
+    /// Bytes written to the file so far.
+    pub fn received(&self) -> u64 {
+        fs::metadata(&self.path).map_or(0, |metadata| metadata.len())
+    }
+
+    pub fn total(&self) -> Option<u64> {
+        Some(self.total.load(Ordering::Relaxed)).filter(|&total| total != 0)
+    }
+
+    /// How much of the file has arrived, from 0.0 to 1.0, if its size is
+    /// known.
+    pub fn progress(&self) -> Option<f32> {
+        if self.is_finished() {
+            return Some(1.0);
+        }
+        let total = self.total()?;
+        Some((self.received() as f64 / total as f64).min(1.0) as f32)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+/// Marks a file as only partly downloaded, so an interrupted download is
+/// started over rather than played as if it were complete.
+fn marker(path: &Path) -> PathBuf {
+    let mut marker = path.as_os_str().to_owned();
+    marker.push(".part");
+    PathBuf::from(marker)
+}
+
+/// Downloads `url` to `path` in the background, unless it's already there
+/// or on its way.
+pub fn start(url: &str, path: &Path) -> io::Result<()> {
+    let mut headers = curl("-fsIL", url)?;
+    let mut transfer = curl("-fsSL", url)?;
+    let mut downloads = DOWNLOADS.lock().unwrap();
+    if downloads
+        .iter()
+        .any(|download| download.path == path && !download.has_failed())
+        || (path.exists() && !marker(path).exists())
+    {
+        return Ok(());
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    File::create(marker(path))?;
+    let file = File::create(path)?;
+    let download = Arc::new(Download {
+        url: url.to_string(),
+        path: path.to_path_buf(),
+        total: AtomicU64::new(0),
+        finished: AtomicBool::new(false),
+        failed: AtomicBool::new(false),
+    });
+    downloads.retain(|download| download.path != path);
+    downloads.push(Arc::clone(&download));
+
+    thread::spawn(move || {
+        // The size comes from the headers of the last response, after any
+        // redirects.
+        let headers = headers
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+            .unwrap_or_default();
+        let total = headers
+            .lines()
+            .rev()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .find_map(|(_, value)| value.trim().parse().ok());
+        download.total.store(total.unwrap_or(0), Ordering::Relaxed);
+
+        let succeeded = transfer
+            .stdout(file)
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if succeeded {
+            fs::remove_file(marker(&download.path)).ok();
+        }
+        download.failed.store(!succeeded, Ordering::Relaxed);
+        download.finished.store(true, Ordering::Relaxed);
+    });
+    Ok(())
+}
+
+/// The download still writing to `path`, if there is one.
+pub fn active(path: &Path) -> Option<Arc<Download>> {
+    DOWNLOADS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|download| download.path == path && !download.is_finished())
+        .cloned()
+}
+
+/// Reads a file that's still downloading, waiting for the download to
+/// catch up rather than stopping at the end of what's arrived so far.
+pub struct DownloadReader {
+    file: File,
+    download: Arc<Download>,
+}
+
+impl DownloadReader {
+    pub fn open(download: Arc<Download>) -> io::Result<Self> {
+        Ok(Self {
+            file: File::open(&download.path)?,
+            download,
+        })
+    }
+}
+
+impl Read for DownloadReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Checked before reading, so nothing written in between is
+            // mistaken for the end of the file.
+            let finished = self.download.is_finished();
+            let read = self.file.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            if finished {
+                if self.download.has_failed() {
+                    return Err(io::Error::other("the download failed"));
+                }
+                return Ok(0);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Seek for DownloadReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let SeekFrom::End(offset) = pos else {
+            return self.file.seek(pos);
+        };
+        // The end isn't where it will be until the size is known.
+        loop {
+            if let Some(total) = self.download.total() {
+                return self
+                    .file
+                    .seek(SeekFrom::Start(total.saturating_add_signed(offset)));
+            }
+            if self.download.is_finished() {
+                return self.file.seek(pos);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Folds surround sound down to stereo. Without it the output only gets a
+//! file's first two channels, losing the center (and with it, usually, the
+//! dialogue) and the surrounds.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::time::Duration;
+use symphonia::core::audio::Channels;
+
+const C: f32 = FRAC_1_SQRT_2;
+
+/// Speakers in the usual WAV/FLAC channel order, by channel count, for
+/// files that don't say what their channels are.
+fn wave_order(channels: usize) -> Option<Vec<Channels>> {
+    const L: Channels = Channels::FRONT_LEFT;
+    const R: Channels = Channels::FRONT_RIGHT;
+    const FC: Channels = Channels::FRONT_CENTRE;
+    const LFE: Channels = Channels::LFE1;
+    const BL: Channels = Channels::REAR_LEFT;
+    const BR: Channels = Channels::REAR_RIGHT;
+    const BC: Channels = Channels::REAR_CENTRE;
+    const SL: Channels = Channels::SIDE_LEFT;
+    const SR: Channels = Channels::SIDE_RIGHT;
+    Some(match channels {
+        3 => vec![L, R, FC],
+        4 => vec![L, R, BL, BR],
+        5 => vec![L, R, FC, BL, BR],
+        6 => vec![L, R, FC, LFE, BL, BR],
+        7 => vec![L, R, FC, LFE, BC, SL, SR],
+        8 => vec![L, R, FC, LFE, BL, BR, SL, SR],
+        _ => return None,
+    })
+}
+
+/// How much of a speaker goes to the left and right, after ITU-R BS.775:
+/// fronts as they are, center and surrounds at -3 dB, a single rear center
+/// split between the sides, and the LFE channel left out.
+fn gains(speaker: Channels) -> (f32, f32) {
+    let left = Channels::FRONT_LEFT_CENTRE
+        | Channels::REAR_LEFT
+        | Channels::REAR_LEFT_CENTRE
+        | Channels::SIDE_LEFT
+        | Channels::TOP_FRONT_LEFT
+        | Channels::TOP_REAR_LEFT
+        | Channels::FRONT_LEFT_HIGH;
+    let right = Channels::FRONT_RIGHT_CENTRE
+        | Channels::REAR_RIGHT
+        | Channels::REAR_RIGHT_CENTRE
+        | Channels::SIDE_RIGHT
+        | Channels::TOP_FRONT_RIGHT
+        | Channels::TOP_REAR_RIGHT
+        | Channels::FRONT_RIGHT_HIGH;
+    let center = Channels::FRONT_CENTRE
+        | Channels::FRONT_CENTRE_HIGH
+        | Channels::TOP_CENTRE
+        | Channels::TOP_FRONT_CENTRE;
+    let rear_center = Channels::REAR_CENTRE | Channels::TOP_REAR_CENTRE;
+
+    if speaker == Channels::FRONT_LEFT || speaker == Channels::FRONT_LEFT_WIDE {
+        (1.0, 0.0)
+    } else if speaker == Channels::FRONT_RIGHT || speaker == Channels::FRONT_RIGHT_WIDE {
+        (0.0, 1.0)
+    } else if left.contains(speaker) {
+        (C, 0.0)
+    } else if right.contains(speaker) {
+        (0.0, C)
+    } else if center.contains(speaker) {
+        (C, C)
+    } else if rear_center.contains(speaker) {
+        (0.5, 0.5)
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+/// How a layout of `channels` is usually written, e.g. "5.1".
+pub fn layout_name(channels: u16) -> String {
+    match channels {
+        1 => "mono".to_string(),
+        2 => "stereo".to_string(),
+        3 => "3.0".to_string(),
+        4 => "quad".to_string(),
+        5 => "5.0".to_string(),
+        6 => "5.1".to_string(),
+        7 => "6.1".to_string(),
+        8 => "7.1".to_string(),
+        _ => format!("{} ch", channels),
+    }
+}
+
+/// Mixes sources with more than two channels down to two, a frame at a
+/// time. Mono and stereo sources pass through untouched.
+pub struct DownmixSource<I> {
+    input: I,
+    /// The speaker each channel is for, as the decoder reported them.
+    speakers: Option<Vec<Channels>>,
+    /// Left and right gain for each input channel.
+    gains: Vec<(f32, f32)>,
+    frame: Vec<f32>,
+    /// The right sample of the frame being handed out.
+    right: Option<f32>,
+}
+
+impl<I> DownmixSource<I>
+where
+    I: Source<Item = f32>,
+{
+    /// `speakers` gives the channel order, when the decoder knows it;
+    /// otherwise it's taken to be WAV's.
+    pub fn new(input: I, speakers: Option<Vec<Channels>>) -> Self {
+        Self {
+            input,
+            speakers,
+            gains: Vec::new(),
+            frame: Vec::new(),
+            right: None,
+        }
+    }
+
+    fn update_gains(&mut self, channels: usize) {
+        let speakers = self
+            .speakers
+            .clone()
+            .filter(|speakers| speakers.len() == channels)
+            .or_else(|| wave_order(channels));
+        self.gains = match speakers {
+            Some(speakers) => speakers.into_iter().map(gains).collect(),
+            // Unknown layouts alternate between the sides.
+            None => (0..channels)
+                .map(|channel| {
+                    if channel % 2 == 0 {
+                        (1.0, 0.0)
+                    } else {
+                        (0.0, 1.0)
+                    }
+                })
+                .collect(),
+        };
+    }
+}
+
+impl<I> Iterator for DownmixSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+        let channels = self.input.channels() as usize;
+        if channels <= 2 {
+            return self.input.next();
+        }
+        if self.gains.len() != channels {
+            self.update_gains(channels);
+        }
+
+        self.frame.clear();
+        self.frame.extend(self.input.by_ref().take(channels));
+        if self.frame.is_empty() {
+            return None;
+        }
+        let (left, right) = self
+            .frame
+            .iter()
+            .zip(&self.gains)
+            .fold((0.0, 0.0), |(left, right), (sample, gains)| {
+                (left + sample * gains.0, right + sample * gains.1)
+            });
+        // Loud passages on every channel at once can add up past full
+        // scale.
+        self.right = Some(right.clamp(-1.0, 1.0));
+        Some(left.clamp(-1.0, 1.0))
+    }
+}
+
+impl<I> Source for DownmixSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        let channels = self.input.channels() as usize;
+        let pending = self.right.is_some() as usize;
+        let len = self.input.current_frame_len()?;
+        if channels <= 2 {
+            return Some(len + pending);
+        }
+        Some(len / channels * 2 + pending)
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels().min(2)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.right = None;
+        Ok(())
+    }
+}
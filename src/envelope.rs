@@ -0,0 +1,316 @@
+//! Volume automation: keyframes of gain at points in a track, faded between
+//! as it plays, e.g. to duck a loud section. Kept per file in a shared
+//! `envelope.tsv` (`path<TAB>millis<TAB>dB` per line), next to the
+//! bookmarks. The track's own gain from the library is added on top.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::looping::Looper;
+use crate::paths;
+use crate::resample::Resampler;
+
+const ENVELOPE_FILE: &str = "envelope.tsv";
+
+pub const MIN_GAIN_DB: f32 = -60.0;
+pub const MAX_GAIN_DB: f32 = 12.0;
+
+/// Keyframes closer than this to a position count as at it, for removing.
+const NEAR: Duration = Duration::from_secs(1);
+/// Time the gain takes to settle after a jump, from a seek or an edit, so
+/// it doesn't click.
+const SMOOTHING: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub position: Duration,
+    pub gain_db: f32,
+}
+
+/// The current track's keyframes, shared between the UI and the audio
+/// thread.
+pub struct Envelope {
+    key: Mutex<String>,
+    /// In order of position.
+    keyframes: Mutex<Vec<Keyframe>>,
+    /// Bumped on every change, so the source knows to copy them again.
+    version: AtomicUsize,
+    /// Gain of the whole track, in dB, as an `f32`'s bits.
+    offset: AtomicU32,
+}
+
+impl Envelope {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            key: Mutex::new(String::new()),
+            keyframes: Mutex::new(Vec::new()),
+            version: AtomicUsize::new(0),
+            offset: AtomicU32::new(0.0f32.to_bits()),
+        })
+    }
+
+    /// Switches to the keyframes of `audio_path`.
+    pub fn load<P: AsRef<Path>>(&self, audio_path: P) {
+        let key = fs::canonicalize(&audio_path)
+            .unwrap_or_else(|_| audio_path.as_ref().to_path_buf())
+            .to_string_lossy()
+            .into_owned();
+
+        let mut keyframes: Vec<Keyframe> = read_store()
+            .lines()
+            .filter_map(parse_line)
+            .filter(|(path, _)| *path == key)
+            .map(|(_, keyframe)| keyframe)
+            .collect();
+        keyframes.sort_by_key(|keyframe| keyframe.position);
+
+        *self.key.lock().unwrap() = key;
+        *self.keyframes.lock().unwrap() = keyframes;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn offset(&self) -> f32 {
+        f32::from_bits(self.offset.load(Ordering::Relaxed))
+    }
+
+    /// Plays the whole track `gain_db` louder, or quieter if negative.
+    pub fn set_offset(&self, gain_db: f32) {
+        self.offset.store(gain_db.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn keyframes(&self) -> Vec<Keyframe> {
+        self.keyframes.lock().unwrap().clone()
+    }
+
+    /// Gain at `position`, or `None` with no keyframes.
+    pub fn gain_db_at(&self, position: Duration) -> Option<f32> {
+        let keyframes = self.keyframes.lock().unwrap();
+        (!keyframes.is_empty()).then(|| gain_at(&keyframes, position.as_secs_f64()))
+    }
+
+    /// Adds a keyframe, in place of any already at `position`.
+    pub fn set(&self, position: Duration, gain_db: f32) {
+        let gain_db = gain_db.clamp(MIN_GAIN_DB, MAX_GAIN_DB);
+        self.change(|keyframes| {
+            keyframes.retain(|keyframe| keyframe.position != position);
+            keyframes.push(Keyframe { position, gain_db });
+            keyframes.sort_by_key(|keyframe| keyframe.position);
+        });
+    }
+
+    /// Removes the keyframe nearest `position`, if there's one within a
+    /// second of it.
+    pub fn remove_near(&self, position: Duration) -> bool {
+        let distance = |keyframe: &Keyframe| keyframe.position.abs_diff(position);
+        let nearest = self
+            .keyframes()
+            .into_iter()
+            .filter(|keyframe| distance(keyframe) <= NEAR)
+            .min_by_key(distance);
+        let Some(nearest) = nearest else {
+            return false;
+        };
+        self.change(|keyframes| keyframes.retain(|keyframe| *keyframe != nearest));
+        true
+    }
+
+    pub fn clear(&self) {
+        self.change(Vec::clear);
+    }
+
+    fn change(&self, edit: impl FnOnce(&mut Vec<Keyframe>)) {
+        edit(&mut self.keyframes.lock().unwrap());
+        self.version.fetch_add(1, Ordering::Release);
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = store_path() else {
+            return;
+        };
+        let key = self.key.lock().unwrap().clone();
+
+        let store = read_store();
+        let mut contents: String = store
+            .lines()
+            .filter(|line| parse_line(line).is_none_or(|(path, _)| path != key))
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        for keyframe in self.keyframes() {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\n",
+                key,
+                keyframe.position.as_millis(),
+                keyframe.gain_db
+            ));
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        fs::write(path, contents).ok();
+    }
+}
+
+/// Gain `seconds` into the track: straight lines in dB from one keyframe
+/// to the next, held before the first and after the last.
+fn gain_at(keyframes: &[Keyframe], seconds: f64) -> f32 {
+    let next = keyframes.partition_point(|keyframe| keyframe.position.as_secs_f64() <= seconds);
+    match (
+        next.checked_sub(1).map(|index| keyframes[index]),
+        keyframes.get(next).copied(),
+    ) {
+        (Some(before), Some(after)) => {
+            let start = before.position.as_secs_f64();
+            let length = after.position.as_secs_f64() - start;
+            let fraction = ((seconds - start) / length) as f32;
+            before.gain_db + (after.gain_db - before.gain_db) * fraction
+        }
+        (Some(keyframe), None) | (None, Some(keyframe)) => keyframe.gain_db,
+        (None, None) => 0.0,
+    }
+}
+
+fn store_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(ENVELOPE_FILE))
+}
+
+fn read_store() -> String {
+    store_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default()
+}
+
+fn parse_line(line: &str) -> Option<(&str, Keyframe)> {
+    let mut fields = line.splitn(3, '\t');
+    let path = fields.next()?;
+    let millis = fields.next()?.parse().ok()?;
+    let gain_db = fields.next()?.trim().parse().ok()?;
+    Some((
+        path,
+        Keyframe {
+            position: Duration::from_millis(millis),
+            gain_db,
+        },
+    ))
+}
+
+/// Applies the [`Envelope`]'s gain, and the track's, to its input.
+pub struct EnvelopeSource<I> {
+    input: I,
+    envelope: Arc<Envelope>,
+    /// For the position in the track.
+    resampler: Arc<Resampler>,
+    looper: Arc<Looper>,
+    /// Copy of the keyframes, and the envelope version it's of.
+    keyframes: Vec<Keyframe>,
+    version: Option<usize>,
+    gain: f32,
+    /// Samples of the current frame handed out so far.
+    sample: u16,
+}
+
+impl<I> EnvelopeSource<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(
+        input: I,
+        envelope: Arc<Envelope>,
+        resampler: Arc<Resampler>,
+        looper: Arc<Looper>,
+    ) -> Self {
+        Self {
+            input,
+            envelope,
+            resampler,
+            looper,
+            keyframes: Vec::new(),
+            version: None,
+            gain: 1.0,
+            sample: 0,
+        }
+    }
+
+    fn start_frame(&mut self) {
+        let starting = self.version.is_none();
+        let version = self.envelope.version.load(Ordering::Acquire);
+        if self.version != Some(version) {
+            self.version = Some(version);
+            self.keyframes = self.envelope.keyframes();
+        }
+        let mut gain_db = self.envelope.offset();
+        if !self.keyframes.is_empty() {
+            let position = self
+                .resampler
+                .position()
+                .saturating_sub(self.looper.shift())
+                .as_secs_f64();
+            gain_db += gain_at(&self.keyframes, position);
+        }
+        let target = if gain_db == 0.0 {
+            1.0
+        } else {
+            10f32.powf(gain_db / 20.0)
+        };
+        if starting {
+            self.gain = target;
+            return;
+        }
+        let rate = self.input.sample_rate().max(1) as f64;
+        let coefficient = (1.0 - (-1.0 / (SMOOTHING * rate)).exp()) as f32;
+        self.gain += (target - self.gain) * coefficient;
+    }
+}
+
+impl<I> Iterator for EnvelopeSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample == 0 {
+            self.start_frame();
+        }
+        let sample = self.input.next()?;
+        self.sample += 1;
+        if self.sample >= self.input.channels().max(1) {
+            self.sample = 0;
+        }
+        Some(sample * self.gain)
+    }
+}
+
+impl<I> Source for EnvelopeSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.sample = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+//! A parametric equalizer: a few peaking filters, each with its own center
+//! frequency, gain and width, that can be adjusted while playing.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
+
+pub const BANDS: usize = 3;
+pub const MAX_GAIN_DB: f32 = 12.0;
+pub const FREQUENCY_RANGE: RangeInclusive<f32> = 20.0..=20000.0;
+pub const Q_RANGE: RangeInclusive<f32> = 0.3..=10.0;
+
+/// Flat bands spread over the bass, mids and treble.
+pub const DEFAULT_BANDS: [Band; BANDS] =
+    [Band::flat(100.0), Band::flat(1000.0), Band::flat(8000.0)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub frequency: f32,
+    pub gain_db: f32,
+    /// How narrow the band is: its center frequency over its width.
+    pub q: f32,
+}
+
+impl Band {
+    const fn flat(frequency: f32) -> Self {
+        Self {
+            frequency,
+            gain_db: 0.0,
+            q: 1.0,
+        }
+    }
+
+    /// The band brought within the ranges the equalizer allows.
+    fn clamped(self) -> Self {
+        Self {
+            frequency: self
+                .frequency
+                .clamp(*FREQUENCY_RANGE.start(), *FREQUENCY_RANGE.end()),
+            gain_db: self.gain_db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB),
+            q: self.q.clamp(*Q_RANGE.start(), *Q_RANGE.end()),
+        }
+    }
+
+    /// Peaking filter coefficients from the Audio EQ Cookbook, normalized
+    /// so the leading feedback coefficient is 1.
+    fn coefficients(&self, sample_rate: u32) -> Coefficients {
+        // A band can't sit at or above the Nyquist frequency.
+        let frequency = self.frequency.min(sample_rate as f32 * 0.45);
+        let amplitude = 10f32.powf(self.gain_db / 40.0);
+        let omega = 2.0 * PI * frequency / sample_rate as f32;
+        let alpha = omega.sin() / (2.0 * self.q);
+        let cos = omega.cos();
+        let a0 = 1.0 + alpha / amplitude;
+        Coefficients {
+            b: [
+                (1.0 + alpha * amplitude) / a0,
+                -2.0 * cos / a0,
+                (1.0 - alpha * amplitude) / a0,
+            ],
+            a: [-2.0 * cos / a0, (1.0 - alpha / amplitude) / a0],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Coefficients {
+    b: [f32; 3],
+    a: [f32; 2],
+}
+
+impl Coefficients {
+    /// Gain in dB the filter applies at `frequency`.
+    fn response_db(&self, frequency: f32, sample_rate: u32) -> f32 {
+        let omega = 2.0 * PI * frequency / sample_rate as f32;
+        // H(z) at z = e^(jω), with the powers of z^-1 as (cos nω, -sin nω).
+        let evaluate = |c: [f32; 3]| {
+            let re = c[0] + c[1] * omega.cos() + c[2] * (2.0 * omega).cos();
+            let im = -c[1] * omega.sin() - c[2] * (2.0 * omega).sin();
+            re.hypot(im)
+        };
+        let numerator = evaluate(self.b);
+        let denominator = evaluate([1.0, self.a[0], self.a[1]]);
+        20.0 * (numerator / denominator).max(1e-9).log10()
+    }
+}
+
+/// Band settings shared between the UI adjusting them and the audio thread
+/// applying them.
+pub struct Equalizer {
+    /// Frequency, gain and Q of each band, as bit patterns.
+    bands: [[AtomicU32; 3]; BANDS],
+    /// Bumped on every change, so the filters know to update.
+    version: AtomicUsize,
+}
+
+impl Equalizer {
+    pub fn new() -> Arc<Self> {
+        let equalizer = Self {
+            bands: std::array::from_fn(|_| std::array::from_fn(|_| AtomicU32::new(0))),
+            version: AtomicUsize::new(0),
+        };
+        equalizer.set_bands(DEFAULT_BANDS);
+        Arc::new(equalizer)
+    }
+
+    pub fn bands(&self) -> [Band; BANDS] {
+        std::array::from_fn(|index| {
+            let [frequency, gain_db, q] = self.bands[index]
+                .each_ref()
+                .map(|value| f32::from_bits(value.load(Ordering::Relaxed)));
+            Band {
+                frequency,
+                gain_db,
+                q,
+            }
+        })
+    }
+
+    pub fn set_band(&self, index: usize, band: Band) {
+        let band = band.clamped();
+        let values = [band.frequency, band.gain_db, band.q];
+        for (slot, value) in self.bands[index].iter().zip(values) {
+            slot.store(value.to_bits(), Ordering::Relaxed);
+        }
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn set_bands(&self, bands: [Band; BANDS]) {
+        for (index, band) in bands.into_iter().enumerate() {
+            self.set_band(index, band);
+        }
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.bands().iter().all(|band| band.gain_db == 0.0)
+    }
+
+    /// Gain in dB of all the bands together at `frequency`.
+    pub fn response_db(&self, frequency: f32, sample_rate: u32) -> f32 {
+        self.bands()
+            .iter()
+            .map(|band| {
+                band.coefficients(sample_rate)
+                    .response_db(frequency, sample_rate)
+            })
+            .sum()
+    }
+}
+
+/// Reads bands written as `freq:gain[:q]`, comma separated, e.g.
+/// `100:+3,8000:-2:0.7`. Bands left out stay flat.
+pub fn parse(value: &str) -> Option<[Band; BANDS]> {
+    let mut bands = DEFAULT_BANDS;
+    let entries: Vec<&str> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect();
+    if entries.len() > BANDS {
+        return None;
+    }
+    for (band, entry) in bands.iter_mut().zip(entries) {
+        let mut parts = entry.split(':').map(|part| part.trim().parse::<f32>());
+        band.frequency = parts.next()?.ok()?;
+        band.gain_db = parts.next()?.ok()?;
+        if let Some(q) = parts.next() {
+            band.q = q.ok()?;
+        }
+        let finite = [band.frequency, band.gain_db, band.q]
+            .iter()
+            .all(|value| value.is_finite());
+        if parts.next().is_some() || !finite {
+            return None;
+        }
+        *band = band.clamped();
+    }
+    Some(bands)
+}
+
+/// Writes bands the way [`parse`] reads them.
+pub fn format(bands: &[Band; BANDS]) -> String {
+    bands
+        .iter()
+        .map(|band| format!("{:.0}:{:+.1}:{:.2}", band.frequency, band.gain_db, band.q))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Applies an [`Equalizer`], passing samples straight through while it's
+/// flat.
+pub struct EqSource<I> {
+    input: I,
+    equalizer: Arc<Equalizer>,
+    /// Equalizer version the coefficients were worked out for.
+    version: Option<usize>,
+    flat: bool,
+    coefficients: [Coefficients; BANDS],
+    /// Last two inputs and outputs of each band, for each channel.
+    history: Vec<[[f32; 4]; BANDS]>,
+    channel: usize,
+}
+
+impl<I> EqSource<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(input: I, equalizer: Arc<Equalizer>) -> Self {
+        Self {
+            input,
+            equalizer,
+            version: None,
+            flat: true,
+            coefficients: [Coefficients::default(); BANDS],
+            history: Vec::new(),
+            channel: 0,
+        }
+    }
+
+    fn update(&mut self) {
+        let version = self.equalizer.version.load(Ordering::Acquire);
+        if self.version == Some(version) {
+            return;
+        }
+        self.version = Some(version);
+        let bands = self.equalizer.bands();
+        let sample_rate = self.input.sample_rate().max(1);
+        self.flat = bands.iter().all(|band| band.gain_db == 0.0);
+        self.coefficients = bands.map(|band| band.coefficients(sample_rate));
+    }
+}
+
+impl<I> Iterator for EqSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let channels = self.input.channels().max(1) as usize;
+        if self.channel == 0 {
+            self.update();
+        }
+        if self.history.len() != channels {
+            self.history = vec![[[0.0; 4]; BANDS]; channels];
+            self.channel = 0;
+        }
+
+        let mut sample = self.input.next()?;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % channels;
+        if self.flat {
+            return Some(sample);
+        }
+        for (c, state) in self.coefficients.iter().zip(&mut self.history[channel]) {
+            let [x1, x2, y1, y2] = *state;
+            let y = c.b[0] * sample + c.b[1] * x1 + c.b[2] * x2 - c.a[0] * y1 - c.a[1] * y2;
+            *state = [sample, x1, y, y1];
+            sample = y;
+        }
+        Some(sample)
+    }
+}
+
+impl<I> Source for EqSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.history.clear();
+        Ok(())
+    }
+}
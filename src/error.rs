@@ -0,0 +1,78 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rodio::decoder::DecoderError;
+
+#[derive(Debug)]
+pub enum ApzError {
+    /// A track couldn't be opened.
+    Open { path: PathBuf, source: io::Error },
+    /// A track was opened but isn't audio apz can decode.
+    Decode { path: PathBuf, source: DecoderError },
+    /// The audio output device couldn't be opened or used.
+    Device(String),
+    /// Terminal and other I/O.
+    Io(io::Error),
+}
+
+impl ApzError {
+    pub fn open(path: &Path, source: io::Error) -> Self {
+        Self::Open {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn decode(path: &Path, source: DecoderError) -> Self {
+        Self::Decode {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for ApzError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn name(path: &Path) -> std::borrow::Cow<'_, str> {
+            path.file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+        }
+
+        match self {
+            Self::Open { path, source } => write!(f, "Can't open {}: {}", name(path), source),
+            Self::Decode { path, source } => write!(f, "Can't decode {}: {}", name(path), source),
+            Self::Device(message) => write!(f, "Audio device error: {}", message),
+            Self::Io(source) => write!(f, "{}", source),
+        }
+    }
+}
+
+impl std::error::Error for ApzError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Open { source, .. } | Self::Io(source) => Some(source),
+            Self::Decode { source, .. } => Some(source),
+            Self::Device(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ApzError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<rodio::StreamError> for ApzError {
+    fn from(error: rodio::StreamError) -> Self {
+        Self::Device(error.to_string())
+    }
+}
+
+impl From<rodio::PlayError> for ApzError {
+    fn from(error: rodio::PlayError) -> Self {
+        Self::Device(error.to_string())
+    }
+}
@@ -0,0 +1,44 @@
+//! Everything the main loop reacts to arrives on one channel, so it can
+//! sleep until there's something to do: terminal input from a thread of its
+//! own, and commands from the remote controls, each running on theirs.
+
+use crossterm::event;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+
+use crate::command::Command;
+
+pub enum Event {
+    Input(event::Event),
+    Command(Command),
+}
+
+/// Reads key presses and mouse movement as they happen. If the terminal
+/// stops delivering input there's no way left to control apz from it, so
+/// that's treated as a request to quit.
+pub fn read_input(events: Sender<Event>) {
+    thread::spawn(move || {
+        loop {
+            let event = match event::read() {
+                Ok(event) => Event::Input(event),
+                Err(_) => Event::Command(Command::Quit),
+            };
+            let quit = matches!(event, Event::Command(Command::Quit));
+            if events.send(event).is_err() || quit {
+                break;
+            }
+        }
+    });
+}
+
+/// Passes on commands from the remote controls, which only know about
+/// [`Command`]s.
+pub fn forward_commands(commands: Receiver<Command>, events: Sender<Event>) {
+    thread::spawn(move || {
+        for command in commands {
+            if events.send(Event::Command(command)).is_err() {
+                break;
+            }
+        }
+    });
+}
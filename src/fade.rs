@@ -0,0 +1,170 @@
+use rodio::source::SeekError;
+use rodio::{Sample, Source};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Which way the gain of a [`FadeSource`] is heading, set from the player.
+///
+/// Pausing and seeking wait for a fade out on a thread of their own, so
+/// each takes a numbered turn; one that's been overtaken by the time the
+/// fade is done is dropped.
+pub struct Fade {
+    audible: AtomicBool,
+    turns: AtomicU64,
+    seeks: AtomicU64,
+    /// Holds the gain at silence until the latest seek has been made.
+    seeking: AtomicBool,
+    /// Where that seek is headed, in seconds as `f64` bits.
+    target: AtomicU64,
+}
+
+impl Fade {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            audible: AtomicBool::new(true),
+            turns: AtomicU64::new(0),
+            seeks: AtomicU64::new(0),
+            seeking: AtomicBool::new(false),
+            target: AtomicU64::new(0),
+        })
+    }
+
+    /// Fades in, unless a seek is waiting, which fades in once it's made.
+    pub fn fade_in(&self) {
+        self.turns.fetch_add(1, Ordering::Relaxed);
+        if !self.seeking.load(Ordering::Relaxed) {
+            self.audible.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Fades out, returning the turn [`Fade::is_turn`] checks for.
+    pub fn fade_out(&self) -> u64 {
+        self.audible.store(false, Ordering::Relaxed);
+        self.turns.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Whether nothing has faded in or out since `turn`.
+    pub fn is_turn(&self, turn: u64) -> bool {
+        self.turns.load(Ordering::Relaxed) == turn
+    }
+
+    /// Fades out ahead of a seek to `position`, returning the one
+    /// [`Fade::is_seek`] checks for.
+    pub fn start_seek(&self, position: Duration) -> u64 {
+        self.target
+            .store(position.as_secs_f64().to_bits(), Ordering::Relaxed);
+        self.seeking.store(true, Ordering::Relaxed);
+        self.audible.store(false, Ordering::Relaxed);
+        self.seeks.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Where a seek still waiting on its fade is headed.
+    pub fn pending(&self) -> Option<Duration> {
+        self.seeking
+            .load(Ordering::Relaxed)
+            .then(|| Duration::from_secs_f64(f64::from_bits(self.target.load(Ordering::Relaxed))))
+    }
+
+    /// Whether `seek` is the latest one, and hasn't been cancelled.
+    pub fn is_seek(&self, seek: u64) -> bool {
+        self.seeks.load(Ordering::Relaxed) == seek
+    }
+
+    /// Lets the gain rise again once a seek has been made, fading in if
+    /// playing.
+    pub fn end_seek(&self, playing: bool) {
+        self.seeking.store(false, Ordering::Relaxed);
+        if playing {
+            self.audible.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Drops a seek still waiting on its fade.
+    pub fn cancel_seek(&self) {
+        self.seeks.fetch_add(1, Ordering::Relaxed);
+        self.seeking.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Ramps the gain linearly towards full or silent over `ramp`, so that
+/// starting and stopping mid-track doesn't click.
+pub struct FadeSource<I> {
+    input: I,
+    fade: Arc<Fade>,
+    gain: f32,
+    /// Gain change per sample.
+    step: f32,
+}
+
+impl<I> FadeSource<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    pub fn new(input: I, fade: Arc<Fade>, ramp: Duration) -> Self {
+        let samples = ramp.as_secs_f32() * input.sample_rate() as f32 * input.channels() as f32;
+        Self {
+            input,
+            fade,
+            gain: 1.0,
+            step: if samples >= 1.0 { samples.recip() } else { 1.0 },
+        }
+    }
+}
+
+impl<I> Iterator for FadeSource<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let target = if self.fade.audible.load(Ordering::Relaxed) {
+            1.0
+        } else {
+            0.0
+        };
+        if self.gain < target {
+            self.gain = (self.gain + self.step).min(target);
+        } else if self.gain > target {
+            self.gain = (self.gain - self.step).max(target);
+        }
+
+        let sample = self.input.next()?;
+        Some(if self.gain >= 1.0 {
+            sample
+        } else {
+            sample.amplify(self.gain)
+        })
+    }
+}
+
+impl<I> Source for FadeSource<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.gain = 0.0;
+        Ok(())
+    }
+}
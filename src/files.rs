@@ -0,0 +1,155 @@
+//! Moving the current track out of the way while triaging: to the trash, or
+//! into a "keep" or "reject" directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::paths;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileAction {
+    Trash,
+    MoveTo(PathBuf),
+}
+
+impl FileAction {
+    /// Where the file goes, for asking before doing it.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Trash => "the trash".to_string(),
+            Self::MoveTo(dir) => dir.display().to_string(),
+        }
+    }
+}
+
+/// Performs `action` on `path`, returning where the file ended up.
+pub fn apply(action: &FileAction, path: &Path) -> io::Result<PathBuf> {
+    match action {
+        FileAction::Trash => trash(path),
+        FileAction::MoveTo(dir) => {
+            fs::create_dir_all(dir)?;
+            let destination = unused_name(dir, path);
+            move_file(path, &destination)?;
+            Ok(destination)
+        }
+    }
+}
+
+/// The trash as the desktop sees it: `~/.Trash` on macOS, and elsewhere the
+/// freedesktop.org trash, with the info file that lets it be restored.
+fn trash(path: &Path) -> io::Result<PathBuf> {
+    if cfg!(target_os = "windows") {
+        return Err(io::Error::other(
+            "the Recycle Bin isn't supported, use --reject-dir instead",
+        ));
+    }
+
+    if cfg!(target_os = "macos") {
+        let trash = paths::home_dir()
+            .ok_or_else(|| io::Error::other("no home directory"))?
+            .join(".Trash");
+        let destination = unused_name(&trash, path);
+        move_file(path, &destination)?;
+        return Ok(destination);
+    }
+
+    let trash = paths::data_home()
+        .ok_or_else(|| io::Error::other("no home directory"))?
+        .join("Trash");
+    let files = trash.join("files");
+    let info = trash.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    let destination = unused_name(&files, path);
+    let name = destination
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let original = fs::canonicalize(path)?;
+    let info_file = info.join(format!("{}.trashinfo", name));
+    fs::write(
+        &info_file,
+        format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            original.display(),
+            local_timestamp()
+        ),
+    )?;
+    if let Err(e) = move_file(path, &destination) {
+        fs::remove_file(info_file).ok();
+        return Err(e);
+    }
+    Ok(destination)
+}
+
+/// `dir` joined with the file name of `path`, numbered if that's taken, as
+/// in `take (2).wav`.
+fn unused_name(dir: &Path, path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or(candidate)
+}
+
+/// Renames `from` to `to`, copying instead when they're on different file
+/// systems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    // Don't leave two copies behind if the original can't be removed.
+    fs::remove_file(from).inspect_err(|_| {
+        fs::remove_file(to).ok();
+    })
+}
+
+/// The current time as `YYYY-MM-DDThh:mm:ss`, which the trash spec wants.
+/// It should be local time, but UTC is as close as std gets.
+fn local_timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
@@ -0,0 +1,165 @@
+//! The demo-scene fire: heat rises from the bottom row, cooling and
+//! drifting at random as it goes, and the bass feeds it, so the flames leap
+//! on every kick and die down to embers in quiet passages. Each cell is two
+//! pixels of fire, one above the other.
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Color,
+    widgets::{Block, Borders},
+};
+use std::time::Instant;
+
+use crate::shuffle::Rng;
+use crate::theme::{self, Theme};
+use crate::visualizer::{Scene, Visualizer};
+
+/// The fire burns on the spectrum below this.
+const LOW_FREQUENCY: f32 = 250.0;
+/// Bar level below which there's no fuel, so the noise floor doesn't keep
+/// it alight.
+const GATE: f32 = 0.3;
+/// Times a second heat moves up a pixel, whatever the frame rate.
+const STEPS_PER_SECOND: f32 = 40.0;
+/// Steps made up in one frame at most, after a pause in drawing.
+const MAX_STEPS: usize = 8;
+/// Heat too low to show.
+const EMBER: f32 = 0.05;
+/// Colors from cooling embers to white heat, for true color terminals.
+const GRADIENT: [(f32, (u8, u8, u8)); 5] = [
+    (0.0, (60, 0, 0)),
+    (0.35, (200, 24, 0)),
+    (0.6, (255, 120, 0)),
+    (0.85, (255, 215, 40)),
+    (1.0, (255, 255, 220)),
+];
+
+pub struct Fire {
+    /// Heat of every pixel, from 0.0 to 1.0, a row at a time from the top.
+    heat: Vec<f32>,
+    size: (usize, usize),
+    rng: Rng,
+    last_frame: Option<Instant>,
+    /// Steps owed from earlier frames, as a fraction of one.
+    owed: f32,
+}
+
+impl Fire {
+    pub fn new() -> Self {
+        Self {
+            heat: Vec::new(),
+            size: (0, 0),
+            rng: Rng::seeded(),
+            last_frame: None,
+            owed: 0.0,
+        }
+    }
+
+    /// Feeds the bottom row with `fuel`, from 0.0 to 1.0, and moves the
+    /// rest of the heat up a pixel.
+    fn step(&mut self, fuel: f32) {
+        let (width, height) = self.size;
+        // More fuel means slower cooling as well as more heat, so loud
+        // flames reach higher than quiet ones, right to the top.
+        let cooling = 2.0 / (height as f32 * (0.25 + 0.75 * fuel));
+        for y in 0..height - 1 {
+            for x in 0..width {
+                let random = self.rng.next_u64();
+                let drift = (random % 3) as usize;
+                let from = (x + width + drift - 1) % width;
+                let decay = cooling * ((random >> 8) % 256) as f32 / 255.0;
+                self.heat[y * width + x] = (self.heat[(y + 1) * width + from] - decay).max(0.0);
+            }
+        }
+        let bottom = (height - 1) * width;
+        for x in 0..width {
+            let flicker = 0.6 + 0.4 * self.rng.next_f64() as f32;
+            self.heat[bottom + x] = fuel * flicker;
+        }
+    }
+}
+
+impl Visualizer for Fire {
+    fn name(&self) -> &'static str {
+        "fire"
+    }
+
+    fn render(&mut self, frame: &mut Frame, area: Rect, scene: &Scene) {
+        let block = Block::default().borders(Borders::ALL).title("Fire");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let size = (inner.width as usize, inner.height as usize * 2);
+        if size != self.size {
+            self.size = size;
+            self.heat = vec![0.0; size.0 * size.1];
+        }
+        if self.heat.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        let elapsed = self
+            .last_frame
+            .map_or(0.0, |last| (now - last).as_secs_f32());
+        self.last_frame = Some(now);
+        self.owed += elapsed * STEPS_PER_SECOND;
+        let steps = (self.owed as usize).min(MAX_STEPS);
+        self.owed = (self.owed - steps as f32).min(1.0);
+        let fuel = if scene.active { fuel(scene) } else { 0.0 };
+        for _ in 0..steps {
+            self.step(fuel);
+        }
+
+        let width = size.0;
+        for row in 0..inner.height as usize {
+            for x in 0..width {
+                let top = self.heat[row * 2 * width + x];
+                let bottom = self.heat[(row * 2 + 1) * width + x];
+                let (symbol, fg, bg) = match (top >= EMBER, bottom >= EMBER) {
+                    (false, false) => continue,
+                    (true, true) => ("▀", top, Some(bottom)),
+                    (true, false) => ("▀", top, None),
+                    (false, true) => ("▄", bottom, None),
+                };
+                let cell = &mut frame.buffer_mut()[(inner.x + x as u16, inner.y + row as u16)];
+                cell.set_symbol(symbol);
+                cell.set_fg(heat_color(scene.theme, fg));
+                if let Some(bg) = bg {
+                    cell.set_bg(heat_color(scene.theme, bg));
+                }
+            }
+        }
+    }
+}
+
+/// How loud the bass is, from 0.0 to 1.0. The loudest bar counts rather
+/// than the average, so a bass line a few bars wide burns as high as a
+/// broad rumble.
+fn fuel(scene: &Scene) -> f32 {
+    let split = scene.spectrum.position_of(LOW_FREQUENCY).unwrap_or(0.25);
+    let low = (scene.bars.len() as f32 * split).ceil() as usize;
+    let level = scene.bars.iter().take(low).copied().fold(0.0, f32::max);
+    ((level - GATE) / (1.0 - GATE)).clamp(0.0, 1.0)
+}
+
+fn heat_color(theme: &Theme, heat: f32) -> Color {
+    if !theme.truecolor {
+        return match heat {
+            heat if heat < 0.3 => Color::Red,
+            heat if heat < 0.55 => Color::LightRed,
+            heat if heat < 0.8 => Color::Yellow,
+            heat if heat < 0.95 => Color::LightYellow,
+            _ => Color::White,
+        };
+    }
+    let rgb = |(r, g, b)| Color::Rgb(r, g, b);
+    let next = GRADIENT
+        .iter()
+        .position(|&(stop, _)| stop >= heat)
+        .unwrap_or(GRADIENT.len() - 1)
+        .max(1);
+    let (from, to) = (GRADIENT[next - 1], GRADIENT[next]);
+    theme::blend(rgb(from.1), rgb(to.1), (heat - from.0) / (to.0 - from.0))
+}
@@ -0,0 +1,306 @@
+//! Writes 16-bit FLAC files. Each channel of each block is coded with
+//! whichever of FLAC's fixed polynomial predictors leaves the smallest
+//! residual, which gets most of the way to what a full encoder would
+//! manage on typical material.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+const BLOCK_SIZE: usize = 4096;
+const BITS_PER_SAMPLE: u32 = 16;
+/// Where the total sample count sits in the file, within STREAMINFO.
+const TOTAL_SAMPLES_OFFSET: u64 = 4 + 4 + 13;
+const MAX_RICE_PARAMETER: u32 = 14;
+
+pub struct FlacWriter {
+    file: BufWriter<File>,
+    channels: usize,
+    /// Samples of the block being collected, one list per channel.
+    block: Vec<Vec<i32>>,
+    /// Channel the next sample belongs to.
+    pending: usize,
+    /// Complete frames written so far: one sample from every channel.
+    frames: u64,
+    blocks_written: u64,
+}
+
+impl FlacWriter {
+    pub fn new(file: File, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        if !(1..=8).contains(&channels) {
+            return Err(io::Error::other("FLAC supports 1 to 8 channels"));
+        }
+        let mut file = BufWriter::new(file);
+        file.write_all(b"fLaC")?;
+
+        // STREAMINFO, the only metadata block, so also the last. Frame sizes
+        // and the MD5 signature are left as unknown, which is allowed.
+        let mut info = BitWriter::default();
+        info.write(BLOCK_SIZE as u64, 16);
+        info.write(BLOCK_SIZE as u64, 16);
+        info.write(0, 24);
+        info.write(0, 24);
+        info.write(sample_rate as u64, 20);
+        info.write(channels as u64 - 1, 3);
+        info.write(BITS_PER_SAMPLE as u64 - 1, 5);
+        info.write(0, 36);
+        let mut info = info.finish();
+        info.extend_from_slice(&[0; 16]);
+
+        file.write_all(&[0x80])?;
+        file.write_all(&(info.len() as u32).to_be_bytes()[1..])?;
+        file.write_all(&info)?;
+
+        Ok(Self {
+            file,
+            channels: channels as usize,
+            block: vec![Vec::with_capacity(BLOCK_SIZE); channels as usize],
+            pending: 0,
+            frames: 0,
+            blocks_written: 0,
+        })
+    }
+
+    /// Adds the next sample, channels interleaved as they come from a
+    /// decoder.
+    pub fn write(&mut self, sample: i16) -> io::Result<()> {
+        self.block[self.pending].push(sample as i32);
+        self.pending = (self.pending + 1) % self.channels;
+        if self.pending == 0 {
+            self.frames += 1;
+            if self.block[0].len() == BLOCK_SIZE {
+                self.write_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        // A partly written frame is dropped.
+        for channel in &mut self.block {
+            channel.truncate(self.frames as usize % BLOCK_SIZE);
+        }
+        if !self.block[0].is_empty() {
+            self.write_block()?;
+        }
+
+        // The total is the low 36 bits of 5 bytes that start with the last
+        // 4 bits of the bits-per-sample field.
+        let bits_per_sample = (BITS_PER_SAMPLE as u64 - 1) & 0x0f;
+        let packed = (bits_per_sample << 36) | (self.frames & 0xf_ffff_ffff);
+        self.file.seek(SeekFrom::Start(TOTAL_SAMPLES_OFFSET))?;
+        self.file.write_all(&packed.to_be_bytes()[3..])?;
+        self.file.flush()
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let length = self.block[0].len();
+        let mut frame = BitWriter::default();
+
+        frame.write(0b11_1111_1111_1110, 14);
+        frame.write(0, 1);
+        // Fixed block size, so frames are numbered rather than samples.
+        frame.write(0, 1);
+        let size_code = if length == BLOCK_SIZE { 0b1100 } else { 0b0111 };
+        frame.write(size_code, 4);
+        // The sample rate is the one in STREAMINFO.
+        frame.write(0, 4);
+        frame.write(self.channels as u64 - 1, 4);
+        frame.write(0b100, 3);
+        frame.write(0, 1);
+        for byte in utf8_number(self.blocks_written) {
+            frame.write(byte as u64, 8);
+        }
+        if size_code == 0b0111 {
+            frame.write(length as u64 - 1, 16);
+        }
+        let crc = crc8(&frame.bytes);
+        frame.write(crc as u64, 8);
+
+        for channel in &self.block {
+            write_subframe(&mut frame, channel);
+        }
+        let mut bytes = frame.finish();
+        let crc = crc16(&bytes);
+        bytes.extend_from_slice(&crc.to_be_bytes());
+        self.file.write_all(&bytes)?;
+
+        for channel in &mut self.block {
+            channel.clear();
+        }
+        self.blocks_written += 1;
+        Ok(())
+    }
+}
+
+/// Residuals of FLAC's fixed predictor of the given order, which predicts
+/// each sample from the `order` before it.
+fn residuals(samples: &[i32], order: usize) -> Vec<i32> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |back: usize| samples[i - back];
+            let prediction = match order {
+                0 => 0,
+                1 => s(1),
+                2 => 2 * s(1) - s(2),
+                3 => 3 * s(1) - 3 * s(2) + s(3),
+                _ => 4 * s(1) - 6 * s(2) + 4 * s(3) - s(4),
+            };
+            samples[i] - prediction
+        })
+        .collect()
+}
+
+fn zigzag(residual: i32) -> u32 {
+    ((residual << 1) ^ (residual >> 31)) as u32
+}
+
+/// Roughly the Rice parameter that minimizes the coded size, from the mean
+/// of the folded residuals. It can be one too high, so the one below is
+/// tried as well.
+fn rice_parameter(residuals: &[i32]) -> u32 {
+    let sum: u64 = residuals.iter().map(|&r| zigzag(r) as u64).sum();
+    let mean = sum / residuals.len().max(1) as u64;
+    (64 - mean.leading_zeros()).min(MAX_RICE_PARAMETER)
+}
+
+fn rice_bits(residuals: &[i32], parameter: u32) -> u64 {
+    residuals
+        .iter()
+        .map(|&r| (zigzag(r) >> parameter) as u64 + 1 + parameter as u64)
+        .sum()
+}
+
+fn write_subframe(frame: &mut BitWriter, samples: &[i32]) {
+    let verbatim_bits = samples.len() as u64 * BITS_PER_SAMPLE as u64;
+    let best = (0..=4.min(samples.len()))
+        .map(|order| {
+            let residuals = residuals(samples, order);
+            let estimate = rice_parameter(&residuals);
+            let (bits, parameter) = [estimate, estimate.saturating_sub(1)]
+                .into_iter()
+                .map(|parameter| (rice_bits(&residuals, parameter), parameter))
+                .min()
+                .unwrap();
+            let bits = order as u64 * BITS_PER_SAMPLE as u64 + bits;
+            (bits, order, residuals, parameter)
+        })
+        .min_by_key(|(bits, ..)| *bits);
+
+    match best {
+        Some((bits, order, residuals, parameter)) if bits < verbatim_bits => {
+            frame.write(0, 1);
+            frame.write(0b001000 | order as u64, 6);
+            frame.write(0, 1);
+            for &sample in &samples[..order] {
+                frame.write_signed(sample, BITS_PER_SAMPLE);
+            }
+            // Rice coding with 4-bit parameters, in a single partition.
+            frame.write(0, 2);
+            frame.write(0, 4);
+            frame.write(parameter as u64, 4);
+            for residual in residuals {
+                let folded = zigzag(residual);
+                frame.write_unary(folded >> parameter);
+                frame.write((folded & ((1 << parameter) - 1)) as u64, parameter);
+            }
+        }
+        _ => {
+            frame.write(0, 1);
+            frame.write(0b000001, 6);
+            frame.write(0, 1);
+            for &sample in samples {
+                frame.write_signed(sample, BITS_PER_SAMPLE);
+            }
+        }
+    }
+}
+
+/// FLAC's variant of UTF-8 for frame numbers, which allows up to 36 bits.
+fn utf8_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    let mut continuation = Vec::new();
+    let mut rest = value;
+    // Each continuation byte holds 6 bits; the first byte holds whatever
+    // fits after a marker of one bit per byte in the sequence.
+    while rest >= 1 << (6 - continuation.len()) {
+        continuation.push(0x80 | (rest & 0x3f) as u8);
+        rest >>= 6;
+    }
+    let count = continuation.len() + 1;
+    let marker = !(0xffu8 >> count);
+    let mut bytes = vec![marker | rest as u8];
+    bytes.extend(continuation.into_iter().rev());
+    bytes
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Packs values most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for bit in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> bit) & 1) as u8;
+            self.used += 1;
+            if self.used == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.used = 0;
+            }
+        }
+    }
+
+    fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write(value as u64 & ((1 << bits) - 1), bits);
+    }
+
+    /// `value` zeros then a one.
+    fn write_unary(&mut self, value: u32) {
+        for _ in 0..value {
+            self.write(0, 1);
+        }
+        self.write(1, 1);
+    }
+
+    /// The bytes written, the last padded with zeros.
+    fn finish(mut self) -> Vec<u8> {
+        if self.used > 0 {
+            self.bytes.push(self.current << (8 - self.used));
+        }
+        self.bytes
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::paths;
+
+const HISTORY_FILE: &str = "history.tsv";
+/// The oldest plays are forgotten once there are more than this.
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct Play {
+    pub path: PathBuf,
+    pub played_at: SystemTime,
+    /// How much of the track was heard, from 0.0 to 1.0.
+    pub completion: f32,
+}
+
+/// Appends a play of `path` to the history, persisted across sessions in
+/// `history.tsv` (`unix-seconds<TAB>percent<TAB>path` per line).
+pub fn record(path: &Path, completion: f32) {
+    let Some(store) = store_path() else {
+        return;
+    };
+
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let percent = (completion.clamp(0.0, 1.0) * 100.0).round();
+
+    let contents = fs::read_to_string(&store).unwrap_or_default();
+    let mut lines: Vec<&str> = contents.lines().collect();
+    let line = format!("{}\t{}\t{}", seconds, percent, path.display());
+    lines.push(&line);
+    let keep = lines.len().saturating_sub(MAX_ENTRIES);
+
+    if let Some(dir) = store.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    let contents: String = lines[keep..]
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect();
+    fs::write(store, contents).ok();
+}
+
+/// The last `limit` tracks played, newest first, each listed once.
+pub fn recent(limit: usize) -> Vec<Play> {
+    let contents = store_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default();
+
+    let mut plays: Vec<Play> = Vec::new();
+    for play in contents.lines().rev().filter_map(parse_line) {
+        if plays.len() == limit {
+            break;
+        }
+        if !plays.iter().any(|p| p.path == play.path) {
+            plays.push(play);
+        }
+    }
+    plays
+}
+
+fn store_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(HISTORY_FILE))
+}
+
+fn parse_line(line: &str) -> Option<Play> {
+    let mut fields = line.splitn(3, '\t');
+    let seconds = fields.next()?.parse().ok()?;
+    let percent: f32 = fields.next()?.parse().ok()?;
+    let path = PathBuf::from(fields.next()?);
+
+    Some(Play {
+        path,
+        played_at: UNIX_EPOCH + Duration::from_secs(seconds),
+        completion: percent / 100.0,
+    })
+}
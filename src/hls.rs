@@ -0,0 +1,664 @@
+//! HLS: audio published over HTTP as a playlist of short segments, as live
+//! radio and many archived broadcasts are. Segments are fetched in the
+//! background, unpacked from MPEG-TS when they come in it, and decoded as
+//! one continuous stream.
+
+use rodio::Source;
+use rodio::decoder::DecoderError;
+use rodio::source::SeekError;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{self, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::decoder::AudioSource;
+use crate::download;
+use crate::error::ApzError;
+
+/// Bytes fetched ahead of playback before fetching waits for it to catch
+/// up, so an archived broadcast isn't held in memory whole.
+const MAX_BUFFERED: usize = 4 << 20;
+/// Live streams start this many segments back from the newest, as the
+/// spec recommends, to have something in hand if the next one is late.
+const LIVE_START_SEGMENTS: usize = 3;
+/// Attempts at fetching a segment or playlist before giving up.
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+const TS_PACKET_LEN: usize = 188;
+const TS_SYNC: u8 = 0x47;
+
+/// Whether `path` is the URL of an HLS playlist.
+pub fn is_playlist(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    let without_query = path.split(['?', '#']).next().unwrap_or_default();
+    download::is_url(&path) && without_query.to_ascii_lowercase().ends_with(".m3u8")
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    url: String,
+    duration: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct Playlist {
+    url: String,
+    /// Longest a segment lasts, which is how often a live playlist changes.
+    target_duration: Duration,
+    /// Number of the first segment listed, counting from the start of the
+    /// stream.
+    media_sequence: u64,
+    segments: Vec<Segment>,
+    /// Header every segment depends on, from `#EXT-X-MAP`.
+    map: Option<String>,
+    /// Set by `#EXT-X-ENDLIST` once no more segments will be added, as for
+    /// an archived broadcast.
+    ended: bool,
+}
+
+impl Playlist {
+    /// Fetches the media playlist at `url`. A master playlist is followed
+    /// to its highest bitrate variant.
+    fn fetch(url: &str) -> Result<Self, String> {
+        let text = download::fetch(url)?;
+        if !text.trim_start().starts_with("#EXTM3U") {
+            return Err("not an HLS playlist".to_string());
+        }
+        match best_variant(url, &text) {
+            Some(variant) => Self::parse(&variant, &download::fetch(&variant)?),
+            None => Self::parse(url, &text),
+        }
+    }
+
+    fn parse(url: &str, text: &str) -> Result<Self, String> {
+        let mut playlist = Self {
+            url: url.to_string(),
+            target_duration: Duration::from_secs(6),
+            media_sequence: 0,
+            segments: Vec::new(),
+            map: None,
+            ended: false,
+        };
+        let mut duration = Duration::ZERO;
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let Some(tag) = line.strip_prefix('#') else {
+                playlist.segments.push(Segment {
+                    url: resolve(url, line),
+                    duration: std::mem::take(&mut duration),
+                });
+                continue;
+            };
+            let (name, value) = tag.split_once(':').unwrap_or((tag, ""));
+            match name {
+                "EXT-X-TARGETDURATION" => playlist.target_duration = seconds(value),
+                "EXT-X-MEDIA-SEQUENCE" => playlist.media_sequence = value.parse().unwrap_or(0),
+                "EXTINF" => duration = seconds(value.split(',').next().unwrap_or_default()),
+                "EXT-X-ENDLIST" => playlist.ended = true,
+                "EXT-X-MAP" => playlist.map = attribute(value, "URI").map(|uri| resolve(url, &uri)),
+                "EXT-X-KEY"
+                    if attribute(value, "METHOD").is_some_and(|method| method != "NONE") =>
+                {
+                    return Err("encrypted streams aren't supported".to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(playlist)
+    }
+
+    fn duration(&self) -> Duration {
+        self.segments.iter().map(|segment| segment.duration).sum()
+    }
+}
+
+fn seconds(value: &str) -> Duration {
+    Duration::try_from_secs_f64(value.trim().parse().unwrap_or(0.0)).unwrap_or_default()
+}
+
+/// URL of the variant with the highest `BANDWIDTH` in a master playlist.
+fn best_variant(url: &str, text: &str) -> Option<String> {
+    let mut best: Option<(u64, &str)> = None;
+    let mut bandwidth = None;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            bandwidth = Some(
+                attribute(attributes, "BANDWIDTH")
+                    .and_then(|bandwidth| bandwidth.parse().ok())
+                    .unwrap_or(0),
+            );
+        } else if !line.starts_with('#')
+            && let Some(bandwidth) = bandwidth.take()
+            && best.is_none_or(|(best, _)| bandwidth > best)
+        {
+            best = Some((bandwidth, line));
+        }
+    }
+    best.map(|(_, uri)| resolve(url, uri))
+}
+
+/// Value of `name` in an attribute list like `METHOD=NONE,URI="a,b.ts"`.
+fn attribute(list: &str, name: &str) -> Option<String> {
+    let mut rest = list;
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, after) = quoted.split_once('"')?;
+                (value, after.split_once(',').map_or("", |(_, next)| next))
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        if key.trim() == name {
+            return Some(value.to_string());
+        }
+        rest = next;
+    }
+    None
+}
+
+/// Resolves `uri` against the URL of the playlist it appears in.
+fn resolve(base: &str, uri: &str) -> String {
+    if download::is_url(uri) {
+        return uri.to_string();
+    }
+    let base = base.split(['?', '#']).next().unwrap_or_default();
+    if uri.starts_with('/') {
+        let host_end = base
+            .find("://")
+            .and_then(|scheme| base[scheme + 3..].find('/').map(|end| scheme + 3 + end))
+            .unwrap_or(base.len());
+        return format!("{}{}", &base[..host_end], uri);
+    }
+    let dir = base.rfind('/').map_or(base, |end| &base[..=end]);
+    format!("{}{}", dir, uri)
+}
+
+/// Pulls the audio out of MPEG-TS segments: finds the audio stream through
+/// the program tables and strips the packet and PES headers around it.
+#[derive(Default)]
+struct TsDemuxer {
+    pmt_pid: Option<u16>,
+    audio_pid: Option<u16>,
+}
+
+impl TsDemuxer {
+    fn unpack(&mut self, segment: &[u8], out: &mut Vec<u8>) {
+        for packet in segment.chunks_exact(TS_PACKET_LEN) {
+            if packet[0] != TS_SYNC {
+                continue;
+            }
+            let unit_start = packet[1] & 0x40 != 0;
+            let pid = u16::from_be_bytes([packet[1] & 0x1f, packet[2]]);
+            let adaptation = (packet[3] >> 4) & 0x3;
+            if adaptation & 0x1 == 0 {
+                continue;
+            }
+            let start = if adaptation & 0x2 != 0 {
+                5 + packet[4] as usize
+            } else {
+                4
+            };
+            let Some(payload) = packet.get(start..) else {
+                continue;
+            };
+
+            if pid == 0 && unit_start {
+                self.pmt_pid = program_map_pid(payload);
+            } else if Some(pid) == self.pmt_pid && unit_start {
+                self.audio_pid = audio_pid(payload);
+            } else if Some(pid) == self.audio_pid {
+                let payload = if unit_start {
+                    // The PES header: start code, stream id, length, two
+                    // bytes of flags, then the rest of the header's length.
+                    let Some(&header_len) = payload.get(8) else {
+                        continue;
+                    };
+                    payload.get(9 + header_len as usize..).unwrap_or_default()
+                } else {
+                    payload
+                };
+                out.extend_from_slice(payload);
+            }
+        }
+    }
+}
+
+/// The PSI section a table payload starts, after its pointer field.
+fn section(payload: &[u8]) -> Option<&[u8]> {
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    let length = (u16::from_be_bytes([*section.get(1)? & 0x0f, *section.get(2)?])) as usize;
+    // Up to the CRC at the end.
+    section.get(..(3 + length).checked_sub(4)?)
+}
+
+/// The PID of the first program's map, from the program association table.
+fn program_map_pid(payload: &[u8]) -> Option<u16> {
+    section(payload)?
+        .get(8..)?
+        .chunks_exact(4)
+        .find(|program| program[0..2] != [0, 0])
+        .map(|program| u16::from_be_bytes([program[2] & 0x1f, program[3]]))
+}
+
+/// The PID of the first AAC or MP3 stream in a program map table.
+fn audio_pid(payload: &[u8]) -> Option<u16> {
+    let section = section(payload)?;
+    let info_len = u16::from_be_bytes([*section.get(10)? & 0x0f, *section.get(11)?]) as usize;
+    let mut streams = section.get(12 + info_len..)?;
+    while streams.len() >= 5 {
+        let stream_type = streams[0];
+        let pid = u16::from_be_bytes([streams[1] & 0x1f, streams[2]]);
+        let es_info_len = u16::from_be_bytes([streams[3] & 0x0f, streams[4]]) as usize;
+        // ADTS AAC, MPEG-1 and MPEG-2 audio.
+        if matches!(stream_type, 0x0f | 0x03 | 0x04) {
+            return Some(pid);
+        }
+        streams = streams.get(5 + es_info_len..)?;
+    }
+    None
+}
+
+/// Drops the ID3 tag packed audio segments start with, which would
+/// otherwise land in the middle of the stream.
+fn strip_id3(segment: &[u8]) -> &[u8] {
+    if segment.len() < 10 || &segment[..3] != b"ID3" {
+        return segment;
+    }
+    let size = segment[6..10]
+        .iter()
+        .fold(0usize, |size, &byte| (size << 7) | (byte & 0x7f) as usize);
+    let footer = if segment[5] & 0x10 != 0 { 10 } else { 0 };
+    segment.get(10 + size + footer..).unwrap_or_default()
+}
+
+fn is_transport_stream(segment: &[u8]) -> bool {
+    segment.first() == Some(&TS_SYNC)
+        && segment
+            .get(TS_PACKET_LEN)
+            .is_none_or(|&byte| byte == TS_SYNC)
+}
+
+#[derive(Default)]
+struct Buffer {
+    data: VecDeque<u8>,
+    /// The last segment has been fetched.
+    ended: bool,
+    error: Option<String>,
+    /// The reader has gone, so there's no point fetching more.
+    closed: bool,
+}
+
+/// Audio fetched but not yet read, handed from the fetching thread to the
+/// reader.
+#[derive(Default)]
+struct Shared {
+    buffer: Mutex<Buffer>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn update(&self, update: impl FnOnce(&mut Buffer)) {
+        update(&mut self.buffer.lock().unwrap());
+        self.changed.notify_all();
+    }
+
+    /// Waits until there's room for more. Returns false once the reader
+    /// has gone.
+    fn wait_for_room(&self) -> bool {
+        let buffer = self.buffer.lock().unwrap();
+        let buffer = self
+            .changed
+            .wait_while(buffer, |buffer| {
+                buffer.data.len() >= MAX_BUFFERED && !buffer.closed
+            })
+            .unwrap();
+        !buffer.closed
+    }
+
+    fn is_closed(&self) -> bool {
+        self.buffer.lock().unwrap().closed
+    }
+}
+
+fn with_retries<T>(mut attempt: impl FnMut() -> Result<T, String>) -> Result<T, String> {
+    let mut tries = 1;
+    loop {
+        match attempt() {
+            Err(_) if tries < ATTEMPTS => {
+                tries += 1;
+                thread::sleep(RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Fetches segments from `start` on, following a live playlist as new ones
+/// are added.
+fn fetch_segments(mut playlist: Playlist, start: usize, shared: Arc<Shared>) {
+    let mut demuxer = TsDemuxer::default();
+    let mut next = playlist.media_sequence + start as u64;
+    let result = (|| {
+        if let Some(map) = &playlist.map {
+            let header = with_retries(|| download::fetch_bytes(map))?;
+            shared.update(|buffer| buffer.data.extend(header));
+        }
+        loop {
+            // A live stream that got ahead of us picks up at the oldest
+            // segment still listed.
+            next = next.max(playlist.media_sequence);
+            let skip = (next - playlist.media_sequence) as usize;
+            let mut fetched = false;
+            for segment in playlist.segments.iter().skip(skip) {
+                if !shared.wait_for_room() {
+                    return Ok(());
+                }
+                let data = with_retries(|| download::fetch_bytes(&segment.url))?;
+                let mut audio = Vec::new();
+                if is_transport_stream(&data) {
+                    demuxer.unpack(&data, &mut audio);
+                } else {
+                    audio.extend_from_slice(strip_id3(&data));
+                }
+                shared.update(|buffer| buffer.data.extend(audio));
+                next += 1;
+                fetched = true;
+            }
+            if playlist.ended {
+                return Ok(());
+            }
+
+            // A playlist that hasn't changed is checked again sooner.
+            let wait = if fetched {
+                playlist.target_duration
+            } else {
+                playlist.target_duration / 2
+            };
+            thread::sleep(wait.max(RETRY_DELAY));
+            if shared.is_closed() {
+                return Ok(());
+            }
+            playlist = with_retries(|| Playlist::fetch(&playlist.url))?;
+        }
+    })();
+    shared.update(|buffer| match result {
+        Ok(()) => buffer.ended = true,
+        Err(e) => buffer.error = Some(e),
+    });
+}
+
+/// Reads the audio of an HLS stream as one file, waiting for segments to
+/// arrive.
+struct HlsReader {
+    shared: Arc<Shared>,
+}
+
+impl HlsReader {
+    fn start(playlist: Playlist, start: usize) -> Self {
+        let shared = Arc::new(Shared::default());
+        let fetching = Arc::clone(&shared);
+        thread::spawn(move || fetch_segments(playlist, start, fetching));
+        Self { shared }
+    }
+}
+
+impl Drop for HlsReader {
+    fn drop(&mut self) {
+        self.shared.update(|buffer| buffer.closed = true);
+    }
+}
+
+impl Read for HlsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let buffer = self.shared.buffer.lock().unwrap();
+        let mut buffer = self
+            .shared
+            .changed
+            .wait_while(buffer, |buffer| {
+                buffer.data.is_empty() && !buffer.ended && buffer.error.is_none()
+            })
+            .unwrap();
+        if buffer.data.is_empty() {
+            return match &buffer.error {
+                Some(e) => Err(io::Error::other(e.clone())),
+                None => Ok(0),
+            };
+        }
+        let read = buf.len().min(buffer.data.len());
+        for (to, from) in buf.iter_mut().zip(buffer.data.drain(..read)) {
+            *to = from;
+        }
+        drop(buffer);
+        self.shared.changed.notify_all();
+        Ok(read)
+    }
+}
+
+/// Decodes a stream that can only be read forward. rodio's decoder works
+/// out the format by reading ahead and going back, which a stream can't
+/// do, so this drives symphonia itself.
+struct StreamDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn codecs::Decoder>,
+    track: u32,
+    /// The last packet decoded.
+    samples: Option<SampleBuffer<i16>>,
+    /// Samples of `samples` already played.
+    played: usize,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl StreamDecoder {
+    fn new(reader: HlsReader) -> Result<Self, SymphoniaError> {
+        let stream =
+            MediaSourceStream::new(Box::new(ReadOnlySource::new(reader)), Default::default());
+        let probed = symphonia::default::get_probe().format(
+            &Hint::new(),
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+        let track = format
+            .default_track()
+            .ok_or(SymphoniaError::Unsupported("no audio track"))?;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+        let mut decoded = Self {
+            track: track.id,
+            format,
+            decoder,
+            samples: None,
+            played: 0,
+            channels: 0,
+            sample_rate: 0,
+        };
+        // The first packet settles the channels and sample rate.
+        if !decoded.decode_next() {
+            return Err(SymphoniaError::DecodeError("no audio in the stream"));
+        }
+        Ok(decoded)
+    }
+
+    fn len(&self) -> usize {
+        self.samples.as_ref().map_or(0, SampleBuffer::len)
+    }
+
+    /// Decodes the next packet into `samples`, skipping any that are
+    /// damaged. False once the stream ends.
+    fn decode_next(&mut self) -> bool {
+        loop {
+            let Ok(packet) = self.format.next_packet() else {
+                return false;
+            };
+            if packet.track_id() != self.track {
+                continue;
+            }
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    if decoded.frames() == 0 {
+                        continue;
+                    }
+                    let samples = match &mut self.samples {
+                        Some(samples)
+                            if samples.capacity() >= decoded.capacity() * spec.channels.count() =>
+                        {
+                            samples
+                        }
+                        samples => {
+                            samples.insert(SampleBuffer::new(decoded.capacity() as u64, spec))
+                        }
+                    };
+                    samples.copy_interleaved_ref(decoded);
+                    self.played = 0;
+                    self.channels = spec.channels.count() as u16;
+                    self.sample_rate = spec.rate;
+                    return true;
+                }
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl Iterator for StreamDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.played == self.len() && !self.decode_next() {
+            return None;
+        }
+        let sample = self.samples.as_ref()?.samples()[self.played];
+        self.played += 1;
+        Some(sample)
+    }
+}
+
+impl Source for StreamDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.len() - self.played)
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Decodes an HLS stream. Archived broadcasts can be seeked in, by starting
+/// over from the segment the position falls in. The player always wraps it
+/// in a [`BufferedSource`](crate::buffering::BufferedSource), so that
+/// happens on the thread decoding ahead, not the audio thread.
+pub struct HlsSource {
+    path: PathBuf,
+    playlist: Playlist,
+    source: AudioSource,
+}
+
+/// Opens the HLS stream at `path`, a playlist URL.
+pub fn open(path: &Path) -> Result<AudioSource, ApzError> {
+    let playlist = Playlist::fetch(&path.to_string_lossy())
+        .map_err(|e| ApzError::open(path, io::Error::other(e)))?;
+    let start = if playlist.ended {
+        0
+    } else {
+        playlist.segments.len().saturating_sub(LIVE_START_SEGMENTS)
+    };
+    let source = decode(path, &playlist, start)?;
+    Ok(Box::new(HlsSource {
+        path: path.to_path_buf(),
+        playlist,
+        source,
+    }))
+}
+
+fn decode(path: &Path, playlist: &Playlist, start: usize) -> Result<AudioSource, ApzError> {
+    let reader = HlsReader::start(playlist.clone(), start);
+    let source = StreamDecoder::new(reader).map_err(|e| {
+        let e = match e {
+            SymphoniaError::IoError(e) => return ApzError::open(path, e),
+            SymphoniaError::Unsupported(_) => DecoderError::UnrecognizedFormat,
+            SymphoniaError::DecodeError(message) => DecoderError::DecodeError(message),
+            SymphoniaError::LimitError(message) => DecoderError::LimitError(message),
+            _ => DecoderError::UnrecognizedFormat,
+        };
+        ApzError::decode(path, e)
+    })?;
+    Ok(Box::new(source))
+}
+
+impl Iterator for HlsSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        self.source.next()
+    }
+}
+
+impl Source for HlsSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.playlist.ended.then(|| self.playlist.duration())
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        if !self.playlist.ended {
+            return Err(SeekError::NotSupported {
+                underlying_source: std::any::type_name::<Self>(),
+            });
+        }
+        let mut start = Duration::ZERO;
+        let mut index = 0;
+        for segment in &self.playlist.segments[..self.playlist.segments.len().saturating_sub(1)] {
+            if start + segment.duration > pos {
+                break;
+            }
+            start += segment.duration;
+            index += 1;
+        }
+
+        let mut source =
+            decode(&self.path, &self.playlist, index).map_err(|e| SeekError::Other(Box::new(e)))?;
+        // Segments are seconds long, so the rest of the way is decoded and
+        // thrown away.
+        let frames =
+            (pos.saturating_sub(start).as_secs_f64() * source.sample_rate() as f64) as usize;
+        let samples = frames * source.channels() as usize;
+        source.by_ref().take(samples).for_each(drop);
+        self.source = source;
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! Runs the user's shell commands when something happens in the player: a
+//! track starting or ending, or playback pausing or resuming. The track is
+//! described to the command in `APZ_*` environment variables, for
+//! scrobblers, home automation and the like.
+
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Instant;
+
+use crate::now_playing::{self, Snapshot};
+use crate::player::PlaybackState;
+
+/// Commands to run on each event, from `--on-start` and the rest.
+#[derive(Debug, Clone, Default)]
+pub struct HookConfig {
+    pub on_start: Option<String>,
+    pub on_end: Option<String>,
+    pub on_pause: Option<String>,
+    pub on_resume: Option<String>,
+}
+
+impl HookConfig {
+    pub fn is_empty(&self) -> bool {
+        self.on_start.is_none()
+            && self.on_end.is_none()
+            && self.on_pause.is_none()
+            && self.on_resume.is_none()
+    }
+}
+
+/// Track that has started playing, as last seen.
+struct Playing {
+    loaded_at: Instant,
+    snapshot: Snapshot,
+}
+
+pub struct Hooks {
+    config: HookConfig,
+    playing: Option<Playing>,
+}
+
+impl Hooks {
+    pub fn new(config: HookConfig) -> Self {
+        Self {
+            config,
+            playing: None,
+        }
+    }
+
+    /// Runs the hooks for whatever has happened since the last call.
+    /// `loaded_at` tells a track apart from the same one loaded again.
+    pub fn update(&mut self, snapshot: &Snapshot, loaded_at: Instant) {
+        let ended = self.playing.as_ref().is_some_and(|playing| {
+            playing.loaded_at != loaded_at || snapshot.state == PlaybackState::Stopped
+        });
+        if ended {
+            self.end();
+        }
+
+        match &mut self.playing {
+            None if snapshot.state == PlaybackState::Playing => {
+                run(&self.config.on_start, "start", snapshot);
+                self.playing = Some(Playing {
+                    loaded_at,
+                    snapshot: snapshot.clone(),
+                });
+            }
+            None => {}
+            Some(playing) => {
+                match (playing.snapshot.state, snapshot.state) {
+                    (PlaybackState::Playing, PlaybackState::Paused) => {
+                        run(&self.config.on_pause, "pause", snapshot);
+                    }
+                    (PlaybackState::Paused, PlaybackState::Playing) => {
+                        run(&self.config.on_resume, "resume", snapshot);
+                    }
+                    _ => {}
+                }
+                playing.snapshot = snapshot.clone();
+            }
+        }
+    }
+
+    /// Runs the end hook for the track playing, with where it had got to.
+    fn end(&mut self) {
+        if let Some(playing) = self.playing.take() {
+            run(&self.config.on_end, "end", &playing.snapshot);
+        }
+    }
+}
+
+impl Drop for Hooks {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+/// Starts `command` in the shell without waiting for it. Its output is
+/// thrown away, since it would land on top of the UI.
+fn run(command: &Option<String>, event: &str, snapshot: &Snapshot) {
+    let Some(command) = command else {
+        return;
+    };
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let track = &snapshot.track;
+    let tag = |value: &Option<String>| value.clone().unwrap_or_default();
+    shell
+        .arg(command)
+        .env("APZ_EVENT", event)
+        .env("APZ_STATE", now_playing::state_name(snapshot.state))
+        .env("APZ_PATH", &snapshot.path)
+        .env("APZ_FILENAME", &snapshot.filename)
+        .env("APZ_TITLE", tag(&track.title))
+        .env("APZ_ARTIST", tag(&track.artist))
+        .env("APZ_ALBUM", tag(&track.album))
+        .env(
+            "APZ_TRACK_NUMBER",
+            track
+                .track_number
+                .map(|number| number.to_string())
+                .unwrap_or_default(),
+        )
+        .env(
+            "APZ_POSITION",
+            format!("{:.3}", snapshot.position.as_secs_f64()),
+        )
+        .env(
+            "APZ_DURATION",
+            format!("{:.3}", snapshot.duration.as_secs_f64()),
+        )
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if let Ok(mut child) = shell.spawn() {
+        // Reaped in the background, so it doesn't linger as a zombie.
+        thread::spawn(move || child.wait());
+    }
+}
@@ -0,0 +1,335 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::command::Command;
+use crate::json;
+use crate::now_playing::Snapshot;
+
+const REMOTE_PAGE: &str = include_str!("../assets/remote.html");
+
+/// Longest request or header line, and most headers, read from a client.
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+
+/// Minimal HTTP/1.1 remote-control server, also serving a single-page web
+/// remote at `/`. Reads are answered from a snapshot refreshed by the main
+/// loop; writes are forwarded to it as [`Command`]s, so the player itself
+/// never leaves the main thread.
+pub struct HttpServer {
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+}
+
+impl HttpServer {
+    pub fn start(addr: &str, commands: Sender<Command>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let snapshot = Arc::new(Mutex::new(None));
+
+        let shared = Arc::clone(&snapshot);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let commands = commands.clone();
+                let snapshot = Arc::clone(&shared);
+                thread::spawn(move || {
+                    handle_connection(stream, &commands, &snapshot).ok();
+                });
+            }
+        });
+
+        Ok(Self { snapshot })
+    }
+
+    pub fn update(&self, snapshot: &Snapshot) {
+        *self.snapshot.lock().unwrap() = Some(snapshot.clone());
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    /// Whether the client sent `X-Apz: 1`. A browser won't add that header
+    /// to a cross-site request without a preflight, which is never granted,
+    /// so requiring it keeps other web pages from driving the player.
+    trusted: bool,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn html(body: &str) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+
+    fn accepted() -> Self {
+        Self {
+            status: "202 Accepted",
+            content_type: "application/json",
+            body: r#"{"ok":true}"#.to_string(),
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: format!(r#"{{"ok":false,"error":{}}}"#, json::string(message)),
+        }
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    commands: &Sender<Command>,
+    snapshot: &Mutex<Option<Snapshot>>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let response = match read_request(&mut reader)? {
+        Some(request) => route(&request, commands, snapshot),
+        None => Response::error("400 Bad Request", "malformed request"),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<Request>> {
+    let mut request_line = String::new();
+    if !read_line(reader, &mut request_line)? {
+        return Ok(None);
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+
+    let mut content_length = 0;
+    let mut trusted = false;
+    for count in 0.. {
+        let mut header = String::new();
+        if count == MAX_HEADERS || !read_line(reader, &mut header)? {
+            return Ok(None);
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().unwrap_or(0);
+        } else if name.eq_ignore_ascii_case("x-apz") {
+            trusted = value == "1";
+        }
+    }
+
+    // Parameters are accepted in the query string or as a form-encoded body.
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut query = query.to_string();
+    if content_length > 0 {
+        let mut body = vec![0u8; content_length.min(64 * 1024)];
+        reader.read_exact(&mut body)?;
+        if !query.is_empty() {
+            query.push('&');
+        }
+        query.push_str(&String::from_utf8_lossy(&body));
+    }
+
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        trusted,
+    }))
+}
+
+/// Reads one line of at most [`MAX_LINE`] bytes into `line`, returning
+/// false if the connection closed first or the line is too long.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<bool> {
+    io::Read::take(reader, MAX_LINE).read_line(line)?;
+    Ok(line.ends_with('\n'))
+}
+
+fn route(
+    request: &Request,
+    commands: &Sender<Command>,
+    snapshot: &Mutex<Option<Snapshot>>,
+) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+
+    if request.method != "GET" && !request.trusted {
+        return Response::error("403 Forbidden", "missing X-Apz: 1 header");
+    }
+
+    let command =
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", [""]) => return Response::html(REMOTE_PAGE),
+            ("GET", ["status" | "queue"]) => {
+                return match snapshot.lock().unwrap().as_ref() {
+                    Some(snapshot) if segments[0] == "status" => Response::json(snapshot.to_json()),
+                    Some(snapshot) => Response::json(snapshot.queue_json()),
+                    None => Response::error("503 Service Unavailable", "player is starting"),
+                };
+            }
+            ("POST", ["play"]) => Command::Play,
+            ("POST", ["pause"]) => Command::Pause,
+            ("POST", ["toggle"]) => Command::TogglePause,
+            ("POST", ["next"]) => Command::NextTrack,
+            ("POST", ["previous"]) => Command::PreviousTrack,
+            ("POST", ["seek"]) => {
+                if let Some(position) = param(&request.query, "position") {
+                    match position.parse().ok().and_then(|position: f64| {
+                        Duration::try_from_secs_f64(position.max(0.0)).ok()
+                    }) {
+                        Some(position) => Command::SeekTo(position),
+                        None => return Response::error("400 Bad Request", "invalid position"),
+                    }
+                } else if let Some(offset) = param(&request.query, "offset") {
+                    // No seek can go further than the length of the track.
+                    let duration = snapshot
+                        .lock()
+                        .unwrap()
+                        .as_ref()
+                        .map_or(0, |snapshot| snapshot.duration.as_secs_f64().ceil() as u64);
+                    match offset
+                        .parse()
+                        .ok()
+                        .filter(|offset: &i64| offset.unsigned_abs() <= duration)
+                    {
+                        Some(offset) => Command::Seek(offset),
+                        None => return Response::error("400 Bad Request", "invalid offset"),
+                    }
+                } else {
+                    return Response::error("400 Bad Request", "expected position or offset");
+                }
+            }
+            ("POST", ["volume"]) => match param(&request.query, "level")
+                .and_then(|l| l.parse().ok())
+                .filter(|level: &f32| level.is_finite())
+            {
+                Some(level) => Command::SetVolume(f32::clamp(level, 0.0, 100.0) / 100.0),
+                None => return Response::error("400 Bad Request", "expected level (0-100)"),
+            },
+            ("POST", ["queue"]) => match param(&request.query, "path") {
+                Some(path) if !path.is_empty() => Command::Enqueue(PathBuf::from(path)),
+                _ => return Response::error("400 Bad Request", "expected path"),
+            },
+            ("POST", ["queue", index, "play"]) => match index.parse() {
+                Ok(index) => {
+                    if let Some(refusal) = check_index(snapshot, index, false) {
+                        return refusal;
+                    }
+                    Command::PlayIndex(index)
+                }
+                Err(_) => return Response::error("400 Bad Request", "invalid queue index"),
+            },
+            ("DELETE", ["queue", index]) => match index.parse() {
+                Ok(index) => {
+                    if let Some(refusal) = check_index(snapshot, index, true) {
+                        return refusal;
+                    }
+                    Command::RemoveFromQueue(index)
+                }
+                Err(_) => return Response::error("400 Bad Request", "invalid queue index"),
+            },
+            _ => return Response::error("404 Not Found", "no such endpoint"),
+        };
+
+    if commands.send(command).is_err() {
+        return Response::error("503 Service Unavailable", "player is shutting down");
+    }
+    Response::accepted()
+}
+
+/// Why the queue entry at `index` can't be played, or removed if
+/// `removing`, as of the latest snapshot.
+fn check_index(
+    snapshot: &Mutex<Option<Snapshot>>,
+    index: usize,
+    removing: bool,
+) -> Option<Response> {
+    let snapshot = snapshot.lock().unwrap();
+    let Some(snapshot) = snapshot.as_ref() else {
+        return Some(Response::error(
+            "503 Service Unavailable",
+            "player is starting",
+        ));
+    };
+    if index >= snapshot.queue.len() {
+        Some(Response::error("404 Not Found", "no such queue entry"))
+    } else if removing && index == snapshot.queue_index {
+        Some(Response::error(
+            "409 Conflict",
+            "the track that's playing can't be removed",
+        ))
+    } else {
+        None
+    }
+}
+
+fn param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
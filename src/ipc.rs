@@ -0,0 +1,126 @@
+//! Control socket used to keep a single apz instance per user: later
+//! invocations hand their files to the running player instead of opening a
+//! second audio stream. Only available on Unix platforms.
+
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+
+use crate::command::Command;
+
+/// `$XDG_RUNTIME_DIR/apz.sock`, or without a runtime directory, a socket in
+/// a directory of the user's own under the shared temporary directory.
+#[cfg(unix)]
+fn socket_path() -> PathBuf {
+    let user = std::env::var("USER").unwrap_or_else(|_| "apz".to_string());
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join(format!("apz-{}", user)))
+        .join("apz.sock")
+}
+
+/// Makes sure only this user can reach the socket's directory, creating it
+/// if need be. Fails if it belongs to someone else.
+#[cfg(unix)]
+fn secure_dir(path: &std::path::Path) -> std::io::Result<()> {
+    use std::fs::{self, DirBuilder, Permissions};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let Some(dir) = path.parent() else {
+        return Ok(());
+    };
+    if std::env::var_os("XDG_RUNTIME_DIR").is_some() {
+        // Already private to the user.
+        return Ok(());
+    }
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            // Only the owner can change the mode, so this also checks it's ours.
+            fs::set_permissions(dir, Permissions::from_mode(0o700))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Removes the socket file when the listening instance exits.
+#[cfg_attr(not(unix), allow(dead_code))]
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Sends `paths` to an already running instance. Returns `false` if there is
+/// no instance listening, in which case the caller should start playing
+/// itself.
+#[cfg(unix)]
+pub fn enqueue_in_running_instance(paths: &[PathBuf]) -> bool {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    let Ok(mut stream) = UnixStream::connect(socket_path()) else {
+        return false;
+    };
+
+    for path in paths {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        if writeln!(stream, "enqueue {}", path.display()).is_err() {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Starts listening on the control socket, forwarding each received line
+/// as a [`Command`].
+#[cfg(unix)]
+pub fn listen(commands: Sender<Command>) -> Option<ControlSocket> {
+    use std::fs::{self, Permissions};
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = socket_path();
+    // With --new-instance another player may still be listening, and keeps
+    // the socket.
+    if UnixStream::connect(&path).is_ok() {
+        return None;
+    }
+    secure_dir(&path).ok()?;
+    // Nobody answered on this path, so any file left there is stale.
+    fs::remove_file(&path).ok();
+    let listener = UnixListener::bind(&path).ok()?;
+    fs::set_permissions(&path, Permissions::from_mode(0o600)).ok()?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let commands = commands.clone();
+            std::thread::spawn(move || {
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    if let Some(command) = Command::parse(&line)
+                        && commands.send(command).is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    Some(ControlSocket { path })
+}
+
+#[cfg(not(unix))]
+pub fn enqueue_in_running_instance(_paths: &[PathBuf]) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+pub fn listen(_commands: Sender<Command>) -> Option<ControlSocket> {
+    None
+}
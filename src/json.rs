@@ -0,0 +1,62 @@
+/// Quotes and escapes `value` as a JSON string literal.
+pub fn string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The value of the first `key` found in `json`, at any depth: a string
+/// unescaped, a number or literal as written. Enough for picking fields out
+/// of messages whose keys don't repeat, without parsing them whole.
+pub fn field(json: &str, key: &str) -> Option<String> {
+    let quoted = string(key);
+    let mut rest = json;
+    loop {
+        let start = rest.find(&quoted)? + quoted.len();
+        rest = &rest[start..];
+        if let Some(value) = rest.trim_start().strip_prefix(':') {
+            rest = value.trim_start();
+            break;
+        }
+    }
+
+    let Some(text) = rest.strip_prefix('"') else {
+        if rest.starts_with(['{', '[']) {
+            return None;
+        }
+        let end = rest.find([',', '}', ']']).unwrap_or(rest.len());
+        return Some(rest[..end].trim().to_string()).filter(|value| !value.is_empty());
+    };
+    let mut value = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&code, 16).ok()?;
+                    value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+
+const LIBRARY_FILE: &str = "library.tsv";
+pub const MAX_RATING: u8 = 5;
+/// Furthest a track's own gain goes either way, in dB.
+pub const MAX_GAIN_DB: f32 = 12.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrackStats {
+    pub plays: u32,
+    /// From 1 to [`MAX_RATING`] stars.
+    pub rating: Option<u8>,
+    /// Added to the volume whenever the track plays, in dB.
+    pub gain_db: f32,
+}
+
+/// Play counts, star ratings and gains of every track that has any,
+/// persisted across sessions in `library.tsv`
+/// (`path<TAB>plays<TAB>rating<TAB>gain` per line, with a rating of 0 for
+/// unrated and the gain in dB, which older files leave out).
+pub struct Library {
+    tracks: HashMap<String, TrackStats>,
+}
+
+impl Library {
+    pub fn load() -> Self {
+        let tracks = store_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default()
+            .lines()
+            .filter_map(parse_line)
+            .collect();
+        Self { tracks }
+    }
+
+    pub fn get(&self, path: &Path) -> TrackStats {
+        self.tracks.get(&key(path)).copied().unwrap_or_default()
+    }
+
+    fn update(path: &Path, change: impl FnOnce(&mut TrackStats)) {
+        let mut library = Self::load();
+        change(library.tracks.entry(key(path)).or_default());
+        library.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = store_path() else {
+            return;
+        };
+
+        let mut keys: Vec<&String> = self.tracks.keys().collect();
+        keys.sort();
+        let contents: String = keys
+            .into_iter()
+            .map(|key| {
+                let stats = self.tracks[key];
+                format!(
+                    "{}\t{}\t{}\t{}\n",
+                    key,
+                    stats.plays,
+                    stats.rating.unwrap_or(0),
+                    stats.gain_db
+                )
+            })
+            .collect();
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        fs::write(path, contents).ok();
+    }
+}
+
+pub fn stats(path: &Path) -> TrackStats {
+    Library::load().get(path)
+}
+
+pub fn count_play(path: &Path) {
+    Library::update(path, |stats| stats.plays += 1);
+}
+
+/// Sets the rating of `path`, clearing it for `None`.
+pub fn rate(path: &Path, rating: Option<u8>) {
+    let rating = rating.map(|stars| stars.clamp(1, MAX_RATING));
+    Library::update(path, |stats| stats.rating = rating);
+}
+
+/// Sets the gain `path` is always played with, in dB.
+pub fn set_gain(path: &Path, gain_db: f32) {
+    let gain_db = gain_db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
+    Library::update(path, |stats| stats.gain_db = gain_db);
+}
+
+fn key(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn store_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(LIBRARY_FILE))
+}
+
+fn parse_line(line: &str) -> Option<(String, TrackStats)> {
+    let mut fields = line.splitn(4, '\t');
+    let path = fields.next()?.to_string();
+    let plays = fields.next()?.parse().ok()?;
+    let rating = fields.next()?.parse().ok().filter(|&stars| stars > 0);
+    let gain_db = fields
+        .next()
+        .and_then(|gain| gain.parse().ok())
+        .unwrap_or(0.0);
+    Some((
+        path,
+        TrackStats {
+            plays,
+            rating,
+            gain_db,
+        },
+    ))
+}
@@ -0,0 +1,213 @@
+//! Loops a stretch of the track by seeking the decoder back whenever it
+//! reaches the end, below the fade and everything else in the chain so the
+//! jump doesn't set off a fade in.
+
+use rodio::source::SeekError;
+use rodio::{Sample, Source};
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Gain ramp either side of each jump back, so the loop doesn't click.
+const DECLICK: f64 = 0.002;
+
+/// The loop, shared between the UI and the audio thread.
+pub struct Looper {
+    /// Loop start and end in microseconds, with no loop while the end is 0.
+    start: AtomicU64,
+    end: AtomicU64,
+    /// Whether the track carries on underneath, for a loop roll.
+    slip: AtomicBool,
+    /// Set to end a loop roll where the track would have got to.
+    release: AtomicBool,
+    /// Seconds the loop has jumped back by since the last seek, as an
+    /// `f64`'s bits.
+    shift: AtomicU64,
+    /// Times the loop has gone back to its start.
+    laps: AtomicU32,
+}
+
+impl Looper {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            start: AtomicU64::new(0),
+            end: AtomicU64::new(0),
+            slip: AtomicBool::new(false),
+            release: AtomicBool::new(false),
+            shift: AtomicU64::new(0.0f64.to_bits()),
+            laps: AtomicU32::new(0),
+        })
+    }
+
+    pub fn range(&self) -> Option<Range<Duration>> {
+        let end = self.end.load(Ordering::Relaxed);
+        let start = self.start.load(Ordering::Relaxed);
+        (end > start).then(|| Duration::from_micros(start)..Duration::from_micros(end))
+    }
+
+    /// Loops `range`. With `slip` the track plays on silently underneath,
+    /// and picks up from there once the loop is released.
+    pub fn set(&self, range: Range<Duration>, slip: bool) {
+        self.end.store(0, Ordering::Relaxed);
+        self.start
+            .store(range.start.as_micros() as u64, Ordering::Relaxed);
+        self.slip.store(slip, Ordering::Relaxed);
+        self.release.store(false, Ordering::Relaxed);
+        self.end
+            .store(range.end.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn is_slip(&self) -> bool {
+        self.slip.load(Ordering::Relaxed)
+    }
+
+    /// Stops looping, jumping ahead to where the track would be for a
+    /// loop roll.
+    pub fn release(&self) {
+        if self.range().is_some() {
+            self.release.store(self.is_slip(), Ordering::Relaxed);
+            self.end.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Stops looping where the loop is.
+    pub fn clear(&self) {
+        self.end.store(0, Ordering::Relaxed);
+        self.release.store(false, Ordering::Relaxed);
+    }
+
+    /// How far the loop has taken playback back from where the track would
+    /// be, which the position has to allow for.
+    pub fn shift(&self) -> Duration {
+        Duration::from_secs_f64(f64::from_bits(self.shift.load(Ordering::Relaxed)))
+    }
+
+    pub fn laps(&self) -> u32 {
+        self.laps.load(Ordering::Relaxed)
+    }
+
+    fn set_shift(&self, seconds: f64) {
+        self.shift.store(seconds.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Plays the [`Looper`]'s loop, if there is one.
+pub struct LoopSource<I> {
+    input: I,
+    looper: Arc<Looper>,
+    /// Seconds into the track of the next frame.
+    elapsed: f64,
+    /// Seconds jumped back since the last seek.
+    shift: f64,
+    /// Samples of the current frame handed out so far.
+    sample: u16,
+    gain: f32,
+}
+
+impl<I> LoopSource<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    pub fn new(input: I, looper: Arc<Looper>) -> Self {
+        looper.clear();
+        looper.set_shift(0.0);
+        Self {
+            input,
+            looper,
+            elapsed: 0.0,
+            shift: 0.0,
+            sample: 0,
+            gain: 1.0,
+        }
+    }
+
+    fn jump(&mut self, to: f64) {
+        if self
+            .input
+            .try_seek(Duration::from_secs_f64(to.max(0.0)))
+            .is_ok()
+        {
+            self.shift += self.elapsed - to;
+            self.elapsed = to;
+            self.looper.set_shift(self.shift);
+            self.gain = 0.0;
+        }
+    }
+
+    fn start_frame(&mut self) {
+        if self.looper.release.swap(false, Ordering::Relaxed) && self.shift > 0.0 {
+            self.jump(self.elapsed + self.shift);
+        }
+
+        let mut ceiling = 1.0;
+        if let Some(range) = self.looper.range() {
+            let (start, end) = (range.start.as_secs_f64(), range.end.as_secs_f64());
+            if self.elapsed >= end {
+                self.jump(start);
+                self.looper.laps.fetch_add(1, Ordering::Relaxed);
+            } else if self.elapsed >= start {
+                ceiling = ((end - self.elapsed) / DECLICK).min(1.0) as f32;
+            }
+        }
+        let step = (1.0 / (DECLICK * self.input.sample_rate().max(1) as f64)) as f32;
+        self.gain = (self.gain + step).min(ceiling);
+    }
+}
+
+impl<I> Iterator for LoopSource<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.sample == 0 {
+            self.start_frame();
+        }
+        let sample = self.input.next()?;
+        self.sample += 1;
+        if self.sample >= self.input.channels().max(1) {
+            self.sample = 0;
+            self.elapsed += 1.0 / self.input.sample_rate().max(1) as f64;
+        }
+        Some(if self.gain >= 1.0 {
+            sample
+        } else {
+            sample.amplify(self.gain)
+        })
+    }
+}
+
+impl<I> Source for LoopSource<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.elapsed = pos.as_secs_f64();
+        self.shift = 0.0;
+        self.looper.set_shift(0.0);
+        self.sample = 0;
+        Ok(())
+    }
+}
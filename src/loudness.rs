@@ -0,0 +1,277 @@
+//! Loudness and true peak as ITU-R BS.1770 measures them, which is what
+//! EBU R128 and ReplayGain 2.0 are based on.
+
+use rodio::Source;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::path::Path;
+
+use crate::decoder;
+use crate::error::ApzError;
+
+/// Blocks quieter than this are left out of the integrated loudness.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// As are blocks this far below the loudness of the rest.
+const RELATIVE_GATE: f64 = -10.0;
+/// Blocks are 400 ms long and start every 100 ms.
+const STEPS_PER_BLOCK: usize = 4;
+const STEPS_PER_SECOND: u32 = 10;
+/// True peak is read from the signal upsampled four times.
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+pub fn to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.log10()
+}
+
+/// Measures a track a frame at a time, so it never has to be held in memory.
+pub struct LoudnessMeter {
+    channels: Vec<Channel>,
+    step_length: usize,
+    /// Frames into the current 100 ms step.
+    position: usize,
+    /// Weighted mean square of the last few steps.
+    steps: VecDeque<f64>,
+    /// Weighted mean square of every block, for gating at the end.
+    blocks: Vec<f64>,
+}
+
+struct Channel {
+    weight: f64,
+    shelf: Biquad,
+    high_pass: Biquad,
+    sum_squares: f64,
+    true_peak: TruePeak,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let rate = sample_rate.max(1) as f64;
+        let channels = (0..channels.max(1))
+            .map(|index| Channel {
+                weight: weight(index, channels),
+                shelf: Biquad::high_shelf(rate),
+                high_pass: Biquad::high_pass(rate),
+                sum_squares: 0.0,
+                true_peak: TruePeak::new(),
+            })
+            .collect();
+        Self {
+            channels,
+            step_length: (sample_rate / STEPS_PER_SECOND).max(1) as usize,
+            position: 0,
+            steps: VecDeque::with_capacity(STEPS_PER_BLOCK),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Adds one sample per channel.
+    pub fn push(&mut self, frame: &[f32]) {
+        for (channel, &sample) in self.channels.iter_mut().zip(frame) {
+            let filtered = channel
+                .high_pass
+                .process(channel.shelf.process(sample as f64));
+            channel.sum_squares += filtered * filtered;
+            channel.true_peak.push(sample);
+        }
+
+        self.position += 1;
+        if self.position < self.step_length {
+            return;
+        }
+        let step = self
+            .channels
+            .iter_mut()
+            .map(|channel| channel.weight * std::mem::take(&mut channel.sum_squares))
+            .sum::<f64>()
+            / self.step_length as f64;
+        self.position = 0;
+        if self.steps.len() == STEPS_PER_BLOCK {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(step);
+        if self.steps.len() == STEPS_PER_BLOCK {
+            self.blocks
+                .push(self.steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64);
+        }
+    }
+
+    /// Integrated loudness in LUFS, or `None` for silence and tracks too
+    /// short to measure.
+    pub fn integrated(&self) -> Option<f64> {
+        integrated(&self.blocks)
+    }
+
+    /// Highest true peak of any channel, as an amplitude where 1.0 is full
+    /// scale.
+    pub fn true_peak(&self) -> f32 {
+        self.channels
+            .iter()
+            .map(|channel| channel.true_peak.peak)
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Decodes all of `path` through a meter.
+pub fn measure(path: &Path) -> Result<LoudnessMeter, ApzError> {
+    let mut source = decoder::open(path)?.source;
+    let channels = source.channels().max(1) as usize;
+    let mut meter = LoudnessMeter::new(source.sample_rate(), channels);
+    let mut frame = vec![0.0; channels];
+    'decode: loop {
+        for sample in frame.iter_mut() {
+            let Some(next) = source.next() else {
+                break 'decode;
+            };
+            *sample = next as f32 / 32768.0;
+        }
+        meter.push(&frame);
+    }
+    Ok(meter)
+}
+
+/// Integrated loudness of several tracks played one after the other, as for
+/// an album.
+pub fn combined<'a>(meters: impl IntoIterator<Item = &'a LoudnessMeter>) -> Option<f64> {
+    let blocks: Vec<f64> = meters
+        .into_iter()
+        .flat_map(|meter| meter.blocks.iter().copied())
+        .collect();
+    integrated(&blocks)
+}
+
+/// Loudness of the blocks that pass both gates.
+fn integrated(blocks: &[f64]) -> Option<f64> {
+    let gated = |threshold: f64| {
+        let (sum, count) = blocks
+            .iter()
+            .filter(|&&block| loudness(block) > threshold)
+            .fold((0.0, 0), |(sum, count), block| (sum + block, count + 1));
+        (count > 0).then(|| sum / count as f64)
+    };
+
+    let relative = loudness(gated(ABSOLUTE_GATE)?) + RELATIVE_GATE;
+    gated(relative.max(ABSOLUTE_GATE)).map(loudness)
+}
+
+fn loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+/// Surround channels count for more and the LFE channel not at all, going
+/// by the usual 5.1 order of L, R, C, LFE, Ls, Rs.
+fn weight(index: usize, channels: usize) -> f64 {
+    match (channels, index) {
+        (6, 3) => 0.0,
+        (6, 4 | 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// The K-weighting filters: a shelf modelling the head, then a high-pass.
+/// Coefficients are worked out for the sample rate rather than using the
+/// 48 kHz ones in the standard.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn high_shelf(rate: f64) -> Self {
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * 1681.974450955533 / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Self::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        )
+    }
+
+    fn high_pass(rate: f64) -> Self {
+        let q = 0.5003270373238773;
+        let k = (PI * 38.13547087602444 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        Self::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        )
+    }
+
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// Peak of the signal between samples as well as at them, found by
+/// interpolating [`OVERSAMPLING`] points per sample with a windowed sinc.
+struct TruePeak {
+    /// Most recent sample first.
+    history: VecDeque<f32>,
+    peak: f32,
+    taps: [[f32; TAPS_PER_PHASE]; OVERSAMPLING],
+}
+
+impl TruePeak {
+    fn new() -> Self {
+        let length = TAPS_PER_PHASE * OVERSAMPLING;
+        let center = (length - 1) as f64 / 2.0;
+        let mut taps = [[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+        for (phase, taps) in taps.iter_mut().enumerate() {
+            for (index, tap) in taps.iter_mut().enumerate() {
+                let n = index * OVERSAMPLING + phase;
+                let t = (n as f64 - center) / OVERSAMPLING as f64;
+                let sinc = if t == 0.0 {
+                    1.0
+                } else {
+                    (PI * t).sin() / (PI * t)
+                };
+                let window = (PI * (n as f64 + 0.5) / length as f64).sin().powi(2);
+                *tap = (sinc * window) as f32;
+            }
+            // Each phase passes a constant signal through unchanged.
+            let sum: f32 = taps.iter().sum();
+            for tap in taps.iter_mut() {
+                *tap /= sum;
+            }
+        }
+
+        Self {
+            history: VecDeque::from(vec![0.0; TAPS_PER_PHASE]),
+            peak: 0.0,
+            taps,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.history.pop_back();
+        self.history.push_front(sample);
+        self.peak = self.peak.max(sample.abs());
+        for taps in &self.taps {
+            let interpolated: f32 = taps.iter().zip(&self.history).map(|(t, s)| t * s).sum();
+            self.peak = self.peak.max(interpolated.abs());
+        }
+    }
+}
@@ -1,86 +1,890 @@
+mod ab;
+mod analyze;
+mod bookmarks;
+mod buffering;
+mod capture;
+mod cast;
+mod chapters;
+mod clip;
+mod command;
+mod compressor;
 mod config;
 mod controls;
+mod cover;
+mod cues;
+mod decoder;
+mod directory;
+mod discord;
+mod download;
+mod downmix;
+mod envelope;
+mod eq;
+mod error;
+mod events;
+mod fade;
+mod files;
+mod fire;
+mod flac;
+mod history;
+mod hls;
+mod hooks;
+mod http;
+mod ipc;
+mod json;
+mod library;
+mod looping;
+mod loudness;
+mod media_keys;
+mod metadata;
+mod metronome;
+mod midi;
+mod mpd;
+mod network_output;
+mod normalize;
+mod now_playing;
+mod opus;
+mod palette;
+mod paths;
 mod player;
+mod playlist;
+mod podcast;
+mod power;
+mod queue;
+mod rain;
+mod raw;
+mod record;
+mod render;
+mod resample;
+mod scope;
+mod script;
+mod shuffle;
+mod shutdown;
+mod silence;
 mod spectrum;
+mod stereo;
+mod stream_info;
+mod stretch;
+mod tags;
 mod tee_source;
+mod terminal_title;
+mod theme;
+mod tracker;
 mod ui;
+mod visualizer;
+mod wav;
 mod waveform;
+mod ytdlp;
 
 use crossterm::{
+    event::EnableMouseCapture,
     execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+    terminal::{EnterAlternateScreen, enable_raw_mode},
 };
-use ratatui::{Terminal, backend::CrosstermBackend};
+use ratatui::{Terminal, backend::CrosstermBackend, layout::Rect};
 use std::io;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::command::Command;
 use crate::config::Config;
 use crate::controls::{ControlAction, handle_input};
-use crate::player::Player;
-use crate::ui::UIState;
+use crate::cover::Cover;
+use crate::directory::ScanConfig;
+use crate::discord::DiscordPresence;
+use crate::error::ApzError;
+use crate::events::Event;
+use crate::files::FileAction;
+use crate::hooks::Hooks;
+use crate::http::HttpServer;
+use crate::media_keys::MediaKeys;
+use crate::mpd::MpdServer;
+use crate::now_playing::{Snapshot, StatusFile};
+use crate::player::{OutputConfig, PlaybackState, Player};
+use crate::podcast::Feed;
+use crate::power::PowerMonitor;
+use crate::queue::Queue;
+use crate::script::Scripts;
+use crate::silence::SilenceConfig;
+use crate::spectrum::SpectrumConfig;
+use crate::terminal_title::TerminalTitle;
+use crate::ui::{PodcastList, Precision, UIState};
+use crate::waveform::WaveformConfig;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::from_args();
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), ApzError> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("analyze") => process::exit(analyze::run(&args[2..])),
+        Some("normalize") => process::exit(normalize::run(&args[2..])),
+        Some("record") => process::exit(record::run(&args[2..])),
+        Some("render") => process::exit(render::run(&args[2..])),
+        Some("visualize") => {
+            let args = [&["--loopback".to_string()], &args[2..]].concat();
+            process::exit(record::run(&args))
+        }
+        _ => {}
+    }
+
+    let mut config = Config::from_args();
+    if let Some(playlist) = &config.watch {
+        match playlist::read(playlist) {
+            Ok(tracks) => config.audio_paths.extend(tracks),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", playlist.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+    config.audio_paths = directory::expand(
+        &config.audio_paths,
+        &ScanConfig {
+            recursive: config.recursive,
+            order: config.sort_order,
+            min_rating: config.min_rating,
+        },
+    );
+    let feed = config.podcast.as_deref().map(|url| {
+        Feed::fetch(url).unwrap_or_else(|e| {
+            eprintln!("Couldn't load the feed: {}", e);
+            process::exit(1);
+        })
+    });
+    // With nothing else to play, start on the newest episode not yet
+    // listened to.
+    if config.audio_paths.is_empty()
+        && let Some(feed) = &feed
+    {
+        let unlistened = feed.episodes.iter().find(|episode| {
+            !episode
+                .path()
+                .is_some_and(|path| podcast::progress(&path).listened)
+        });
+        if let Some(episode) = unlistened.or(feed.episodes.first()) {
+            match episode.download() {
+                Ok(path) => config.audio_paths.push(path),
+                Err(e) => {
+                    eprintln!("Couldn't download {}: {}", episode.url, e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+    if config.audio_paths.is_empty() {
+        eprintln!("No playable files found");
+        process::exit(1);
+    }
+    // The file compared against is loaded alongside the first, not queued.
+    let mut compare = config
+        .ab
+        .then(|| config.audio_paths.split_off(1))
+        .and_then(|mut rest| rest.pop());
+    if let Some(mode) = config.shuffle {
+        shuffle::shuffle(&mut config.audio_paths, mode);
+    }
 
     let spectrum_config = if config.use_visualizer {
-        Some((config.num_bars, config.smoothing, config.bass_boost))
+        Some(SpectrumConfig {
+            num_bars: config.num_bars,
+            smoothing: config.smoothing,
+            bass_boost: config.bass_boost,
+            min_freq: config.min_freq,
+            max_freq: config.max_freq,
+            curve: config.freq_curve,
+            rate: config.fps,
+            beats: config.beat_pulse,
+        })
     } else {
         None
     };
 
-    let player = Player::new(
-        &config.audio_path,
-        false,
+    let silence_config = (config.skip_silence || config.trim_silence).then_some(SilenceConfig {
+        threshold_db: config.silence_threshold,
+        min_length: config.silence_min_length,
+        skip: config.skip_silence,
+        trim: config.trim_silence,
+    });
+
+    // Another instance can't read this process's stdin.
+    let reads_stdin = config.audio_paths.iter().any(|path| raw::is_stdin(path));
+    // Only this instance would be watching the playlist or downloading.
+    let hand_off = !config.new_instance
+        && !reads_stdin
+        && config.watch.is_none()
+        && !config.ab
+        && config.podcast.is_none();
+    if hand_off && ipc::enqueue_in_running_instance(&config.audio_paths) {
+        println!(
+            "Added {} track(s) to the running apz instance",
+            config.audio_paths.len()
+        );
+        return Ok(());
+    }
+    // Pages are only looked into by the instance that plays them, as it
+    // has to see the download through.
+    for path in config.audio_paths.iter_mut().chain(compare.iter_mut()) {
+        if ytdlp::is_page(path) {
+            *path = ytdlp::open(&path.to_string_lossy()).unwrap_or_else(|e| {
+                eprintln!("Couldn't play {}: {}", path.display(), e);
+                process::exit(1);
+            });
+        }
+    }
+
+    if let Some(soundfont) = &config.soundfont {
+        midi::set_soundfont(soundfont.clone());
+    }
+    raw::set_format(config.raw_format);
+
+    let mut queue = Queue::new(config.audio_paths.clone());
+    let mut player = Player::new(
+        &config.audio_paths[0],
+        WaveformConfig {
+            enhanced: config.enhanced_waveform,
+            stereo: config.stereo_waveform,
+            db_scale: config.waveform_db,
+            resolution: config.waveform_resolution,
+            reduction: config.waveform_reduction,
+        },
         spectrum_config,
         config.volume_step,
         config.seek_step,
+        config.fade,
+        silence_config,
+        match (config.cast.clone(), config.output.clone()) {
+            (Some(device), _) => OutputConfig::Cast(device),
+            (None, Some(target)) => OutputConfig::Network(target),
+            (None, None) => OutputConfig::Device,
+        },
+        config.prebuffer,
     )
-    .map_err(|e| {
-        eprintln!("Failed to load audio file: {}", e);
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
         process::exit(1);
-    })?;
+    });
+
+    if let Some(other) = &compare
+        && let Err(e) = player.compare_with(other)
+    {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    player.practice_step = config.practice_step;
+
+    let mut ui_state = UIState::new(&player, config.theme);
+    ui_state.precise_time = config.precise_time;
+    ui_state.auto_advance = config.auto_advance;
+    ui_state.shuffle = config.shuffle;
+    ui_state.keep_dir = config.keep_dir.clone();
+    ui_state.reject_dir = config.reject_dir.clone();
+    ui_state.hidden = config.hidden_panels.clone();
+    ui_state.spectrum_layout = config.spectrum_layout;
+    ui_state.cover_width = config.cover_width;
+    ui_state.cover = config.cover_width.map(|_| Cover::load(player.path()));
+    if let Some(visualizer) = config.visualization.as_deref().and_then(visualizer::named) {
+        *ui_state.visualizer.get_mut() = visualizer;
+    }
+    ui_state.equalizer.set_bands(config.eq);
+    ui_state.compressor.configure(config.compressor);
+    ui_state.compressor.set_enabled(config.compress);
+    ui_state.stereo.set_width(config.stereo_width);
+    ui_state.stereo.set_mono(config.mono);
+    ui_state.stereo.set_solo(config.solo);
+    ui_state.resampler.set_quality(config.resampler);
+    ui_state.resampler.set_transpose(config.transpose);
+    ui_state.metronome.set_enabled(config.metronome);
+    ui_state.podcast_feed = config.podcast.clone();
+    ui_state.podcasts = feed.map(PodcastList::new);
+    if let Some(name) = player.cast_name() {
+        ui_state.toast(format!("Casting to {}", name));
+    }
+    resume_episode(&mut player, &mut ui_state);
+
+    let (command_sender, commands) = mpsc::channel();
+    let http = config.serve_addr.as_deref().map(|addr| {
+        HttpServer::start(addr, command_sender.clone()).unwrap_or_else(|e| {
+            eprintln!("Failed to start HTTP server on {}: {}", addr, e);
+            process::exit(1);
+        })
+    });
+    let mpd = config.mpd_addr.as_deref().map(|addr| {
+        MpdServer::start(addr, command_sender.clone()).unwrap_or_else(|e| {
+            eprintln!("Failed to start MPD server on {}: {}", addr, e);
+            process::exit(1);
+        })
+    });
+    let media_keys = if config.media_keys {
+        MediaKeys::start(command_sender.clone(), config.seek_step)
+    } else {
+        None
+    };
+    if let Some(playlist) = config.watch.clone() {
+        playlist::watch(playlist, command_sender.clone());
+    }
+    let scripts = Scripts::load(&config.scripts, command_sender.clone());
+    shutdown::forward_signals(command_sender.clone());
+    let _control_socket = ipc::listen(command_sender.clone());
+    let (event_sender, events) = mpsc::channel();
+    events::forward_commands(commands, event_sender.clone());
 
-    let duration = player.duration();
-    let waveform = player.waveform().clone();
-    let spectrum = player.spectrum();
-    let mut ui_state = UIState::new(&config.audio_path, duration, waveform, spectrum);
+    let mut integrations = Integrations {
+        presence: config.discord_client_id.clone().map(DiscordPresence::start),
+        title: config.set_title.then(TerminalTitle::new),
+        status_file: config.status_file.clone().map(StatusFile::new),
+        http,
+        mpd,
+        media_keys,
+        hooks: (!config.hooks.is_empty()).then(|| Hooks::new(config.hooks.clone())),
+        scripts,
+        commands: command_sender,
+    };
 
+    shutdown::install_panic_hook();
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
+    if config.mouse {
+        execute!(stdout, EnableMouseCapture)?;
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
+    events::read_input(event_sender);
 
-    let result = run_event_loop(&mut terminal, &player, &mut ui_state);
+    let result = run_event_loop(
+        &mut terminal,
+        &mut player,
+        &mut queue,
+        &mut ui_state,
+        &events,
+        &mut integrations,
+        &mut Pacing {
+            fps: config.fps,
+            power: PowerMonitor::new(config.low_power),
+        },
+    );
+    record_play(&player, &mut ui_state);
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+    shutdown::restore_terminal();
 
     result
 }
 
 fn run_event_loop(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-    player: &Player,
+    player: &mut Player,
+    queue: &mut Queue,
     ui_state: &mut UIState,
-) -> Result<(), Box<dyn std::error::Error>> {
+    events: &Receiver<Event>,
+    integrations: &mut Integrations,
+    pacing: &mut Pacing,
+) -> Result<(), ApzError> {
+    // Redraw right away after input or a command, otherwise only when the
+    // next frame is due. Waking up regularly to check on playback is cheap;
+    // drawing is what costs CPU.
+    let mut redraw = true;
+    let mut next_frame = Instant::now();
+    // The UI's copy of the queue is only refreshed when the queue changes.
+    let mut queue_version = None;
+    // Clips being saved in the background.
+    let mut exports: Vec<Receiver<Result<PathBuf, ApzError>>> = Vec::new();
+
     loop {
-        ui_state.position = player.position();
-        ui_state.volume = player.volume();
-        ui_state.state = player.state();
+        if pacing.power.poll() {
+            player.set_low_power(pacing.power.low_power());
+            ui_state.waveform = player.waveform().clone();
+            redraw = true;
+        }
+
+        if redraw || Instant::now() >= next_frame {
+            ui_state.position = player.position();
+            ui_state.volume = player.volume();
+            ui_state.state = player.state();
+            if let Some(spectrum) = &ui_state.spectrum {
+                spectrum.set_playing(ui_state.state == PlaybackState::Playing);
+            }
+            ui_state.buffer = player.buffer_status();
+            // Casting starts and stops, and the output is reopened, after
+            // the UI was made.
+            if ui_state.output != player.output_name() {
+                ui_state.output = player.output_name().to_string();
+            }
+            ui_state.tracker = player.tracker_position();
+            ui_state.beats = player.beats();
+            ui_state.roll = player.rolling();
+            ui_state.practice = player.practice();
+            ui_state.queue_index = queue.current_index();
+            ui_state.queue_len = queue.len();
+            if queue_version != Some(queue.version()) {
+                queue_version = Some(queue.version());
+                ui_state.queue = queue.tracks().to_vec();
+            }
+            if let Some(panel) = &mut ui_state.queue_panel {
+                panel.follow_queue(queue.version());
+            }
+
+            integrations.update(ui_state, queue);
 
-        terminal.draw(|f| ui::render(f, ui_state))?;
+            terminal.draw(|f| ui::render(f, ui_state))?;
 
-        match handle_input(player)? {
-            ControlAction::Quit => break,
-            ControlAction::Continue => {}
+            redraw = false;
+            let size = terminal.size()?;
+            next_frame = Instant::now()
+                + pacing.frame_interval(ui_state, Rect::new(0, 0, size.width, size.height));
+        }
+
+        let timeout = next_frame
+            .saturating_duration_since(Instant::now())
+            .min(PLAYBACK_CHECK_INTERVAL);
+        let action = match events.recv_timeout(timeout) {
+            Ok(Event::Input(event)) => handle_input(event, player, ui_state)?,
+            Ok(Event::Command(command)) => ControlAction::Command(command),
+            Err(RecvTimeoutError::Timeout) => ControlAction::Idle,
+            Err(RecvTimeoutError::Disconnected) => ControlAction::Quit,
+        };
+        match action {
+            ControlAction::Quit | ControlAction::Command(Command::Quit) => break,
+            ControlAction::Idle => {}
+            ControlAction::Continue => redraw = true,
+            ControlAction::Command(command) => {
+                apply_command(command, player, queue, ui_state, &integrations.commands);
+                redraw = true;
+            }
+            ControlAction::File(action) => {
+                file_away(&action, player, queue, ui_state);
+                redraw = true;
+            }
+            ControlAction::TogglePractice => {
+                if player.practice().is_some() {
+                    player.stop_practice();
+                } else if let Some(clip) = ui_state.clip() {
+                    player.start_practice(clip);
+                } else {
+                    ui_state.error = Some("Mark the loop to practise with I and O".to_string());
+                }
+                redraw = true;
+            }
+            ControlAction::Flub => {
+                player.flub();
+                redraw = true;
+            }
+            ControlAction::Tap => {
+                player.tap();
+                redraw = true;
+            }
+            ControlAction::ExportClip(output) => {
+                if let Some(clip) = ui_state.clip() {
+                    exports.push(clip::export_in_background(
+                        ui_state.path.clone(),
+                        clip,
+                        output,
+                    ));
+                }
+                redraw = true;
+            }
+        }
+
+        exports.retain(|export| match export.try_recv() {
+            Ok(result) => {
+                match result {
+                    Ok(path) => ui_state.toast(format!("Saved {}", path.display())),
+                    Err(e) => ui_state.error = Some(format!("Couldn't save the clip: {}", e)),
+                }
+                redraw = true;
+                false
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => false,
+        });
+
+        if player.receive_waveform() {
+            ui_state.waveform = player.waveform().clone();
+            ui_state.compare_waveform = player.compare_waveform().cloned();
+            redraw = true;
+        }
+        // The beat is only needed for loop rolls, which are played from the
+        // deck, and the metronome.
+        if ui_state.deck || ui_state.metronome.is_enabled() {
+            player.find_beats();
+        }
+        if player.receive_beats() || player.follow_practice() || ui_state.receive_feed() {
+            redraw = true;
+        }
+        player.skip_silence();
+        player.resume();
+        match player.finish_opening() {
+            Some(Ok(())) => {
+                ui_state.load_track(player);
+                redraw = true;
+            }
+            Some(Err(e)) => {
+                ui_state.toast_error(e.to_string());
+                redraw = true;
+            }
+            None => {}
+        }
+        player.rebuffer();
+        if let Some(error) = player.follow_cast() {
+            ui_state.error = Some(error);
         }
 
         if player.is_finished() {
-            break;
+            let play = !std::mem::take(&mut ui_state.stop_after_track) && ui_state.auto_advance;
+            if !advance_track(player, queue, ui_state, play) {
+                record_play(player, ui_state);
+                player.stop();
+            }
+            redraw = true;
         }
     }
 
     Ok(())
 }
+
+const IDLE_FPS: u32 = 2;
+/// Longest the loop sleeps without checking whether the track has ended or
+/// reached a silence to skip.
+const PLAYBACK_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+const PLAYED_FRACTION: f32 = 0.5;
+
+/// How often the screen is redrawn.
+struct Pacing {
+    fps: u32,
+    power: PowerMonitor,
+}
+
+impl Pacing {
+    /// A playing spectrum or gain reduction meter animates at the full frame
+    /// rate. Otherwise playback redraws only as often as the clock or the
+    /// progress bar, `width` cells wide, visibly moves; everything else
+    /// changes in response to events.
+    fn frame_interval(&self, ui_state: &UIState, screen: Rect) -> Duration {
+        let fps = if self.power.low_power() {
+            self.fps.min(power::LOW_POWER_FPS)
+        } else {
+            self.fps
+        };
+        let fastest = Duration::from_secs(1) / fps.max(1);
+        let idle = Duration::from_secs(1) / IDLE_FPS;
+        if ui_state.state != PlaybackState::Playing {
+            return idle;
+        }
+        if ui_state.spectrum.is_some() || ui_state.shows_reduction(screen) {
+            return fastest;
+        }
+
+        // The progress bar fills in eighths of a cell.
+        let mut interval = ui_state.clock_precision().tick().min(idle);
+        if !ui_state.duration.is_zero() {
+            interval = interval.min(ui_state.duration / (screen.width.max(1) as u32 * 8));
+        }
+        interval.max(fastest)
+    }
+}
+
+fn apply_command(
+    command: Command,
+    player: &mut Player,
+    queue: &mut Queue,
+    ui_state: &mut UIState,
+    commands: &Sender<Command>,
+) {
+    match command {
+        Command::Enqueue(path) => {
+            if let Some(path) = find_audio(path, Command::Enqueue, ui_state, commands) {
+                queue.push(path);
+            }
+        }
+        Command::Open(path) => {
+            let Some(path) = find_audio(path, Command::Open, ui_state, commands) else {
+                return;
+            };
+            queue.push(path);
+            if let Some(path) = queue.jump(queue.len() - 1) {
+                start_track(path, player, ui_state);
+            }
+        }
+        Command::RemoveFromQueue(index) => {
+            queue.remove(index);
+        }
+        Command::ReplaceQueue(tracks) => queue.replace(tracks),
+        Command::InsertNext(path) => {
+            if let Some(path) = find_audio(path, Command::InsertNext, ui_state, commands) {
+                queue.insert_next(path);
+            }
+        }
+        Command::RemoveTracks(indices) => match queue.remove_all(&indices) {
+            0 => ui_state.toast_error("The track that's playing can't be removed"),
+            count => ui_state.toast(controls::tracks_toast("Removed", count)),
+        },
+        Command::MoveInQueue(from, to) => {
+            let moved = queue.move_track(from, to);
+            // The panel has already moved its marks along with the entry.
+            if let Some(panel) = &mut ui_state.queue_panel
+                && moved
+                && panel.version == Some(queue.version() - 1)
+            {
+                panel.version = Some(queue.version());
+            }
+        }
+        Command::MoveToTop(indices) => queue.move_to_top(&indices),
+        Command::PlayNext(indices) => queue.play_next(&indices),
+        Command::ClearQueue => queue.clear(),
+        Command::PlayIndex(index) => {
+            let previous = queue.current_index();
+            // A track that won't load leaves the queue where it was.
+            if let Some(path) = queue.jump(index)
+                && !start_track(path, player, ui_state)
+            {
+                queue.jump(previous);
+            }
+        }
+        // Handled by the event loop.
+        Command::Quit => {}
+        Command::Play => player.play(),
+        Command::Pause => player.pause(),
+        Command::TogglePause => player.toggle_play_pause(),
+        Command::Stop => {
+            record_play(player, ui_state);
+            player.stop();
+        }
+        Command::Seek(offset) => {
+            if let Some(position) = player.seek(offset) {
+                ui_state.toast_seek(position);
+            }
+        }
+        Command::SeekTo(position) => {
+            if let Some(position) = player.seek_to(position) {
+                ui_state.toast_seek(position);
+            }
+        }
+        Command::SetVolume(volume) => {
+            player.set_volume(volume);
+            ui_state.toast_volume(player.volume());
+        }
+        Command::Toast(text) => ui_state.toast(text),
+        Command::Error(text) => ui_state.error = Some(text),
+        Command::NextTrack => {
+            advance_track(player, queue, ui_state, true);
+        }
+        Command::PreviousTrack => {
+            while let Some(path) = queue.retreat() {
+                if start_track(path, player, ui_state) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Moves the current track to the trash or a triage directory and drops it
+/// from the queue, carrying on with the track after it.
+fn file_away(action: &FileAction, player: &mut Player, queue: &mut Queue, ui_state: &mut UIState) {
+    let playing = player.state() == PlaybackState::Playing;
+    record_play(player, ui_state);
+    // Paused rather than stopped, so a move that fails carries on from
+    // where it was.
+    player.pause();
+
+    let destination = match files::apply(action, &ui_state.path) {
+        Ok(destination) => destination,
+        Err(e) => {
+            ui_state.error = Some(format!("Couldn't move {}: {}", ui_state.filename, e));
+            if playing {
+                player.play();
+            }
+            return;
+        }
+    };
+
+    let mut next = queue.remove_current().map(Path::to_path_buf);
+    if next.is_none() {
+        ui_state.toast(format!(
+            "Moved the last track to {}",
+            destination.parent().unwrap_or(&destination).display()
+        ));
+    }
+    while let Some(path) = next {
+        if load_track(&path, player, ui_state) {
+            if playing {
+                player.play();
+            }
+            return;
+        }
+        next = queue.advance().map(Path::to_path_buf);
+    }
+}
+
+/// Loads the next playable track in the queue, skipping files that fail to
+/// load, and starts it unless `play` is false. Returns `false` once the
+/// queue is exhausted.
+fn advance_track(
+    player: &mut Player,
+    queue: &mut Queue,
+    ui_state: &mut UIState,
+    play: bool,
+) -> bool {
+    while let Some(path) = queue.advance() {
+        if load_track(path, player, ui_state) {
+            if play {
+                player.play();
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// Loads and plays `path`.
+fn start_track(path: &Path, player: &mut Player, ui_state: &mut UIState) -> bool {
+    let loaded = load_track(path, player, ui_state);
+    if loaded {
+        player.play();
+    }
+    loaded
+}
+
+/// Loads `path`, leaving it paused. A file that can't be played is reported
+/// in a toast rather than ending the session.
+fn load_track(path: &Path, player: &mut Player, ui_state: &mut UIState) -> bool {
+    record_play(player, ui_state);
+    match player.load(path) {
+        Ok(()) => {
+            ui_state.load_track(player);
+            resume_episode(player, ui_state);
+            true
+        }
+        Err(e) => {
+            ui_state.toast_error(e.to_string());
+            false
+        }
+    }
+}
+
+/// Where to play `path` from: itself, unless it's a page yt-dlp has to
+/// find the audio on. That takes a while, so it's done on a thread of its
+/// own, which sends `then` with where the audio turned out to be.
+fn find_audio(
+    path: PathBuf,
+    then: fn(PathBuf) -> Command,
+    ui_state: &mut UIState,
+    commands: &Sender<Command>,
+) -> Option<PathBuf> {
+    if !ytdlp::is_page(&path) {
+        return Some(path);
+    }
+    ui_state.toast(format!("Looking for the audio on {}", path.display()));
+    let commands = commands.clone();
+    thread::spawn(move || {
+        let command = match ytdlp::open(&path.to_string_lossy()) {
+            Ok(found) => then(found),
+            Err(e) => Command::Error(format!("Couldn't play {}: {}", path.display(), e)),
+        };
+        commands.send(command).ok();
+    });
+    None
+}
+
+/// Picks a podcast episode up where it was left off.
+fn resume_episode(player: &mut Player, ui_state: &mut UIState) {
+    if !podcast::is_episode(player.path()) {
+        return;
+    }
+    let progress = podcast::progress(player.path());
+    if !progress.listened && !progress.position.is_zero() {
+        player.resume_from(progress.position);
+        ui_state.toast(format!(
+            "Resuming at {}",
+            ui::format_time(progress.position, Precision::Seconds)
+        ));
+    }
+}
+
+/// Adds the loaded track to the history with how much of it was played,
+/// unless none of it was, and counts it as played if that was at least
+/// [`PLAYED_FRACTION`] of it.
+fn record_play(player: &Player, ui_state: &mut UIState) {
+    let finished = player.is_finished();
+    if raw::is_stdin(player.path()) || (player.position().is_zero() && !finished) {
+        return;
+    }
+    let completion = if finished {
+        1.0
+    } else if player.duration().is_zero() {
+        0.0
+    } else {
+        player.position().as_secs_f32() / player.duration().as_secs_f32()
+    };
+    history::record(player.path(), completion);
+    if podcast::is_episode(player.path()) {
+        podcast::record(player.path(), player.position(), completion);
+    }
+    if completion >= PLAYED_FRACTION {
+        library::count_play(player.path());
+        ui_state.stats = library::stats(player.path());
+    }
+}
+
+/// Outside observers of playback that get refreshed once per loop iteration.
+struct Integrations {
+    presence: Option<DiscordPresence>,
+    title: Option<TerminalTitle>,
+    status_file: Option<StatusFile>,
+    http: Option<HttpServer>,
+    mpd: Option<MpdServer>,
+    media_keys: Option<MediaKeys>,
+    hooks: Option<Hooks>,
+    scripts: Option<Scripts>,
+    /// For work done on other threads to report back through.
+    commands: Sender<Command>,
+}
+
+impl Integrations {
+    fn update(&mut self, ui_state: &UIState, queue: &Queue) {
+        if let Some(presence) = self.presence.as_mut() {
+            presence.update(&ui_state.display_title(), ui_state.state, ui_state.position);
+        }
+
+        if let Some(title) = self.title.as_mut() {
+            title.update(
+                &ui_state.display_title(),
+                ui_state.state,
+                ui_state.position,
+                ui_state.duration,
+            );
+        }
+
+        let snapshot = Snapshot::capture(ui_state, queue);
+
+        if let Some(status_file) = self.status_file.as_mut() {
+            status_file.update(&snapshot);
+        }
+
+        if let Some(http) = &self.http {
+            http.update(&snapshot);
+        }
+
+        if let Some(mpd) = &self.mpd {
+            mpd.update(&snapshot);
+        }
+
+        if let Some(media_keys) = self.media_keys.as_mut() {
+            media_keys.update(&snapshot);
+        }
+
+        if let Some(hooks) = self.hooks.as_mut() {
+            hooks.update(&snapshot, ui_state.loaded_at);
+        }
+
+        if let Some(scripts) = self.scripts.as_mut() {
+            scripts.update(&snapshot, ui_state.loaded_at);
+        }
+    }
+}
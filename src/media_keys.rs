@@ -0,0 +1,259 @@
+//! System media controls (SMTC on Windows, the Now Playing center on macOS),
+//! so hardware play/pause/next keys reach apz even when the terminal isn't
+//! focused. Other platforms get a no-op stub.
+
+pub use platform::MediaKeys;
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+mod platform {
+    use std::sync::mpsc::Sender;
+
+    use crate::command::Command;
+    use crate::now_playing::Snapshot;
+
+    pub struct MediaKeys;
+
+    impl MediaKeys {
+        pub fn start(_commands: Sender<Command>, _seek_step: i64) -> Option<Self> {
+            None
+        }
+
+        pub fn update(&mut self, _snapshot: &Snapshot) {}
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+mod platform {
+    use std::path::PathBuf;
+    use std::sync::mpsc::Sender;
+    use std::time::{Duration, Instant};
+
+    use souvlaki::{
+        MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition,
+        PlatformConfig, SeekDirection,
+    };
+
+    use crate::command::Command;
+    use crate::now_playing::Snapshot;
+    use crate::player::PlaybackState;
+
+    /// Registration with the OS media controls. Button presses arrive as
+    /// [`Command`]s on the main loop's channel; [`MediaKeys::update`] keeps
+    /// the OS overlay in sync with what's playing.
+    pub struct MediaKeys {
+        controls: MediaControls,
+        #[cfg(target_os = "windows")]
+        window: windows::HiddenWindow,
+        published: Option<Published>,
+    }
+
+    struct Published {
+        path: PathBuf,
+        state: PlaybackState,
+        position: Duration,
+        at: Instant,
+    }
+
+    impl MediaKeys {
+        pub fn start(commands: Sender<Command>, seek_step: i64) -> Option<Self> {
+            // SMTC is bound to a window, and a console may not own one (e.g.
+            // under Windows Terminal), so apz creates its own invisible one.
+            #[cfg(target_os = "windows")]
+            let window = windows::HiddenWindow::create()?;
+
+            let config = PlatformConfig {
+                display_name: "apz",
+                dbus_name: "apz",
+                #[cfg(target_os = "windows")]
+                hwnd: Some(window.hwnd()),
+                #[cfg(not(target_os = "windows"))]
+                hwnd: None,
+            };
+
+            let mut controls = MediaControls::new(config).ok()?;
+            controls
+                .attach(move |event| {
+                    for command in commands_for(event, seek_step) {
+                        commands.send(command).ok();
+                    }
+                })
+                .ok()?;
+
+            Some(Self {
+                controls,
+                #[cfg(target_os = "windows")]
+                window,
+                published: None,
+            })
+        }
+
+        pub fn update(&mut self, snapshot: &Snapshot) {
+            #[cfg(target_os = "macos")]
+            macos::pump_run_loop();
+            #[cfg(target_os = "windows")]
+            self.window.pump_messages();
+
+            let track_changed = self
+                .published
+                .as_ref()
+                .is_none_or(|published| published.path != snapshot.path);
+            if track_changed {
+                self.controls
+                    .set_metadata(MediaMetadata {
+                        title: Some(
+                            snapshot
+                                .track
+                                .title
+                                .as_deref()
+                                .unwrap_or(&snapshot.filename),
+                        ),
+                        artist: snapshot.track.artist.as_deref(),
+                        album: snapshot.track.album.as_deref(),
+                        duration: Some(snapshot.duration),
+                        cover_url: None,
+                    })
+                    .ok();
+            }
+
+            // The OS extrapolates the position while playing, so only resend
+            // it on state changes and seeks.
+            let in_sync = self.published.as_ref().is_some_and(|published| {
+                let expected = match published.state {
+                    PlaybackState::Playing => published.position + published.at.elapsed(),
+                    PlaybackState::Paused | PlaybackState::Stopped => published.position,
+                };
+                published.state == snapshot.state
+                    && expected.abs_diff(snapshot.position) <= Duration::from_secs(2)
+            });
+            if !track_changed && in_sync {
+                return;
+            }
+
+            let progress = Some(MediaPosition(snapshot.position));
+            let playback = match snapshot.state {
+                PlaybackState::Playing => MediaPlayback::Playing { progress },
+                PlaybackState::Paused => MediaPlayback::Paused { progress },
+                PlaybackState::Stopped => MediaPlayback::Stopped,
+            };
+            self.controls.set_playback(playback).ok();
+
+            self.published = Some(Published {
+                path: snapshot.path.clone(),
+                state: snapshot.state,
+                position: snapshot.position,
+                at: Instant::now(),
+            });
+        }
+    }
+
+    fn commands_for(event: MediaControlEvent, seek_step: i64) -> Vec<Command> {
+        let offset = |direction| match direction {
+            SeekDirection::Forward => 1,
+            SeekDirection::Backward => -1,
+        };
+
+        match event {
+            MediaControlEvent::Play => vec![Command::Play],
+            MediaControlEvent::Pause => vec![Command::Pause],
+            MediaControlEvent::Toggle => vec![Command::TogglePause],
+            MediaControlEvent::Next => vec![Command::NextTrack],
+            MediaControlEvent::Previous => vec![Command::PreviousTrack],
+            MediaControlEvent::Stop => vec![Command::Stop],
+            MediaControlEvent::Seek(direction) => {
+                vec![Command::Seek(offset(direction) * seek_step)]
+            }
+            MediaControlEvent::SeekBy(direction, amount) => {
+                vec![Command::Seek(offset(direction) * amount.as_secs() as i64)]
+            }
+            MediaControlEvent::SetPosition(MediaPosition(position)) => {
+                vec![Command::SeekTo(position)]
+            }
+            MediaControlEvent::SetVolume(volume) => {
+                vec![Command::SetVolume(volume.clamp(0.0, 1.0) as f32)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod macos {
+        use std::ffi::c_void;
+
+        #[link(name = "CoreFoundation", kind = "framework")]
+        unsafe extern "C" {
+            static kCFRunLoopDefaultMode: *const c_void;
+            fn CFRunLoopRunInMode(mode: *const c_void, seconds: f64, return_after: u8) -> i32;
+        }
+
+        /// Remote-command handlers are delivered through the main thread's run
+        /// loop, which a terminal app never runs on its own. Drains whatever
+        /// is pending without blocking.
+        pub fn pump_run_loop() {
+            unsafe {
+                CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, 0);
+            }
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    mod windows {
+        use std::ffi::c_void;
+        use std::ptr;
+
+        use windows_sys::Win32::Foundation::HWND;
+        use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            CreateWindowExW, DestroyWindow, DispatchMessageW, MSG, PM_REMOVE, PeekMessageW,
+            TranslateMessage,
+        };
+        use windows_sys::w;
+
+        pub struct HiddenWindow(HWND);
+
+        impl HiddenWindow {
+            pub fn create() -> Option<Self> {
+                // The predefined STATIC class saves registering one; the window
+                // is never shown.
+                let hwnd = unsafe {
+                    CreateWindowExW(
+                        0,
+                        w!("STATIC"),
+                        w!("apz"),
+                        0,
+                        0,
+                        0,
+                        0,
+                        0,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        GetModuleHandleW(ptr::null()),
+                        ptr::null(),
+                    )
+                };
+                (!hwnd.is_null()).then_some(Self(hwnd))
+            }
+
+            pub fn hwnd(&self) -> *mut c_void {
+                self.0
+            }
+
+            pub fn pump_messages(&self) {
+                unsafe {
+                    let mut message: MSG = std::mem::zeroed();
+                    while PeekMessageW(&mut message, self.0, 0, 0, PM_REMOVE) != 0 {
+                        TranslateMessage(&message);
+                        DispatchMessageW(&message);
+                    }
+                }
+            }
+        }
+
+        impl Drop for HiddenWindow {
+            fn drop(&mut self) {
+                unsafe {
+                    DestroyWindow(self.0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::{Hint, ProbeResult};
+
+use crate::{tracker, ytdlp};
+
+#[derive(Debug, Clone, Default)]
+pub struct TrackInfo {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+}
+
+impl TrackInfo {
+    /// Reads tags from `path`, returning empty info if the file can't be
+    /// probed or carries no tags. Streams found with yt-dlp are described
+    /// by the site instead.
+    pub fn read<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        Self::read_probed(path, probe(path).as_mut())
+    }
+
+    /// Like [`TrackInfo::read`], for a file that's already been probed.
+    pub fn read_probed(path: &Path, probed: Option<&mut ProbeResult>) -> Self {
+        if let Some(info) = ytdlp::track_info(path) {
+            return info;
+        }
+        probed
+            .and_then(read_tags)
+            .map(|tags| Self::from_tags(&tags))
+            .or_else(|| {
+                // Tracker modules have no tags, just a song name.
+                tracker::title(path).map(|title| Self {
+                    title: Some(title),
+                    ..Self::default()
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    fn from_tags(tags: &[Tag]) -> Self {
+        let mut info = Self::default();
+
+        for tag in tags {
+            let value = tag.value.to_string().trim().to_string();
+            if value.is_empty() {
+                continue;
+            }
+
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => info.title = Some(value),
+                Some(StandardTagKey::Artist) => info.artist = Some(value),
+                Some(StandardTagKey::AlbumArtist) if info.artist.is_none() => {
+                    info.artist = Some(value)
+                }
+                Some(StandardTagKey::Album) => info.album = Some(value),
+                Some(StandardTagKey::TrackNumber) => {
+                    // Track numbers are often stored as "3/12".
+                    info.track_number = value.split('/').next().and_then(|n| n.parse().ok());
+                }
+                _ => {}
+            }
+        }
+
+        info
+    }
+
+    /// "Artist – Title" when tags are present, otherwise `fallback`
+    /// (usually the file name).
+    pub fn display_title(&self, fallback: &str) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{} – {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            _ => fallback.to_string(),
+        }
+    }
+}
+
+/// Opens `path` with symphonia, for its tags and codec details.
+pub fn probe(path: &Path) -> Option<ProbeResult> {
+    let file = File::open(path).ok()?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()
+}
+
+fn read_tags(probed: &mut ProbeResult) -> Option<Vec<Tag>> {
+    // Tags may live in the container itself (Vorbis comments, MP4 atoms) or
+    // in a tag block found ahead of it while probing (ID3v2).
+    if let Some(revision) = probed.format.metadata().current() {
+        return Some(revision.tags().to_vec());
+    }
+
+    probed
+        .metadata
+        .get()
+        .and_then(|metadata| metadata.current().map(|revision| revision.tags().to_vec()))
+}
@@ -0,0 +1,191 @@
+//! A click on every beat of the track, to play along to. It's mixed in
+//! after the effects, at the output rate, so it sounds the same whatever
+//! they do to the music, and follows the position in the track rather than
+//! counting time, so it stays on the beat through seeks, loops and changes
+//! of pitch or tempo.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::analyze::BeatGrid;
+use crate::looping::Looper;
+use crate::resample::Resampler;
+
+const CLICK_FREQUENCY: f64 = 1500.0;
+const CLICK_LENGTH: f64 = 0.03;
+/// Time for the click to die away to about a third.
+const CLICK_DECAY: f64 = 0.008;
+const CLICK_LEVEL: f64 = 0.4;
+/// How late into a beat the click can still be played, for the first frame
+/// found to be past it. Later than that, the beat was arrived at by a seek.
+const CLICK_WINDOW: f64 = 0.05;
+
+/// The metronome, shared between the UI and the audio thread.
+pub struct Metronome {
+    enabled: AtomicBool,
+    /// Beat length and first beat in seconds, as `f64`s' bits, with no grid
+    /// while the beat is 0.
+    beat: AtomicU64,
+    first_beat: AtomicU64,
+}
+
+impl Metronome {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            enabled: AtomicBool::new(false),
+            beat: AtomicU64::new(0.0f64.to_bits()),
+            first_beat: AtomicU64::new(0.0f64.to_bits()),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Clicks on the beats of `grid`, or not at all for `None`.
+    pub fn set_grid(&self, grid: Option<BeatGrid>) {
+        self.beat.store(0.0f64.to_bits(), Ordering::Relaxed);
+        if let Some(grid) = grid {
+            let first_beat = grid.first_beat.as_secs_f64();
+            self.first_beat
+                .store(first_beat.to_bits(), Ordering::Relaxed);
+            self.beat
+                .store(grid.beat.as_secs_f64().to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// The beat length and first beat in seconds.
+    fn grid(&self) -> Option<(f64, f64)> {
+        let beat = f64::from_bits(self.beat.load(Ordering::Relaxed));
+        let first_beat = f64::from_bits(self.first_beat.load(Ordering::Relaxed));
+        (beat > 0.0).then_some((beat, first_beat))
+    }
+}
+
+/// Adds the [`Metronome`]'s click to its input while it's on.
+pub struct MetronomeSource<I> {
+    input: I,
+    metronome: Arc<Metronome>,
+    /// For the position in the track.
+    resampler: Arc<Resampler>,
+    looper: Arc<Looper>,
+    /// Beat, counted from the first, that the last frame fell in.
+    beat: Option<i64>,
+    /// Seconds into the click playing, if there is one.
+    click: Option<f64>,
+    /// Level of the click for the current frame.
+    level: f32,
+    /// Samples of the current frame handed out so far.
+    sample: u16,
+}
+
+impl<I> MetronomeSource<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(
+        input: I,
+        metronome: Arc<Metronome>,
+        resampler: Arc<Resampler>,
+        looper: Arc<Looper>,
+    ) -> Self {
+        Self {
+            input,
+            metronome,
+            resampler,
+            looper,
+            beat: None,
+            click: None,
+            level: 0.0,
+            sample: 0,
+        }
+    }
+
+    fn start_frame(&mut self) {
+        match self
+            .metronome
+            .grid()
+            .filter(|_| self.metronome.is_enabled())
+        {
+            Some((beat, first_beat)) => {
+                let position = self
+                    .resampler
+                    .position()
+                    .saturating_sub(self.looper.shift())
+                    .as_secs_f64();
+                let since_first = position - first_beat;
+                let index = (since_first / beat).floor();
+                if self.beat != Some(index as i64) {
+                    self.beat = Some(index as i64);
+                    if since_first - index * beat < CLICK_WINDOW {
+                        self.click = Some(0.0);
+                    }
+                }
+            }
+            None => self.beat = None,
+        }
+
+        self.level = 0.0;
+        if let Some(time) = self.click {
+            let envelope = (-time / CLICK_DECAY).exp();
+            self.level =
+                (CLICK_LEVEL * envelope * (2.0 * PI * CLICK_FREQUENCY * time).sin()) as f32;
+            let time = time + 1.0 / self.input.sample_rate().max(1) as f64;
+            self.click = (time < CLICK_LENGTH).then_some(time);
+        }
+    }
+}
+
+impl<I> Iterator for MetronomeSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample == 0 {
+            self.start_frame();
+        }
+        let sample = self.input.next()?;
+        self.sample += 1;
+        if self.sample >= self.input.channels().max(1) {
+            self.sample = 0;
+        }
+        Some(sample + self.level)
+    }
+}
+
+impl<I> Source for MetronomeSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.sample = 0;
+        Ok(())
+    }
+}
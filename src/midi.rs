@@ -0,0 +1,169 @@
+//! Standard MIDI file playback through rustysynth and a SoundFont.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use rustysynth::{MidiFile, MidiFileSequencer, SoundFont, Synthesizer, SynthesizerSettings};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+pub const EXTENSIONS: [&str; 2] = ["mid", "midi"];
+
+/// Tried in order when `--soundfont` isn't given.
+const DEFAULT_SOUNDFONTS: [&str; 3] = [
+    "/usr/share/sounds/sf2/FluidR3_GM.sf2",
+    "/usr/share/soundfonts/FluidR3_GM.sf2",
+    "/usr/share/soundfonts/default.sf2",
+];
+
+const SAMPLE_RATE: u32 = 44_100;
+/// Frames rendered per call into the synthesizer; a multiple of its
+/// internal block size, so every call ends on a block boundary.
+const BLOCK_FRAMES: usize = 512;
+/// After a seek, how long the notes released on the way are left to fade
+/// out before audio resumes.
+const SEEK_SETTLE: Duration = Duration::from_millis(250);
+
+static SOUNDFONT_PATH: OnceLock<PathBuf> = OnceLock::new();
+static SOUNDFONT: OnceLock<Result<Arc<SoundFont>, &'static str>> = OnceLock::new();
+
+pub fn set_soundfont(path: PathBuf) {
+    SOUNDFONT_PATH.set(path).ok();
+}
+
+pub fn is_midi(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+}
+
+/// General MIDI SoundFonts run to a hundred megabytes, so the first MIDI
+/// file loads one and every later one shares it.
+pub fn soundfont() -> Result<Arc<SoundFont>, &'static str> {
+    SOUNDFONT
+        .get_or_init(|| {
+            let path = SOUNDFONT_PATH
+                .get()
+                .cloned()
+                .or_else(|| {
+                    DEFAULT_SOUNDFONTS
+                        .iter()
+                        .map(PathBuf::from)
+                        .find(|path| path.exists())
+                })
+                .ok_or("MIDI playback needs a SoundFont (--soundfont <path>)")?;
+            let file = File::open(path).map_err(|_| "couldn't open the SoundFont")?;
+            SoundFont::new(&mut BufReader::new(file))
+                .map(Arc::new)
+                .map_err(|_| "couldn't read the SoundFont")
+        })
+        .clone()
+}
+
+pub struct MidiSource {
+    sequencer: MidiFileSequencer,
+    midi_file: Arc<MidiFile>,
+    left: Vec<f32>,
+    right: Vec<f32>,
+    /// Interleaved position within the current block.
+    cursor: usize,
+}
+
+impl MidiSource {
+    pub fn new<R: Read>(reader: &mut R, soundfont: &Arc<SoundFont>) -> Result<Self, &'static str> {
+        let midi_file = MidiFile::new(reader).map_err(|_| "not a valid MIDI file")?;
+        let settings = SynthesizerSettings::new(SAMPLE_RATE as i32);
+        let synthesizer =
+            Synthesizer::new(soundfont, &settings).map_err(|_| "couldn't start the synthesizer")?;
+
+        let midi_file = Arc::new(midi_file);
+        let mut sequencer = MidiFileSequencer::new(synthesizer);
+        sequencer.play(&midi_file, false);
+
+        Ok(Self {
+            sequencer,
+            midi_file,
+            left: vec![0.0; BLOCK_FRAMES],
+            right: vec![0.0; BLOCK_FRAMES],
+            cursor: BLOCK_FRAMES * 2,
+        })
+    }
+
+    fn render_block(&mut self) {
+        self.sequencer.render(&mut self.left, &mut self.right);
+        self.cursor = 0;
+    }
+}
+
+impl Iterator for MidiSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.cursor >= BLOCK_FRAMES * 2 {
+            if self.sequencer.get_position() >= self.midi_file.get_length() {
+                return None;
+            }
+            self.render_block();
+        }
+
+        let (frame, channel) = (self.cursor / 2, self.cursor % 2);
+        let sample = [self.left[frame], self.right[frame]][channel];
+        self.cursor += 1;
+        Some(sample)
+    }
+}
+
+impl Source for MidiSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(self.midi_file.get_length()))
+    }
+
+    /// rustysynth can't seek, but its sequencer advances by `speed` block
+    /// lengths per block, so one block at the right speed lands on `pos`.
+    /// Every event up to there is replayed, which keeps program changes
+    /// and controllers right.
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let target = pos.as_secs_f64().min(self.midi_file.get_length());
+        if target < self.sequencer.get_position() {
+            self.sequencer.play(&self.midi_file, false);
+        }
+
+        let block_size = self.sequencer.get_synthesizer().get_block_size();
+        let remaining = target - self.sequencer.get_position();
+        if remaining > 0.0 {
+            let block = block_size as f64 / SAMPLE_RATE as f64;
+            self.sequencer.set_speed(remaining / block);
+            self.sequencer
+                .render(&mut self.left[..block_size], &mut self.right[..block_size]);
+        }
+
+        // The events skipped over all fire at once; with time stopped, the
+        // notes they released die away before playback resumes.
+        self.sequencer.set_speed(0.0);
+        let settle = SEEK_SETTLE.as_secs_f64() * SAMPLE_RATE as f64;
+        for _ in 0..(settle as usize).div_ceil(BLOCK_FRAMES) {
+            self.sequencer.render(&mut self.left, &mut self.right);
+        }
+        self.sequencer.set_speed(1.0);
+        self.cursor = BLOCK_FRAMES * 2;
+        Ok(())
+    }
+}
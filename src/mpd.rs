@@ -0,0 +1,577 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command::Command;
+use crate::now_playing::Snapshot;
+use crate::player::PlaybackState;
+
+const PROTOCOL_VERSION: &str = "0.23.0";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const SUPPORTED_COMMANDS: &[&str] = &[
+    "add",
+    "addid",
+    "clear",
+    "close",
+    "command_list_begin",
+    "command_list_end",
+    "command_list_ok_begin",
+    "commands",
+    "currentsong",
+    "decoders",
+    "delete",
+    "deleteid",
+    "idle",
+    "lsinfo",
+    "move",
+    "next",
+    "noidle",
+    "notcommands",
+    "outputs",
+    "pause",
+    "ping",
+    "play",
+    "playid",
+    "playlistid",
+    "playlistinfo",
+    "plchanges",
+    "plchangesposid",
+    "previous",
+    "seek",
+    "seekcur",
+    "seekid",
+    "setvol",
+    "stats",
+    "status",
+    "stop",
+    "tagtypes",
+    "urlhandlers",
+    "volume",
+];
+
+/// Serves a subset of the MPD protocol so existing MPD clients can drive
+/// playback and the queue. Song ids are queue positions plus one; apz has
+/// no music database, so `add` takes file paths and library browsing
+/// commands return empty results.
+pub struct MpdServer {
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+}
+
+impl MpdServer {
+    pub fn start(addr: &str, commands: Sender<Command>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let snapshot = Arc::new(Mutex::new(None));
+        let started = Instant::now();
+
+        let shared = Arc::clone(&snapshot);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let session = Session {
+                    commands: commands.clone(),
+                    snapshot: Arc::clone(&shared),
+                    started,
+                };
+                thread::spawn(move || {
+                    session.run(stream).ok();
+                });
+            }
+        });
+
+        Ok(Self { snapshot })
+    }
+
+    pub fn update(&self, snapshot: &Snapshot) {
+        *self.snapshot.lock().unwrap() = Some(snapshot.clone());
+    }
+}
+
+struct Session {
+    commands: Sender<Command>,
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+    started: Instant,
+}
+
+struct Ack {
+    code: u8,
+    message: String,
+}
+
+impl Ack {
+    fn arg(message: impl Into<String>) -> Self {
+        Self {
+            code: 2,
+            message: message.into(),
+        }
+    }
+
+    fn unknown(command: &str) -> Self {
+        Self {
+            code: 5,
+            message: format!("unknown command \"{}\"", command),
+        }
+    }
+}
+
+enum Outcome {
+    Reply(String),
+    Idle(Vec<String>),
+    Close,
+}
+
+impl Session {
+    fn run(self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        writeln!(writer, "OK MPD {}", PROTOCOL_VERSION)?;
+
+        let mut pending = Vec::new();
+        let mut command_list: Option<(bool, Vec<String>)> = None;
+        // The baseline is `None` when the client started idling before the
+        // player published its first state.
+        let mut idle: Option<(Vec<String>, Option<Snapshot>)> = None;
+
+        loop {
+            match reader.read_until(b'\n', &mut pending) {
+                Ok(0) => return Ok(()),
+                Ok(_) if pending.ends_with(b"\n") => {}
+                Ok(_) => continue,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if let Some((subsystems, baseline)) = &idle
+                        && let Some(current) = self.current()
+                    {
+                        let changed = match baseline {
+                            Some(baseline) => changed_subsystems(baseline, &current, subsystems),
+                            None => subsystems.clone(),
+                        };
+                        if !changed.is_empty() {
+                            write_changes(&mut writer, &changed)?;
+                            idle = None;
+                        }
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+
+            let line = String::from_utf8_lossy(&pending).trim().to_string();
+            pending.clear();
+
+            if idle.is_some() {
+                // The only command allowed while idling is `noidle`.
+                if line == "noidle" {
+                    writeln!(writer, "OK")?;
+                }
+                idle = None;
+                continue;
+            }
+
+            if let Some((list_ok, lines)) = command_list.as_mut() {
+                if line == "command_list_end" {
+                    let list_ok = *list_ok;
+                    let lines = std::mem::take(lines);
+                    command_list = None;
+                    if !self.run_command_list(&mut writer, &lines, list_ok)? {
+                        return Ok(());
+                    }
+                } else {
+                    lines.push(line);
+                }
+                continue;
+            }
+
+            match line.as_str() {
+                "command_list_begin" => command_list = Some((false, Vec::new())),
+                "command_list_ok_begin" => command_list = Some((true, Vec::new())),
+                _ => match self.execute(&line) {
+                    Ok(Outcome::Reply(reply)) => writeln!(writer, "{}OK", reply)?,
+                    Ok(Outcome::Idle(subsystems)) => idle = Some((subsystems, self.current())),
+                    Ok(Outcome::Close) => return Ok(()),
+                    Err(ack) => write_ack(&mut writer, &ack, 0, &line)?,
+                },
+            }
+        }
+    }
+
+    /// Runs a `command_list_begin` block, stopping at the first error.
+    /// Returns `false` if the client asked to close the connection.
+    fn run_command_list(
+        &self,
+        writer: &mut impl Write,
+        lines: &[String],
+        list_ok: bool,
+    ) -> io::Result<bool> {
+        let mut reply = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            match self.execute(line) {
+                Ok(Outcome::Reply(output)) => {
+                    reply.push_str(&output);
+                    if list_ok {
+                        reply.push_str("list_OK\n");
+                    }
+                }
+                Ok(Outcome::Idle(_)) => {}
+                Ok(Outcome::Close) => return Ok(false),
+                Err(ack) => {
+                    writer.write_all(reply.as_bytes())?;
+                    write_ack(writer, &ack, index, line)?;
+                    return Ok(true);
+                }
+            }
+        }
+
+        writeln!(writer, "{}OK", reply)?;
+        Ok(true)
+    }
+
+    fn current(&self) -> Option<Snapshot> {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    fn send(&self, command: Command) -> Result<Outcome, Ack> {
+        self.commands
+            .send(command)
+            .map_err(|_| Ack::arg("player is shutting down"))?;
+        Ok(Outcome::Reply(String::new()))
+    }
+
+    fn execute(&self, line: &str) -> Result<Outcome, Ack> {
+        let args = tokenize(line);
+        let Some(name) = args.first().map(String::as_str) else {
+            return Err(Ack::unknown(""));
+        };
+
+        match name {
+            "ping" | "clearerror" | "noidle" => return Ok(Outcome::Reply(String::new())),
+            "close" => return Ok(Outcome::Close),
+            "idle" => {
+                let subsystems = if args.len() > 1 {
+                    args[1..].to_vec()
+                } else {
+                    vec!["player".into(), "mixer".into(), "playlist".into()]
+                };
+                return Ok(Outcome::Idle(subsystems));
+            }
+            _ => {}
+        }
+
+        let Some(snapshot) = self.current() else {
+            return Err(Ack::arg("player is starting"));
+        };
+
+        match name {
+            "status" => Ok(Outcome::Reply(status(&snapshot))),
+            "currentsong" => Ok(Outcome::Reply(
+                snapshot
+                    .queue
+                    .get(snapshot.queue_index)
+                    .map(|_| song(&snapshot, snapshot.queue_index))
+                    .unwrap_or_default(),
+            )),
+            "playlistinfo" | "playlistid" | "plchanges" => {
+                let songs = match (name, args.get(1)) {
+                    ("playlistinfo", Some(pos)) => vec![parse_index(pos, snapshot.queue.len())?],
+                    ("playlistid", Some(id)) => vec![parse_id(id, snapshot.queue.len())?],
+                    _ => (0..snapshot.queue.len()).collect(),
+                };
+                Ok(Outcome::Reply(
+                    songs.into_iter().map(|i| song(&snapshot, i)).collect(),
+                ))
+            }
+            "plchangesposid" => Ok(Outcome::Reply(
+                (0..snapshot.queue.len())
+                    .map(|i| format!("cpos: {}\nId: {}\n", i, i + 1))
+                    .collect(),
+            )),
+            "play" => match args.get(1) {
+                Some(pos) => self.send(Command::PlayIndex(parse_index(pos, snapshot.queue.len())?)),
+                None => self.send(Command::Play),
+            },
+            "playid" => match args.get(1) {
+                Some(id) => self.send(Command::PlayIndex(parse_id(id, snapshot.queue.len())?)),
+                None => self.send(Command::Play),
+            },
+            "pause" => match args.get(1).map(String::as_str) {
+                Some("1") => self.send(Command::Pause),
+                Some("0") => self.send(Command::Play),
+                _ => self.send(Command::TogglePause),
+            },
+            "stop" => self.send(Command::Stop),
+            "next" => self.send(Command::NextTrack),
+            "previous" => self.send(Command::PreviousTrack),
+            "seekcur" => {
+                let time = args.get(1).ok_or_else(|| Ack::arg("missing time"))?;
+                if time.starts_with('+') || time.starts_with('-') {
+                    // No seek can go further than the length of the track.
+                    let offset: f64 = time
+                        .parse()
+                        .ok()
+                        .filter(|offset: &f64| offset.abs() <= snapshot.duration.as_secs_f64())
+                        .ok_or_else(|| Ack::arg("invalid time"))?;
+                    self.send(Command::Seek(offset.round() as i64))
+                } else {
+                    self.send(Command::SeekTo(parse_time(time)?))
+                }
+            }
+            "seek" | "seekid" => {
+                let (Some(song), Some(time)) = (args.get(1), args.get(2)) else {
+                    return Err(Ack::arg("expected song and time"));
+                };
+                let index = if name == "seek" {
+                    parse_index(song, snapshot.queue.len())?
+                } else {
+                    parse_id(song, snapshot.queue.len())?
+                };
+                if index != snapshot.queue_index {
+                    self.send(Command::PlayIndex(index))?;
+                }
+                self.send(Command::SeekTo(parse_time(time)?))
+            }
+            "setvol" => {
+                let level: f32 = args
+                    .get(1)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| Ack::arg("invalid volume"))?;
+                self.send(Command::SetVolume(level.clamp(0.0, 100.0) / 100.0))
+            }
+            "volume" => {
+                let delta: f32 = args
+                    .get(1)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| Ack::arg("invalid volume change"))?;
+                let level = (snapshot.volume * 100.0 + delta).clamp(0.0, 100.0);
+                self.send(Command::SetVolume(level / 100.0))
+            }
+            "add" | "addid" => {
+                let uri = args.get(1).ok_or_else(|| Ack::arg("missing uri"))?;
+                let uri = uri.strip_prefix("file://").unwrap_or(uri);
+                self.send(Command::Enqueue(PathBuf::from(uri)))?;
+                Ok(Outcome::Reply(if name == "addid" {
+                    format!("Id: {}\n", snapshot.queue.len() + 1)
+                } else {
+                    String::new()
+                }))
+            }
+            "delete" | "deleteid" => {
+                let arg = args.get(1).ok_or_else(|| Ack::arg("missing argument"))?;
+                let index = if name == "delete" {
+                    parse_index(arg, snapshot.queue.len())?
+                } else {
+                    parse_id(arg, snapshot.queue.len())?
+                };
+                self.send(Command::RemoveFromQueue(index))
+            }
+            // The playing track can't be removed, so it stays.
+            "clear" => self.send(Command::ClearQueue),
+            "move" => {
+                let (Some(from), Some(to)) = (args.get(1), args.get(2)) else {
+                    return Err(Ack::arg("expected from and to"));
+                };
+                let from = parse_index(from, snapshot.queue.len())?;
+                let to = parse_index(to, snapshot.queue.len())?;
+                self.send(Command::MoveInQueue(from, to))
+            }
+            "outputs" => Ok(Outcome::Reply(
+                "outputid: 0\noutputname: default\nplugin: rodio\noutputenabled: 1\n".into(),
+            )),
+            "stats" => Ok(Outcome::Reply(format!(
+                "artists: 0\nalbums: 0\nsongs: {}\nuptime: {}\nplaytime: {}\ndb_playtime: 0\n",
+                snapshot.queue.len(),
+                self.started.elapsed().as_secs(),
+                self.started.elapsed().as_secs(),
+            ))),
+            "commands" => Ok(Outcome::Reply(
+                SUPPORTED_COMMANDS
+                    .iter()
+                    .map(|c| format!("command: {}\n", c))
+                    .collect(),
+            )),
+            "tagtypes" => Ok(Outcome::Reply(
+                ["Artist", "Album", "Title", "Track"]
+                    .iter()
+                    .map(|t| format!("tagtype: {}\n", t))
+                    .collect(),
+            )),
+            "notcommands" | "decoders" | "urlhandlers" | "lsinfo" | "listplaylists" | "list"
+            | "find" | "search" => Ok(Outcome::Reply(String::new())),
+            _ => Err(Ack::unknown(name)),
+        }
+    }
+}
+
+fn status(snapshot: &Snapshot) -> String {
+    let state = match snapshot.state {
+        PlaybackState::Playing => "play",
+        PlaybackState::Paused => "pause",
+        PlaybackState::Stopped => "stop",
+    };
+    let elapsed = snapshot.position.as_secs_f64();
+    let duration = snapshot.duration.as_secs_f64();
+
+    let mut status = format!(
+        concat!(
+            "volume: {}\nrepeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\n",
+            "playlist: {}\nplaylistlength: {}\nstate: {}\n"
+        ),
+        (snapshot.volume * 100.0).round() as u32,
+        snapshot.queue_version + 1,
+        snapshot.queue.len(),
+        state,
+    );
+
+    if snapshot.queue_index < snapshot.queue.len() {
+        status.push_str(&format!(
+            "song: {}\nsongid: {}\ntime: {}:{}\nelapsed: {:.3}\nduration: {:.3}\n",
+            snapshot.queue_index,
+            snapshot.queue_index + 1,
+            elapsed as u64,
+            duration as u64,
+            elapsed,
+            duration,
+        ));
+        if snapshot.queue_index + 1 < snapshot.queue.len() {
+            status.push_str(&format!(
+                "nextsong: {}\nnextsongid: {}\n",
+                snapshot.queue_index + 1,
+                snapshot.queue_index + 2
+            ));
+        }
+    }
+
+    status
+}
+
+fn song(snapshot: &Snapshot, index: usize) -> String {
+    let path = &snapshot.queue[index];
+    let mut song = format!("file: {}\n", path.display());
+
+    // Tags and duration are only known for the loaded track.
+    if index == snapshot.queue_index {
+        let tags = [
+            ("Artist", &snapshot.track.artist),
+            ("Album", &snapshot.track.album),
+            ("Title", &snapshot.track.title),
+        ];
+        for (name, value) in tags {
+            if let Some(value) = value {
+                song.push_str(&format!("{}: {}\n", name, value));
+            }
+        }
+        if let Some(track) = snapshot.track.track_number {
+            song.push_str(&format!("Track: {}\n", track));
+        }
+        song.push_str(&format!(
+            "Time: {}\nduration: {:.3}\n",
+            snapshot.duration.as_secs(),
+            snapshot.duration.as_secs_f64()
+        ));
+    } else if let Some(name) = path.file_stem() {
+        song.push_str(&format!("Title: {}\n", name.to_string_lossy()));
+    }
+
+    song.push_str(&format!("Pos: {}\nId: {}\n", index, index + 1));
+    song
+}
+
+fn changed_subsystems(before: &Snapshot, after: &Snapshot, wanted: &[String]) -> Vec<String> {
+    let mut changed = Vec::new();
+
+    if before.state != after.state || before.path != after.path {
+        changed.push("player");
+    }
+    if (before.volume - after.volume).abs() > f32::EPSILON {
+        changed.push("mixer");
+    }
+    if before.queue_version != after.queue_version {
+        changed.push("playlist");
+    }
+
+    changed
+        .into_iter()
+        .filter(|subsystem| wanted.iter().any(|w| w == subsystem))
+        .map(String::from)
+        .collect()
+}
+
+fn write_changes(writer: &mut impl Write, changed: &[String]) -> io::Result<()> {
+    for subsystem in changed {
+        writeln!(writer, "changed: {}", subsystem)?;
+    }
+    writeln!(writer, "OK")
+}
+
+fn write_ack(writer: &mut impl Write, ack: &Ack, index: usize, line: &str) -> io::Result<()> {
+    let command = line.split_whitespace().next().unwrap_or_default();
+    writeln!(
+        writer,
+        "ACK [{}@{}] {{{}}} {}",
+        ack.code, index, command, ack.message
+    )
+}
+
+fn parse_index(value: &str, len: usize) -> Result<usize, Ack> {
+    value
+        .parse()
+        .ok()
+        .filter(|&index| index < len)
+        .ok_or_else(|| Ack::arg("Bad song index"))
+}
+
+fn parse_id(value: &str, len: usize) -> Result<usize, Ack> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|&id| id >= 1 && id <= len)
+        .map(|id| id - 1)
+        .ok_or_else(|| Ack::arg("No such song"))
+}
+
+fn parse_time(value: &str) -> Result<Duration, Ack> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| Ack::arg("invalid time"))
+}
+
+/// Splits an MPD command line into arguments, honouring double quotes and
+/// backslash escapes inside them.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut arg = String::new();
+        if c == '"' {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => arg.extend(chars.next()),
+                    c => arg.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+
+    args
+}
@@ -0,0 +1,193 @@
+//! Sending the audio over the network instead of to a sound card, so apz
+//! running on a server can feed a Snapcast server or anything else that
+//! takes raw PCM. The audio goes out as 16-bit little-endian stereo at
+//! 48 kHz, Snapcast's default sample format, paced to play in real time.
+
+use rodio::source::UniformSourceIterator;
+use rodio::{Sink, Source};
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::ApzError;
+
+pub const SAMPLE_RATE: u32 = 48000;
+const CHANNELS: u16 = 2;
+/// Frames sent at a time: 5 ms, small enough for a UDP datagram to fit in
+/// an Ethernet frame.
+const BLOCK_FRAMES: usize = 240;
+/// How often a lost TCP connection is tried again. Playback carries on
+/// meanwhile, the audio going nowhere.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+/// How long connecting may take at start, and then how long a reconnect or
+/// a write may hold up the audio before the connection is given up on.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const STALL_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Where `--output` sends the audio.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// Connects to `host:port`, such as a Snapcast TCP source in server
+    /// mode.
+    Tcp(String),
+    /// Sends datagrams to `host:port`.
+    Udp(String),
+}
+
+impl Target {
+    /// Parses `tcp://host:port` or `udp://host:port`.
+    pub fn parse(url: &str) -> Option<Self> {
+        let (scheme, address) = url.split_once("://")?;
+        let address = address.trim_end_matches('/');
+        if address.rsplit_once(':')?.1.parse::<u16>().is_err() {
+            return None;
+        }
+        match scheme {
+            "tcp" => Some(Self::Tcp(address.to_string())),
+            "udp" => Some(Self::Udp(address.to_string())),
+            _ => None,
+        }
+    }
+
+    fn connect(&self, timeout: Duration) -> io::Result<Connection> {
+        match self {
+            Self::Tcp(address) => {
+                let mut last_error = None;
+                for address in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&address, timeout) {
+                        Ok(stream) => {
+                            stream.set_nodelay(true)?;
+                            stream.set_write_timeout(Some(STALL_TIMEOUT))?;
+                            return Ok(Connection::Tcp(stream));
+                        }
+                        Err(e) => last_error = Some(e),
+                    }
+                }
+                Err(last_error.unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "no addresses found")
+                }))
+            }
+            Self::Udp(address) => {
+                let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+                socket.connect(address)?;
+                Ok(Connection::Udp(socket))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(f, "tcp://{}", address),
+            Self::Udp(address) => write!(f, "udp://{}", address),
+        }
+    }
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Connection {
+    fn send(&mut self, block: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.write_all(block),
+            // Nobody listening is no reason to stop.
+            Self::Udp(socket) => socket.send(block).map(drop).or(Ok(())),
+        }
+    }
+}
+
+/// The thread sending the audio, stopped when this is dropped.
+pub struct NetworkOutput {
+    stopped: Arc<AtomicBool>,
+}
+
+impl Drop for NetworkOutput {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Connects to `target` and returns a sink whose audio is sent there.
+pub fn start(target: &Target) -> Result<(NetworkOutput, Sink), ApzError> {
+    let connection = target
+        .connect(CONNECT_TIMEOUT)
+        .map_err(|e| ApzError::Device(format!("can't connect to {}: {}", target, e)))?;
+    let (sink, queue) = Sink::new_idle();
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let target = target.clone();
+    let stopping = Arc::clone(&stopped);
+    thread::spawn(move || {
+        let audio = UniformSourceIterator::<_, i16>::new(queue, CHANNELS, SAMPLE_RATE);
+        send(audio, Some(target), Some(connection), &stopping);
+    });
+    Ok((NetworkOutput { stopped }, sink))
+}
+
+/// Returns a sink played in real time but sent nowhere, for keeping time
+/// while the audio is played somewhere else, as when casting.
+pub fn start_muted() -> (NetworkOutput, Sink) {
+    let (sink, queue) = Sink::new_idle();
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let stopping = Arc::clone(&stopped);
+    thread::spawn(move || {
+        let audio = UniformSourceIterator::<_, i16>::new(queue, CHANNELS, SAMPLE_RATE);
+        send(audio, None, None, &stopping);
+    });
+    (NetworkOutput { stopped }, sink)
+}
+
+fn send(
+    mut audio: impl Source<Item = i16>,
+    target: Option<Target>,
+    mut connection: Option<Connection>,
+    stopped: &AtomicBool,
+) {
+    let block_duration = Duration::from_secs(BLOCK_FRAMES as u64) / SAMPLE_RATE;
+    let mut reconnect_at = Instant::now();
+    let mut block = Vec::with_capacity(BLOCK_FRAMES * CHANNELS as usize * 2);
+    let mut due = Instant::now();
+
+    while !stopped.load(Ordering::Relaxed) {
+        block.clear();
+        for sample in audio.by_ref().take(BLOCK_FRAMES * CHANNELS as usize) {
+            block.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        if let Some(target) = &target
+            && connection.is_none()
+            && Instant::now() >= reconnect_at
+        {
+            connection = target.connect(STALL_TIMEOUT).ok();
+            reconnect_at = Instant::now() + RECONNECT_INTERVAL;
+        }
+        // A write that times out leaves the stream partway through a
+        // block, so it's dropped like any other failure.
+        if let Some(open) = &mut connection
+            && open.send(&block).is_err()
+        {
+            connection = None;
+            reconnect_at = Instant::now() + RECONNECT_INTERVAL;
+        }
+
+        // Keeping to the clock rather than sleeping a block's length each
+        // time, so the time spent sending doesn't add up to a drift.
+        due += block_duration;
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        } else if now - due > block_duration * 20 {
+            // Fell far behind, after a stall; start counting again.
+            due = now;
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! `apz normalize`: measures files and writes ReplayGain tags to them, so
+//! players that read the tags play everything at the same loudness. Only the
+//! tags change; the audio isn't re-encoded.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::analyze;
+use crate::directory::{self, ScanConfig};
+use crate::loudness::{self, LoudnessMeter};
+use crate::tags;
+
+/// ReplayGain 2.0 brings tracks to this loudness, in LUFS.
+const REPLAYGAIN_REFERENCE: f64 = -18.0;
+/// Opus's R128 gain tags are relative to EBU R128's target instead.
+const R128_REFERENCE: f64 = -23.0;
+
+/// Gain to apply to a track or album, and its true peak before that.
+#[derive(Debug, Clone, Copy)]
+struct Gain {
+    loudness: f64,
+    peak: f32,
+}
+
+impl Gain {
+    fn replaygain_db(&self) -> f64 {
+        REPLAYGAIN_REFERENCE - self.loudness
+    }
+
+    /// R128 gains are whole 1/256ths of a dB.
+    fn r128(&self) -> i16 {
+        ((R128_REFERENCE - self.loudness) * 256.0)
+            .round()
+            .clamp(i16::MIN as f64, i16::MAX as f64) as i16
+    }
+}
+
+/// Runs `apz normalize` with the arguments that follow it, returning the exit
+/// status: 1 if any file couldn't be measured or tagged.
+pub fn run(args: &[String]) -> i32 {
+    let mut scan = ScanConfig::default();
+    let mut dry_run = false;
+    let mut paths = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "-r" | "--recursive" => scan.recursive = true,
+            "-n" | "--dry-run" => dry_run = true,
+            "-h" | "--help" => usage(""),
+            arg if arg.starts_with('-') => usage(&format!("Unknown option: {}", arg)),
+            arg => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    let paths = directory::expand(&paths, &scan);
+    if paths.is_empty() {
+        usage("No audio files specified");
+    }
+
+    let meters = analyze::map_parallel(&paths, loudness::measure);
+    let mut failed = false;
+
+    // Each directory's files are taken to be an album.
+    let mut albums: BTreeMap<&Path, Vec<(&PathBuf, &LoudnessMeter)>> = BTreeMap::new();
+    for (path, meter) in paths.iter().zip(&meters) {
+        match meter {
+            Ok(meter) => albums
+                .entry(path.parent().unwrap_or(Path::new("")))
+                .or_default()
+                .push((path, meter)),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+
+    for tracks in albums.values() {
+        let album =
+            loudness::combined(tracks.iter().map(|(_, meter)| *meter)).map(|loudness| Gain {
+                loudness,
+                peak: tracks
+                    .iter()
+                    .map(|(_, meter)| meter.true_peak())
+                    .fold(0.0, f32::max),
+            });
+
+        for (path, meter) in tracks {
+            let (Some(track), Some(album)) = (
+                meter.integrated().map(|loudness| Gain {
+                    loudness,
+                    peak: meter.true_peak(),
+                }),
+                album,
+            ) else {
+                eprintln!("{}: too quiet to measure", path.display());
+                failed = true;
+                continue;
+            };
+
+            println!(
+                "{}: {:.1} LUFS, track gain {:+.2} dB, album gain {:+.2} dB",
+                path.display(),
+                track.loudness,
+                track.replaygain_db(),
+                album.replaygain_db()
+            );
+            if dry_run {
+                continue;
+            }
+            if let Err(e) = tags::write_custom(path, &fields(path, track, album)) {
+                eprintln!("{}: couldn't write tags: {}", path.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed { 1 } else { 0 }
+}
+
+fn usage(error: &str) -> ! {
+    if !error.is_empty() {
+        eprintln!("Error: {}", error);
+    }
+    eprintln!("Usage: apz normalize [OPTIONS] <audio_file|directory>...");
+    eprintln!(
+        "\nWrites ReplayGain tags (R128 gain tags for Opus) to MP3, FLAC, Ogg Vorbis\nand Opus files. The files in each directory are treated as an album."
+    );
+    eprintln!("\nOptions:");
+    eprintln!("  -r, --recursive        Include files in subdirectories of a directory");
+    eprintln!("  -n, --dry-run          Show the gains without writing anything");
+    process::exit(if error.is_empty() { 0 } else { 1 });
+}
+
+/// The tags for a file. Opus players take their gain from `R128_*` tags
+/// rather than ReplayGain's, and apply it on top of the gain in the header.
+fn fields(path: &Path, track: Gain, album: Gain) -> Vec<(&'static str, String)> {
+    let opus = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("opus"));
+    if opus {
+        return vec![
+            ("R128_TRACK_GAIN", track.r128().to_string()),
+            ("R128_ALBUM_GAIN", album.r128().to_string()),
+        ];
+    }
+
+    vec![
+        (
+            "REPLAYGAIN_TRACK_GAIN",
+            format!("{:.2} dB", track.replaygain_db()),
+        ),
+        ("REPLAYGAIN_TRACK_PEAK", format!("{:.6}", track.peak)),
+        (
+            "REPLAYGAIN_ALBUM_GAIN",
+            format!("{:.2} dB", album.replaygain_db()),
+        ),
+        ("REPLAYGAIN_ALBUM_PEAK", format!("{:.6}", album.peak)),
+    ]
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::json;
+use crate::metadata::TrackInfo;
+use crate::player::PlaybackState;
+use crate::queue::Queue;
+use crate::ui::UIState;
+
+const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Copy of the player state taken once per main-loop iteration, for
+/// consumers that can't borrow the UI state directly (background servers,
+/// the status file).
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub filename: String,
+    pub track: TrackInfo,
+    pub state: PlaybackState,
+    pub position: Duration,
+    pub duration: Duration,
+    pub volume: f32,
+    pub queue: Vec<PathBuf>,
+    pub queue_index: usize,
+    pub queue_version: u64,
+}
+
+impl Snapshot {
+    pub fn capture(state: &UIState, queue: &Queue) -> Self {
+        Self {
+            path: state.path.clone(),
+            filename: state.filename.clone(),
+            track: state.track.clone(),
+            state: state.state,
+            position: state.position,
+            duration: state.duration,
+            volume: state.volume,
+            queue: queue.tracks().to_vec(),
+            queue_index: queue.current_index(),
+            queue_version: queue.version(),
+        }
+    }
+
+    /// Serializes what's currently playing as a single JSON object.
+    pub fn to_json(&self) -> String {
+        let optional = |value: &Option<String>| match value {
+            Some(value) => json::string(value),
+            None => "null".to_string(),
+        };
+
+        format!(
+            concat!(
+                "{{\"path\":{},\"filename\":{},\"title\":{},\"artist\":{},\"album\":{},",
+                "\"track_number\":{},\"state\":{},\"position\":{:.3},\"duration\":{:.3},",
+                "\"volume\":{:.2}}}"
+            ),
+            json::string(&self.path.to_string_lossy()),
+            json::string(&self.filename),
+            optional(&self.track.title),
+            optional(&self.track.artist),
+            optional(&self.track.album),
+            self.track
+                .track_number
+                .map_or("null".to_string(), |n| n.to_string()),
+            json::string(state_name(self.state)),
+            self.position.as_secs_f64(),
+            self.duration.as_secs_f64(),
+            self.volume,
+        )
+    }
+
+    pub fn queue_json(&self) -> String {
+        let tracks: Vec<String> = self
+            .queue
+            .iter()
+            .map(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                format!(
+                    r#"{{"path":{},"name":{}}}"#,
+                    json::string(&path.to_string_lossy()),
+                    json::string(&name)
+                )
+            })
+            .collect();
+
+        format!(
+            r#"{{"current":{},"tracks":[{}]}}"#,
+            self.queue_index,
+            tracks.join(",")
+        )
+    }
+}
+
+pub fn state_name(state: PlaybackState) -> &'static str {
+    match state {
+        PlaybackState::Playing => "playing",
+        PlaybackState::Paused => "paused",
+        PlaybackState::Stopped => "stopped",
+    }
+}
+
+/// Periodically mirrors [`Snapshot::to_json`] into a file for OBS overlays and status
+/// bars. Writes go through a temporary file and a rename so readers never
+/// see a half-written document. The file is removed on exit.
+pub struct StatusFile {
+    path: PathBuf,
+    last_write: Option<Instant>,
+    last_state: Option<PlaybackState>,
+}
+
+impl StatusFile {
+    pub fn new(path: PathBuf) -> Self {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        Self {
+            path,
+            last_write: None,
+            last_state: None,
+        }
+    }
+
+    pub fn update(&mut self, snapshot: &Snapshot) {
+        let state_changed = self.last_state != Some(snapshot.state);
+        let due = self
+            .last_write
+            .is_none_or(|written| written.elapsed() >= WRITE_INTERVAL);
+        if !state_changed && !due {
+            return;
+        }
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        if fs::write(&temp, snapshot.to_json()).is_ok() {
+            fs::rename(&temp, &self.path).ok();
+        }
+
+        self.last_write = Some(Instant::now());
+        self.last_state = Some(snapshot.state);
+    }
+}
+
+impl Drop for StatusFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
@@ -0,0 +1,258 @@
+//! Ogg Opus playback. Symphonia can demux Opus but has no decoder for it,
+//! so `.opus` files are read here instead.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use ogg::PacketReader;
+use rodio::Source;
+use rodio::decoder::DecoderError;
+use rodio::source::SeekError;
+use ropus::{Channels, DecodeMode, Decoder};
+
+/// Opus always decodes at 48 kHz; granule positions count samples at this rate.
+const SAMPLE_RATE: u32 = 48_000;
+/// Decoding starts this far before a seek target so the decoder has
+/// converged by the time audio is heard (RFC 7845, section 4.6).
+const SEEK_PREROLL: u64 = 3840;
+
+pub struct OpusSource<R: Read + Seek> {
+    reader: PacketReader<R>,
+    decoder: Decoder,
+    channels: u16,
+    pre_skip: u64,
+    /// From the header, in Q7.8 dB.
+    output_gain: i16,
+    total_duration: Option<Duration>,
+    /// Granule position at the end of the last decoded packet.
+    granule: u64,
+    /// Frames still to drop from the front of `buffer`, for the pre-skip
+    /// and for landing exactly on a seek target.
+    skip: u64,
+    buffer: Vec<i16>,
+    cursor: usize,
+    pcm: Vec<i16>,
+}
+
+impl<R: Read + Seek> OpusSource<R> {
+    pub fn new(mut data: R) -> Result<Self, DecoderError> {
+        let last_granule = last_granule(&mut data).map_err(io_error)?;
+        let mut reader = PacketReader::new(data);
+
+        let head = reader
+            .read_packet()
+            .map_err(|_| DecoderError::UnrecognizedFormat)?
+            .ok_or(DecoderError::UnrecognizedFormat)?;
+        let head = head.data;
+        if head.len() < 19 || !head.starts_with(b"OpusHead") {
+            return Err(DecoderError::UnrecognizedFormat);
+        }
+        let channels = head[9] as u16;
+        let pre_skip = u16::from_le_bytes([head[10], head[11]]) as u64;
+        let output_gain = i16::from_le_bytes([head[16], head[17]]);
+        // Mapping family 0 is plain mono/stereo; the others are multichannel.
+        if head[18] != 0 || !(1..=2).contains(&channels) {
+            return Err(DecoderError::DecodeError("unsupported Opus channel layout"));
+        }
+
+        // OpusTags, which the metadata reader already handles.
+        reader.read_packet().map_err(ogg_error)?;
+
+        let decoder = new_decoder(channels, output_gain)
+            .ok_or(DecoderError::DecodeError("couldn't create Opus decoder"))?;
+        let pcm = vec![0; decoder.max_frame_samples_per_channel() * channels as usize];
+
+        Ok(Self {
+            reader,
+            decoder,
+            channels,
+            pre_skip,
+            output_gain,
+            total_duration: last_granule.map(|granule| {
+                Duration::from_secs_f64(
+                    granule.saturating_sub(pre_skip) as f64 / SAMPLE_RATE as f64,
+                )
+            }),
+            granule: 0,
+            skip: pre_skip,
+            buffer: Vec::new(),
+            cursor: 0,
+            pcm,
+        })
+    }
+
+    /// Decodes the next packet into `buffer`. Returns `false` at the end of
+    /// the stream or on an unreadable packet.
+    fn decode_next(&mut self) -> bool {
+        let Ok(Some(packet)) = self.reader.read_packet() else {
+            return false;
+        };
+        let Ok(frames) = self
+            .decoder
+            .decode(&packet.data, &mut self.pcm, DecodeMode::Normal)
+        else {
+            return false;
+        };
+
+        let start = self.granule;
+        let mut frames = frames as u64;
+        // The last page's granule position marks where the audio really
+        // ends; the final packet is usually padded past it.
+        if packet.last_in_stream() {
+            frames = frames.min(packet.absgp_page().saturating_sub(start));
+        }
+        self.granule = if packet.last_in_page() {
+            packet.absgp_page()
+        } else {
+            start + frames
+        };
+
+        let dropped = self.skip.min(frames);
+        self.skip -= dropped;
+        let channels = self.channels as usize;
+        self.buffer.clear();
+        self.buffer
+            .extend_from_slice(&self.pcm[dropped as usize * channels..frames as usize * channels]);
+        self.cursor = 0;
+        true
+    }
+}
+
+impl<R: Read + Seek> Iterator for OpusSource<R> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        while self.cursor >= self.buffer.len() {
+            if !self.decode_next() {
+                return None;
+            }
+        }
+        let sample = self.buffer[self.cursor];
+        self.cursor += 1;
+        Some(sample)
+    }
+}
+
+impl<R: Read + Seek> Source for OpusSource<R> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let target = (pos.as_secs_f64() * SAMPLE_RATE as f64) as u64 + self.pre_skip;
+        let start = target.saturating_sub(SEEK_PREROLL);
+        // Decoder state from before the jump would bleed into the new position.
+        if let Some(decoder) = new_decoder(self.channels, self.output_gain) {
+            self.decoder = decoder;
+        }
+        self.buffer.clear();
+        self.cursor = 0;
+
+        if start == 0 {
+            // Near the beginning: replay from the first audio packet.
+            self.reader
+                .seek_bytes(SeekFrom::Start(0))
+                .map_err(seek_error)?;
+            // OpusHead and OpusTags.
+            for _ in 0..2 {
+                self.reader.read_packet().map_err(seek_error)?;
+            }
+            self.granule = 0;
+            self.skip = target;
+            return Ok(());
+        }
+
+        let found = self.reader.seek_absgp(None, start).map_err(seek_error)?;
+        if !found {
+            // Past the end: leave nothing to play.
+            self.reader
+                .seek_bytes(SeekFrom::End(0))
+                .map_err(seek_error)?;
+            return Ok(());
+        }
+
+        // Page granule positions only say where a page ends, so decode up to
+        // the end of the first page to find where its packets started.
+        let channels = self.channels as usize;
+        let mut decoded = Vec::new();
+        let mut page_end = None;
+        while page_end.is_none() {
+            let Ok(Some(packet)) = self.reader.read_packet() else {
+                break;
+            };
+            let Ok(frames) = self
+                .decoder
+                .decode(&packet.data, &mut self.pcm, DecodeMode::Normal)
+            else {
+                break;
+            };
+            decoded.extend_from_slice(&self.pcm[..frames * channels]);
+            if packet.last_in_page() {
+                page_end = Some(packet.absgp_page());
+            }
+        }
+
+        let page_end = page_end.unwrap_or(target);
+        let page_start = page_end.saturating_sub((decoded.len() / channels) as u64);
+        let dropped = target
+            .saturating_sub(page_start)
+            .min((decoded.len() / channels) as u64);
+        self.granule = page_end;
+        self.skip = target.saturating_sub(page_start) - dropped;
+        self.buffer = decoded.split_off(dropped as usize * channels);
+        Ok(())
+    }
+}
+
+fn new_decoder(channels: u16, output_gain: i16) -> Option<Decoder> {
+    let layout = if channels == 1 {
+        Channels::Mono
+    } else {
+        Channels::Stereo
+    };
+    let mut decoder = Decoder::new(SAMPLE_RATE, layout).ok()?;
+    decoder.set_gain(output_gain as i32).ok()?;
+    Some(decoder)
+}
+
+/// Granule position of the final page, for the track length. Ogg has no
+/// index, so this scans backwards from the end for the last page header.
+fn last_granule<R: Read + Seek>(data: &mut R) -> std::io::Result<Option<u64>> {
+    let length = data.seek(SeekFrom::End(0))?;
+    let tail_length = length.min(64 * 1024);
+    data.seek(SeekFrom::Start(length - tail_length))?;
+    let mut tail = vec![0; tail_length as usize];
+    data.read_exact(&mut tail)?;
+    data.seek(SeekFrom::Start(0))?;
+
+    let granule = tail
+        .windows(4)
+        .rposition(|window| window == b"OggS")
+        .and_then(|offset| tail.get(offset + 6..offset + 14))
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()));
+    Ok(granule)
+}
+
+fn io_error(error: std::io::Error) -> DecoderError {
+    DecoderError::IoError(error.to_string())
+}
+
+fn ogg_error(error: ogg::OggReadError) -> DecoderError {
+    DecoderError::IoError(error.to_string())
+}
+
+fn seek_error<E: std::error::Error + Send + 'static>(error: E) -> SeekError {
+    SeekError::Other(Box::new(error))
+}
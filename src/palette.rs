@@ -0,0 +1,343 @@
+//! The `:` command line, which gives every action a name so nothing depends
+//! on remembering its key, e.g. `:seek 2:00`, `:vol 40` or `:theme nord`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::command::Command;
+use crate::cues;
+use crate::envelope;
+use crate::library;
+use crate::paths;
+use crate::resample::{self, Quality};
+use crate::stereo::{self, Channel};
+use crate::theme::{self, Theme};
+use crate::ui::{self, Panel, SpectrumLayout};
+use crate::visualizer::{self, Visualizer};
+
+const COMMANDS: &str = "play, pause, toggle, stop, next, prev, restart, seek, vol, open, enqueue, \
+                        insert, queue, clear, theme, hide, show, layout, visualizer, freq, curve, \
+                        remaining, stop-after, auto-advance, bookmark, rate, recent, podcast, in, \
+                        out, clip, eq, compress, mono, width, solo, resampler, transpose, ab, \
+                        diff, deck, cue, practice, metronome, tap, gain, track-gain, quit";
+
+pub enum Action {
+    Command(Command),
+    /// Change the volume by this many percentage points.
+    VolumeBy(f32),
+    Restart,
+    Theme(Theme),
+    Hide(Panel),
+    Show(Panel),
+    /// Draw the spectrum this way, or the next way for `None`.
+    SpectrumLayout(Option<SpectrumLayout>),
+    /// Show this visualization, or the next for `None`.
+    Visualizer(Option<Box<dyn Visualizer>>),
+    /// Spread the spectrum's bars over this range of frequencies, in Hz.
+    SpectrumRange(f32, f32),
+    /// Space the spectrum's bars with this exponent.
+    SpectrumCurve(f32),
+    ToggleRemaining,
+    StopAfterTrack,
+    AutoAdvance,
+    Bookmark(String),
+    /// Set or, for `None`, clear the current track's rating.
+    Rate(Option<u8>),
+    /// Open the Recent panel.
+    Recent,
+    /// List the episodes of a podcast feed, or of the last one listed.
+    Podcast(Option<String>),
+    /// Open the queue panel.
+    Queue,
+    /// Open the equalizer's bands for adjusting.
+    Equalizer,
+    /// Turn the compressor on or off.
+    Compressor,
+    /// Switch between the files in A/B mode.
+    Ab,
+    /// Play the difference between the files in A/B mode, or stop.
+    Diff,
+    /// Switch between mono and stereo.
+    Mono,
+    /// Play only one channel, on both sides, or go back to both.
+    Solo(Option<Channel>),
+    /// Change how tracks are converted to the device's sample rate.
+    Resampler(Quality),
+    /// Set the stereo width, 1.0 being as recorded.
+    Width(f32),
+    /// Play this many semitones up or down.
+    Transpose(i32),
+    /// Mark the in or out point of a clip, at the current position unless
+    /// a time is given.
+    MarkIn(Option<Duration>),
+    MarkOut(Option<Duration>),
+    /// Save the marked clip to a file, or ask where to for `None`.
+    ExportClip(Option<PathBuf>),
+    /// Open deck mode.
+    Deck,
+    /// Set a hot cue, at the current position unless a time is given.
+    SetCue(usize, Option<Duration>),
+    ClearCue(usize),
+    /// Start or stop practising along to the marked clip.
+    Practice,
+    /// Turn the metronome on or off, or the other way for `None`.
+    Metronome(Option<bool>),
+    /// Put a beat at the current position.
+    Tap,
+    /// Add a volume keyframe, in dB, at the current position unless a time
+    /// is given.
+    SetGain(f32, Option<Duration>),
+    /// Remove the volume keyframe at or near a time, or the current
+    /// position.
+    RemoveGain(Option<Duration>),
+    ClearGain,
+    /// Set the gain the current track is always played with, in dB.
+    TrackGain(f32),
+    Quit,
+}
+
+pub fn parse(line: &str) -> Result<Action, String> {
+    let line = line.trim();
+    let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let argument = argument.trim();
+
+    let action = match name {
+        "play" => Action::Command(Command::Play),
+        "pause" => Action::Command(Command::Pause),
+        "toggle" => Action::Command(Command::TogglePause),
+        "stop" => Action::Command(Command::Stop),
+        "next" => Action::Command(Command::NextTrack),
+        "prev" | "previous" => Action::Command(Command::PreviousTrack),
+        "restart" => Action::Restart,
+        "seek" => {
+            let (sign, time) = match argument.strip_prefix(['+', '-']) {
+                Some(time) if argument.starts_with('-') => (-1, time),
+                Some(time) => (1, time),
+                None => (0, argument),
+            };
+            let time = parse_time(time)?;
+            if sign == 0 {
+                Action::Command(Command::SeekTo(time))
+            } else {
+                Action::Command(Command::Seek(sign * time.as_secs_f64().round() as i64))
+            }
+        }
+        "vol" | "volume" => {
+            let level: f32 = argument
+                .trim_end_matches('%')
+                .parse()
+                .ok()
+                .filter(|level: &f32| level.is_finite())
+                .ok_or_else(|| format!("expected a volume in percent, got '{}'", argument))?;
+            if argument.starts_with(['+', '-']) {
+                Action::VolumeBy(level)
+            } else {
+                Action::Command(Command::SetVolume(level.clamp(0.0, 100.0) / 100.0))
+            }
+        }
+        "open" | "enqueue" | "insert" if argument.is_empty() => {
+            return Err(format!("{} requires a path", name));
+        }
+        "open" => Action::Command(Command::Open(paths::expand_home(argument))),
+        "enqueue" => Action::Command(Command::Enqueue(paths::expand_home(argument))),
+        "insert" => Action::Command(Command::InsertNext(paths::expand_home(argument))),
+        "queue" => Action::Queue,
+        "eq" => Action::Equalizer,
+        "compress" => Action::Compressor,
+        "ab" => Action::Ab,
+        "diff" => Action::Diff,
+        "mono" => Action::Mono,
+        "solo" => Action::Solo(match argument {
+            "" | "off" => None,
+            _ => Some(Channel::named(argument).ok_or_else(|| {
+                format!("solo must be one of: {}, off", stereo::CHANNELS.join(", "))
+            })?),
+        }),
+        "resampler" => Action::Resampler(Quality::named(argument).ok_or_else(|| {
+            format!(
+                "resampler must be one of: {}",
+                resample::QUALITIES.join(", ")
+            )
+        })?),
+        "width" => {
+            let percent: f32 = argument
+                .trim_end_matches('%')
+                .parse()
+                .ok()
+                .filter(|percent| (0.0..=stereo::MAX_WIDTH * 100.0).contains(percent))
+                .ok_or_else(|| {
+                    format!("expected a width of 0 to {}%", stereo::MAX_WIDTH * 100.0)
+                })?;
+            Action::Width(percent / 100.0)
+        }
+        "transpose" => Action::Transpose(
+            argument
+                .parse()
+                .ok()
+                .filter(|semitones: &i32| semitones.abs() <= resample::MAX_TRANSPOSE)
+                .ok_or_else(|| {
+                    format!(
+                        "expected semitones from -{0} to +{0}",
+                        resample::MAX_TRANSPOSE
+                    )
+                })?,
+        ),
+        "clear" => Action::Command(Command::ClearQueue),
+        "theme" => Action::Theme(
+            Theme::named(argument)
+                .ok_or_else(|| format!("theme must be one of: {}", theme::PRESETS.join(", ")))?,
+        ),
+        "hide" => Action::Hide(parse_panel(argument)?),
+        "show" => Action::Show(parse_panel(argument)?),
+        "layout" => Action::SpectrumLayout(
+            (!argument.is_empty())
+                .then(|| {
+                    SpectrumLayout::named(argument).ok_or_else(|| {
+                        format!("layout must be one of: {}", ui::SPECTRUM_LAYOUTS.join(", "))
+                    })
+                })
+                .transpose()?,
+        ),
+        "visualizer" => Action::Visualizer(
+            (!argument.is_empty())
+                .then(|| {
+                    visualizer::named(argument).ok_or_else(|| {
+                        format!(
+                            "visualizer must be one of: {}",
+                            visualizer::names().join(", ")
+                        )
+                    })
+                })
+                .transpose()?,
+        ),
+        "freq" => {
+            let invalid = || "expected a range in Hz like 40 16000".to_string();
+            let (min, max) = argument.split_once(' ').ok_or_else(invalid)?;
+            let min: f32 = min.trim().parse().map_err(|_| invalid())?;
+            let max: f32 = max.trim().parse().map_err(|_| invalid())?;
+            if !(min >= 0.0 && min < max && max.is_finite()) {
+                return Err(invalid());
+            }
+            Action::SpectrumRange(min, max)
+        }
+        "curve" => Action::SpectrumCurve(
+            argument
+                .parse()
+                .ok()
+                .filter(|curve: &f32| curve.is_finite() && *curve > 0.0)
+                .ok_or_else(|| "expected a positive exponent like 1.5".to_string())?,
+        ),
+        "remaining" => Action::ToggleRemaining,
+        "stop-after" => Action::StopAfterTrack,
+        "auto-advance" => Action::AutoAdvance,
+        "bookmark" => Action::Bookmark(argument.to_string()),
+        "rate" => {
+            let stars: u8 = argument
+                .parse()
+                .ok()
+                .filter(|&stars| stars <= library::MAX_RATING)
+                .ok_or_else(|| format!("expected 0 to {} stars", library::MAX_RATING))?;
+            Action::Rate((stars > 0).then_some(stars))
+        }
+        "recent" | "history" => Action::Recent,
+        "podcast" => Action::Podcast((!argument.is_empty()).then(|| argument.to_string())),
+        "in" | "out" => {
+            let time = (!argument.is_empty())
+                .then(|| parse_time(argument))
+                .transpose()?;
+            if name == "in" {
+                Action::MarkIn(time)
+            } else {
+                Action::MarkOut(time)
+            }
+        }
+        "clip" => Action::ExportClip((!argument.is_empty()).then(|| paths::expand_home(argument))),
+        "deck" => Action::Deck,
+        "practice" => Action::Practice,
+        "metronome" => Action::Metronome(match argument {
+            "" => None,
+            "on" => Some(true),
+            "off" => Some(false),
+            _ => return Err("metronome must be on or off".to_string()),
+        }),
+        "tap" => Action::Tap,
+        "cue" => {
+            let (slot, time) = argument.split_once(' ').unwrap_or((argument, ""));
+            let slot = slot
+                .parse::<usize>()
+                .ok()
+                .filter(|slot| (1..=cues::SLOTS).contains(slot))
+                .ok_or_else(|| format!("expected a cue from 1 to {}", cues::SLOTS))?
+                - 1;
+            match time.trim() {
+                "" => Action::SetCue(slot, None),
+                "clear" => Action::ClearCue(slot),
+                time => Action::SetCue(slot, Some(parse_time(time)?)),
+            }
+        }
+        "gain" => {
+            let (gain, time) = argument.split_once(' ').unwrap_or((argument, ""));
+            let time = (!time.trim().is_empty())
+                .then(|| parse_time(time.trim()))
+                .transpose()?;
+            match gain {
+                "clear" => Action::ClearGain,
+                "remove" => Action::RemoveGain(time),
+                gain => Action::SetGain(parse_gain(gain)?, time),
+            }
+        }
+        "track-gain" => {
+            let gain_db = parse_gain(argument)?;
+            if gain_db.abs() > library::MAX_GAIN_DB {
+                return Err(format!(
+                    "expected a track gain from -{0} to +{0} dB",
+                    library::MAX_GAIN_DB
+                ));
+            }
+            Action::TrackGain(gain_db)
+        }
+        "q" | "quit" => Action::Quit,
+        _ => return Err(format!("unknown command '{}' (try: {})", name, COMMANDS)),
+    };
+    Ok(action)
+}
+
+/// Accepts `ss`, `mm:ss` or `hh:mm:ss`, with fractional seconds.
+fn parse_time(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("expected a time like 2:00 or 90, got '{}'", value);
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let part: f64 = part.parse().map_err(|_| invalid())?;
+        if !part.is_finite() || part < 0.0 {
+            return Err(invalid());
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// Accepts decibels like `-6`, `+3` or `-4.5dB`.
+pub fn parse_gain(value: &str) -> Result<f32, String> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    number
+        .trim()
+        .parse()
+        .ok()
+        .filter(|gain| (envelope::MIN_GAIN_DB..=envelope::MAX_GAIN_DB).contains(gain))
+        .ok_or_else(|| {
+            format!(
+                "expected a gain from {} to +{} dB, got '{}'",
+                envelope::MIN_GAIN_DB,
+                envelope::MAX_GAIN_DB,
+                value
+            )
+        })
+}
+
+fn parse_panel(name: &str) -> Result<Panel, String> {
+    Panel::named(name).ok_or_else(|| format!("panel must be one of: {}", ui::PANELS.join(", ")))
+}
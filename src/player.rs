@@ -1,120 +1,1032 @@
-use rodio::{Decoder, OutputStream, Sink, Source};
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::{OutputStream, Sink, Source, cpal};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 
-use crate::spectrum::SpectrumAnalyzer;
+use crate::ab::{AbSource, Comparison};
+use crate::analyze::{self, BeatGrid, Beats};
+use crate::buffering::{BufferStatus, BufferedSource, Buffering};
+use crate::cast::{Cast, Change};
+use crate::chapters::{self, Chapter};
+use crate::compressor::{Compressor, CompressorSource};
+use crate::decoder::{self, Decoded};
+use crate::download;
+use crate::downmix::DownmixSource;
+use crate::envelope::{Envelope, EnvelopeSource};
+use crate::eq::{EqSource, Equalizer};
+use crate::error::ApzError;
+use crate::fade::{Fade, FadeSource};
+use crate::hls;
+use crate::library;
+use crate::looping::{LoopSource, Looper};
+use crate::metadata::{self, TrackInfo};
+use crate::metronome::{Metronome, MetronomeSource};
+use crate::network_output::{self, NetworkOutput, Target};
+use crate::resample::{ResampleSource, Resampler};
+use crate::silence::{self, SilenceConfig, Silences};
+use crate::spectrum::{Spectrum, SpectrumConfig};
+use crate::stereo::{Stereo, StereoSource};
+use crate::stream_info::{FormatSource, LiveFormat, StreamInfo};
+use crate::stretch::StretchSource;
 use crate::tee_source::TeeSource;
-use crate::waveform::{self, WaveformData};
+use crate::tracker::{SharedPosition, TrackerPosition};
+use crate::waveform::{self, WaveformConfig, WaveformData};
+
+/// Tempo a practice loop starts at.
+const PRACTICE_TEMPO: f32 = 0.7;
+
+/// Taps further apart than this start counting the tempo afresh.
+const TAP_GAP: Duration = Duration::from_secs(2);
+/// Most recent taps the tempo is worked out from.
+const TAPS: usize = 8;
+
+/// How far the player's clock can get from a Chromecast's before it's put
+/// right.
+const MAX_CAST_DRIFT: Duration = Duration::from_millis(1500);
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PlaybackState {
     Playing,
     Paused,
+    /// Waiting at the start of the track, after playback was stopped or
+    /// reached the end of the queue.
+    Stopped,
+}
+
+/// A loop being practised along to, played slowly at first and faster with
+/// every clean repetition until it's up to full speed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Practice {
+    pub tempo: f32,
+    /// Times through the loop so far.
+    pub repetitions: u32,
+    /// Whether the time through playing now counts towards speeding up.
+    pub clean: bool,
+    /// The looper's lap count as of the last repetition counted.
+    laps: u32,
+}
+
+/// Where the player sends its audio.
+#[derive(Debug, Clone)]
+pub enum OutputConfig {
+    /// The default sound device.
+    Device,
+    Network(Target),
+    /// A Chromecast, by name or address, with the player keeping time
+    /// while it plays.
+    Cast(String),
+}
+
+/// Kept open for as long as the player plays through it.
+enum Output {
+    Device { _stream: OutputStream },
+    Network { _sender: NetworkOutput },
+    Muted { _clock: NetworkOutput },
 }
 
 pub struct Player {
-    _stream: OutputStream,
+    _output: Output,
     sink: Arc<Sink>,
     state: Arc<Mutex<PlaybackState>>,
+    path: PathBuf,
     duration: Duration,
     waveform: WaveformData,
-    spectrum: Option<Arc<Mutex<SpectrumAnalyzer>>>,
+    waveform_config: WaveformConfig,
+    /// Set when the waveform was skipped in low-power mode.
+    waveform_pending: bool,
+    waveform_job: Option<Receiver<WaveformData>>,
+    low_power: bool,
+    spectrum: Option<Spectrum>,
+    chapters: Vec<Chapter>,
+    track_info: TrackInfo,
+    stream_info: StreamInfo,
+    live_format: Arc<LiveFormat>,
+    tracker: Option<SharedPosition>,
+    fade: Arc<Fade>,
+    fade_duration: Duration,
+    equalizer: Arc<Equalizer>,
+    compressor: Arc<Compressor>,
+    stereo: Arc<Stereo>,
+    resampler: Arc<Resampler>,
+    looper: Arc<Looper>,
+    beats: Beats,
+    beat_job: Option<Receiver<BeatGrid>>,
+    envelope: Arc<Envelope>,
+    metronome: Arc<Metronome>,
+    /// Positions tapped along to the beat, most recent last.
+    taps: Vec<Duration>,
+    practice: Option<Practice>,
+    /// Set in A/B mode, while the track being compared is loaded.
+    comparison: Option<Comparison>,
+    /// Waveform of the file compared against, once it's been generated.
+    compare_waveform: Option<WaveformData>,
+    compare_waveform_job: Option<Receiver<WaveformData>>,
+    /// Set when silences are skipped or trimmed.
+    silence_config: Option<SilenceConfig>,
+    silence_scan: Option<Receiver<Silences>>,
+    silences: Silences,
+    /// Where a track trimmed of silence starts and ends.
+    start: Duration,
+    end: Option<Duration>,
+    /// Where to pick up a track that's still downloading, once it's got
+    /// that far.
+    resume: Option<Duration>,
+    /// Audio a network stream buffers before it starts, or carries on after
+    /// running out.
+    prebuffer: Duration,
+    buffering: Option<Arc<Buffering>>,
+    /// Held paused until the stream has buffered enough.
+    waiting: bool,
+    /// An HLS stream being opened on a thread of its own, to be loaded by
+    /// [`Player::finish_opening`].
+    opening: Option<Receiver<Result<Decoded, ApzError>>>,
+    cast: Option<Cast>,
+    /// Held paused until the cast has caught up.
+    held: bool,
+    /// What the audio is going to, for the status bar.
+    output_name: String,
     pub volume_step: f32,
     pub seek_step: i64,
+    pub practice_step: f32,
+}
+
+/// Rate of the default output device, which is what
+/// `OutputStream::try_default` opens it at.
+fn device_rate() -> Option<u32> {
+    let device = cpal::default_host().default_output_device()?;
+    Some(device.default_output_config().ok()?.sample_rate().0)
+}
+
+fn device_name() -> Option<String> {
+    cpal::default_host().default_output_device()?.name().ok()
 }
 
 impl Player {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<P: AsRef<Path>>(
         path: P,
-        enhanced_waveform: bool,
-        spectrum_config: Option<(usize, f32, f32)>, // (num_bars, smoothing, bass_boost)
+        waveform_config: WaveformConfig,
+        spectrum_config: Option<SpectrumConfig>,
         volume_step: f32,
         seek_step: i64,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let (_stream, stream_handle) = OutputStream::try_default()?;
-        let sink = Sink::try_new(&stream_handle)?;
-
-        let file = File::open(&path)?;
-        let source = Decoder::new(BufReader::new(file))?;
-
-        let duration = source.total_duration().unwrap_or(Duration::from_secs(0));
-
-        let spectrum = if let Some((num_bars, smoothing, bass_boost)) = spectrum_config {
-            let analyzer = Arc::new(Mutex::new(SpectrumAnalyzer::new(
-                num_bars, smoothing, bass_boost,
-            )));
-            let sample_buffer = analyzer.lock().unwrap().get_sample_buffer();
-            let tee_source = TeeSource::new(source.convert_samples(), sample_buffer);
-            sink.append(tee_source);
-            Some(analyzer)
-        } else {
-            sink.append(source);
-            None
+        fade_duration: Duration,
+        silence_config: Option<SilenceConfig>,
+        output: OutputConfig,
+        prebuffer: Duration,
+    ) -> Result<Self, ApzError> {
+        let mut cast = None;
+        let output_name = match &output {
+            OutputConfig::Device => device_name().unwrap_or_else(|| "default".to_string()),
+            OutputConfig::Network(target) => target.to_string(),
+            OutputConfig::Cast(device) => device.clone(),
+        };
+        let (_output, sink, output_rate) = match &output {
+            OutputConfig::Device => {
+                let (stream, stream_handle) = OutputStream::try_default()?;
+                let sink = Sink::try_new(&stream_handle)?;
+                (Output::Device { _stream: stream }, sink, device_rate())
+            }
+            OutputConfig::Network(target) => {
+                let (network, sink) = network_output::start(target)?;
+                (
+                    Output::Network { _sender: network },
+                    sink,
+                    Some(network_output::SAMPLE_RATE),
+                )
+            }
+            OutputConfig::Cast(device) => {
+                let connected = Cast::connect(device)
+                    .map_err(|e| ApzError::Device(format!("can't cast: {}", e)))?;
+                let (clock, sink) = network_output::start_muted();
+                if let Some(volume) = connected.volume() {
+                    sink.set_volume(volume);
+                }
+                cast = Some(connected);
+                (
+                    Output::Muted { _clock: clock },
+                    sink,
+                    Some(network_output::SAMPLE_RATE),
+                )
+            }
         };
 
-        sink.pause();
-
-        let waveform = waveform::generate_waveform(&path, 100, enhanced_waveform)
-            .unwrap_or_else(|_| WaveformData::new(vec![0.0; 100], false));
+        let spectrum = spectrum_config.map(Spectrum::start);
 
-        Ok(Player {
-            _stream,
+        let mut player = Player {
+            _output,
             sink: Arc::new(sink),
             state: Arc::new(Mutex::new(PlaybackState::Paused)),
-            duration,
-            waveform,
+            path: PathBuf::new(),
+            duration: Duration::from_secs(0),
+            waveform: WaveformData::flat(100, WaveformConfig::default()),
+            waveform_config,
+            waveform_pending: false,
+            waveform_job: None,
+            low_power: false,
             spectrum,
+            chapters: Vec::new(),
+            track_info: TrackInfo::default(),
+            stream_info: StreamInfo::default(),
+            live_format: LiveFormat::new(),
+            tracker: None,
+            fade: Fade::new(),
+            fade_duration,
+            equalizer: Equalizer::new(),
+            compressor: Compressor::new(),
+            stereo: Stereo::new(),
+            resampler: Resampler::new(output_rate),
+            looper: Looper::new(),
+            beats: Beats::Unknown,
+            beat_job: None,
+            envelope: Envelope::new(),
+            metronome: Metronome::new(),
+            taps: Vec::new(),
+            practice: None,
+            comparison: None,
+            compare_waveform: None,
+            compare_waveform_job: None,
+            silence_config,
+            silence_scan: None,
+            silences: Silences::default(),
+            start: Duration::ZERO,
+            end: None,
+            resume: None,
+            opening: None,
+            prebuffer,
+            buffering: None,
+            waiting: false,
+            cast,
+            held: false,
+            output_name,
             volume_step,
             seek_step,
-        })
+            practice_step: 0.05,
+        };
+        player.load(path)?;
+
+        Ok(player)
+    }
+
+    /// Replaces the current track with `path`, leaving the player paused at
+    /// the start of the new file. On error the current track is untouched.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), ApzError> {
+        let path = path.as_ref();
+        if hls::is_playlist(path) {
+            self.open_in_background(path);
+            return Ok(());
+        }
+        let decoded = decoder::open(path)?;
+        self.load_decoded(path, decoded)
+    }
+
+    /// Fetching an HLS playlist and finding out the stream's format both
+    /// wait on the network, so they're done on a thread of their own, with
+    /// nothing loaded meanwhile.
+    fn open_in_background(&mut self, path: &Path) {
+        let (sender, receiver) = mpsc::channel();
+        let opening = path.to_path_buf();
+        thread::spawn(move || {
+            sender.send(decoder::open(&opening)).ok();
+        });
+
+        self.pause();
+        {
+            let _state = self.state.lock().unwrap();
+            self.fade.cancel_seek();
+            self.sink.clear();
+        }
+        self.stop_practice();
+        self.set_path(path);
+        self.duration = Duration::ZERO;
+        self.chapters = Vec::new();
+        self.track_info = TrackInfo::read(path);
+        self.stream_info = StreamInfo::read(path, None);
+        self.tracker = None;
+        self.buffering = None;
+        self.waiting = false;
+        self.opening = Some(receiver);
+    }
+
+    /// Loads the stream [`Player::load`] started opening, once it's open,
+    /// playing it if play was pressed meanwhile, or leaving it stopped.
+    /// Returns how that went, or `None` if there's nothing new.
+    pub fn finish_opening(&mut self) -> Option<Result<(), ApzError>> {
+        let result = match self.opening.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(ApzError::open(
+                &self.path,
+                io::Error::other("gave up opening the stream"),
+            )),
+        };
+        self.opening = None;
+        let state = self.state();
+        let resume = self.resume;
+        let path = self.path.clone();
+        let result = result.and_then(|decoded| self.load_decoded(&path, decoded));
+        if result.is_ok() {
+            self.resume = resume;
+            match state {
+                PlaybackState::Playing => self.play(),
+                PlaybackState::Stopped => *self.state.lock().unwrap() = state,
+                PlaybackState::Paused => {}
+            }
+        }
+        Some(result)
+    }
+
+    fn load_decoded(&mut self, path: &Path, mut decoded: Decoded) -> Result<(), ApzError> {
+        // Tags and codec details both come from the one probe.
+        let mut probed = metadata::probe(path);
+        let track_info = TrackInfo::read_probed(path, probed.as_mut());
+        let stream_info = StreamInfo::read(path, probed.as_mut());
+        // Anything coming over the network is decoded ahead, so a slow
+        // connection pauses playback instead of breaking it up.
+        let buffering = if hls::is_playlist(path) || download::active(path).is_some() {
+            let source = BufferedSource::new(decoded.source, self.prebuffer);
+            let buffering = source.buffering();
+            decoded.source = Box::new(source);
+            Some(buffering)
+        } else {
+            None
+        };
+        // Loading anything else ends the comparison.
+        let comparison = self.comparison.clone().filter(|c| c.a == path);
+        if let Some(comparison) = &comparison {
+            let b = decoder::open(&comparison.b)?.source;
+            let switch = Arc::clone(&comparison.switch);
+            decoded.source = Box::new(AbSource::new(decoded.source, b, switch));
+        }
+        if comparison.is_none() {
+            self.compare_waveform = None;
+            self.compare_waveform_job = None;
+        }
+        self.comparison = comparison;
+        let source = LoopSource::new(decoded.source, Arc::clone(&self.looper));
+        let source = FadeSource::new(source, Arc::clone(&self.fade), self.fade_duration);
+        let live_format = LiveFormat::new();
+        let source = FormatSource::new(source.convert_samples(), Arc::clone(&live_format));
+        let source = DownmixSource::new(source, stream_info.speakers.clone());
+        let source = StretchSource::new(source, Arc::clone(&self.resampler));
+        let source = ResampleSource::new(source, Arc::clone(&self.resampler));
+        let source = StereoSource::new(source, Arc::clone(&self.stereo));
+        let source = EqSource::new(source, Arc::clone(&self.equalizer));
+        let source = CompressorSource::new(source, Arc::clone(&self.compressor));
+        let source = EnvelopeSource::new(
+            source,
+            Arc::clone(&self.envelope),
+            Arc::clone(&self.resampler),
+            Arc::clone(&self.looper),
+        );
+        let source = MetronomeSource::new(
+            source,
+            Arc::clone(&self.metronome),
+            Arc::clone(&self.resampler),
+            Arc::clone(&self.looper),
+        );
+
+        self.pause();
+        {
+            let _state = self.state.lock().unwrap();
+            self.fade.cancel_seek();
+            self.sink.clear();
+        }
+        self.stop_practice();
+        self.envelope.load(path);
+        self.envelope.set_offset(library::stats(path).gain_db);
+        self.live_format = live_format;
+        self.duration = source.total_duration().unwrap_or(Duration::from_secs(0));
+
+        if let Some(spectrum) = &self.spectrum {
+            let tee_source = TeeSource::new(source, spectrum.ring());
+            self.sink.append(tee_source);
+        } else {
+            self.sink.append(source);
+        }
+
+        self.opening = None;
+        self.set_path(path);
+        self.chapters = chapters::load_chapters(path);
+        self.track_info = track_info;
+        self.stream_info = stream_info;
+        self.tracker = decoded.tracker;
+        self.silences = Silences::default();
+        self.start = Duration::ZERO;
+        self.end = None;
+        self.resume = None;
+        self.waiting = buffering.is_some();
+        self.buffering = buffering;
+        let silence_config = self.silence_config.filter(|_| !decoder::is_stream(path));
+        if let Some(config) = silence_config.filter(|config| config.trim) {
+            self.start = silence::leading(path, &config);
+            if !self.start.is_zero() {
+                self.sink.try_seek(self.start).ok();
+            }
+        }
+        self.silence_scan = silence_config.map(|config| silence::scan(path.to_path_buf(), config));
+        if let Some(cast) = &self.cast {
+            let live = decoder::is_stream(path) && self.duration.is_zero();
+            cast.load(path, &self.track_info, self.start, live);
+        }
+
+        Ok(())
+    }
+
+    /// Switches to `path`, forgetting what was worked out about the last
+    /// track. Reloading the same file, to play it again, keeps its waveform.
+    fn set_path(&mut self, path: &Path) {
+        let reloaded = path == self.path;
+        self.path = path.to_path_buf();
+        if reloaded {
+            return;
+        }
+        self.beats = Beats::Unknown;
+        self.beat_job = None;
+        self.metronome.set_grid(None);
+        self.taps.clear();
+        self.waveform_job = None;
+        if decoder::is_stream(path) {
+            // Stdin can only be read once, and the player is reading it;
+            // a live stream never ends.
+            self.waveform = WaveformData::flat(100, self.waveform_config);
+            self.waveform_pending = false;
+        } else if self.low_power {
+            // Decoding the whole file for the waveform is the most
+            // expensive part of loading; leave it until power is back.
+            self.waveform = WaveformData::flat(100, self.waveform_config);
+            self.waveform_pending = true;
+        } else {
+            self.generate_waveform();
+        }
+    }
+
+    /// Shows a flat waveform until the real one has been generated and
+    /// picked up by [`Player::receive_waveform`].
+    fn generate_waveform(&mut self) {
+        self.waveform = WaveformData::flat(100, self.waveform_config);
+        self.waveform_pending = false;
+        self.waveform_job = Some(waveform::generate_in_background(
+            self.path.clone(),
+            self.waveform_config,
+        ));
+    }
+
+    /// Returns `true` once when the waveform for the current track, or for
+    /// the one it's compared against, is ready.
+    pub fn receive_waveform(&mut self) -> bool {
+        let received = |job: &mut Option<Receiver<WaveformData>>| {
+            let waveform = job.as_ref()?.try_recv().ok()?;
+            *job = None;
+            Some(waveform)
+        };
+        let mut changed = false;
+        if let Some(waveform) = received(&mut self.waveform_job) {
+            self.waveform = waveform;
+            changed = true;
+        }
+        if let Some(waveform) = received(&mut self.compare_waveform_job) {
+            self.compare_waveform = Some(waveform);
+            changed = true;
+        }
+        changed
+    }
+
+    pub fn compare_waveform(&self) -> Option<&WaveformData> {
+        self.compare_waveform.as_ref()
+    }
+
+    /// Starts looking for the beat of the current track, unless it's been
+    /// looked for already.
+    pub fn find_beats(&mut self) {
+        if self.beats != Beats::Unknown {
+            return;
+        }
+        // A stream can't be read ahead of playback to find it.
+        if decoder::is_stream(&self.path) {
+            self.beats = Beats::NotFound;
+        } else {
+            self.beats = Beats::Finding;
+            self.beat_job = Some(analyze::find_beats(self.path.clone()));
+        }
+    }
+
+    /// Returns `true` once when the search for the beat has finished.
+    pub fn receive_beats(&mut self) -> bool {
+        let Some(job) = &self.beat_job else {
+            return false;
+        };
+        self.beats = match job.try_recv() {
+            Ok(grid) => Beats::Found(grid),
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => Beats::NotFound,
+        };
+        self.beat_job = None;
+        if let Beats::Found(grid) = self.beats {
+            self.metronome.set_grid(Some(grid));
+        }
+        true
+    }
+
+    pub fn beats(&self) -> Beats {
+        self.beats
+    }
+
+    pub fn envelope(&self) -> Arc<Envelope> {
+        Arc::clone(&self.envelope)
+    }
+
+    pub fn metronome(&self) -> Arc<Metronome> {
+        Arc::clone(&self.metronome)
+    }
+
+    /// Puts a beat at the current position, to correct the one found or
+    /// give a track without one a beat. Tapping a few times in a row sets
+    /// the tempo from the taps as well, which a track without a beat needs
+    /// before it has one.
+    pub fn tap(&mut self) {
+        let position = self.position();
+        if self
+            .taps
+            .last()
+            .is_none_or(|&last| position <= last || position - last > TAP_GAP)
+        {
+            self.taps.clear();
+        }
+        self.taps.push(position);
+        if self.taps.len() > TAPS {
+            self.taps.remove(0);
+        }
+
+        let beat = match (self.taps.as_slice(), self.beats) {
+            ([first, .., last], _) => (*last - *first) / (self.taps.len() as u32 - 1),
+            (_, Beats::Found(grid)) => grid.beat,
+            _ => return,
+        };
+        // The earliest beat in the track, so the grid covers all of it.
+        let first_beat = Duration::from_secs_f64(position.as_secs_f64() % beat.as_secs_f64());
+        let grid = BeatGrid { beat, first_beat };
+        self.beats = Beats::Found(grid);
+        self.beat_job = None;
+        self.metronome.set_grid(Some(grid));
+    }
+
+    /// Loops the `beats` beats from the one at or before the current
+    /// position, with the track carrying on underneath, or lets go of the
+    /// loop if it's already that long. A roll that's going keeps its start
+    /// and only changes length. Needs the beat to have been found.
+    pub fn roll(&self, beats: u32) -> bool {
+        let Beats::Found(grid) = self.beats else {
+            return false;
+        };
+        if self.rolling() == Some(beats) {
+            self.looper.release();
+            return true;
+        }
+        let start = match self.looper.range() {
+            Some(range) if self.looper.is_slip() => range.start,
+            _ => grid.beat_before(self.position()),
+        };
+        self.looper.set(start..start + grid.beat * beats, true);
+        true
+    }
+
+    /// Length in beats of the loop roll that's going.
+    pub fn rolling(&self) -> Option<u32> {
+        let Beats::Found(grid) = self.beats else {
+            return None;
+        };
+        let range = self.looper.range().filter(|_| self.looper.is_slip())?;
+        let length = (range.end - range.start).as_secs_f64() / grid.beat.as_secs_f64();
+        Some(length.round() as u32)
+    }
+
+    /// Lets go of a loop roll, carrying on from where the track has got to.
+    pub fn end_roll(&self) {
+        if self.looper.is_slip() {
+            self.looper.release();
+        }
+    }
+
+    /// Loops `range` from its start, slowed down to [`PRACTICE_TEMPO`].
+    pub fn start_practice(&mut self, range: Range<Duration>) {
+        self.looper.set(range.clone(), false);
+        self.practice = Some(Practice {
+            tempo: PRACTICE_TEMPO,
+            repetitions: 0,
+            clean: true,
+            laps: self.looper.laps(),
+        });
+        self.resampler.set_tempo(PRACTICE_TEMPO);
+        self.seek_faded(range.start);
+        self.play();
+    }
+
+    /// Stops looping and goes back to full speed, carrying on from where
+    /// the loop had got to.
+    pub fn stop_practice(&mut self) {
+        if self.practice.take().is_some() {
+            self.looper.clear();
+            self.resampler.set_tempo(1.0);
+        }
+    }
+
+    pub fn practice(&self) -> Option<Practice> {
+        self.practice
+    }
+
+    /// Keeps the tempo where it is after the current time through the loop.
+    pub fn flub(&mut self) {
+        if let Some(practice) = &mut self.practice {
+            practice.clean = false;
+        }
+    }
+
+    /// Counts the repetitions the looper has finished since last checked,
+    /// speeding up after each clean one. Returns `true` if there were any.
+    pub fn follow_practice(&mut self) -> bool {
+        let Some(practice) = &mut self.practice else {
+            return false;
+        };
+        let laps = self.looper.laps();
+        if laps == practice.laps {
+            return false;
+        }
+        practice.repetitions += laps.wrapping_sub(practice.laps);
+        practice.laps = laps;
+        if practice.clean {
+            // In whole percent, so it lands on full speed exactly.
+            let tempo = ((practice.tempo + self.practice_step) * 100.0).round() / 100.0;
+            practice.tempo = tempo.min(1.0);
+            self.resampler.set_tempo(practice.tempo);
+        }
+        practice.clean = true;
+        true
+    }
+
+    /// Drops a loop roll without jumping ahead, for when playback is about
+    /// to be moved anyway.
+    fn cancel_roll(&self) {
+        if self.looper.is_slip() {
+            self.looper.clear();
+        }
+    }
+
+    /// Trades visual polish for battery life: the spectrum stops easing
+    /// between frames and waveforms aren't computed for new tracks.
+    pub fn set_low_power(&mut self, low_power: bool) {
+        self.low_power = low_power;
+        if let Some(spectrum) = &self.spectrum {
+            spectrum.set_animate(!low_power);
+        }
+        if !low_power && self.waveform_pending {
+            self.generate_waveform();
+        }
     }
 
     pub fn play(&self) {
-        self.sink.play();
-        *self.state.lock().unwrap() = PlaybackState::Playing;
+        // Under the lock, so a pause waiting on its fade can't follow it.
+        let mut state = self.state.lock().unwrap();
+        self.fade.fade_in();
+        if !self.waiting && !self.held {
+            self.sink.play();
+        }
+        if let Some(cast) = &self.cast {
+            cast.play();
+        }
+        *state = PlaybackState::Playing;
     }
 
     pub fn pause(&self) {
-        self.sink.pause();
-        *self.state.lock().unwrap() = PlaybackState::Paused;
+        let mut state = self.state.lock().unwrap();
+        let turn = self.fade.fade_out();
+        if *state == PlaybackState::Playing && self.fades() {
+            // Paused once the track has faded to silence, unless it's been
+            // played again by then.
+            let sink = Arc::clone(&self.sink);
+            let state = Arc::clone(&self.state);
+            let fade = Arc::clone(&self.fade);
+            let delay = self.fade_duration;
+            thread::spawn(move || {
+                thread::sleep(delay);
+                let _state = state.lock().unwrap();
+                if fade.is_turn(turn) {
+                    sink.pause();
+                }
+            });
+        } else {
+            self.sink.pause();
+        }
+        if let Some(cast) = &self.cast {
+            cast.pause();
+        }
+        *state = PlaybackState::Paused;
+    }
+
+    /// Whether there's anything playing to fade out.
+    fn fades(&self) -> bool {
+        !self.sink.empty() && !self.fade_duration.is_zero()
+    }
+
+    /// Seeks between a fade out and a fade in. A source fades in from
+    /// silence after every seek, so a paused track does too once resumed.
+    /// A playing track is seeked from a thread once it's faded out, so a
+    /// second seek before then takes the place of the first.
+    fn seek_faded(&self, position: Duration) {
+        self.cancel_roll();
+        if let Some(cast) = &self.cast {
+            cast.seek(position);
+        }
+        let mut state = self.state.lock().unwrap();
+        let playing = *state == PlaybackState::Playing;
+        if playing && self.fades() {
+            let seek = self.fade.start_seek(position);
+            let sink = Arc::clone(&self.sink);
+            let state = Arc::clone(&self.state);
+            let fade = Arc::clone(&self.fade);
+            let delay = self.fade_duration;
+            thread::spawn(move || {
+                thread::sleep(delay);
+                let state = state.lock().unwrap();
+                if fade.is_seek(seek) {
+                    sink.try_seek(position).ok();
+                    fade.end_seek(*state == PlaybackState::Playing);
+                }
+            });
+            return;
+        }
+        self.fade.cancel_seek();
+        self.sink.try_seek(position).ok();
+        if playing {
+            self.fade.fade_in();
+        } else {
+            // Stopped means at the start; anywhere else is just paused.
+            *state = PlaybackState::Paused;
+        }
     }
 
     pub fn toggle_play_pause(&self) {
         let state = *self.state.lock().unwrap();
         match state {
             PlaybackState::Playing => self.pause(),
-            PlaybackState::Paused => self.play(),
+            PlaybackState::Paused | PlaybackState::Stopped => self.play(),
         }
     }
 
     pub fn set_volume(&self, volume: f32) {
         self.sink.set_volume(volume.clamp(0.0, 1.0));
+        if let Some(cast) = &self.cast {
+            cast.set_volume(volume.clamp(0.0, 1.0));
+        }
     }
 
     pub fn volume(&self) -> f32 {
         self.sink.volume()
     }
 
-    pub fn seek(&self, offset: i64) {
+    /// Seeks `offset` seconds from the current position. Returns where it
+    /// went, or `None` if that's past the end.
+    pub fn seek(&self, offset: i64) -> Option<Duration> {
         let current = self.position().as_secs() as i64;
-        let new_position = (current + offset).max(0) as u64;
-        let duration = self.duration.as_secs();
+        self.seek_to(Duration::from_secs(
+            current.saturating_add(offset).max(0) as u64
+        ))
+    }
+
+    /// Seeks to `position`, returning it, or `None` if it's past the end.
+    pub fn seek_to(&self, position: Duration) -> Option<Duration> {
+        if position >= self.duration {
+            return None;
+        }
+        self.seek_faded(position);
+        Some(position)
+    }
+
+    /// Seeks to `position` as soon as [`Player::resume`] finds enough of
+    /// the track downloaded, so playback doesn't stall waiting for it.
+    pub fn resume_from(&mut self, position: Duration) {
+        self.resume = Some(position);
+        self.resume();
+    }
+
+    pub fn resume(&mut self) {
+        let Some(position) = self.resume.filter(|_| self.opening.is_none()) else {
+            return;
+        };
+        if let Some(download) = download::active(&self.path) {
+            let needed = position.as_secs_f32() / self.duration.as_secs_f32();
+            if download
+                .progress()
+                .is_none_or(|progress| progress <= needed)
+            {
+                return;
+            }
+        }
+        self.resume = None;
+        self.seek_to(position);
+    }
 
-        if new_position < duration {
-            self.sink.try_seek(Duration::from_secs(new_position)).ok();
+    pub fn next_chapter(&self) {
+        let next = match chapters::chapter_at(&self.chapters, self.position()) {
+            Some(current) => current + 1,
+            None => 0,
+        };
+        if let Some(chapter) = self.chapters.get(next) {
+            self.seek_to(chapter.start);
         }
     }
 
+    pub fn previous_chapter(&self) {
+        let position = self.position();
+        let Some(current) = chapters::chapter_at(&self.chapters, position) else {
+            return;
+        };
+
+        // Like most players, jump to the start of the current chapter unless
+        // we're already within the first few seconds of it.
+        let target = if position - self.chapters[current].start > Duration::from_secs(3) {
+            current
+        } else {
+            current.saturating_sub(1)
+        };
+        self.seek_to(self.chapters[target].start);
+    }
+
+    /// Jumps to the end of any silent gap the playhead has entered, and
+    /// picks up where a trimmed track ends. Silences are found in the
+    /// background, so the first few seconds of a long file may play before
+    /// either takes effect.
+    pub fn skip_silence(&mut self) {
+        let Some(config) = self.silence_config else {
+            return;
+        };
+        if let Some(silences) = self
+            .silence_scan
+            .as_ref()
+            .and_then(|scan| scan.try_recv().ok())
+        {
+            if config.trim {
+                self.end = silences.trailing.filter(|&end| end > self.start);
+            }
+            self.silences = silences;
+            self.silence_scan = None;
+        }
+
+        if !config.skip || self.state() != PlaybackState::Playing {
+            return;
+        }
+        let position = self.position();
+        if let Some(gap) = self
+            .silences
+            .gaps
+            .iter()
+            .find(|gap| gap.contains(&position))
+        {
+            self.seek_to(gap.end);
+        }
+    }
+
+    /// Holds a network stream that has run out of audio until it has
+    /// buffered enough to carry on, the same as when it starts.
+    pub fn rebuffer(&mut self) {
+        let Some(buffering) = &self.buffering else {
+            return;
+        };
+        let playing = self.state() == PlaybackState::Playing;
+        if !self.waiting {
+            if playing && buffering.is_starved() {
+                self.waiting = true;
+                self.sink.pause();
+            }
+            return;
+        }
+        // A pre-buffer longer than the buffer holds settles for a full one.
+        if buffering.is_ended() || buffering.ahead() >= self.prebuffer || buffering.fill() >= 1.0 {
+            self.waiting = false;
+            if playing && !self.held {
+                self.sink.play();
+                self.fade.fade_in();
+            }
+        }
+    }
+
+    /// Keeps up with the Chromecast being cast to: holds playback while it
+    /// loads or buffers, follows it if it drifts, and takes on anything
+    /// done from elsewhere, such as pausing it from a phone. Returns what
+    /// went wrong, if anything did.
+    pub fn follow_cast(&mut self) -> Option<String> {
+        let cast = self.cast.as_ref()?;
+        let changes = cast.poll();
+        let pending = cast.is_pending();
+        let remote = cast.position();
+
+        let mut error = None;
+        for change in changes {
+            match change {
+                Change::Played => {
+                    let mut state = self.state.lock().unwrap();
+                    self.fade.fade_in();
+                    if !self.waiting && !self.held {
+                        self.sink.play();
+                    }
+                    *state = PlaybackState::Playing;
+                }
+                Change::Paused => {
+                    self.sink.pause();
+                    *self.state.lock().unwrap() = PlaybackState::Paused;
+                }
+                Change::Volume(volume) => self.sink.set_volume(volume),
+                Change::Failed(message) => error = Some(message),
+                Change::Lost(message) => {
+                    // The muted clock would otherwise carry on with nothing
+                    // playing.
+                    self.pause();
+                    error = Some(format!("{}, so playback is paused", message));
+                }
+            }
+        }
+
+        let playing = self.state() == PlaybackState::Playing;
+        if pending && playing && !self.held {
+            self.held = true;
+            self.sink.pause();
+        } else if !pending && self.held {
+            self.held = false;
+            if playing && !self.waiting {
+                self.sink.play();
+            }
+        }
+        if let Some(remote) = remote
+            && !pending
+            && playing
+            && self.position().abs_diff(remote) > MAX_CAST_DRIFT
+        {
+            self.sink.try_seek(remote).ok();
+        }
+        error
+    }
+
+    /// The Chromecast being cast to.
+    pub fn cast_name(&self) -> Option<&str> {
+        self.cast.as_ref().map(Cast::name)
+    }
+
+    /// The sound device, network address or Chromecast played to.
+    pub fn output_name(&self) -> &str {
+        self.cast_name().unwrap_or(&self.output_name)
+    }
+
+    /// How full a network stream's buffer is.
+    pub fn buffer_status(&self) -> Option<BufferStatus> {
+        if self.opening.is_some() {
+            return Some(BufferStatus {
+                fill: 0.0,
+                waiting: true,
+            });
+        }
+        self.buffering.as_ref().map(|buffering| BufferStatus {
+            fill: buffering.fill(),
+            waiting: self.waiting,
+        })
+    }
+
+    /// Pauses at the start of the track. A track that has played to the end
+    /// is reloaded, so it can be played again.
+    pub fn stop(&mut self) {
+        if self.sink.empty() && self.opening.is_none() {
+            let path = self.path.clone();
+            self.load(&path).ok();
+        } else {
+            self.seek_faded(self.start);
+            self.pause();
+        }
+        *self.state.lock().unwrap() = PlaybackState::Stopped;
+    }
+
     pub fn restart(&self) {
-        self.sink.try_seek(Duration::from_secs(0)).ok();
+        self.seek_faded(self.start);
         self.play();
     }
 
     pub fn position(&self) -> Duration {
-        self.sink.get_pos()
+        // A seek waiting on its fade counts as made, so another stacks on it.
+        if let Some(target) = self.fade.pending() {
+            return target;
+        }
+        if self.opening.is_some() {
+            return Duration::ZERO;
+        }
+        // The sink's clock, put right for any change of speed.
+        let played = self.sink.get_pos().as_secs_f64() + self.resampler.drift();
+        Duration::from_secs_f64(played.max(0.0)).saturating_sub(self.looper.shift())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     pub fn duration(&self) -> Duration {
@@ -125,15 +1037,73 @@ impl Player {
         *self.state.lock().unwrap()
     }
 
+    /// Whether a playing track has reached its end.
     pub fn is_finished(&self) -> bool {
-        self.sink.empty()
+        self.opening.is_none()
+            && self.state() == PlaybackState::Playing
+            && (self.sink.empty() || self.end.is_some_and(|end| self.position() >= end))
     }
 
     pub fn waveform(&self) -> &WaveformData {
         &self.waveform
     }
 
-    pub fn spectrum(&self) -> Option<Arc<Mutex<SpectrumAnalyzer>>> {
-        self.spectrum.as_ref().map(Arc::clone)
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.chapters
+    }
+
+    pub fn track_info(&self) -> &TrackInfo {
+        &self.track_info
+    }
+
+    pub fn stream_info(&self) -> &StreamInfo {
+        &self.stream_info
+    }
+
+    pub fn live_format(&self) -> Arc<LiveFormat> {
+        Arc::clone(&self.live_format)
+    }
+
+    /// Pattern and row being played, for tracker modules.
+    pub fn tracker_position(&self) -> Option<TrackerPosition> {
+        self.tracker
+            .as_ref()
+            .map(|position| *position.lock().unwrap())
+    }
+
+    pub fn spectrum(&self) -> Option<Spectrum> {
+        self.spectrum.clone()
+    }
+
+    pub fn equalizer(&self) -> Arc<Equalizer> {
+        Arc::clone(&self.equalizer)
+    }
+
+    pub fn compressor(&self) -> Arc<Compressor> {
+        Arc::clone(&self.compressor)
+    }
+
+    pub fn stereo(&self) -> Arc<Stereo> {
+        Arc::clone(&self.stereo)
+    }
+
+    /// Switches to A/B mode, comparing the current track with `other`.
+    pub fn compare_with(&mut self, other: &Path) -> Result<(), ApzError> {
+        let path = self.path.clone();
+        self.comparison = Some(Comparison::new(&path, other));
+        self.load(&path)?;
+        self.compare_waveform_job = Some(waveform::generate_in_background(
+            other.to_path_buf(),
+            self.waveform_config,
+        ));
+        Ok(())
+    }
+
+    pub fn comparison(&self) -> Option<&Comparison> {
+        self.comparison.as_ref()
+    }
+
+    pub fn resampler(&self) -> Arc<Resampler> {
+        Arc::clone(&self.resampler)
     }
 }
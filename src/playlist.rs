@@ -0,0 +1,57 @@
+//! `.m3u` playlists: one path per line, with `#` lines for comments and
+//! extended info. `--watch` keeps the queue in step with one, so other
+//! programs can feed the player by editing the file.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::command::Command;
+
+/// How often a watched playlist is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Reads the tracks listed in `path`. Relative entries are relative to the
+/// playlist's own directory.
+pub fn read(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let contents = fs::read_to_string(path)?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
+
+/// Sends the playlist's tracks whenever the file changes. There's no file
+/// change notification in std, so this polls the modification time and size.
+pub fn watch(path: PathBuf, commands: Sender<Command>) {
+    thread::spawn(move || {
+        let stamp = |path: &Path| {
+            fs::metadata(path)
+                .map(|metadata| (metadata.modified().ok(), metadata.len()))
+                .unwrap_or((None::<SystemTime>, 0))
+        };
+        let mut last = stamp(&path);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = stamp(&path);
+            if current == last {
+                continue;
+            }
+            last = current;
+            // A file that's mid-rewrite or briefly missing is picked up on the
+            // next change instead.
+            let Ok(tracks) = read(&path) else {
+                continue;
+            };
+            if commands.send(Command::ReplaceQueue(tracks)).is_err() {
+                break;
+            }
+        }
+    });
+}
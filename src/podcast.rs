@@ -0,0 +1,238 @@
+//! Podcast feeds: the episodes an RSS feed lists, each downloaded to the
+//! cache and played as it arrives, and how far into each one you got.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{download, paths};
+
+const PROGRESS_FILE: &str = "podcasts.tsv";
+/// Subdirectory of the cache episodes are downloaded to.
+const EPISODES_DIR: &str = "podcasts";
+/// Share of an episode that has to be heard for it to count as listened,
+/// leaving room for closing credits and ads.
+const LISTENED_FRACTION: f32 = 0.95;
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub title: String,
+    /// Newest first, as feeds list them.
+    pub episodes: Vec<Episode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Episode {
+    pub title: String,
+    /// Where the audio is, from the item's `<enclosure>`.
+    pub url: String,
+    /// Publication date, e.g. "08 Oct 2024".
+    pub published: Option<String>,
+}
+
+/// How far into an episode you got, persisted across sessions in
+/// `podcasts.tsv` (`path<TAB>millis<TAB>listened` per line).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub position: Duration,
+    pub listened: bool,
+}
+
+impl Feed {
+    pub fn fetch(url: &str) -> Result<Self, String> {
+        let xml = download::fetch(url)?;
+        Self::parse(&xml).ok_or_else(|| format!("{} isn't a podcast feed", url))
+    }
+
+    fn parse(xml: &str) -> Option<Self> {
+        let channel = &xml[xml.find("<channel")?..];
+        let mut items = channel.split("<item");
+        let title = element(items.next()?, "title").unwrap_or_default();
+        let episodes = items
+            .filter_map(|item| {
+                let item = item.split("</item>").next()?;
+                let url = attribute(item, "enclosure", "url")?;
+                Some(Episode {
+                    title: element(item, "title").unwrap_or_else(|| url.clone()),
+                    published: element(item, "pubDate").map(|date| short_date(&date)),
+                    url,
+                })
+            })
+            .collect();
+        Some(Self { title, episodes })
+    }
+}
+
+impl Episode {
+    /// Where the episode is downloaded to.
+    pub fn path(&self) -> Option<PathBuf> {
+        let url = self.url.split(['?', '#']).next().unwrap_or_default();
+        let extension = url
+            .rsplit('/')
+            .next()
+            .and_then(|file| file.rsplit_once('.'))
+            .map(|(_, extension)| extension)
+            .filter(|extension| extension.len() <= 4)
+            .unwrap_or("mp3");
+        download::cache_path(EPISODES_DIR, &self.title, &self.url, extension)
+    }
+
+    /// Starts downloading the episode, unless it already has been, and
+    /// returns where to play it from.
+    pub fn download(&self) -> io::Result<PathBuf> {
+        let path = self
+            .path()
+            .ok_or_else(|| io::Error::other("no cache directory"))?;
+        download::start(&self.url, &path)?;
+        Ok(path)
+    }
+}
+
+/// Text of the first `<name>` element in `xml`, unescaped.
+fn element(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{}", name);
+    let start = xml.match_indices(&open).find_map(|(index, _)| {
+        let rest = &xml[index + open.len()..];
+        if !rest.starts_with(['>', ' ', '\t', '\n', '\r']) {
+            return None;
+        }
+        Some(index + open.len() + rest.find('>')? + 1)
+    })?;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    let text = xml[start..end].trim();
+    let text = match text.strip_prefix("<![CDATA[") {
+        Some(data) => data.strip_suffix("]]>").unwrap_or(data).to_string(),
+        None => unescape(text),
+    };
+    Some(text.trim().to_string())
+}
+
+/// Value of `name` in the first `<tag ...>` in `xml`.
+fn attribute(xml: &str, tag: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{} ", tag))?;
+    let tag = &xml[start..start + xml[start..].find('>')?];
+    let (_, rest) = tag.split_once(&format!(" {}=", name))?;
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = rest[1..].split(quote).next()?;
+    Some(unescape(value))
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity.strip_prefix('#').and_then(|n| n.parse().ok()),
+            }
+            .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// "Tue, 08 Oct 2024 10:00:00 +0000" becomes "08 Oct 2024".
+fn short_date(date: &str) -> String {
+    let date = date.split_once(", ").map_or(date, |(_, date)| date);
+    date.split_whitespace()
+        .take(3)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether `path` is a downloaded episode.
+pub fn is_episode(path: &Path) -> bool {
+    paths::cache_dir().is_some_and(|dir| path.starts_with(dir.join(EPISODES_DIR)))
+}
+
+pub fn progress(path: &Path) -> Progress {
+    let key = path.to_string_lossy();
+    read_store()
+        .lines()
+        .filter_map(parse_line)
+        .find(|(entry, _)| *entry == key)
+        .map(|(_, progress)| progress)
+        .unwrap_or_default()
+}
+
+/// Remembers where playback of the episode at `path` got to. Once nearly
+/// all of it has been heard it's marked listened, and stays that way.
+pub fn record(path: &Path, position: Duration, completion: f32) {
+    let Some(store) = store_path() else {
+        return;
+    };
+    let key = path.to_string_lossy();
+    let listened = completion >= LISTENED_FRACTION || progress(path).listened;
+    let position = if completion >= LISTENED_FRACTION {
+        Duration::ZERO
+    } else {
+        position
+    };
+
+    let contents = read_store();
+    let mut lines: Vec<String> = contents
+        .lines()
+        .filter(|line| parse_line(line).is_none_or(|(entry, _)| entry != key))
+        .map(str::to_string)
+        .collect();
+    lines.push(format!(
+        "{}\t{}\t{}",
+        key,
+        position.as_millis(),
+        listened as u8
+    ));
+
+    if let Some(dir) = store.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    let contents: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    fs::write(store, contents).ok();
+}
+
+fn store_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(PROGRESS_FILE))
+}
+
+fn read_store() -> String {
+    store_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default()
+}
+
+fn parse_line(line: &str) -> Option<(&str, Progress)> {
+    let mut fields = line.splitn(3, '\t');
+    let path = fields.next()?;
+    let millis = fields.next()?.parse().ok()?;
+    let listened = fields.next()? == "1";
+    Some((
+        path,
+        Progress {
+            position: Duration::from_millis(millis),
+            listened,
+        },
+    ))
+}
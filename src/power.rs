@@ -0,0 +1,90 @@
+//! Battery detection for the low-power mode.
+
+use std::time::{Duration, Instant};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Highest frame rate, for the screen and the spectrum, while saving power.
+pub const LOW_POWER_FPS: u32 = 10;
+
+/// Decides whether apz should save power: always with `--low-power`,
+/// otherwise whenever the machine is running on battery.
+pub struct PowerMonitor {
+    forced: bool,
+    low_power: bool,
+    last_check: Option<Instant>,
+}
+
+impl PowerMonitor {
+    pub fn new(forced: bool) -> Self {
+        Self {
+            forced,
+            low_power: forced,
+            last_check: None,
+        }
+    }
+
+    pub fn low_power(&self) -> bool {
+        self.low_power
+    }
+
+    /// Re-checks the power source every so often. Returns `true` when the
+    /// mode has changed since the last call.
+    pub fn poll(&mut self) -> bool {
+        if self.forced
+            || self
+                .last_check
+                .is_some_and(|last| last.elapsed() < CHECK_INTERVAL)
+        {
+            return false;
+        }
+
+        self.last_check = Some(Instant::now());
+        let low_power = on_battery();
+        let changed = low_power != self.low_power;
+        self.low_power = low_power;
+        changed
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn on_battery() -> bool {
+    let Ok(supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+
+    let read = |path: std::path::PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+    let mut discharging = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        match read(path.join("type")).trim() {
+            "Mains" if read(path.join("online")).trim() == "1" => return false,
+            "Battery" if read(path.join("status")).trim() == "Discharging" => {
+                discharging = true;
+            }
+            _ => {}
+        }
+    }
+    discharging
+}
+
+#[cfg(target_os = "macos")]
+fn on_battery() -> bool {
+    std::process::Command::new("pmset")
+        .args(["-g", "batt"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("'Battery Power'"))
+}
+
+#[cfg(target_os = "windows")]
+fn on_battery() -> bool {
+    use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    // ACLineStatus is 0 when offline, 1 when online and 255 when unknown.
+    unsafe { GetSystemPowerStatus(&mut status) != 0 && status.ACLineStatus == 0 }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn on_battery() -> bool {
+    false
+}
@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+
+/// Ordered list of tracks with a cursor on the one currently loaded.
+pub struct Queue {
+    tracks: Vec<PathBuf>,
+    current: usize,
+    version: u64,
+}
+
+impl Queue {
+    pub fn new(tracks: Vec<PathBuf>) -> Self {
+        Self {
+            tracks,
+            current: 0,
+            version: 0,
+        }
+    }
+
+    pub fn current(&self) -> Option<&Path> {
+        self.tracks.get(self.current).map(PathBuf::as_path)
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Incremented whenever the list of tracks changes.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn tracks(&self) -> &[PathBuf] {
+        &self.tracks
+    }
+
+    pub fn push(&mut self, path: PathBuf) {
+        self.tracks.push(path);
+        self.version += 1;
+    }
+
+    /// Inserts `path` right after the current track.
+    pub fn insert_next(&mut self, path: PathBuf) {
+        let index = (self.current + 1).min(self.tracks.len());
+        self.tracks.insert(index, path);
+        self.version += 1;
+    }
+
+    /// Moves the track at `from` to `to`, shifting the ones in between. The
+    /// cursor stays on the same track.
+    pub fn move_track(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.tracks.len() || to >= self.tracks.len() || from == to {
+            return false;
+        }
+
+        let track = self.tracks.remove(from);
+        self.tracks.insert(to, track);
+        if self.current == from {
+            self.current = to;
+        } else if from < self.current && self.current <= to {
+            self.current -= 1;
+        } else if to <= self.current && self.current < from {
+            self.current += 1;
+        }
+        self.version += 1;
+        true
+    }
+
+    /// Removes every track except the current one.
+    pub fn clear(&mut self) {
+        if self.tracks.len() > 1 {
+            let current = self.tracks.swap_remove(self.current);
+            self.tracks = vec![current];
+            self.current = 0;
+            self.version += 1;
+        }
+    }
+
+    /// Replaces every track, keeping the cursor on the loaded one. If it's
+    /// no longer listed it stays at its old position, so playback carries on
+    /// into whatever follows it in the new list.
+    pub fn replace(&mut self, mut tracks: Vec<PathBuf>) {
+        if let Some(current) = self.tracks.get(self.current) {
+            let found = tracks
+                .iter()
+                .enumerate()
+                .filter(|(_, track)| *track == current)
+                .map(|(index, _)| index)
+                .min_by_key(|index| index.abs_diff(self.current));
+            match found {
+                Some(index) => self.current = index,
+                None => {
+                    self.current = self.current.min(tracks.len());
+                    tracks.insert(self.current, current.clone());
+                }
+            }
+        }
+        self.tracks = tracks;
+        self.version += 1;
+    }
+
+    /// Removes the track at `index`. The currently loaded track can't be
+    /// removed, so this returns `false` for it and for out-of-range indices.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if index >= self.tracks.len() || index == self.current {
+            return false;
+        }
+
+        self.tracks.remove(index);
+        if index < self.current {
+            self.current -= 1;
+        }
+        self.version += 1;
+        true
+    }
+
+    /// Removes the tracks at `indices`, all but the current one. Returns how
+    /// many went.
+    pub fn remove_all(&mut self, indices: &[usize]) -> usize {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+            .into_iter()
+            .rev()
+            .filter(|&index| self.remove(index))
+            .count()
+    }
+
+    /// Moves the tracks at `indices` to the top of the queue, keeping their
+    /// order.
+    pub fn move_to_top(&mut self, indices: &[usize]) {
+        let mut indices: Vec<usize> = indices
+            .iter()
+            .copied()
+            .filter(|&index| index < self.tracks.len())
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        // Each one comes from below the ones already moved, so moving it
+        // leaves the indices of the rest alone.
+        for (to, from) in indices.into_iter().enumerate() {
+            self.move_track(from, to);
+        }
+    }
+
+    /// Moves the tracks at `indices` to play right after the current one,
+    /// keeping their order. The current track itself stays put.
+    pub fn play_next(&mut self, indices: &[usize]) {
+        let mut indices: Vec<usize> = indices
+            .iter()
+            .copied()
+            .filter(|&index| index < self.tracks.len() && index != self.current)
+            .collect();
+        if indices.is_empty() {
+            return;
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut moved = Vec::new();
+        for index in indices.into_iter().rev() {
+            moved.push(self.tracks.remove(index));
+            if index < self.current {
+                self.current -= 1;
+            }
+        }
+        moved.reverse();
+        let next = self.current + 1;
+        self.tracks.splice(next..next, moved);
+        self.version += 1;
+    }
+
+    /// Removes the currently loaded track, leaving the cursor on the one that
+    /// followed it, or the one before if it was last. Returns the new current
+    /// track, or `None` if it was the only one, in which case it's kept.
+    pub fn remove_current(&mut self) -> Option<&Path> {
+        if self.tracks.len() < 2 {
+            return None;
+        }
+
+        self.tracks.remove(self.current);
+        self.current = self.current.min(self.tracks.len() - 1);
+        self.version += 1;
+        self.current()
+    }
+
+    /// Moves the cursor to `index`, if it exists.
+    pub fn jump(&mut self, index: usize) -> Option<&Path> {
+        if index < self.tracks.len() {
+            self.current = index;
+            self.current()
+        } else {
+            None
+        }
+    }
+
+    /// Moves the cursor to the next track, if there is one.
+    pub fn advance(&mut self) -> Option<&Path> {
+        if self.current + 1 < self.tracks.len() {
+            self.current += 1;
+            self.current()
+        } else {
+            None
+        }
+    }
+
+    /// Moves the cursor to the previous track, if there is one.
+    pub fn retreat(&mut self) -> Option<&Path> {
+        if self.current > 0 {
+            self.current -= 1;
+            self.current()
+        } else {
+            None
+        }
+    }
+}
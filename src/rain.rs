@@ -0,0 +1,164 @@
+//! Falling streams of glyphs, matrix style, for screenshots and lo-fi
+//! streams. Each column of the panel stands for a stretch of the spectrum,
+//! low frequencies on the left, and starts drops as often, and as fast and
+//! long, as that stretch is loud.
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier},
+    widgets::{Block, Borders},
+};
+use std::time::Instant;
+
+use crate::shuffle::Rng;
+use crate::theme::{self, Theme};
+use crate::visualizer::{Scene, Visualizer};
+
+/// Half-width katakana, then digits.
+const GLYPHS: &[char] = &[
+    'ｦ', 'ｧ', 'ｨ', 'ｩ', 'ｪ', 'ｫ', 'ｬ', 'ｭ', 'ｮ', 'ｯ', 'ｱ', 'ｲ', 'ｳ', 'ｴ', 'ｵ', 'ｶ', 'ｷ', 'ｸ', 'ｹ',
+    'ｺ', 'ｻ', 'ｼ', 'ｽ', 'ｾ', 'ｿ', 'ﾀ', 'ﾁ', 'ﾂ', 'ﾃ', 'ﾄ', 'ﾅ', 'ﾆ', 'ﾇ', 'ﾈ', 'ﾉ', 'ﾊ', 'ﾋ', 'ﾌ',
+    'ﾍ', 'ﾎ', 'ﾏ', 'ﾐ', 'ﾑ', 'ﾒ', 'ﾓ', 'ﾔ', 'ﾕ', 'ﾖ', 'ﾗ', 'ﾘ', 'ﾙ', 'ﾚ', 'ﾛ', 'ﾜ', 'ﾝ', '0', '1',
+    '2', '3', '4', '5', '6', '7', '8', '9',
+];
+/// Bar level below which a column stays dark, so the noise floor doesn't
+/// drizzle.
+const GATE: f32 = 0.3;
+/// Drops a second a column starts at full level.
+const MAX_SPAWN_RATE: f64 = 3.0;
+/// Rows a second a drop falls, quiet to loud.
+const SPEED: (f32, f32) = (8.0, 40.0);
+/// Share of the glyphs that change each second, so the streams shimmer.
+const FLICKER: f64 = 2.0;
+/// Longest a frame is taken to last, so drops don't leap after a pause in
+/// drawing.
+const MAX_STEP: f32 = 0.1;
+
+struct Drop {
+    column: u16,
+    /// Row of the leading glyph, counting down from the top.
+    head: f32,
+    speed: f32,
+    /// Rows of trail behind the head.
+    length: f32,
+}
+
+pub struct Rain {
+    drops: Vec<Drop>,
+    /// A glyph for every cell, which the drops light up as they pass.
+    glyphs: Vec<char>,
+    size: (u16, u16),
+    rng: Rng,
+    last_frame: Option<Instant>,
+}
+
+impl Rain {
+    pub fn new() -> Self {
+        Self {
+            drops: Vec::new(),
+            glyphs: Vec::new(),
+            size: (0, 0),
+            rng: Rng::seeded(),
+            last_frame: None,
+        }
+    }
+
+    fn glyph(&mut self) -> char {
+        GLYPHS[self.rng.next_u64() as usize % GLYPHS.len()]
+    }
+
+    /// Moves the drops on by `step` seconds, starting new ones from the
+    /// levels in `bars`.
+    fn step(&mut self, step: f32, bars: &[f32], active: bool) {
+        let (width, height) = self.size;
+        for drop in &mut self.drops {
+            drop.head += drop.speed * step;
+        }
+        self.drops
+            .retain(|drop| drop.head - drop.length < height as f32);
+
+        for _ in 0..(self.glyphs.len() as f64 * FLICKER * step as f64) as usize {
+            let cell = self.rng.next_u64() as usize % self.glyphs.len();
+            self.glyphs[cell] = self.glyph();
+        }
+
+        if !active || bars.is_empty() {
+            return;
+        }
+        for column in 0..width {
+            let level = bars[column as usize * bars.len() / width as usize];
+            let energy = ((level - GATE) / (1.0 - GATE)).max(0.0);
+            let chance = MAX_SPAWN_RATE * energy as f64 * step as f64;
+            if energy == 0.0 || self.rng.next_f64() > chance {
+                continue;
+            }
+            self.drops.push(Drop {
+                column,
+                head: 0.0,
+                speed: SPEED.0 + (SPEED.1 - SPEED.0) * energy,
+                length: 2.0 + energy * height as f32,
+            });
+        }
+    }
+}
+
+impl Visualizer for Rain {
+    fn name(&self) -> &'static str {
+        "rain"
+    }
+
+    fn render(&mut self, frame: &mut Frame, area: Rect, scene: &Scene) {
+        let block = Block::default().borders(Borders::ALL).title("Rain");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let size = (inner.width, inner.height);
+        if size != self.size {
+            self.size = size;
+            self.drops.clear();
+            self.glyphs = (0..size.0 as usize * size.1 as usize)
+                .map(|_| self.glyph())
+                .collect();
+        }
+        if self.glyphs.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let step = self
+            .last_frame
+            .map_or(0.0, |last| (now - last).as_secs_f32().min(MAX_STEP));
+        self.last_frame = Some(now);
+        self.step(step, &scene.bars, scene.active);
+
+        for drop in &self.drops {
+            let head = drop.head.floor() as i32;
+            let tail = (drop.head - drop.length).floor() as i32;
+            for row in tail.max(0)..=head.min(inner.height as i32 - 1) {
+                let age = (head - row) as f32 / drop.length.max(1.0);
+                let cell = &mut frame.buffer_mut()[(inner.x + drop.column, inner.y + row as u16)];
+                cell.set_char(
+                    self.glyphs[row as usize * inner.width as usize + drop.column as usize],
+                );
+                cell.set_fg(trail_color(scene.theme, age, scene.active));
+                if row == head {
+                    cell.modifier.insert(Modifier::BOLD);
+                }
+            }
+        }
+    }
+}
+
+/// Color `age` of the way down a trail, from the bright head to the end.
+fn trail_color(theme: &Theme, age: f32, active: bool) -> Color {
+    let body = if active { theme.playing } else { theme.paused };
+    if age == 0.0 {
+        theme.highlight
+    } else if theme.truecolor {
+        theme::blend(body, theme.muted, age)
+    } else if age < 0.6 {
+        body
+    } else {
+        theme.muted
+    }
+}
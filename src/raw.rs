@@ -0,0 +1,132 @@
+//! Headerless PCM read from stdin (`apz -`), for the end of sox or ffmpeg
+//! pipelines.
+
+use rodio::Source;
+use std::io::{self, BufReader, Read, Stdin};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub const STDIN: &str = "-";
+
+pub const SAMPLE_FORMATS: [&str; 6] = ["s16le", "s16be", "s24le", "s32le", "f32le", "u8"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFormat {
+    S16Le,
+    S16Be,
+    S24Le,
+    S32Le,
+    F32Le,
+    U8,
+}
+
+impl SampleFormat {
+    /// Names as sox and ffmpeg spell them.
+    pub fn named(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "s16le" => Some(Self::S16Le),
+            "s16be" => Some(Self::S16Be),
+            "s24le" => Some(Self::S24Le),
+            "s32le" => Some(Self::S32Le),
+            "f32le" => Some(Self::F32Le),
+            "u8" => Some(Self::U8),
+            _ => None,
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            Self::S16Le | Self::S16Be => 2,
+            Self::S24Le => 3,
+            Self::S32Le | Self::F32Le => 4,
+            Self::U8 => 1,
+        }
+    }
+
+    fn to_i16(self, bytes: &[u8]) -> i16 {
+        match self {
+            Self::S16Le => i16::from_le_bytes([bytes[0], bytes[1]]),
+            Self::S16Be => i16::from_be_bytes([bytes[0], bytes[1]]),
+            Self::S24Le => i16::from_le_bytes([bytes[1], bytes[2]]),
+            Self::S32Le => i16::from_le_bytes([bytes[2], bytes[3]]),
+            Self::F32Le => {
+                let sample = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+            }
+            Self::U8 => ((bytes[0] as i16) - 128) << 8,
+        }
+    }
+}
+
+/// What the bytes on stdin are; there's no header to read it from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawFormat {
+    pub sample: SampleFormat,
+    pub rate: u32,
+    pub channels: u16,
+}
+
+impl Default for RawFormat {
+    fn default() -> Self {
+        Self {
+            sample: SampleFormat::S16Le,
+            rate: 44_100,
+            channels: 2,
+        }
+    }
+}
+
+static FORMAT: OnceLock<RawFormat> = OnceLock::new();
+
+pub fn set_format(format: RawFormat) {
+    FORMAT.set(format).ok();
+}
+
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN
+}
+
+pub struct RawSource {
+    reader: BufReader<Stdin>,
+    format: RawFormat,
+}
+
+impl RawSource {
+    pub fn stdin() -> Self {
+        Self {
+            reader: BufReader::new(io::stdin()),
+            format: FORMAT.get().copied().unwrap_or_default(),
+        }
+    }
+}
+
+impl Iterator for RawSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let mut bytes = [0; 4];
+        let bytes = &mut bytes[..self.format.sample.width()];
+        // A sample cut short by the end of the stream is dropped.
+        self.reader.read_exact(bytes).ok()?;
+        Some(self.format.sample.to_i16(bytes))
+    }
+}
+
+impl Source for RawSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.format.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.format.rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,499 @@
+//! `apz record`: shows the levels and spectrum of an input device, such as a
+//! microphone, and optionally records it to a WAV file. With `--loopback`,
+//! or as `apz visualize`, it listens to whatever the system is playing.
+
+use crossterm::event::{self, KeyCode, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{EnterAlternateScreen, enable_raw_mode};
+use ratatui::{
+    Frame, Terminal,
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+};
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::capture::{self, Capture, Input, Source};
+use crate::command::Command;
+use crate::config::Config;
+use crate::error::ApzError;
+use crate::events::{self, Event};
+use crate::loudness;
+use crate::shutdown;
+use crate::spectrum::{Spectrum, SpectrumConfig};
+use crate::tee_source::SampleRing;
+use crate::theme::{self, Theme};
+use crate::ui::{self, Precision, SpectrumLayout};
+use crate::wav::WavWriter;
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+/// Bottom of the level meters, in dBFS.
+const METER_FLOOR: f32 = -60.0;
+/// How long the peak marker stays put before dropping back.
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+
+pub fn run(args: &[String]) -> i32 {
+    let mut source = Source::Default;
+    let mut output = None;
+    let mut theme = Theme::default();
+    let mut beat_pulse = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(String::as_str);
+        match args[i].as_str() {
+            "-o" | "--out" => {
+                output = Some(PathBuf::from(
+                    value.unwrap_or_else(|| usage("--out requires a file")),
+                ));
+                i += 2;
+            }
+            "--device" => {
+                source = Source::Named(
+                    value
+                        .unwrap_or_else(|| usage("--device requires a name"))
+                        .to_string(),
+                );
+                i += 2;
+            }
+            "--loopback" => {
+                source = Source::Loopback;
+                i += 1;
+            }
+            "--beat-pulse" => {
+                beat_pulse = true;
+                i += 1;
+            }
+            "--list-devices" => return list_devices(),
+            "--theme" => {
+                theme = value.and_then(Theme::named).unwrap_or_else(|| {
+                    usage(&format!(
+                        "--theme must be one of: {}",
+                        theme::PRESETS.join(", ")
+                    ))
+                });
+                i += 2;
+            }
+            "-h" | "--help" => usage(""),
+            arg => usage(&format!("Unknown option: {}", arg)),
+        }
+    }
+
+    let result = Capture::start(&source).and_then(|capture| {
+        let recording = match output {
+            Some(path) => Some(Recording {
+                writer: WavWriter::create(&path, capture.sample_rate, capture.channels)?,
+                path,
+                sample_rate: capture.sample_rate,
+                frames: 0,
+                paused: false,
+            }),
+            None => None,
+        };
+        monitor(Monitor::new(
+            capture,
+            recording,
+            theme.for_terminal(),
+            beat_pulse,
+        ))
+    });
+
+    match result {
+        Ok(Some(recording)) => {
+            let seconds = recording.length().as_secs_f64();
+            let path = recording.path.clone();
+            match recording.writer.finish() {
+                Ok(()) => {
+                    println!("Recorded {:.1}s to {}", seconds, path.display());
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Couldn't finish {}: {}", path.display(), e);
+                    1
+                }
+            }
+        }
+        Ok(None) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn usage(error: &str) -> ! {
+    if !error.is_empty() {
+        eprintln!("Error: {}", error);
+    }
+    eprintln!("Usage: apz record [OPTIONS]");
+    eprintln!("       apz visualize [OPTIONS]   (the same as apz record --loopback)");
+    eprintln!("\nShows the level and spectrum of an input device and records it as WAV.");
+    eprintln!("\nOptions:");
+    eprintln!("  -o, --out <file>       Record to a 16-bit WAV file");
+    eprintln!("  --device <name>        Input device to use, by part of its name");
+    eprintln!("  --loopback             Capture what the system is playing");
+    eprintln!("  --list-devices         List input devices and exit");
+    eprintln!("  --beat-pulse           Pulse the spectrum on each beat and show the tempo");
+    eprintln!("  --theme <name>         Color theme: default, nord, gruvbox, dracula, monochrome");
+    eprintln!("\nControls:");
+    eprintln!("  Space    - Pause/resume recording");
+    eprintln!("  c        - Clear the clip indicators");
+    eprintln!("  m        - Switch the spectrum between bars and mirrored");
+    eprintln!("  Q/Esc    - Quit");
+    process::exit(if error.is_empty() { 0 } else { 1 });
+}
+
+fn list_devices() -> i32 {
+    match capture::input_devices() {
+        Ok(names) if names.is_empty() => {
+            eprintln!("No input devices found");
+            1
+        }
+        Ok(names) => {
+            for name in names {
+                println!("{}", name);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+struct Recording {
+    path: PathBuf,
+    writer: WavWriter,
+    sample_rate: u32,
+    frames: u64,
+    paused: bool,
+}
+
+impl Recording {
+    fn length(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.sample_rate.max(1) as f64)
+    }
+}
+
+/// Level of one channel.
+#[derive(Default)]
+struct Meter {
+    /// Highest level since the screen was last drawn.
+    peak: f32,
+    held: f32,
+    held_at: Option<Instant>,
+    clipped: bool,
+}
+
+impl Meter {
+    /// Takes the level to show, and lets the held peak fall back once it's
+    /// been up long enough.
+    fn take(&mut self) -> f32 {
+        let peak = std::mem::take(&mut self.peak);
+        let expired = self.held_at.is_none_or(|at| at.elapsed() > PEAK_HOLD);
+        if peak >= self.held || expired {
+            self.held = peak;
+            self.held_at = Some(Instant::now());
+        }
+        peak
+    }
+}
+
+struct Monitor {
+    capture: Capture,
+    spectrum: Spectrum,
+    ring: Arc<SampleRing>,
+    meters: Vec<Meter>,
+    recording: Option<Recording>,
+    error: Option<String>,
+    layout: SpectrumLayout,
+    theme: Theme,
+}
+
+impl Monitor {
+    fn new(capture: Capture, recording: Option<Recording>, theme: Theme, beat_pulse: bool) -> Self {
+        let defaults = Config::default();
+        let spectrum = Spectrum::start(SpectrumConfig {
+            num_bars: defaults.num_bars,
+            smoothing: defaults.smoothing,
+            bass_boost: defaults.bass_boost,
+            min_freq: defaults.min_freq,
+            max_freq: defaults.max_freq,
+            curve: defaults.freq_curve,
+            rate: defaults.fps,
+            beats: beat_pulse,
+        });
+        let ring = spectrum.ring();
+        ring.set_format(capture.channels, capture.sample_rate);
+
+        Self {
+            ring,
+            spectrum,
+            meters: (0..capture.channels).map(|_| Meter::default()).collect(),
+            capture,
+            recording,
+            error: None,
+            layout: SpectrumLayout::default(),
+            theme,
+        }
+    }
+
+    fn push(&mut self, samples: &[f32]) -> io::Result<()> {
+        let channels = self.meters.len().max(1);
+        for frame in samples.chunks_exact(channels) {
+            for (meter, &sample) in self.meters.iter_mut().zip(frame) {
+                meter.peak = meter.peak.max(sample.abs());
+                meter.clipped |= sample.abs() >= 1.0;
+            }
+            self.ring.push(frame);
+        }
+
+        if let Some(recording) = self.recording.as_mut().filter(|r| !r.paused) {
+            for &sample in samples {
+                let scaled = (sample * 32767.0).round();
+                recording
+                    .writer
+                    .write(scaled.clamp(i16::MIN as f32, i16::MAX as f32) as i16)?;
+            }
+            recording.frames += (samples.len() / channels) as u64;
+        }
+        Ok(())
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let meter_rows = self.meters.len().min(8) as u16 + 2;
+        let chunks = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(meter_rows),
+            Constraint::Length(3),
+        ])
+        .split(frame.area());
+
+        self.render_title(frame, chunks[0]);
+        ui::render_spectrum_bars(
+            frame,
+            chunks[1],
+            &self.theme,
+            self.layout,
+            true,
+            &self.spectrum,
+            &self.spectrum.bars(),
+        );
+        self.render_meters(frame, chunks[2]);
+        self.render_footer(frame, chunks[3]);
+    }
+
+    fn render_title(&self, frame: &mut Frame, area: Rect) {
+        let source = format!(
+            "{} · {} Hz · {} ch",
+            self.capture.device_name, self.capture.sample_rate, self.capture.channels
+        );
+        let mut line = match &self.recording {
+            Some(recording) => {
+                let (symbol, color) = if recording.paused {
+                    ("⏸ ", self.theme.paused)
+                } else {
+                    ("● REC ", self.theme.danger)
+                };
+                vec![
+                    Span::styled(
+                        symbol,
+                        Style::default().fg(color).add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(
+                        "{}  {}  ",
+                        ui::format_time(recording.length(), Precision::Tenths),
+                        recording.path.display()
+                    )),
+                ]
+            }
+            None => vec![Span::styled(
+                "Monitoring  ",
+                Style::default().fg(self.theme.playing),
+            )],
+        };
+        line.push(Span::styled(
+            source,
+            Style::default().fg(self.theme.secondary),
+        ));
+
+        let title = Paragraph::new(Line::from(line))
+            .block(Block::default().borders(Borders::ALL).title("apz record"));
+        frame.render_widget(title, area);
+    }
+
+    fn render_meters(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title("Level");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let names: &[&str] = if self.meters.len() == 2 {
+            &["L", "R"]
+        } else {
+            &[]
+        };
+        // Room for the channel name before the bar and the level after it.
+        let bar_width = inner.width.saturating_sub(2 + 16) as usize;
+        let theme = &self.theme;
+        let scale = |amplitude: f32| {
+            let db = loudness::to_db(amplitude.max(1e-6));
+            (((db - METER_FLOOR) / -METER_FLOOR).clamp(0.0, 1.0) * bar_width as f32) as usize
+        };
+
+        let mut lines = Vec::new();
+        for (index, meter) in self.meters.iter_mut().enumerate().take(8) {
+            let peak = meter.take();
+            let filled = scale(peak);
+            let held = scale(meter.held).min(bar_width.saturating_sub(1));
+            let db = loudness::to_db(peak.max(1e-6));
+            let color = if db > -3.0 {
+                theme.danger
+            } else if db > -12.0 {
+                theme.warning
+            } else {
+                theme.good
+            };
+
+            let name = names
+                .get(index)
+                .map_or_else(|| (index + 1).to_string(), |name| name.to_string());
+            let mut spans = vec![Span::raw(format!("{:<2}", name))];
+            spans.extend((0..bar_width).map(|x| {
+                if x < filled {
+                    Span::styled("█", Style::default().fg(color))
+                } else if x == held && meter.held > 0.0 {
+                    Span::styled("│", Style::default().fg(theme.highlight))
+                } else {
+                    Span::styled("─", Style::default().fg(theme.muted))
+                }
+            }));
+            let level = if db <= METER_FLOOR {
+                "   -inf dB".to_string()
+            } else {
+                format!(" {:>6.1} dB", db)
+            };
+            spans.push(Span::raw(level));
+            if meter.clipped {
+                spans.push(Span::styled(
+                    " CLIP",
+                    Style::default()
+                        .fg(theme.danger)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            lines.push(Line::from(spans));
+        }
+        frame.render_widget(Paragraph::new(lines), inner);
+    }
+
+    fn render_footer(&self, frame: &mut Frame, area: Rect) {
+        if let Some(error) = &self.error {
+            let message = Paragraph::new(Span::styled(
+                error.as_str(),
+                Style::default().fg(self.theme.danger),
+            ))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(self.theme.danger))
+                    .title("Error"),
+            );
+            frame.render_widget(message, area);
+            return;
+        }
+
+        let key = Style::default()
+            .fg(self.theme.highlight)
+            .add_modifier(Modifier::BOLD);
+        let mut line = Vec::new();
+        if self.recording.is_some() {
+            line.extend([
+                Span::styled("[Space]", key),
+                Span::raw(" pause/resume recording  "),
+            ]);
+        }
+        line.extend([
+            Span::styled("[C]", key),
+            Span::raw(" clear clip indicators  "),
+            Span::styled("[M]", key),
+            Span::raw(" mirror  "),
+            Span::styled("[Q]", key),
+            Span::raw(" quit"),
+        ]);
+        let controls = Paragraph::new(Line::from(line))
+            .block(Block::default().borders(Borders::ALL).title("Controls"));
+        frame.render_widget(controls, area);
+    }
+}
+
+/// Runs the monitor until quit, handing back the recording to be finished.
+fn monitor(mut monitor: Monitor) -> Result<Option<Recording>, ApzError> {
+    let (command_sender, commands) = mpsc::channel();
+    let (event_sender, events) = mpsc::channel();
+    shutdown::forward_signals(command_sender);
+    events::forward_commands(commands, event_sender.clone());
+
+    shutdown::install_panic_hook();
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    events::read_input(event_sender);
+
+    let result = run_loop(&mut terminal, &mut monitor, &events);
+    shutdown::restore_terminal();
+    result.map(|()| monitor.recording)
+}
+
+fn run_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    monitor: &mut Monitor,
+    events: &mpsc::Receiver<Event>,
+) -> Result<(), ApzError> {
+    loop {
+        // Only what has arrived so far, so a device delivering faster than
+        // real time can't keep the screen from being drawn.
+        let queued: Vec<Input> = monitor.capture.input.try_iter().collect();
+        for input in queued {
+            match input {
+                Input::Samples(samples) => monitor.push(&samples)?,
+                Input::Error(e) => monitor.error = Some(e),
+            }
+        }
+        terminal.draw(|frame| monitor.render(frame))?;
+
+        let key = match events.recv_timeout(FRAME_INTERVAL) {
+            Ok(Event::Input(event::Event::Key(key))) => key,
+            Ok(Event::Command(Command::Quit)) | Err(RecvTimeoutError::Disconnected) => {
+                return Ok(());
+            }
+            Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+        };
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char(' ') => {
+                if let Some(recording) = &mut monitor.recording {
+                    recording.paused = !recording.paused;
+                }
+            }
+            KeyCode::Char('c') | KeyCode::Char('C') => {
+                for meter in &mut monitor.meters {
+                    meter.clipped = false;
+                }
+            }
+            KeyCode::Char('m') | KeyCode::Char('M') => {
+                monitor.layout = monitor.layout.next();
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! `apz render`: runs a file through the same processing as playback and
+//! writes the result to a WAV file, so what was heard can be kept.
+
+use rodio::Source;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::decoder;
+use crate::eq::{self, Band, EqSource, Equalizer};
+use crate::error::ApzError;
+use crate::loudness;
+use crate::resample::Resampler;
+use crate::silence::{self, SilenceConfig};
+use crate::stretch::StretchSource;
+use crate::wav::WavWriter;
+
+/// Loudness `--normalize` aims for, in LUFS: ReplayGain's reference.
+const NORMALIZE_TARGET: f64 = -18.0;
+/// Highest true peak normalizing may raise a track to, in dBTP.
+const PEAK_CEILING: f64 = -1.0;
+/// Slowest and fastest `--tempo`.
+const TEMPO_RANGE: (f32, f32) = (0.25, 4.0);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderConfig {
+    pub gain_db: f32,
+    /// Bring the track to [`NORMALIZE_TARGET`] before applying the gain.
+    pub normalize: bool,
+    pub silence: Option<SilenceConfig>,
+    pub eq: [Band; eq::BANDS],
+    /// Speed, without changing the pitch.
+    pub tempo: f32,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            gain_db: 0.0,
+            normalize: false,
+            silence: None,
+            eq: eq::DEFAULT_BANDS,
+            tempo: 1.0,
+        }
+    }
+}
+
+/// Runs `apz render` with the arguments that follow it, returning the exit
+/// status.
+pub fn run(args: &[String]) -> i32 {
+    // The equalizer as it was last left while listening.
+    let mut config = RenderConfig {
+        eq: Config::saved_option("eq")
+            .and_then(|bands| eq::parse(&bands))
+            .unwrap_or(eq::DEFAULT_BANDS),
+        ..RenderConfig::default()
+    };
+    let mut silence = SilenceConfig {
+        threshold_db: -50.0,
+        min_length: Duration::from_secs(2),
+        skip: false,
+        trim: false,
+    };
+    let mut input = None;
+    let mut output = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(String::as_str);
+        match args[i].as_str() {
+            "-o" | "--out" => {
+                output = Some(PathBuf::from(
+                    value.unwrap_or_else(|| usage("--out requires a file")),
+                ));
+                i += 2;
+            }
+            "--gain" => {
+                config.gain_db = value
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .filter(|gain| gain.is_finite())
+                    .unwrap_or_else(|| usage("--gain must be a number of dB, e.g. -3"));
+                i += 2;
+            }
+            "--eq" => {
+                config.eq = value.and_then(eq::parse).unwrap_or_else(|| {
+                    usage(&format!(
+                        "--eq takes up to {} bands as freq:gain[:q], e.g. 100:+3,8000:-2:0.7",
+                        eq::BANDS
+                    ))
+                });
+                i += 2;
+            }
+            "--tempo" => {
+                let (slowest, fastest) = TEMPO_RANGE;
+                config.tempo = value
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .filter(|tempo| (slowest..=fastest).contains(tempo))
+                    .unwrap_or_else(|| {
+                        usage(&format!(
+                            "--tempo must be a speed from {} to {}, e.g. 0.8",
+                            slowest, fastest
+                        ))
+                    });
+                i += 2;
+            }
+            "--normalize" => {
+                config.normalize = true;
+                i += 1;
+            }
+            "--skip-silence" => {
+                silence.skip = true;
+                i += 1;
+            }
+            "--trim-silence" => {
+                silence.trim = true;
+                i += 1;
+            }
+            "--silence-level" => {
+                silence.threshold_db = value
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .unwrap_or_else(|| usage("--silence-level must be a level in dBFS, e.g. -50"))
+                    .min(0.0);
+                i += 2;
+            }
+            "--silence-min" => {
+                silence.min_length = value
+                    .and_then(|value| value.parse().ok())
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .unwrap_or_else(|| usage("--silence-min must be a length in seconds"));
+                i += 2;
+            }
+            "-h" | "--help" => usage(""),
+            arg if arg.starts_with('-') => usage(&format!("Unknown option: {}", arg)),
+            arg if input.is_none() => {
+                input = Some(PathBuf::from(arg));
+                i += 1;
+            }
+            _ => usage("Only one file can be rendered at a time"),
+        }
+    }
+
+    let input = input.unwrap_or_else(|| usage("No audio file specified"));
+    let output = output.unwrap_or_else(|| usage("--out is required"));
+    config.silence = (silence.skip || silence.trim).then_some(silence);
+
+    match render(&input, &output, &config) {
+        Ok(length) => {
+            println!("Wrote {} ({:.1}s)", output.display(), length.as_secs_f64());
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn usage(error: &str) -> ! {
+    if !error.is_empty() {
+        eprintln!("Error: {}", error);
+    }
+    eprintln!("Usage: apz render [OPTIONS] <audio_file> --out <file.wav>");
+    eprintln!("\nWrites the file as 16-bit WAV after the same processing as playback.");
+    eprintln!("\nOptions:");
+    eprintln!("  -o, --out <file>       WAV file to write");
+    eprintln!("  --gain <dB>            Raise or lower the level");
+    eprintln!("  --eq <bands>           Equalizer bands as freq:gain[:q], comma separated");
+    eprintln!("                         (default: the equalizer as last set while playing)");
+    eprintln!("  --tempo <factor>       Speed up or slow down without changing the pitch");
+    eprintln!("  --normalize            Bring the loudness to -18 LUFS, without peaking");
+    eprintln!("                         above -1 dBTP");
+    eprintln!("  --skip-silence         Leave out silent gaps, as playback does");
+    eprintln!("  --trim-silence         Leave out silence at the start and end");
+    eprintln!("  --silence-level <dB>   Level below which audio is silent (default: -50)");
+    eprintln!("  --silence-min <secs>   Shortest gap to skip (default: 2)");
+    process::exit(if error.is_empty() { 0 } else { 1 });
+}
+
+/// Writes `input` to `output` as WAV, returning the length written.
+pub fn render(input: &Path, output: &Path, config: &RenderConfig) -> Result<Duration, ApzError> {
+    let mut gain_db = config.gain_db as f64;
+    if config.normalize {
+        let meter = loudness::measure(input)?;
+        if let Some(loudness) = meter.integrated() {
+            let headroom = PEAK_CEILING - loudness::to_db(meter.true_peak()) as f64;
+            gain_db += (NORMALIZE_TARGET - loudness).min(headroom);
+        }
+    }
+    let gain = 10f64.powf(gain_db / 20.0) as f32;
+
+    let cuts = match &config.silence {
+        Some(silence) => cuts(input, silence)?,
+        None => Vec::new(),
+    };
+
+    let resampler = Resampler::new(None);
+    resampler.set_tempo(config.tempo);
+    let equalizer = Equalizer::new();
+    equalizer.set_bands(config.eq);
+    let source = decoder::open(input)?.source.convert_samples();
+    let source = StretchSource::new(source, resampler);
+    let mut source = EqSource::new(source, equalizer).amplify(gain);
+
+    let channels = source.channels().max(1);
+    let sample_rate = source.sample_rate().max(1);
+    // Silences are found in the track as it is, before the tempo changes.
+    let frame_at =
+        |time: Duration| (time.as_secs_f64() / config.tempo as f64 * sample_rate as f64) as u64;
+    let cuts: Vec<Range<u64>> = cuts
+        .into_iter()
+        .map(|cut| frame_at(cut.start)..frame_at(cut.end))
+        .collect();
+
+    let mut writer = WavWriter::create(output, sample_rate, channels)?;
+    let mut cut = cuts.iter().peekable();
+    let mut frame = vec![0.0; channels as usize];
+    let mut written = 0u64;
+    'decode: for index in 0.. {
+        for sample in frame.iter_mut() {
+            let Some(next) = source.next() else {
+                break 'decode;
+            };
+            *sample = next;
+        }
+
+        while cut.next_if(|cut| cut.end <= index).is_some() {}
+        if cut.peek().is_some_and(|cut| cut.contains(&index)) {
+            continue;
+        }
+        for &sample in &frame {
+            let scaled = (sample * 32_768.0).round();
+            writer.write(scaled.clamp(i16::MIN as f32, i16::MAX as f32) as i16)?;
+        }
+        written += 1;
+    }
+    writer.finish()?;
+
+    Ok(Duration::from_secs_f64(written as f64 / sample_rate as f64))
+}
+
+/// Stretches to leave out, in order: silence at the start and end when
+/// trimming, and gaps in between when skipping.
+fn cuts(path: &Path, config: &SilenceConfig) -> Result<Vec<Range<Duration>>, ApzError> {
+    let silences = silence::detect(path, config)?;
+    let mut cuts = Vec::new();
+    let mut start = Duration::ZERO;
+    if config.trim {
+        start = silence::leading(path, config);
+        cuts.push(Duration::ZERO..start);
+    }
+    if config.skip {
+        cuts.extend(silences.gaps.into_iter().filter(|gap| gap.start >= start));
+    }
+    if config.trim
+        && let Some(trailing) = silences.trailing.filter(|&end| end > start)
+    {
+        cuts.push(trailing..Duration::MAX);
+    }
+    Ok(cuts)
+}
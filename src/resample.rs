@@ -0,0 +1,442 @@
+//! Converts tracks to the output device's sample rate, in place of rodio's
+//! own conversion, which only interpolates linearly between samples and so
+//! lets through aliasing that's audible on bright material. Also plays
+//! tracks faster or slower, like a turntable's pitch control, or higher or
+//! lower in tune, and so keeps track of the position in the track rather
+//! than leaving it to the sink.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, AtomicU8, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+pub const QUALITIES: [&str; 2] = ["linear", "sinc"];
+
+/// Input frames either side of each output frame the sinc filter looks at.
+const SINC_HALF_TAPS: usize = 16;
+/// Furthest the pitch can be moved either way, as on most turntables.
+pub const MAX_PITCH: f32 = 0.08;
+/// Furthest a track can be transposed either way, in semitones.
+pub const MAX_TRANSPOSE: i32 = 12;
+
+/// Points worked out in the filter's table for every input frame it spans;
+/// values in between are interpolated.
+const SINC_PHASES: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Quality {
+    /// Straight lines between neighbouring samples, as rodio does.
+    #[default]
+    Linear,
+    /// A windowed sinc filter: slower, but without the aliasing.
+    Sinc,
+}
+
+impl Quality {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Self::Linear),
+            "sinc" => Some(Self::Sinc),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::Sinc => "sinc",
+        }
+    }
+}
+
+/// Resampling settings shared between the UI and the audio thread.
+pub struct Resampler {
+    quality: AtomicU8,
+    /// Rate the output device was opened at, if it could be found out.
+    device_rate: Option<u32>,
+    /// Playback speed as an `f32`'s bits, 1.0 being as recorded.
+    pitch: AtomicU32,
+    /// Speed without changing the pitch, as an `f32`'s bits.
+    tempo: AtomicU32,
+    /// Semitones to play the track up or down without changing its speed.
+    transpose: AtomicI32,
+    /// Seconds into the track of the audio last handed to the sink, as an
+    /// `f64`'s bits, and which source it's from.
+    position: AtomicU64,
+    source: AtomicU64,
+    /// How far `position` has got ahead of the sink's count of time played,
+    /// in seconds as an `f64`'s bits.
+    drift: AtomicU64,
+}
+
+impl Resampler {
+    pub fn new(device_rate: Option<u32>) -> Arc<Self> {
+        Arc::new(Self {
+            quality: AtomicU8::new(Quality::default() as u8),
+            device_rate,
+            pitch: AtomicU32::new(1.0f32.to_bits()),
+            tempo: AtomicU32::new(1.0f32.to_bits()),
+            transpose: AtomicI32::new(0),
+            position: AtomicU64::new(0.0f64.to_bits()),
+            source: AtomicU64::new(0),
+            drift: AtomicU64::new(0.0f64.to_bits()),
+        })
+    }
+
+    pub fn quality(&self) -> Quality {
+        match self.quality.load(Ordering::Relaxed) {
+            1 => Quality::Sinc,
+            _ => Quality::Linear,
+        }
+    }
+
+    pub fn set_quality(&self, quality: Quality) {
+        self.quality.store(quality as u8, Ordering::Relaxed);
+    }
+
+    pub fn device_rate(&self) -> Option<u32> {
+        self.device_rate
+    }
+
+    pub fn pitch(&self) -> f32 {
+        f32::from_bits(self.pitch.load(Ordering::Relaxed))
+    }
+
+    /// Plays `pitch` times as fast, within [`MAX_PITCH`] of normal speed.
+    pub fn set_pitch(&self, pitch: f32) {
+        let pitch = pitch.clamp(1.0 - MAX_PITCH, 1.0 + MAX_PITCH);
+        self.pitch.store(pitch.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn tempo(&self) -> f32 {
+        f32::from_bits(self.tempo.load(Ordering::Relaxed))
+    }
+
+    /// Plays `tempo` times as fast without changing the pitch.
+    pub fn set_tempo(&self, tempo: f32) {
+        self.tempo.store(tempo.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn transpose(&self) -> i32 {
+        self.transpose.load(Ordering::Relaxed)
+    }
+
+    /// Plays `semitones` higher, or lower if negative, at the same speed.
+    pub fn set_transpose(&self, semitones: i32) {
+        let semitones = semitones.clamp(-MAX_TRANSPOSE, MAX_TRANSPOSE);
+        self.transpose.store(semitones, Ordering::Relaxed);
+    }
+
+    /// How much faster the resampler plays its input: the pitch, and the
+    /// transposition, which the stretch before it makes up for.
+    fn speed(&self) -> f32 {
+        self.pitch() * transpose_ratio(self.transpose())
+    }
+
+    /// How much faster the [`StretchSource`](crate::stretch::StretchSource)
+    /// before the resampler plays without changing the pitch, which the
+    /// position has to allow for.
+    pub fn stretch(&self) -> f32 {
+        self.tempo() / transpose_ratio(self.transpose())
+    }
+
+    /// Position in the track of the audio being handed to the sink.
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(f64::from_bits(self.position.load(Ordering::Relaxed)))
+    }
+
+    /// Seconds to add to the sink's own count of time played to get the
+    /// position in the track, which it drifts from once the pitch or tempo
+    /// has been moved.
+    pub fn drift(&self) -> f64 {
+        f64::from_bits(self.drift.load(Ordering::Relaxed))
+    }
+
+    /// Makes a new source the one whose position is reported, so one that's
+    /// just been replaced can't overwrite it.
+    fn claim(&self) -> u64 {
+        let source = self.source.fetch_add(1, Ordering::Relaxed) + 1;
+        self.position.store(0.0f64.to_bits(), Ordering::Relaxed);
+        self.drift.store(0.0f64.to_bits(), Ordering::Relaxed);
+        source
+    }
+
+    /// Records `seconds` into the track, `played` of which the sink has
+    /// counted.
+    fn set_position(&self, source: u64, seconds: f64, played: f64) {
+        if self.source.load(Ordering::Relaxed) == source {
+            self.position.store(seconds.to_bits(), Ordering::Relaxed);
+            let drift = seconds - played;
+            self.drift.store(drift.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+fn transpose_ratio(semitones: i32) -> f32 {
+    2f32.powf(semitones as f32 / 12.0)
+}
+
+/// One side of a Blackman-windowed sinc low-pass filter, at `SINC_PHASES`
+/// points per input frame out to `SINC_HALF_TAPS`. `cutoff` is a fraction of
+/// the input's Nyquist frequency.
+fn sinc_table(cutoff: f64) -> Vec<f32> {
+    let len = SINC_HALF_TAPS * SINC_PHASES + 1;
+    (0..len)
+        .map(|index| {
+            let t = index as f64 / SINC_PHASES as f64;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (PI * cutoff * t).sin() / (PI * cutoff * t)
+            };
+            // Blackman window over -SINC_HALF_TAPS..SINC_HALF_TAPS.
+            let x = 0.5 + t / (2.0 * SINC_HALF_TAPS as f64);
+            let window = 0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos();
+            (cutoff * sinc * window) as f32
+        })
+        .collect()
+}
+
+/// Resamples its input to the device rate, so rodio has nothing left to
+/// convert, and to the pitch and transposition. Passes the input straight
+/// through when none of them calls for it.
+pub struct ResampleSource<I> {
+    input: I,
+    resampler: Arc<Resampler>,
+    /// Which source this is, to the resampler.
+    id: u64,
+    /// Seconds into the track.
+    elapsed: f64,
+    /// Seconds handed on, as the sink counts them: from the last seek, at
+    /// the output rate whatever the speed.
+    played: f64,
+    /// Input channels and rate the buffered frames and filter are for.
+    format: (u16, u32),
+    /// Recent input frames, interleaved, with enough either side of the
+    /// next output frame for the filter.
+    history: VecDeque<f32>,
+    /// Where the next output frame falls, in input frames from the start
+    /// of `history`.
+    offset: f64,
+    /// Frames of `history` from real input rather than padding, once the
+    /// input has ended.
+    remaining: Option<usize>,
+    /// Speed the filter was worked out for.
+    speed: f32,
+    table: Vec<f32>,
+    frame: Vec<f32>,
+    position: usize,
+}
+
+impl<I> ResampleSource<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(input: I, resampler: Arc<Resampler>) -> Self {
+        let id = resampler.claim();
+        Self {
+            input,
+            resampler,
+            id,
+            elapsed: 0.0,
+            played: 0.0,
+            format: (0, 0),
+            history: VecDeque::new(),
+            offset: 0.0,
+            remaining: None,
+            speed: 1.0,
+            table: Vec::new(),
+            frame: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn target_rate(&self) -> Option<u32> {
+        let input_rate = self.input.sample_rate();
+        let device_rate = self.resampler.device_rate().unwrap_or(input_rate);
+        // Once started, the filter carries on through a return to normal
+        // speed rather than drop the frames it has read ahead.
+        let started = self.format == (self.input.channels().max(1), input_rate);
+        (device_rate != input_rate || self.resampler.speed() != 1.0 || started)
+            .then_some(device_rate)
+    }
+
+    /// Starts over with nothing buffered, as at the start of the track.
+    fn reset(&mut self, target_rate: u32) {
+        let channels = self.input.channels().max(1);
+        let sample_rate = self.input.sample_rate().max(1);
+        self.format = (channels, sample_rate);
+        // Silence before the first frame, for the filter to look back on.
+        self.history.clear();
+        self.history.resize(SINC_HALF_TAPS * channels as usize, 0.0);
+        self.offset = SINC_HALF_TAPS as f64;
+        self.remaining = None;
+        self.set_speed(self.resampler.speed(), target_rate);
+    }
+
+    fn set_speed(&mut self, speed: f32, target_rate: u32) {
+        self.speed = speed;
+        // Downsampling has to filter out what the lower rate can't hold.
+        let step = self.format.1 as f64 * speed as f64 / target_rate as f64;
+        let cutoff = (1.0 / step).min(1.0) * 0.97;
+        self.table = sinc_table(cutoff);
+    }
+
+    fn kernel(&self, distance: f64) -> f32 {
+        let position = distance.abs() * SINC_PHASES as f64;
+        let index = position as usize;
+        if index + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let fraction = (position - index as f64) as f32;
+        self.table[index] + (self.table[index + 1] - self.table[index]) * fraction
+    }
+
+    /// Works out the next output frame. Returns false at the end of the
+    /// input.
+    fn next_frame(&mut self, target_rate: u32) -> bool {
+        let channels = self.format.0 as usize;
+        let buffered = |history: &VecDeque<f32>| history.len() / channels;
+
+        let needed = self.offset as usize + SINC_HALF_TAPS + 1;
+        while buffered(&self.history) < needed {
+            if self.remaining.is_none() {
+                let before = self.history.len();
+                self.history.extend(self.input.by_ref().take(channels));
+                let read = self.history.len() - before;
+                if read == channels {
+                    continue;
+                }
+                self.history.truncate(before);
+                self.remaining = Some(buffered(&self.history));
+            }
+            self.history.extend(std::iter::repeat_n(0.0, channels));
+        }
+        if self
+            .remaining
+            .is_some_and(|remaining| self.offset >= remaining as f64)
+        {
+            return false;
+        }
+
+        let center = self.offset.floor() as usize;
+        let fraction = self.offset - center as f64;
+        self.frame.clear();
+        match self.resampler.quality() {
+            Quality::Linear => {
+                for channel in 0..channels {
+                    let a = self.history[center * channels + channel];
+                    let b = self.history[(center + 1) * channels + channel];
+                    self.frame.push(a + (b - a) * fraction as f32);
+                }
+            }
+            Quality::Sinc => {
+                self.frame.resize(channels, 0.0);
+                for index in center + 1 - SINC_HALF_TAPS..=center + SINC_HALF_TAPS {
+                    let weight = self.kernel(self.offset - index as f64);
+                    for (channel, out) in self.frame.iter_mut().enumerate() {
+                        *out += self.history[index * channels + channel] * weight;
+                    }
+                }
+            }
+        }
+        self.position = 0;
+
+        let step = self.format.1 as f64 * self.speed as f64 / target_rate as f64;
+        self.offset += step;
+        self.elapsed += step / self.format.1 as f64 * self.resampler.stretch() as f64;
+        self.played += 1.0 / target_rate as f64;
+        self.resampler
+            .set_position(self.id, self.elapsed, self.played);
+        // Drop frames the filter won't reach back to again.
+        let unused = (self.offset as usize + 1).saturating_sub(SINC_HALF_TAPS);
+        if unused > 0 {
+            self.history.drain(..unused * channels);
+            self.offset -= unused as f64;
+            if let Some(remaining) = &mut self.remaining {
+                *remaining = remaining.saturating_sub(unused);
+            }
+        }
+        true
+    }
+}
+
+impl<I> Iterator for ResampleSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position < self.frame.len() {
+            self.position += 1;
+            return Some(self.frame[self.position - 1]);
+        }
+        let Some(target_rate) = self.target_rate() else {
+            let sample = self.input.next()?;
+            let samples_per_second = self.input.channels() as u32 * self.input.sample_rate();
+            self.elapsed += self.resampler.stretch() as f64 / samples_per_second.max(1) as f64;
+            self.played += 1.0 / samples_per_second.max(1) as f64;
+            self.resampler
+                .set_position(self.id, self.elapsed, self.played);
+            return Some(sample);
+        };
+        let format = (self.input.channels().max(1), self.input.sample_rate());
+        if format != self.format {
+            self.reset(target_rate);
+        }
+        let speed = self.resampler.speed();
+        if speed != self.speed {
+            self.set_speed(speed, target_rate);
+        }
+        if !self.next_frame(target_rate) {
+            return None;
+        }
+        self.position = 1;
+        Some(self.frame[0])
+    }
+}
+
+impl<I> Source for ResampleSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.target_rate().is_none() {
+            let pending = self.frame.len() - self.position;
+            return self.input.current_frame_len().map(|len| len + pending);
+        }
+        // Input frames are read ahead for the filter, so there's no telling
+        // where a change of format falls in the output; the format is kept
+        // to the device rate throughout instead.
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.target_rate().unwrap_or(self.input.sample_rate())
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.frame.clear();
+        self.position = 0;
+        // The filter picks up from silence at the new position.
+        self.format = (0, 0);
+        self.elapsed = pos.as_secs_f64();
+        self.played = self.elapsed;
+        self.resampler
+            .set_position(self.id, self.elapsed, self.played);
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! An oscilloscope: the latest few milliseconds of audio traced across the
+//! visualizer in Braille dots.
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Borders},
+};
+
+use crate::ui;
+use crate::visualizer::{Scene, Visualizer};
+
+pub struct Scope;
+
+impl Visualizer for Scope {
+    fn name(&self) -> &'static str {
+        "scope"
+    }
+
+    fn render(&mut self, frame: &mut Frame, area: Rect, scene: &Scene) {
+        let block = Block::default().borders(Borders::ALL).title("Oscilloscope");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let dot_columns = inner.width as usize * 2;
+        let dot_rows = inner.height as usize * 4;
+        if dot_columns == 0 || dot_rows == 0 || scene.samples.is_empty() {
+            return;
+        }
+
+        // Starting from a rising zero crossing keeps a steady tone standing
+        // still instead of sliding along from frame to frame.
+        let samples = &scene.samples;
+        let start = (1..samples.len() / 2)
+            .find(|&i| samples[i - 1] < 0.0 && samples[i] >= 0.0)
+            .unwrap_or(0);
+        let shown = &samples[start..];
+        let row_of = |column: usize| {
+            let sample = shown[column * shown.len() / dot_columns];
+            let height = (1.0 - sample.clamp(-1.0, 1.0)) / 2.0 * (dot_rows - 1) as f32;
+            height.round() as usize
+        };
+
+        let width = inner.width as usize;
+        let mut cells = vec![0u8; width * inner.height as usize];
+        let mut previous = row_of(0);
+        for column in 0..dot_columns {
+            // Joined up with the column before, so steep edges don't break
+            // into scattered dots.
+            let row = row_of(column);
+            for row in row.min(previous)..=row.max(previous) {
+                cells[(row / 4) * width + column / 2] |= ui::braille_dot(column % 2, row % 4);
+            }
+            previous = row;
+        }
+
+        let color = if scene.active {
+            scene.theme.playing
+        } else {
+            scene.theme.paused
+        };
+        for (index, &dots) in cells.iter().enumerate() {
+            if dots == 0 {
+                continue;
+            }
+            let x = inner.x + (index % width) as u16;
+            let y = inner.y + (index / width) as u16;
+            let cell = &mut frame.buffer_mut()[(x, y)];
+            cell.set_char(char::from_u32(0x2800 + dots as u32).unwrap_or(' '));
+            cell.set_fg(color);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Plugins written in Rhai. Every `.rhai` file in the config directory's
+//! `scripts` folder is loaded at startup, along with any given by
+//! `--script`. A script's top level runs once, when it's loaded; after that
+//! apz calls the event functions it defines, `on_track_change(track)` and
+//! `on_state_change(state)`, which can drive the player through `player`
+//! and put text on screen through `ui`. Functions keep state between calls
+//! in `this`, a map of their own per script.
+
+use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use crate::command::Command;
+use crate::now_playing::{self, Snapshot};
+use crate::paths;
+use crate::player::PlaybackState;
+
+const SCRIPTS_DIR: &str = "scripts";
+const EXTENSION: &str = "rhai";
+/// Work a script may do per call before it's stopped, so one stuck in a
+/// loop can't freeze the player.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// The player as scripts see it: commands go out through the same channel
+/// as the control socket's, and questions are answered from the last
+/// snapshot.
+#[derive(Clone)]
+struct PlayerApi {
+    commands: Sender<Command>,
+    snapshot: Rc<RefCell<Option<Snapshot>>>,
+}
+
+impl PlayerApi {
+    fn send(&self, command: Command) {
+        self.commands.send(command).ok();
+    }
+
+    fn seek(&self, seconds: f64) -> Result<(), Box<EvalAltResult>> {
+        let position = Duration::try_from_secs_f64(seconds.max(0.0))
+            .ok()
+            .filter(|_| seconds.is_finite())
+            .ok_or_else(|| format!("can't seek to {} seconds", seconds))?;
+        self.send(Command::SeekTo(position));
+        Ok(())
+    }
+
+    fn read<T>(&self, field: impl FnOnce(&Snapshot) -> T) -> Option<T> {
+        self.snapshot.borrow().as_ref().map(field)
+    }
+}
+
+#[derive(Clone)]
+struct UiApi {
+    commands: Sender<Command>,
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+    /// The script's `this`.
+    state: Dynamic,
+}
+
+pub struct Scripts {
+    engine: Engine,
+    /// Holds `player` and `ui` for the top level and the event functions.
+    scope: Scope<'static>,
+    scripts: Vec<Script>,
+    commands: Sender<Command>,
+    snapshot: Rc<RefCell<Option<Snapshot>>>,
+    loaded_at: Option<Instant>,
+    state: Option<PlaybackState>,
+}
+
+impl Scripts {
+    /// Loads and runs the scripts in the config directory and `extra`.
+    /// `None` when there are none.
+    pub fn load(extra: &[PathBuf], commands: Sender<Command>) -> Option<Self> {
+        let mut paths: Vec<PathBuf> = paths::config_dir()
+            .and_then(|dir| fs::read_dir(dir.join(SCRIPTS_DIR)).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+            .collect();
+        paths.sort();
+        paths.extend(extra.iter().cloned());
+        if paths.is_empty() {
+            return None;
+        }
+
+        let snapshot = Rc::new(RefCell::new(None));
+        let engine = engine(commands.clone());
+        let mut scope = Scope::new();
+        scope.push_constant(
+            "player",
+            PlayerApi {
+                commands: commands.clone(),
+                snapshot: snapshot.clone(),
+            },
+        );
+        scope.push_constant(
+            "ui",
+            UiApi {
+                commands: commands.clone(),
+            },
+        );
+        let mut scripts = Self {
+            engine,
+            scope,
+            scripts: Vec::new(),
+            commands,
+            snapshot,
+            loaded_at: None,
+            state: None,
+        };
+        for path in paths {
+            scripts.add(&path);
+        }
+        Some(scripts)
+    }
+
+    fn add(&mut self, path: &Path) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let ast = match self.engine.compile_file(path.to_path_buf()) {
+            Ok(ast) => ast,
+            Err(e) => return self.error(&name, &e.to_string()),
+        };
+        // A copy, so one script's top-level variables don't reach the next.
+        let mut scope = self.scope.clone();
+        if let Err(e) = self.engine.run_ast_with_scope(&mut scope, &ast) {
+            return self.error(&name, &e.to_string());
+        }
+        self.scripts.push(Script {
+            name,
+            ast,
+            state: Dynamic::from_map(Map::new()),
+        });
+    }
+
+    /// Calls the scripts' event functions for whatever has happened since
+    /// the last call. `loaded_at` tells a track apart from the same one
+    /// loaded again.
+    pub fn update(&mut self, snapshot: &Snapshot, loaded_at: Instant) {
+        *self.snapshot.borrow_mut() = Some(snapshot.clone());
+
+        if self.loaded_at != Some(loaded_at) && !snapshot.path.as_os_str().is_empty() {
+            self.loaded_at = Some(loaded_at);
+            self.call("on_track_change", track(snapshot));
+        }
+        if self.state != Some(snapshot.state) {
+            self.state = Some(snapshot.state);
+            let state = now_playing::state_name(snapshot.state);
+            self.call("on_state_change", Dynamic::from(state.to_string()));
+        }
+    }
+
+    /// Calls `function` with `argument` in every script that defines it.
+    fn call(&mut self, function: &str, argument: Dynamic) {
+        let mut errors = Vec::new();
+        for script in &mut self.scripts {
+            let defined = script
+                .ast
+                .iter_functions()
+                .any(|f| f.name == function && f.params.len() == 1);
+            if !defined {
+                continue;
+            }
+            let options = CallFnOptions::new()
+                .eval_ast(false)
+                .bind_this_ptr(&mut script.state);
+            let result = self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut self.scope,
+                &script.ast,
+                function,
+                (argument.clone(),),
+            );
+            if let Err(e) = result {
+                errors.push((script.name.clone(), e.to_string()));
+            }
+        }
+        for (name, error) in errors {
+            self.error(&name, &error);
+        }
+    }
+
+    fn error(&self, name: &str, error: &str) {
+        self.commands
+            .send(Command::Error(format!("{}: {}", name, error)))
+            .ok();
+    }
+}
+
+fn engine(commands: Sender<Command>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    engine.on_print(move |text| {
+        commands.send(Command::Toast(text.to_string())).ok();
+    });
+
+    engine
+        .register_type_with_name::<PlayerApi>("Player")
+        .register_fn("play", |player: PlayerApi| player.send(Command::Play))
+        .register_fn("pause", |player: PlayerApi| player.send(Command::Pause))
+        .register_fn("toggle", |player: PlayerApi| {
+            player.send(Command::TogglePause)
+        })
+        .register_fn("stop", |player: PlayerApi| player.send(Command::Stop))
+        .register_fn("next", |player: PlayerApi| player.send(Command::NextTrack))
+        .register_fn("prev", |player: PlayerApi| {
+            player.send(Command::PreviousTrack)
+        })
+        .register_fn("seek", |player: PlayerApi, seconds: f64| {
+            player.seek(seconds)
+        })
+        .register_fn("seek", |player: PlayerApi, seconds: i64| {
+            player.seek(seconds as f64)
+        })
+        .register_fn("volume", |player: PlayerApi, percent: i64| {
+            player.send(Command::SetVolume(percent.clamp(0, 100) as f32 / 100.0))
+        })
+        .register_fn("enqueue", |player: PlayerApi, path: &str| {
+            player.send(Command::Enqueue(paths::expand_home(path)))
+        })
+        .register_fn("position", |player: PlayerApi| {
+            player
+                .read(|snapshot| snapshot.position.as_secs_f64())
+                .unwrap_or_default()
+        })
+        .register_fn("duration", |player: PlayerApi| {
+            player
+                .read(|snapshot| snapshot.duration.as_secs_f64())
+                .unwrap_or_default()
+        })
+        .register_fn("state", |player: PlayerApi| {
+            player
+                .read(|snapshot| now_playing::state_name(snapshot.state).to_string())
+                .unwrap_or_else(|| "stopped".to_string())
+        })
+        .register_fn("track", |player: PlayerApi| {
+            player.read(track).unwrap_or(Dynamic::UNIT)
+        });
+
+    engine
+        .register_type_with_name::<UiApi>("Ui")
+        .register_fn("message", |ui: UiApi, text: &str| {
+            ui.commands.send(Command::Toast(text.to_string())).ok();
+        })
+        .register_fn("error", |ui: UiApi, text: &str| {
+            ui.commands.send(Command::Error(text.to_string())).ok();
+        });
+
+    engine
+}
+
+/// The track as a map for scripts, with `()` for missing tags.
+fn track(snapshot: &Snapshot) -> Dynamic {
+    let tag = |value: &Option<String>| value.clone().map_or(Dynamic::UNIT, Dynamic::from);
+    let track = &snapshot.track;
+    let mut map = Map::new();
+    map.insert(
+        "path".into(),
+        snapshot.path.to_string_lossy().into_owned().into(),
+    );
+    map.insert("filename".into(), snapshot.filename.clone().into());
+    map.insert("title".into(), tag(&track.title));
+    map.insert("artist".into(), tag(&track.artist));
+    map.insert("album".into(), tag(&track.album));
+    map.insert(
+        "track_number".into(),
+        track
+            .track_number
+            .map_or(Dynamic::UNIT, |number| Dynamic::from(number as i64)),
+    );
+    map.insert("duration".into(), snapshot.duration.as_secs_f64().into());
+    Dynamic::from_map(map)
+}
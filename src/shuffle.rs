@@ -0,0 +1,107 @@
+//! Shuffled play order. Smart shuffle still leaves everything to chance, but
+//! loads the dice towards highly rated tracks and away from ones heard
+//! recently.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::history;
+use crate::library::{self, Library};
+
+pub const SHUFFLE_MODES: [&str; 2] = ["random", "smart"];
+
+/// Unrated tracks are weighted as if they had this many stars.
+const UNRATED_STARS: u8 = 3;
+/// A track played this long ago has recovered about two thirds of its
+/// chances of coming up early.
+const RECENCY_RECOVERY: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+/// Even a track that has only just been played keeps some chance.
+const MIN_RECENCY_WEIGHT: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShuffleMode {
+    /// Every order equally likely.
+    Random,
+    /// Weighted by rating and how long ago each track was last played.
+    Smart,
+}
+
+impl ShuffleMode {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "random" => Some(Self::Random),
+            "smart" => Some(Self::Smart),
+            _ => None,
+        }
+    }
+}
+
+pub fn shuffle(tracks: &mut [PathBuf], mode: ShuffleMode) {
+    let mut rng = Rng::seeded();
+    let weight: Box<dyn Fn(&PathBuf) -> f64> = match mode {
+        ShuffleMode::Random => Box::new(|_| 1.0),
+        ShuffleMode::Smart => {
+            let library = Library::load();
+            let last_played: HashMap<PathBuf, SystemTime> = history::recent(usize::MAX)
+                .into_iter()
+                .map(|play| (play.path, play.played_at))
+                .collect();
+            Box::new(move |path| {
+                let stars = library.get(path).rating.unwrap_or(UNRATED_STARS);
+                let rating = (stars as f64 / library::MAX_RATING as f64).powi(2);
+                let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+                let recency = last_played.get(&canonical).map_or(1.0, |&played_at| {
+                    let since = SystemTime::now()
+                        .duration_since(played_at)
+                        .unwrap_or_default();
+                    let recovered = since.as_secs_f64() / RECENCY_RECOVERY.as_secs_f64();
+                    (1.0 - (-recovered).exp()).max(MIN_RECENCY_WEIGHT)
+                });
+                rating * recency
+            })
+        }
+    };
+
+    // Weighted random sampling without replacement (Efraimidis-Spirakis):
+    // each track draws u^(1/weight) and the highest draws go first, so a
+    // heavier track tends to come earlier without ever being certain to.
+    let mut keyed: Vec<(f64, PathBuf)> = tracks
+        .iter()
+        .map(|path| (rng.next_f64().powf(weight(path).recip()), path.clone()))
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (slot, (_, path)) in tracks.iter_mut().zip(keyed) {
+        *slot = path;
+    }
+}
+
+/// SplitMix64, which is plenty for picking a play order or scattering
+/// visualizer particles, and mixes well even from seeds that differ in only
+/// a few bits.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn seeded() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Self(nanos ^ ((process::id() as u64) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in (0, 1].
+    pub fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+}
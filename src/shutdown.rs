@@ -0,0 +1,57 @@
+//! Leaves the terminal usable however apz exits: normally, on a panic, or
+//! when asked to stop by a signal.
+
+use crossterm::{
+    cursor::Show,
+    event::DisableMouseCapture,
+    execute,
+    terminal::{LeaveAlternateScreen, disable_raw_mode},
+};
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use crate::command::Command;
+
+pub fn restore_terminal() {
+    disable_raw_mode().ok();
+    execute!(
+        io::stdout(),
+        DisableMouseCapture,
+        LeaveAlternateScreen,
+        Show
+    )
+    .ok();
+}
+
+/// Restores the terminal before the panic message is printed, so it isn't
+/// lost on the alternate screen. Panics on background threads leave the UI
+/// alone; the player keeps running without that thread.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if thread::current().name() == Some("main") {
+            restore_terminal();
+        }
+        default_hook(info);
+    }));
+}
+
+/// Turns SIGINT/SIGTERM (and SIGQUIT on Unix) into a [`Command::Quit`], so
+/// the main loop exits the normal way and everything gets cleaned up.
+pub fn forward_signals(commands: Sender<Command>) {
+    let received = Arc::new(AtomicBool::new(false));
+    for &signal in signal_hook::consts::TERM_SIGNALS {
+        signal_hook::flag::register(signal, Arc::clone(&received)).ok();
+    }
+
+    thread::spawn(move || {
+        while !received.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(100));
+        }
+        commands.send(Command::Quit).ok();
+    });
+}
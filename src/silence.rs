@@ -0,0 +1,135 @@
+use rodio::Source;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use crate::decoder::{self, AudioSource};
+use crate::error::ApzError;
+
+/// Silence is measured in windows this long.
+const WINDOW: Duration = Duration::from_millis(10);
+/// Left in place at both ends of a skipped gap, so speech doesn't start or
+/// stop abruptly.
+const PADDING: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy)]
+pub struct SilenceConfig {
+    /// Peak level in dBFS below which audio counts as silent.
+    pub threshold_db: f32,
+    /// Shorter gaps are played through.
+    pub min_length: Duration,
+    pub skip: bool,
+    /// Start at the first sound and end at the last one.
+    pub trim: bool,
+}
+
+impl SilenceConfig {
+    fn threshold(&self) -> u16 {
+        let amplitude = 10f32.powf(self.threshold_db / 20.0);
+        (amplitude * i16::MAX as f32).clamp(1.0, i16::MAX as f32) as u16
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Silences {
+    /// Gaps of at least the minimum length, less [`PADDING`].
+    pub gaps: Vec<Range<Duration>>,
+    /// Where the silence that runs to the end of the track starts.
+    pub trailing: Option<Duration>,
+}
+
+/// Decodes `path` on a background thread and sends back its silences.
+/// Nothing is sent if the file can't be decoded.
+pub fn scan(path: PathBuf, config: SilenceConfig) -> Receiver<Silences> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        if let Ok(silences) = detect(&path, &config) {
+            sender.send(silences).ok();
+        }
+    });
+    receiver
+}
+
+/// Where the first sound in `path` is. Only decodes up to there, so it's
+/// quick enough to wait for before playback starts.
+pub fn leading(path: &Path, config: &SilenceConfig) -> Duration {
+    let Ok(mut windows) = Windows::open(path, config) else {
+        return Duration::ZERO;
+    };
+    let start = windows
+        .find(|&(silent, _)| !silent)
+        .map_or(0, |(_, end)| end - windows.length);
+    windows.time(start)
+}
+
+pub fn detect(path: &Path, config: &SilenceConfig) -> Result<Silences, ApzError> {
+    let mut windows = Windows::open(path, config)?;
+    let length = windows.length;
+    let mut gaps = Vec::new();
+    let mut silent_since = None;
+
+    for (silent, end) in windows.by_ref() {
+        if silent {
+            silent_since.get_or_insert(end - length);
+        } else if let Some(start) = silent_since.take() {
+            gaps.push(start..end - length);
+        }
+    }
+    if let Some(start) = silent_since {
+        gaps.push(start..windows.index);
+    }
+
+    let trailing = silent_since.map(|start| windows.time(start));
+    let gaps = gaps
+        .into_iter()
+        .map(|gap| windows.time(gap.start)..windows.time(gap.end))
+        .filter(|gap| gap.end - gap.start >= config.min_length.max(PADDING * 2))
+        .map(|gap| gap.start + PADDING..gap.end - PADDING)
+        .collect();
+    Ok(Silences { gaps, trailing })
+}
+
+/// Steps through a decoded track a window at a time, yielding whether each
+/// window is silent and the sample index it ends at.
+struct Windows {
+    source: AudioSource,
+    samples_per_second: u64,
+    /// Samples per window, across all channels.
+    length: u64,
+    threshold: u16,
+    index: u64,
+}
+
+impl Windows {
+    fn open(path: &Path, config: &SilenceConfig) -> Result<Self, ApzError> {
+        let source = decoder::open(path)?.source;
+        let channels = source.channels().max(1) as u64;
+        let rate = source.sample_rate().max(1) as u64;
+        Ok(Self {
+            source,
+            samples_per_second: rate * channels,
+            length: (rate * WINDOW.as_millis() as u64 / 1000).max(1) * channels,
+            threshold: config.threshold(),
+            index: 0,
+        })
+    }
+
+    fn time(&self, index: u64) -> Duration {
+        Duration::from_secs_f64(index as f64 / self.samples_per_second as f64)
+    }
+}
+
+impl Iterator for Windows {
+    type Item = (bool, u64);
+
+    fn next(&mut self) -> Option<(bool, u64)> {
+        let mut peak = 0;
+        for _ in 0..self.length {
+            peak = self.source.next()?.unsigned_abs().max(peak);
+            self.index += 1;
+        }
+        Some((peak < self.threshold, self.index))
+    }
+}
@@ -1,68 +1,586 @@
-use rustfft::{FftPlanner, num_complex::Complex};
-use std::sync::{Arc, Mutex};
+//! The spectrum analyzer, which runs on a thread of its own so drawing a
+//! frame only has to read the latest bar levels.
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::power;
+use crate::tee_source::SampleRing;
 
 const SAMPLE_SIZE: usize = 2048;
+/// Level shown as an empty bar; everything from here to 0 dBFS is scaled
+/// linearly onto the bar height.
+pub const FLOOR_DB: f32 = -70.0;
+pub const MIN_BARS: usize = 8;
+pub const MAX_BARS: usize = 512;
+
+/// Beats are found in the kick drum and bass.
+const BEAT_BAND: Range<f32> = 30.0..150.0;
+/// How far above the recent average, in standard deviations, a rise in bass
+/// has to be to count as a beat.
+const BEAT_SENSITIVITY: f32 = 1.5;
+/// Smallest rise that can be a beat, so noise in quiet passages isn't.
+const MIN_BEAT_FLUX: f32 = 1.0;
+/// Beats closer together than this (200 BPM) are taken as one.
+const MIN_BEAT_INTERVAL: Duration = Duration::from_millis(300);
+/// After this long without a beat the tempo is forgotten.
+const BEAT_TIMEOUT: Duration = Duration::from_secs(3);
+/// The tempo is the median of this many beat intervals, folded into
+/// [`TEMPO_RANGE`] so it isn't reported at half or double speed.
+const TEMPO_INTERVALS: usize = 8;
+const TEMPO_RANGE: Range<f32> = 70.0..180.0;
+/// Share of each new estimate in the tempo shown, so it doesn't flicker.
+const TEMPO_SMOOTHING: f32 = 0.2;
+/// Estimates this far from the tempo shown, this many times in a row,
+/// replace it outright.
+const TEMPO_CHANGE: f32 = 0.15;
+const TEMPO_CHANGE_COUNT: usize = 4;
+/// How often the analysis thread checks it's still wanted while it has
+/// nothing to do.
+const IDLE_CHECK: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpectrumConfig {
+    pub num_bars: usize,
+    pub smoothing: f32,
+    pub bass_boost: f32,
+    /// Displayed frequency range in Hz.
+    pub min_freq: f32,
+    pub max_freq: f32,
+    /// Exponent applied when spreading bars over the frequency range; values
+    /// above 1.0 give more bars to the low end.
+    pub curve: f32,
+    /// Times per second the bars are worked out, normally the frame rate.
+    pub rate: u32,
+    /// Detect beats as well, for the beat pulse.
+    pub beats: bool,
+}
+
+/// Handle on a running analyzer. The analysis thread stops once every
+/// handle is gone.
+#[derive(Clone)]
+pub struct Spectrum {
+    shared: Arc<Shared>,
+    thread: Option<Thread>,
+}
+
+/// What the analysis thread and the UI share. Bar levels are published one
+/// atomic at a time, so a frame drawn mid-update can mix levels from two
+/// successive analyses, which can't be told apart on screen.
+struct Shared {
+    ring: Arc<SampleRing>,
+    bars: [AtomicU32; MAX_BARS],
+    /// Bars in the last published analysis.
+    published: AtomicUsize,
+    num_bars: AtomicUsize,
+    bass_boost: AtomicU32,
+    animate: AtomicBool,
+    /// While nothing's playing, the analysis thread sleeps once the bars
+    /// have settled, until woken.
+    playing: AtomicBool,
+    min_freq: AtomicU32,
+    max_freq: AtomicU32,
+    curve: AtomicU32,
+    beats: bool,
+    started: Instant,
+    /// Milliseconds after `started` of the last beat, plus one so zero can
+    /// mean there hasn't been one.
+    last_beat: AtomicU64,
+    /// Tempo in BPM, or zero while it isn't known.
+    tempo: AtomicU32,
+}
+
+impl Spectrum {
+    pub fn start(config: SpectrumConfig) -> Self {
+        let analyzer = SpectrumAnalyzer::new(config);
+        let shared = Arc::new(Shared {
+            ring: Arc::clone(&analyzer.ring),
+            bars: std::array::from_fn(|_| AtomicU32::new(0)),
+            published: AtomicUsize::new(0),
+            num_bars: AtomicUsize::new(analyzer.num_bars),
+            bass_boost: AtomicU32::new(analyzer.bass_boost.to_bits()),
+            animate: AtomicBool::new(true),
+            playing: AtomicBool::new(true),
+            min_freq: AtomicU32::new(analyzer.min_freq.to_bits()),
+            max_freq: AtomicU32::new(analyzer.max_freq.to_bits()),
+            curve: AtomicU32::new(analyzer.curve.to_bits()),
+            beats: config.beats,
+            started: Instant::now(),
+            last_beat: AtomicU64::new(0),
+            tempo: AtomicU32::new(0),
+        });
+
+        let weak = Arc::downgrade(&shared);
+        let thread = thread::Builder::new()
+            .name("spectrum".to_string())
+            .spawn(move || run(analyzer, weak, config.rate))
+            .ok()
+            .map(|handle| handle.thread().clone());
+        Self { shared, thread }
+    }
+
+    fn wake(&self) {
+        if let Some(thread) = &self.thread {
+            thread.unpark();
+        }
+    }
+
+    /// Where the audio to analyze is written.
+    pub fn ring(&self) -> Arc<SampleRing> {
+        Arc::clone(&self.shared.ring)
+    }
+
+    /// Levels of the bars from the latest analysis, each from 0.0 to 1.0.
+    pub fn bars(&self) -> Vec<f32> {
+        let published = self.shared.published.load(Ordering::Acquire);
+        self.shared.bars[..published]
+            .iter()
+            .map(|bar| f32::from_bits(bar.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    pub fn num_bars(&self) -> usize {
+        self.shared.num_bars.load(Ordering::Relaxed)
+    }
+
+    pub fn set_num_bars(&self, num_bars: usize) {
+        let num_bars = num_bars.clamp(MIN_BARS, MAX_BARS);
+        self.shared.num_bars.store(num_bars, Ordering::Relaxed);
+        self.wake();
+    }
+
+    pub fn bass_boost(&self) -> f32 {
+        f32::from_bits(self.shared.bass_boost.load(Ordering::Relaxed))
+    }
+
+    pub fn set_bass_boost(&self, bass_boost: f32) {
+        let bass_boost = bass_boost.max(0.0).to_bits();
+        self.shared.bass_boost.store(bass_boost, Ordering::Relaxed);
+        self.wake();
+    }
+
+    /// The frequencies the bars are spread over, in Hz.
+    pub fn range(&self) -> (f32, f32) {
+        let shared = &self.shared;
+        (
+            f32::from_bits(shared.min_freq.load(Ordering::Relaxed)),
+            f32::from_bits(shared.max_freq.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// Spreads the bars from `min_freq` up to `max_freq`, or as high as the
+    /// sample rate allows.
+    pub fn set_range(&self, min_freq: f32, max_freq: f32) {
+        if !(min_freq >= 0.0 && min_freq < max_freq && max_freq.is_finite()) {
+            return;
+        }
+        let shared = &self.shared;
+        shared.min_freq.store(min_freq.to_bits(), Ordering::Relaxed);
+        shared.max_freq.store(max_freq.to_bits(), Ordering::Relaxed);
+        self.wake();
+    }
+
+    pub fn curve(&self) -> f32 {
+        f32::from_bits(self.shared.curve.load(Ordering::Relaxed))
+    }
+
+    pub fn set_curve(&self, curve: f32) {
+        if !(curve.is_finite() && curve > 0.0) {
+            return;
+        }
+        self.shared.curve.store(curve.to_bits(), Ordering::Relaxed);
+        self.wake();
+    }
+
+    /// Where `frequency` falls along the bars, from 0.0 at the left edge of
+    /// the first to 1.0 at the right edge of the last, or `None` if it's
+    /// outside the range shown.
+    pub fn position_of(&self, frequency: f32) -> Option<f32> {
+        let (min_freq, max_freq) = self.range();
+        let (min_freq, max_freq) = shown_range(min_freq, max_freq, self.shared.ring.sample_rate());
+        if max_freq <= min_freq || !(min_freq..=max_freq).contains(&frequency) {
+            return None;
+        }
+        let position = (frequency / min_freq).ln() / (max_freq / min_freq).ln();
+        Some(position.powf(1.0 / self.curve()))
+    }
+
+    /// The frequency at `position` along the bars, the inverse of
+    /// [`Spectrum::position_of`].
+    pub fn frequency_at(&self, position: f32) -> f32 {
+        let (min_freq, max_freq) = self.range();
+        let (min_freq, max_freq) = shown_range(min_freq, max_freq, self.shared.ring.sample_rate());
+        min_freq * (max_freq / min_freq).powf(position.clamp(0.0, 1.0).powf(self.curve()))
+    }
+
+    pub fn detects_beats(&self) -> bool {
+        self.shared.beats
+    }
+
+    /// How long ago the last beat was, if there's been one.
+    pub fn since_beat(&self) -> Option<Duration> {
+        let at = self
+            .shared
+            .last_beat
+            .load(Ordering::Relaxed)
+            .checked_sub(1)?;
+        Some(
+            self.shared
+                .started
+                .elapsed()
+                .saturating_sub(Duration::from_millis(at)),
+        )
+    }
+
+    /// Tempo of the music, once a few beats in a row have been found.
+    pub fn tempo(&self) -> Option<f32> {
+        let tempo = f32::from_bits(self.shared.tempo.load(Ordering::Relaxed));
+        (tempo > 0.0).then_some(tempo)
+    }
 
-pub struct SpectrumAnalyzer {
-    samples: Arc<Mutex<Vec<f32>>>,
+    /// With animation off, bars jump straight to their new level instead of
+    /// easing towards it, and are worked out no faster than the screen is
+    /// drawn when saving power.
+    pub fn set_animate(&self, animate: bool) {
+        self.shared.animate.store(animate, Ordering::Relaxed);
+        self.wake();
+    }
+
+    pub fn set_playing(&self, playing: bool) {
+        if self.shared.playing.swap(playing, Ordering::Relaxed) != playing {
+            self.wake();
+        }
+    }
+}
+
+/// The analysis thread. Only analyzes while there's new audio, or the bars
+/// are still easing or being reconfigured, and sleeps until woken once
+/// they've settled with nothing playing.
+fn run(mut analyzer: SpectrumAnalyzer, shared: Weak<Shared>, rate: u32) {
+    let mut analyzed = None;
+    let mut beats = BeatTracker::new(rate);
+    // Audio the beat tracker has seen, since only new audio can hold a beat.
+    let mut tracked = None;
+    while let Some(shared) = shared.upgrade() {
+        let animate = shared.animate.load(Ordering::Relaxed);
+        analyzer.animate = animate;
+        analyzer.set_num_bars(shared.num_bars.load(Ordering::Relaxed));
+        analyzer.bass_boost = f32::from_bits(shared.bass_boost.load(Ordering::Relaxed));
+        analyzer.min_freq = f32::from_bits(shared.min_freq.load(Ordering::Relaxed));
+        analyzer.max_freq = f32::from_bits(shared.max_freq.load(Ordering::Relaxed));
+        analyzer.curve = f32::from_bits(shared.curve.load(Ordering::Relaxed));
+
+        let state = (
+            shared.ring.written(),
+            analyzer.num_bars,
+            analyzer.bass_boost,
+            (analyzer.min_freq, analyzer.max_freq, analyzer.curve),
+        );
+        let updated = analyzed != Some(state) && analyzer.update();
+        if updated {
+            for (slot, &bar) in shared.bars.iter().zip(&analyzer.bars) {
+                slot.store(bar.to_bits(), Ordering::Relaxed);
+            }
+            shared.published.store(analyzer.num_bars, Ordering::Release);
+            analyzed = analyzer.settled().then_some(state);
+
+            if shared.beats && tracked != Some(state.0) {
+                tracked = Some(state.0);
+                // Beats are timed by the audio rather than the clock, so
+                // the tempo isn't thrown off by when this thread gets to run.
+                let rate = shared.ring.sample_rate() as f64;
+                let time = Duration::from_secs_f64(state.0 as f64 / rate);
+                if beats.push(&analyzer.spectrum, analyzer.bin_width(), time) {
+                    let at = shared.started.elapsed().as_millis() as u64;
+                    shared.last_beat.store(at + 1, Ordering::Relaxed);
+                }
+                let tempo = beats.tempo.unwrap_or(0.0);
+                shared.tempo.store(tempo.to_bits(), Ordering::Relaxed);
+            }
+        }
+        let idle = !updated && !shared.playing.load(Ordering::Relaxed);
+        drop(shared);
+
+        if idle {
+            thread::park_timeout(IDLE_CHECK);
+            continue;
+        }
+        let rate = if animate {
+            rate
+        } else {
+            rate.min(power::LOW_POWER_FPS)
+        };
+        thread::sleep(Duration::from_secs(1) / rate.max(1));
+    }
+}
+
+struct SpectrumAnalyzer {
+    ring: Arc<SampleRing>,
     bars: Vec<f32>,
     num_bars: usize,
     smoothing: f32,
+    animate: bool,
     bass_boost: f32,
+    min_freq: f32,
+    max_freq: f32,
+    curve: f32,
+    window: Vec<f32>,
+    /// Scales magnitudes so a full-scale sine reads as 1.0 (0 dB).
+    window_scale: f32,
+    // Planned once and reused every frame, along with its working buffers.
+    fft: Arc<dyn Fft<f32>>,
+    samples: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    spectrum: Vec<f32>,
+    /// How far the bars moved in the last update.
+    movement: f32,
 }
 
 impl SpectrumAnalyzer {
-    pub fn new(num_bars: usize, smoothing: f32, bass_boost: f32) -> Self {
+    fn new(config: SpectrumConfig) -> Self {
+        let num_bars = config.num_bars.clamp(MIN_BARS, MAX_BARS);
+        let window: Vec<f32> = (0..SAMPLE_SIZE)
+            .map(|n| {
+                let phase = 2.0 * PI * n as f32 / (SAMPLE_SIZE - 1) as f32;
+                0.5 * (1.0 - phase.cos())
+            })
+            .collect();
+        let fft = FftPlanner::new().plan_fft_forward(SAMPLE_SIZE);
+
         Self {
-            samples: Arc::new(Mutex::new(Vec::new())),
+            ring: SampleRing::new(),
             bars: vec![0.0; num_bars],
             num_bars,
-            smoothing,
-            bass_boost,
+            smoothing: config.smoothing,
+            animate: true,
+            bass_boost: config.bass_boost,
+            min_freq: config.min_freq,
+            max_freq: config.max_freq,
+            curve: config.curve,
+            window_scale: 2.0 / window.iter().sum::<f32>(),
+            window,
+            samples: vec![0.0; SAMPLE_SIZE],
+            buffer: vec![Complex::default(); SAMPLE_SIZE],
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            spectrum: vec![0.0; SAMPLE_SIZE / 2],
+            movement: 0.0,
+            fft,
         }
     }
 
-    pub fn get_sample_buffer(&self) -> Arc<Mutex<Vec<f32>>> {
-        Arc::clone(&self.samples)
+    fn set_num_bars(&mut self, num_bars: usize) {
+        self.num_bars = num_bars.clamp(MIN_BARS, MAX_BARS);
+        self.bars.resize(self.num_bars, 0.0);
     }
 
-    pub fn update(&mut self) {
-        let samples = self.samples.lock().unwrap();
-        if samples.len() < SAMPLE_SIZE {
-            return;
+    /// Works out new bar levels from the latest audio. Returns false if
+    /// there isn't enough audio yet.
+    fn update(&mut self) -> bool {
+        // Each channel is analyzed on its own and their power averaged, so
+        // sound that's out of phase between channels doesn't cancel out the
+        // way it would in a mono mix.
+        let channels = self.ring.channels();
+        self.spectrum.fill(0.0);
+        for channel in 0..channels {
+            if !self.ring.latest(channel, &mut self.samples) {
+                return false;
+            }
+
+            // The most recent window, tapered with a Hann window so the
+            // edges of the block don't leak energy across the whole spectrum.
+            for ((slot, &sample), &weight) in
+                self.buffer.iter_mut().zip(&self.samples).zip(&self.window)
+            {
+                *slot = Complex::new(sample * weight, 0.0);
+            }
+            self.fft
+                .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+            for (power, bin) in self.spectrum.iter_mut().zip(&self.buffer) {
+                *power += (bin.norm() * self.window_scale).powi(2);
+            }
+        }
+        for magnitude in self.spectrum.iter_mut() {
+            *magnitude = (*magnitude / channels as f32).sqrt();
         }
+        let spectrum = &self.spectrum;
+        let bin_width = self.bin_width();
 
-        let mut buffer: Vec<Complex<f32>> = samples[..SAMPLE_SIZE]
-            .iter()
-            .map(|&s| Complex::new(s, 0.0))
-            .collect();
+        let smoothing = if self.animate { self.smoothing } else { 0.0 };
+        self.movement = 0.0;
+        let (min_freq, max_freq) =
+            shown_range(self.min_freq, self.max_freq, self.ring.sample_rate());
+        let ratio = max_freq / min_freq;
+        for (i, bar) in self.bars.iter_mut().enumerate() {
+            // Bars are spaced evenly on a log-frequency axis, the way pitch is
+            // heard; each one takes the loudest bin in its band.
+            let edge = |i: usize| {
+                let position = (i as f32 / self.num_bars as f32).powf(self.curve);
+                min_freq * ratio.powf(position)
+            };
+            let low = ((edge(i) / bin_width) as usize).min(spectrum.len() - 1);
+            let high = ((edge(i + 1) / bin_width) as usize).clamp(low + 1, spectrum.len());
+            let magnitude = spectrum[low..high].iter().copied().fold(0.0, f32::max);
 
-        let mut planner = FftPlanner::new();
-        let fft = planner.plan_fft_forward(SAMPLE_SIZE);
-        fft.process(&mut buffer);
+            let bass_factor = self.bass_boost * (1.0 - i as f32 / self.num_bars as f32);
+            let db = 20.0 * (magnitude * (1.0 + bass_factor)).max(1e-9).log10();
+            let level = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
 
-        let spectrum: Vec<f32> = buffer[..SAMPLE_SIZE / 2]
+            let eased = *bar * smoothing + level * (1.0 - smoothing);
+            self.movement = self.movement.max((eased - *bar).abs());
+            *bar = eased;
+        }
+        true
+    }
+
+    /// Hz covered by each bin of the spectrum.
+    fn bin_width(&self) -> f32 {
+        self.ring.sample_rate() as f32 / SAMPLE_SIZE as f32
+    }
+
+    /// Whether the bars have finished easing towards their levels.
+    fn settled(&self) -> bool {
+        self.movement < 0.001
+    }
+}
+
+/// The lowest and highest frequencies the bars cover, which can't go past
+/// what the sample rate can hold.
+fn shown_range(min_freq: f32, max_freq: f32, sample_rate: u32) -> (f32, f32) {
+    let min_freq = min_freq.max(1.0);
+    (
+        min_freq,
+        max_freq.min(sample_rate as f32 / 2.0).max(min_freq),
+    )
+}
+
+/// Finds beats as sudden rises in bass: spectral flux over the low bands,
+/// measured against a threshold that follows how busy the music has been
+/// over the last second.
+struct BeatTracker {
+    /// Compressed magnitudes of the low bands last time.
+    previous: Vec<f32>,
+    /// Recent flux, for the threshold.
+    history: VecDeque<f32>,
+    history_length: usize,
+    last_beat: Option<Duration>,
+    /// Seconds between recent beats.
+    intervals: VecDeque<f32>,
+    tempo: Option<f32>,
+    /// Estimates in a row that disagreed with `tempo`.
+    disagreements: usize,
+}
+
+impl BeatTracker {
+    /// For a spectrum updated `rate` times a second.
+    fn new(rate: u32) -> Self {
+        let history_length = rate.max(2) as usize;
+        Self {
+            previous: Vec::new(),
+            history: VecDeque::with_capacity(history_length),
+            history_length,
+            last_beat: None,
+            intervals: VecDeque::with_capacity(TEMPO_INTERVALS),
+            tempo: None,
+            disagreements: 0,
+        }
+    }
+
+    /// Takes the spectrum of the audio up to `now`, returning whether it's a
+    /// beat.
+    fn push(&mut self, spectrum: &[f32], bin_width: f32, now: Duration) -> bool {
+        let low = ((BEAT_BAND.start / bin_width) as usize).min(spectrum.len() - 1);
+        let high = ((BEAT_BAND.end / bin_width).ceil() as usize).clamp(low + 1, spectrum.len());
+        // Log compression evens out loud and quiet passages.
+        let current: Vec<f32> = spectrum[low..high]
             .iter()
-            .map(|c| (c.re * c.re + c.im * c.im).sqrt())
+            .map(|magnitude| (1.0 + 100.0 * magnitude).ln())
             .collect();
+        let flux: f32 = if self.previous.len() == current.len() {
+            current
+                .iter()
+                .zip(&self.previous)
+                .map(|(now, before)| (now - before).max(0.0))
+                .sum()
+        } else {
+            0.0
+        };
+        self.previous = current;
 
-        for (i, bar) in self.bars.iter_mut().enumerate() {
-            let freq_index = ((i as f32 / self.num_bars as f32).powf(1.3)
-                * (spectrum.len() - 1) as f32) as usize;
-            let freq_index = freq_index.min(spectrum.len() - 1);
+        let count = self.history.len().max(1) as f32;
+        let mean = self.history.iter().sum::<f32>() / count;
+        let deviation =
+            (self.history.iter().map(|f| (f - mean).powi(2)).sum::<f32>() / count).sqrt();
+        let warmed_up = self.history.len() >= self.history_length / 2;
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back(flux);
 
-            let bass_factor = self.bass_boost * (1.0 - i as f32 / self.num_bars as f32);
-            let amplitude = spectrum[freq_index] * (1.0 + bass_factor);
+        // Also covers a jump back, to another track or an earlier point.
+        if self
+            .last_beat
+            .is_some_and(|at| now < at || now - at > BEAT_TIMEOUT)
+        {
+            self.last_beat = None;
+            self.intervals.clear();
+            self.tempo = None;
+        }
+        let is_beat = warmed_up
+            && flux > MIN_BEAT_FLUX
+            && flux > mean + BEAT_SENSITIVITY * deviation
+            && self
+                .last_beat
+                .is_none_or(|at| now - at >= MIN_BEAT_INTERVAL);
+        if !is_beat {
+            return false;
+        }
 
-            *bar = *bar * self.smoothing + amplitude * (1.0 - self.smoothing);
+        if let Some(last) = self.last_beat {
+            if self.intervals.len() == TEMPO_INTERVALS {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back((now - last).as_secs_f32());
+            self.update_tempo();
         }
+        self.last_beat = Some(now);
+        true
     }
 
-    pub fn bars(&self) -> &[f32] {
-        &self.bars
-    }
+    fn update_tempo(&mut self) {
+        if self.intervals.len() < TEMPO_INTERVALS / 2 {
+            return;
+        }
+        let mut intervals: Vec<f32> = self.intervals.iter().copied().collect();
+        intervals.sort_by(f32::total_cmp);
+        let mut estimate = 60.0 / intervals[intervals.len() / 2];
+        while estimate < TEMPO_RANGE.start {
+            estimate *= 2.0;
+        }
+        while estimate >= TEMPO_RANGE.end {
+            estimate /= 2.0;
+        }
 
-    pub fn num_bars(&self) -> usize {
-        self.num_bars
+        self.tempo = match self.tempo {
+            Some(tempo) if (estimate - tempo).abs() / tempo <= TEMPO_CHANGE => {
+                self.disagreements = 0;
+                Some(tempo + (estimate - tempo) * TEMPO_SMOOTHING)
+            }
+            Some(tempo) if self.disagreements + 1 < TEMPO_CHANGE_COUNT => {
+                self.disagreements += 1;
+                Some(tempo)
+            }
+            _ => {
+                self.disagreements = 0;
+                Some(estimate)
+            }
+        };
     }
 }
@@ -0,0 +1,192 @@
+//! Stereo width, adjusted by scaling the difference between the channels
+//! (the side signal) against what they have in common (the mid), and
+//! soloing one channel. Sources with other than two channels pass through
+//! untouched.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Widest the stereo image can be made, as a multiple of the original.
+pub const MAX_WIDTH: f32 = 2.0;
+
+pub const CHANNELS: [&str; 2] = ["left", "right"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    Left,
+    Right,
+}
+
+impl Channel {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Self::Left),
+            "right" => Some(Self::Right),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Right => "right",
+        }
+    }
+}
+
+/// Stereo settings shared between the UI and the audio thread.
+pub struct Stereo {
+    /// Side level as a multiple of the original, as bits.
+    width: AtomicU32,
+    /// Play both channels' mix on both, without changing the width.
+    mono: AtomicBool,
+    /// Channel played on both sides in place of the mix: 0 for neither, 1
+    /// for left, 2 for right.
+    solo: AtomicU8,
+}
+
+impl Stereo {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            width: AtomicU32::new(1f32.to_bits()),
+            mono: AtomicBool::new(false),
+            solo: AtomicU8::new(0),
+        })
+    }
+
+    pub fn width(&self) -> f32 {
+        f32::from_bits(self.width.load(Ordering::Relaxed))
+    }
+
+    /// Sets the width, leaving it as it is if `width` isn't a number.
+    pub fn set_width(&self, width: f32) {
+        if width.is_nan() {
+            return;
+        }
+        let width = width.clamp(0.0, MAX_WIDTH);
+        self.width.store(width.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.mono.load(Ordering::Relaxed)
+    }
+
+    pub fn set_mono(&self, mono: bool) {
+        self.mono.store(mono, Ordering::Relaxed);
+    }
+
+    pub fn solo(&self) -> Option<Channel> {
+        match self.solo.load(Ordering::Relaxed) {
+            1 => Some(Channel::Left),
+            2 => Some(Channel::Right),
+            _ => None,
+        }
+    }
+
+    pub fn set_solo(&self, solo: Option<Channel>) {
+        let value = match solo {
+            None => 0,
+            Some(Channel::Left) => 1,
+            Some(Channel::Right) => 2,
+        };
+        self.solo.store(value, Ordering::Relaxed);
+    }
+
+    /// Whether the audio comes out as it went in.
+    fn is_neutral(&self) -> bool {
+        !self.is_mono() && self.width() == 1.0 && self.solo().is_none()
+    }
+}
+
+/// Applies [`Stereo`] settings to each pair of samples.
+pub struct StereoSource<I> {
+    input: I,
+    stereo: Arc<Stereo>,
+    /// The right sample of the pair being handed out.
+    right: Option<f32>,
+}
+
+impl<I> StereoSource<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(input: I, stereo: Arc<Stereo>) -> Self {
+        Self {
+            input,
+            stereo,
+            right: None,
+        }
+    }
+}
+
+impl<I> Iterator for StereoSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+        // Samples are always taken in pairs, so left and right can't swap
+        // if the settings change part way through one.
+        let left = self.input.next()?;
+        if self.input.channels() != 2 {
+            return Some(left);
+        }
+        let Some(right) = self.input.next() else {
+            return Some(left);
+        };
+        if self.stereo.is_neutral() {
+            self.right = Some(right);
+            return Some(left);
+        }
+        if let Some(solo) = self.stereo.solo() {
+            let sample = if solo == Channel::Left { left } else { right };
+            self.right = Some(sample);
+            return Some(sample);
+        }
+
+        let width = if self.stereo.is_mono() {
+            0.0
+        } else {
+            self.stereo.width()
+        };
+        let mid = (left + right) / 2.0;
+        let side = (left - right) / 2.0 * width;
+        self.right = Some(mid - side);
+        Some(mid + side)
+    }
+}
+
+impl<I> Source for StereoSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        let pending = self.right.is_some() as usize;
+        self.input.current_frame_len().map(|len| len + pending)
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.right = None;
+        Ok(())
+    }
+}
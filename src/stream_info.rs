@@ -0,0 +1,195 @@
+//! Technical details of the track being played: what it was encoded with,
+//! read once when it's loaded, and the channels and sample rate, followed
+//! live since they can change part way through a chained stream.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use symphonia::core::audio::Channels;
+use symphonia::core::codecs::{CODEC_TYPE_OPUS, CodecParameters};
+use symphonia::core::formats::FormatReader;
+use symphonia::core::probe::ProbeResult;
+use symphonia::core::units::TimeBase;
+
+use crate::{hls, midi, raw, tracker};
+
+#[derive(Debug, Clone, Default)]
+pub struct StreamInfo {
+    /// Short codec name, e.g. "FLAC".
+    pub codec: Option<String>,
+    /// Bit depth of lossless and PCM formats.
+    pub bits_per_sample: Option<u32>,
+    /// Average bitrate in kbps, for lossy formats.
+    pub bitrate: Option<u32>,
+    /// The speaker each channel is for, in the order they're decoded. The
+    /// decoder puts them in the order of the channel mask's bits, whatever
+    /// order the format stores them in (Vorbis's differs from WAV's).
+    pub speakers: Option<Vec<Channels>>,
+}
+
+/// Audio read through to work out a lossy format's bitrate: enough to
+/// average out a variable one, without reading a long file to the end.
+const BITRATE_SPAN: f64 = 30.0;
+
+impl StreamInfo {
+    /// Details of `path`, read from the probe of it, unless it's a stream
+    /// or a format symphonia doesn't handle.
+    pub fn read(path: &Path, probed: Option<&mut ProbeResult>) -> Self {
+        if raw::is_stdin(path) {
+            return Self::named("PCM");
+        }
+        if hls::is_playlist(path) {
+            return Self::named("HLS");
+        }
+        if tracker::is_module(path) {
+            return Self::named("module");
+        }
+        if midi::is_midi(path) {
+            return Self::named("MIDI");
+        }
+        probed.and_then(Self::probe).unwrap_or_default()
+    }
+
+    fn named(codec: &str) -> Self {
+        Self {
+            codec: Some(codec.to_string()),
+            ..Self::default()
+        }
+    }
+
+    fn probe(probed: &mut ProbeResult) -> Option<Self> {
+        let track = probed.format.default_track()?;
+        let (id, params) = (track.id, track.codec_params.clone());
+        let codec = match symphonia::default::get_codecs().get_codec(params.codec) {
+            Some(codec) if codec.short_name.starts_with("pcm") => "PCM".to_string(),
+            Some(codec) => codec.short_name.to_uppercase(),
+            // Opus is played by a decoder of its own, not symphonia's.
+            None if params.codec == CODEC_TYPE_OPUS => "OPUS".to_string(),
+            None => return None,
+        };
+
+        let bits_per_sample = params.bits_per_sample;
+        let bitrate = if bits_per_sample.is_none() {
+            bitrate(probed.format.as_mut(), id, &params)
+        } else {
+            None
+        };
+        Some(Self {
+            codec: Some(codec),
+            bits_per_sample,
+            bitrate,
+            speakers: params.channels.map(|mask| mask.iter().collect()),
+        })
+    }
+}
+
+/// Average bitrate in kbps of the packets at the start of track `id`.
+fn bitrate(format: &mut dyn FormatReader, id: u32, params: &CodecParameters) -> Option<u32> {
+    let time_base = params
+        .time_base
+        .or_else(|| params.sample_rate.map(|rate| TimeBase::new(1, rate)))?;
+    let (mut bytes, mut seconds) = (0, 0.0);
+    while seconds < BITRATE_SPAN {
+        let Ok(packet) = format.next_packet() else {
+            break;
+        };
+        if packet.track_id() != id {
+            continue;
+        }
+        bytes += packet.buf().len();
+        let duration = time_base.calc_time(packet.dur);
+        seconds += duration.seconds as f64 + duration.frac;
+    }
+    (seconds > 0.0).then(|| (bytes as f64 * 8.0 / seconds / 1000.0).round() as u32)
+}
+
+/// Channels and sample rate of the audio as decoded, written from the audio
+/// thread.
+pub struct LiveFormat {
+    channels: AtomicU32,
+    sample_rate: AtomicU32,
+}
+
+impl LiveFormat {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            channels: AtomicU32::new(0),
+            sample_rate: AtomicU32::new(0),
+        })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels.load(Ordering::Relaxed) as u16
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+}
+
+/// Passes samples through, keeping a [`LiveFormat`] up to date.
+pub struct FormatSource<I> {
+    input: I,
+    format: Arc<LiveFormat>,
+    /// Channels and sample rate last written to `format`.
+    written: (u16, u32),
+}
+
+impl<I> FormatSource<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(input: I, format: Arc<LiveFormat>) -> Self {
+        Self {
+            input,
+            format,
+            written: (0, 0),
+        }
+    }
+}
+
+impl<I> Iterator for FormatSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let current = (self.input.channels(), self.input.sample_rate());
+        if current != self.written {
+            self.written = current;
+            let format = &self.format;
+            format.channels.store(current.0 as u32, Ordering::Relaxed);
+            format.sample_rate.store(current.1, Ordering::Relaxed);
+        }
+        self.input.next()
+    }
+}
+
+impl<I> Source for FormatSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
@@ -0,0 +1,246 @@
+//! Changes the tempo without changing the pitch, for practising along to a
+//! track slowed down. Overlapping slices of the input are laid end to end
+//! at the new rate, each moved a little to where it lines up best with the
+//! one before (WSOLA), so the waveform carries on without phase jumps.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::resample::Resampler;
+
+/// Length of each slice. Long enough to hold a couple of periods of a low
+/// note, short enough that drums don't smear.
+const SLICE: f64 = 0.04;
+/// How far a slice may be moved either way to line it up.
+const SEARCH: f64 = 0.01;
+/// Only every this many frames are compared when lining slices up, which
+/// is plenty for finding the best fit and a quarter of the work.
+const STRIDE: usize = 4;
+
+/// Plays its input at the [`Resampler`]'s tempo, less the speed the
+/// resampler transposes it by. Passes it straight through when there's
+/// neither.
+pub struct StretchSource<I> {
+    input: I,
+    resampler: Arc<Resampler>,
+    /// Input channels and rate the buffers are for.
+    format: (u16, u32),
+    /// Hann window over one slice, in frames.
+    window: Vec<f32>,
+    search: usize,
+    /// Input frames, interleaved, from the earliest a slice might still
+    /// start at.
+    buffer: VecDeque<f32>,
+    /// Frames of `buffer` from real input rather than padding, once the
+    /// input has ended.
+    remaining: Option<usize>,
+    /// Where the next slice would start without lining up, in frames into
+    /// `buffer`.
+    target: f64,
+    /// Where the last slice started, if there's been one since the reset.
+    previous: Option<usize>,
+    /// Second half of the last slice, still to be added to the next.
+    tail: Vec<f32>,
+    output: Vec<f32>,
+    position: usize,
+}
+
+impl<I> StretchSource<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(input: I, resampler: Arc<Resampler>) -> Self {
+        Self {
+            input,
+            resampler,
+            format: (0, 0),
+            window: Vec::new(),
+            search: 0,
+            buffer: VecDeque::new(),
+            remaining: None,
+            target: 0.0,
+            previous: None,
+            tail: Vec::new(),
+            output: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn is_stretching(&self) -> bool {
+        // Once started, carries on through a return to normal speed rather
+        // than drop what it has buffered.
+        let format = (self.input.channels().max(1), self.input.sample_rate());
+        self.resampler.stretch() != 1.0 || self.format == format
+    }
+
+    fn reset(&mut self) {
+        let channels = self.input.channels().max(1);
+        let sample_rate = self.input.sample_rate().max(1);
+        self.format = (channels, sample_rate);
+        let slice = ((SLICE * sample_rate as f64) as usize / 2).max(1) * 2;
+        self.window = (0..slice)
+            .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f64 / slice as f64).cos()) as f32)
+            .collect();
+        self.search = (SEARCH * sample_rate as f64) as usize;
+        self.buffer.clear();
+        self.remaining = None;
+        self.target = 0.0;
+        self.previous = None;
+        self.tail = vec![0.0; slice / 2 * channels as usize];
+    }
+
+    /// Reads input until `buffer` holds `frames` frames, padding with
+    /// silence past the end.
+    fn fill(&mut self, frames: usize) {
+        let channels = self.format.0 as usize;
+        while self.buffer.len() < frames * channels {
+            if self.remaining.is_none() {
+                let before = self.buffer.len();
+                self.buffer.extend(self.input.by_ref().take(channels));
+                if self.buffer.len() - before == channels {
+                    continue;
+                }
+                self.buffer.truncate(before);
+                self.remaining = Some(before / channels);
+            }
+            self.buffer.extend(std::iter::repeat_n(0.0, channels));
+        }
+    }
+
+    /// Mono mix of the frame at `index` in `buffer`.
+    fn mono(&self, index: usize) -> f32 {
+        let channels = self.format.0 as usize;
+        (0..channels)
+            .map(|channel| self.buffer[index * channels + channel])
+            .sum()
+    }
+
+    /// Where near `target` a slice lines up best with what would have
+    /// followed `previous`.
+    fn best_start(&self, target: usize, previous: usize) -> usize {
+        let half = self.window.len() / 2;
+        let natural: Vec<f32> = (previous + half..previous + self.window.len())
+            .step_by(STRIDE)
+            .map(|index| self.mono(index))
+            .collect();
+        let mut best = (f32::MIN, target);
+        for start in target.saturating_sub(self.search)..=target + self.search {
+            let fit: f32 = natural
+                .iter()
+                .enumerate()
+                .map(|(i, &sample)| self.mono(start + i * STRIDE) * sample)
+                .sum();
+            if fit > best.0 {
+                best = (fit, start);
+            }
+        }
+        best.1
+    }
+
+    /// Overlaps the next slice with the last, for half a slice of output.
+    /// Returns false once the input has run out.
+    fn next_slice(&mut self) -> bool {
+        let channels = self.format.0 as usize;
+        let slice = self.window.len();
+        let half = slice / 2;
+
+        let target = self.target.round() as usize;
+        self.fill(target + self.search + slice);
+        if self.remaining.is_some_and(|remaining| target >= remaining) {
+            return false;
+        }
+        let start = match self.previous {
+            Some(previous) => self.best_start(target, previous),
+            None => target,
+        };
+
+        self.output.clear();
+        for (i, &weight) in self.window.iter().enumerate() {
+            for channel in 0..channels {
+                let sample = self.buffer[(start + i) * channels + channel] * weight;
+                let index = i % half * channels + channel;
+                if i < half {
+                    self.output.push(self.tail[index] + sample);
+                } else {
+                    self.tail[index] = sample;
+                }
+            }
+        }
+        self.position = 0;
+
+        self.previous = Some(start);
+        self.target += half as f64 * self.resampler.stretch() as f64;
+        // Drop frames no slice will reach back to again.
+        let unused = start.min((self.target as usize).saturating_sub(self.search));
+        self.buffer.drain(..unused * channels);
+        self.previous = Some(start - unused);
+        self.target -= unused as f64;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(unused);
+        }
+        true
+    }
+}
+
+impl<I> Iterator for StretchSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position < self.output.len() {
+            self.position += 1;
+            return Some(self.output[self.position - 1]);
+        }
+        if !self.is_stretching() {
+            return self.input.next();
+        }
+        let format = (self.input.channels().max(1), self.input.sample_rate());
+        if format != self.format {
+            self.reset();
+        }
+        if !self.next_slice() {
+            return None;
+        }
+        self.position = 1;
+        Some(self.output[0])
+    }
+}
+
+impl<I> Source for StretchSource<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        if !self.is_stretching() {
+            let pending = self.output.len() - self.position;
+            return self.input.current_frame_len().map(|len| len + pending);
+        }
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.output.clear();
+        self.position = 0;
+        self.format = (0, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,769 @@
+//! Writes title, artist, album and track number back to a file, along with
+//! free-form fields like ReplayGain's: ID3v2 in MP3s, Vorbis comments in FLAC
+//! and Ogg (Vorbis and Opus). Every other tag and all of the audio is copied
+//! as it is, and the file is only replaced once the new one has been written
+//! in full, keeping its permissions and owner, and any link to it.
+
+use ogg::{PacketReader, PacketWriteEndInfo, PacketWriter};
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+use crate::metadata::TrackInfo;
+
+/// ID3v2 frames and Vorbis comment fields for the tags that can be edited.
+const FIELDS: [(&[u8; 4], &str); 4] = [
+    (b"TIT2", "TITLE"),
+    (b"TPE1", "ARTIST"),
+    (b"TALB", "ALBUM"),
+    (b"TRCK", "TRACKNUMBER"),
+];
+
+/// A tag to replace, and its new value, or `None` to remove it.
+struct Edit {
+    key: Key,
+    value: Option<String>,
+}
+
+enum Key {
+    /// A standard ID3v2 text frame, and the Vorbis comment field for the
+    /// same thing.
+    Text(&'static [u8; 4], &'static str),
+    /// A field with no frame of its own, which goes in a `TXXX` frame with
+    /// this description in ID3v2.
+    Custom(String),
+}
+
+impl Key {
+    fn vorbis_name(&self) -> &str {
+        match self {
+            Self::Text(_, name) => name,
+            Self::Custom(name) => name,
+        }
+    }
+}
+
+impl Edit {
+    /// The value to write, given the track number the file had. A track
+    /// number keeps the album's track count it was written with, as in
+    /// `3/12`.
+    fn value(&self, old_track: Option<&str>) -> Option<String> {
+        let value = self.value.clone()?;
+        let total = old_track
+            .and_then(|old| old.split_once('/'))
+            .map(|(_, total)| total.trim())
+            .filter(|total| !total.is_empty());
+        match (&self.key, total) {
+            (Key::Text(b"TRCK", _), Some(total)) if !value.contains('/') => {
+                Some(format!("{}/{}", value, total))
+            }
+            _ => Some(value),
+        }
+    }
+}
+
+pub fn write(path: &Path, info: &TrackInfo) -> io::Result<()> {
+    let edits = FIELDS
+        .iter()
+        .zip(values(info))
+        .map(|(&(id, name), value)| Edit {
+            key: Key::Text(id, name),
+            value,
+        })
+        .collect::<Vec<_>>();
+    apply(path, &edits)
+}
+
+/// Sets free-form fields, like `REPLAYGAIN_TRACK_GAIN`, replacing any that
+/// are already there.
+pub fn write_custom(path: &Path, fields: &[(&str, String)]) -> io::Result<()> {
+    let edits = fields
+        .iter()
+        .map(|(name, value)| Edit {
+            key: Key::Custom(name.to_string()),
+            value: Some(value.clone()),
+        })
+        .collect::<Vec<_>>();
+    apply(path, &edits)
+}
+
+fn apply(path: &Path, edits: &[Edit]) -> io::Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    // The file a link points to is the one rewritten, so the link stays.
+    let path = &fs::canonicalize(path)?;
+    let data = fs::read(path)?;
+    let updated = match extension.as_str() {
+        "mp3" => update_id3(&data, edits)?,
+        "flac" => update_flac(&data, edits)?,
+        "ogg" | "oga" | "opus" => update_ogg(&data, edits)?,
+        _ => {
+            return Err(io::Error::other(format!(
+                "can't write tags to .{} files",
+                extension
+            )));
+        }
+    };
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".apz-tmp");
+    fs::write(&temporary, &updated)?;
+    if let Err(e) = copy_metadata(path, temporary.as_ref()) {
+        fs::remove_file(&temporary).ok();
+        // A file that isn't ours can't be given the same owner; writing
+        // over it keeps the owner, just not safely.
+        return match e.kind() {
+            io::ErrorKind::PermissionDenied => fs::write(path, updated),
+            _ => Err(e),
+        };
+    }
+    fs::rename(&temporary, path)
+}
+
+/// Gives `to` the permissions, and owner where there is one, of `from`.
+fn copy_metadata(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(from)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        std::os::unix::fs::chown(to, Some(metadata.uid()), Some(metadata.gid()))?;
+    }
+    fs::set_permissions(to, metadata.permissions())
+}
+
+/// The tag values in [`FIELDS`] order, leaving out empty ones.
+fn values(info: &TrackInfo) -> [Option<String>; 4] {
+    let text = |value: &Option<String>| value.clone().filter(|v| !v.trim().is_empty());
+    [
+        text(&info.title),
+        text(&info.artist),
+        text(&info.album),
+        info.track_number.map(|number| number.to_string()),
+    ]
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn update_id3(data: &[u8], edits: &[Edit]) -> io::Result<Vec<u8>> {
+    // Files without a tag get a v2.4 one.
+    let (major, frames, audio) = if data.starts_with(b"ID3") && data.len() >= 10 {
+        let major = data[3];
+        let flags = data[5];
+        if !(3..=4).contains(&major) {
+            return Err(invalid("only ID3v2.3 and v2.4 tags can be edited"));
+        }
+        if flags & 0xc0 != 0 {
+            return Err(invalid(
+                "ID3 tags with unsynchronisation or extended headers can't be edited",
+            ));
+        }
+        let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+        let end = 10 + syncsafe(&data[6..10]) as usize;
+        let frames = data
+            .get(10..end)
+            .ok_or_else(|| invalid("truncated ID3 tag"))?;
+        (major, frames, data.get(end + footer..).unwrap_or_default())
+    } else {
+        (4, &[][..], data)
+    };
+
+    // Frames that aren't being edited are kept, byte for byte.
+    let mut tag = Vec::new();
+    let mut old_track = None;
+    let mut position = 0;
+    while position + 10 <= frames.len() && frames[position] != 0 {
+        let header = &frames[position..position + 10];
+        let size = if major == 4 {
+            syncsafe(&header[4..8])
+        } else {
+            u32::from_be_bytes([header[4], header[5], header[6], header[7]])
+        } as usize;
+        let end = position + 10 + size;
+        if end > frames.len() {
+            return Err(invalid("truncated ID3 frame"));
+        }
+        if &header[..4] == b"TRCK" {
+            old_track = first_text(&frames[position + 10..end]);
+        }
+        let replaced = edits.iter().any(|edit| match &edit.key {
+            Key::Text(id, _) => header[..4] == id[..],
+            Key::Custom(name) => {
+                &header[..4] == b"TXXX"
+                    && first_text(&frames[position + 10..end])
+                        .is_some_and(|description| description.eq_ignore_ascii_case(name))
+            }
+        });
+        if !replaced {
+            tag.extend_from_slice(&frames[position..end]);
+        }
+        position = end;
+    }
+
+    for edit in edits {
+        let Some(value) = edit.value(old_track.as_deref()) else {
+            continue;
+        };
+        // v2.3 has no UTF-8, so it gets UTF-16 with a byte order mark.
+        let encode = |text: &str| -> Vec<u8> {
+            if major == 4 {
+                text.bytes().collect()
+            } else {
+                [0xff, 0xfe]
+                    .into_iter()
+                    .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                    .collect()
+            }
+        };
+        let (id, mut text) = match &edit.key {
+            Key::Text(id, _) => (*id, Vec::new()),
+            Key::Custom(name) => {
+                let mut text = encode(name);
+                text.extend_from_slice(if major == 4 { &[0] } else { &[0, 0] });
+                (b"TXXX", text)
+            }
+        };
+        text.extend(encode(&value));
+        text.insert(0, if major == 4 { 3 } else { 1 });
+
+        tag.extend_from_slice(&id[..]);
+        if major == 4 {
+            tag.extend_from_slice(&to_syncsafe(text.len() as u32));
+        } else {
+            tag.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        }
+        tag.extend_from_slice(&[0, 0]);
+        tag.extend_from_slice(&text);
+    }
+
+    let mut output = b"ID3".to_vec();
+    output.extend_from_slice(&[major, 0, 0]);
+    output.extend_from_slice(&to_syncsafe(tag.len() as u32));
+    output.extend_from_slice(&tag);
+    output.extend_from_slice(audio);
+    Ok(output)
+}
+
+/// The first string in a text frame: the description of a `TXXX` frame,
+/// which comes before its value, or the value of any other.
+fn first_text(body: &[u8]) -> Option<String> {
+    let (&encoding, text) = body.split_first()?;
+    match encoding {
+        0 | 3 => {
+            let end = text
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(text.len());
+            Some(String::from_utf8_lossy(&text[..end]).into_owned())
+        }
+        1 | 2 => {
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| {
+                    if encoding == 2 {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .take_while(|&unit| unit != 0)
+                .collect();
+            // A little-endian byte order mark reads as 0xfeff; a big-endian
+            // one as 0xfffe, which means the rest needs swapping too.
+            let units: Vec<u16> = match units.first() {
+                Some(0xfeff) => units[1..].to_vec(),
+                Some(0xfffe) => units[1..].iter().map(|unit| unit.swap_bytes()).collect(),
+                _ => units,
+            };
+            Some(String::from_utf16_lossy(&units))
+        }
+        _ => None,
+    }
+}
+
+/// ID3 sizes keep the top bit of every byte clear.
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |size, &byte| (size << 7) | (byte & 0x7f) as u32)
+}
+
+fn to_syncsafe(size: u32) -> [u8; 4] {
+    [
+        (size >> 21) as u8 & 0x7f,
+        (size >> 14) as u8 & 0x7f,
+        (size >> 7) as u8 & 0x7f,
+        size as u8 & 0x7f,
+    ]
+}
+
+const FLAC_VORBIS_COMMENT: u8 = 4;
+
+fn update_flac(data: &[u8], edits: &[Edit]) -> io::Result<Vec<u8>> {
+    if !data.starts_with(b"fLaC") {
+        return Err(invalid("not a FLAC file"));
+    }
+
+    let mut blocks = Vec::new();
+    let mut position = 4;
+    loop {
+        let header = data
+            .get(position..position + 4)
+            .ok_or_else(|| invalid("truncated FLAC metadata"))?;
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let body = data
+            .get(position + 4..position + 4 + length)
+            .ok_or_else(|| invalid("truncated FLAC metadata"))?;
+        blocks.push((kind, body.to_vec()));
+        position += 4 + length;
+        if last {
+            break;
+        }
+    }
+
+    match blocks
+        .iter_mut()
+        .find(|(kind, _)| *kind == FLAC_VORBIS_COMMENT)
+    {
+        Some((_, body)) => *body = update_comments(body, edits)?,
+        // Right after the STREAMINFO block, which has to come first.
+        None => blocks.insert(
+            1.min(blocks.len()),
+            (
+                FLAC_VORBIS_COMMENT,
+                update_comments(&empty_comments(), edits)?,
+            ),
+        ),
+    }
+
+    let mut output = b"fLaC".to_vec();
+    let count = blocks.len();
+    for (index, (kind, body)) in blocks.into_iter().enumerate() {
+        if body.len() >= 1 << 24 {
+            return Err(invalid("FLAC metadata block too large"));
+        }
+        let last = if index + 1 == count { 0x80 } else { 0 };
+        output.push(last | kind);
+        output.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        output.extend_from_slice(&body);
+    }
+    output.extend_from_slice(&data[position..]);
+    Ok(output)
+}
+
+fn update_ogg(data: &[u8], edits: &[Edit]) -> io::Result<Vec<u8>> {
+    const VORBIS: &[u8] = b"\x03vorbis";
+    const OPUS: &[u8] = b"OpusTags";
+
+    let mut reader = PacketReader::new(Cursor::new(data));
+    let mut writer = PacketWriter::new(Vec::new());
+    let mut index_in_stream = 0;
+    let mut updated = false;
+
+    while let Some(packet) = reader.read_packet().map_err(io::Error::other)? {
+        if packet.first_in_stream() {
+            index_in_stream = 0;
+        }
+        let end = if packet.last_in_stream() {
+            PacketWriteEndInfo::EndStream
+        } else if packet.last_in_page() {
+            PacketWriteEndInfo::EndPage
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+
+        // Each stream's comments are its second packet.
+        let mut contents = packet.data.clone();
+        if index_in_stream == 1 {
+            for magic in [VORBIS, OPUS] {
+                if let Some(comments) = packet.data.strip_prefix(magic) {
+                    contents = magic.to_vec();
+                    contents.extend(update_comments(comments, edits)?);
+                    updated = true;
+                }
+            }
+        }
+        writer.write_packet(contents, packet.stream_serial(), end, packet.absgp_page())?;
+        index_in_stream += 1;
+    }
+
+    if !updated {
+        return Err(invalid("no Vorbis or Opus comments found"));
+    }
+    Ok(writer.into_inner())
+}
+
+fn empty_comments() -> Vec<u8> {
+    let vendor = b"apz";
+    let mut body = (vendor.len() as u32).to_le_bytes().to_vec();
+    body.extend_from_slice(vendor);
+    body.extend_from_slice(&0u32.to_le_bytes());
+    body
+}
+
+/// Rewrites a Vorbis comment block (vendor string, then `KEY=value`
+/// comments), replacing the edited fields. Anything after the comments,
+/// like Vorbis's framing bit, is kept.
+fn update_comments(body: &[u8], edits: &[Edit]) -> io::Result<Vec<u8>> {
+    let truncated = || invalid("truncated Vorbis comments");
+    let mut position = 0;
+    let read_u32 = |position: &mut usize| -> io::Result<usize> {
+        let bytes = body.get(*position..*position + 4).ok_or_else(truncated)?;
+        *position += 4;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    };
+
+    let vendor_length = read_u32(&mut position)?;
+    let vendor = body
+        .get(position..position + vendor_length)
+        .ok_or_else(truncated)?;
+    position += vendor_length;
+
+    let count = read_u32(&mut position)?;
+    let mut comments = Vec::new();
+    let mut old_track = None;
+    for _ in 0..count {
+        let length = read_u32(&mut position)?;
+        let comment = body
+            .get(position..position + length)
+            .ok_or_else(truncated)?;
+        position += length;
+
+        let (key, value) = comment
+            .iter()
+            .position(|&byte| byte == b'=')
+            .map_or((comment, &[][..]), |equals| {
+                (&comment[..equals], &comment[equals + 1..])
+            });
+        if key.eq_ignore_ascii_case(b"TRACKNUMBER") {
+            old_track = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        if !edits
+            .iter()
+            .any(|edit| key.eq_ignore_ascii_case(edit.key.vorbis_name().as_bytes()))
+        {
+            comments.push(comment.to_vec());
+        }
+    }
+    for edit in edits {
+        if let Some(value) = edit.value(old_track.as_deref()) {
+            comments.push(format!("{}={}", edit.key.vorbis_name(), value).into_bytes());
+        }
+    }
+
+    let mut output = (vendor.len() as u32).to_le_bytes().to_vec();
+    output.extend_from_slice(vendor);
+    output.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        output.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        output.extend_from_slice(&comment);
+    }
+    output.extend_from_slice(&body[position..]);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(id: &'static [u8; 4], name: &'static str, value: &str) -> Edit {
+        Edit {
+            key: Key::Text(id, name),
+            value: Some(value.to_string()),
+        }
+    }
+
+    fn id3_frame(major: u8, id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        if major == 4 {
+            frame.extend_from_slice(&to_syncsafe(body.len() as u32));
+        } else {
+            frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        }
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn id3(major: u8, frames: &[Vec<u8>], audio: &[u8]) -> Vec<u8> {
+        let frames = frames.concat();
+        let mut data = b"ID3".to_vec();
+        data.extend_from_slice(&[major, 0, 0]);
+        data.extend_from_slice(&to_syncsafe(frames.len() as u32));
+        data.extend_from_slice(&frames);
+        data.extend_from_slice(audio);
+        data
+    }
+
+    /// The frames of a tag written by [`update_id3`], and the audio after
+    /// it.
+    fn id3_frames(data: &[u8]) -> (Vec<Vec<u8>>, Vec<u8>) {
+        let major = data[3];
+        let end = 10 + syncsafe(&data[6..10]) as usize;
+        let mut frames = Vec::new();
+        let mut position = 10;
+        while position < end {
+            let header = &data[position..position + 10];
+            let size = if major == 4 {
+                syncsafe(&header[4..8])
+            } else {
+                u32::from_be_bytes([header[4], header[5], header[6], header[7]])
+            } as usize;
+            frames.push(data[position..position + 10 + size].to_vec());
+            position += 10 + size;
+        }
+        (frames, data[end..].to_vec())
+    }
+
+    fn comments(vendor: &str, fields: &[&str], trailer: &[u8]) -> Vec<u8> {
+        let mut body = (vendor.len() as u32).to_le_bytes().to_vec();
+        body.extend_from_slice(vendor.as_bytes());
+        body.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+        for field in fields {
+            body.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            body.extend_from_slice(field.as_bytes());
+        }
+        body.extend_from_slice(trailer);
+        body
+    }
+
+    #[test]
+    fn id3_keeps_frames_it_does_not_edit() {
+        let comment = id3_frame(4, b"COMM", b"\x03eng\0a comment \xff\x00\x01");
+        let picture = id3_frame(4, b"APIC", &[0, 1, 2, 3, 0xff, 0xfe, 0, 7]);
+        let data = id3(
+            4,
+            &[
+                id3_frame(4, b"TIT2", b"\x03Old"),
+                comment.clone(),
+                picture.clone(),
+            ],
+            b"audio frames",
+        );
+
+        let updated = update_id3(&data, &[set(b"TIT2", "TITLE", "New")]).unwrap();
+        let (frames, audio) = id3_frames(&updated);
+        assert_eq!(audio, b"audio frames");
+        assert_eq!(frames[0], comment);
+        assert_eq!(frames[1], picture);
+        assert_eq!(frames[2], id3_frame(4, b"TIT2", b"\x03New"));
+    }
+
+    #[test]
+    fn id3v23_text_is_utf16() {
+        let data = id3(3, &[id3_frame(3, b"TIT2", b"\x00Old")], b"");
+
+        let updated = update_id3(&data, &[set(b"TIT2", "TITLE", "Été")]).unwrap();
+        assert_eq!(updated[3], 3);
+        let (frames, _) = id3_frames(&updated);
+        let mut body = vec![1, 0xff, 0xfe];
+        body.extend("Été".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(frames, [id3_frame(3, b"TIT2", &body)]);
+        assert_eq!(first_text(&body).as_deref(), Some("Été"));
+    }
+
+    #[test]
+    fn id3_track_number_keeps_its_total() {
+        let data = id3(4, &[id3_frame(4, b"TRCK", b"\x033/12")], b"");
+
+        let updated = update_id3(&data, &[set(b"TRCK", "TRACKNUMBER", "5")]).unwrap();
+        let (frames, _) = id3_frames(&updated);
+        assert_eq!(frames, [id3_frame(4, b"TRCK", b"\x035/12")]);
+    }
+
+    #[test]
+    fn id3_rejects_truncated_tags() {
+        let data = id3(4, &[id3_frame(4, b"TIT2", b"\x03Title")], b"");
+        let edits = [set(b"TIT2", "TITLE", "New")];
+        assert!(update_id3(&data[..data.len() - 2], &edits).is_err());
+
+        // A frame running past the end of the tag.
+        let mut data = data;
+        data[10 + 7] += 4;
+        assert!(update_id3(&data, &edits).is_err());
+    }
+
+    #[test]
+    fn truncated_tags_are_not_written() {
+        let path = std::env::temp_dir().join(format!("apz-tags-{}.mp3", std::process::id()));
+        let data = id3(4, &[id3_frame(4, b"TIT2", b"\x03Title")], b"audio");
+        let data = &data[..data.len() - 8];
+        fs::write(&path, data).unwrap();
+
+        let info = TrackInfo {
+            title: Some("New".to_string()),
+            ..Default::default()
+        };
+        assert!(write(&path, &info).is_err());
+        assert_eq!(fs::read(&path).unwrap(), data);
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".apz-tmp");
+        assert!(!Path::new(&temporary).exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn comments_keep_fields_they_do_not_edit() {
+        let body = comments(
+            "encoder 1.0",
+            &["TITLE=Old", "Comment=keep =this", "TRACKNUMBER=3/12", "odd"],
+            &[1],
+        );
+
+        let edits = [
+            set(b"TIT2", "TITLE", "New"),
+            set(b"TRCK", "TRACKNUMBER", "5"),
+        ];
+        let updated = update_comments(&body, &edits).unwrap();
+        assert_eq!(
+            updated,
+            comments(
+                "encoder 1.0",
+                &["Comment=keep =this", "odd", "TITLE=New", "TRACKNUMBER=5/12"],
+                &[1],
+            )
+        );
+    }
+
+    #[test]
+    fn comments_reject_truncation() {
+        let body = comments("apz", &["TITLE=Old"], &[]);
+        let edits = [set(b"TIT2", "TITLE", "New")];
+        for length in [2, 6, 9, body.len() - 1] {
+            assert!(update_comments(&body[..length], &edits).is_err());
+        }
+    }
+
+    fn flac_block(last: bool, kind: u8, body: &[u8]) -> Vec<u8> {
+        let mut block = vec![if last { 0x80 } else { 0 } | kind];
+        block.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        block.extend_from_slice(body);
+        block
+    }
+
+    #[test]
+    fn flac_keeps_blocks_it_does_not_edit() {
+        let stream_info = flac_block(false, 0, &[7; 34]);
+        let picture = flac_block(false, 6, &[0, 1, 2, 0xff]);
+        let padding = [0; 16];
+        let data = [
+            &b"fLaC"[..],
+            &stream_info,
+            &flac_block(
+                false,
+                FLAC_VORBIS_COMMENT,
+                &comments("x", &["TITLE=Old", "GENRE=Jazz"], &[]),
+            ),
+            &picture,
+            &flac_block(true, 1, &padding),
+            b"frames",
+        ]
+        .concat();
+
+        let updated = update_flac(&data, &[set(b"TIT2", "TITLE", "New")]).unwrap();
+        let expected = [
+            &b"fLaC"[..],
+            &stream_info,
+            &flac_block(
+                false,
+                FLAC_VORBIS_COMMENT,
+                &comments("x", &["GENRE=Jazz", "TITLE=New"], &[]),
+            ),
+            &picture,
+            &flac_block(true, 1, &padding),
+            b"frames",
+        ]
+        .concat();
+        assert_eq!(updated, expected);
+    }
+
+    #[test]
+    fn flac_gets_comments_after_stream_info() {
+        let stream_info = flac_block(false, 0, &[7; 34]);
+        let data = [
+            &b"fLaC"[..],
+            &stream_info,
+            &flac_block(true, 1, &[0; 4]),
+            b"frames",
+        ]
+        .concat();
+
+        let updated = update_flac(&data, &[set(b"TIT2", "TITLE", "New")]).unwrap();
+        let expected = [
+            &b"fLaC"[..],
+            &stream_info,
+            &flac_block(
+                false,
+                FLAC_VORBIS_COMMENT,
+                &update_comments(&empty_comments(), &[set(b"TIT2", "TITLE", "New")]).unwrap(),
+            ),
+            &flac_block(true, 1, &[0; 4]),
+            b"frames",
+        ]
+        .concat();
+        assert_eq!(updated, expected);
+    }
+
+    #[test]
+    fn flac_rejects_truncated_metadata() {
+        let data = [&b"fLaC"[..], &flac_block(true, 0, &[7; 34])].concat();
+        let edits = [set(b"TIT2", "TITLE", "New")];
+        assert!(update_flac(&data[..data.len() - 1], &edits).is_err());
+        assert!(update_flac(&data[..6], &edits).is_err());
+    }
+
+    fn ogg(packets: &[(&[u8], PacketWriteEndInfo)]) -> Vec<u8> {
+        let mut writer = PacketWriter::new(Vec::new());
+        for (granule, (packet, end)) in packets.iter().enumerate() {
+            writer
+                .write_packet(packet.to_vec(), 1234, *end, granule as u64)
+                .unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn ogg_packets(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut reader = PacketReader::new(Cursor::new(data));
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.read_packet().unwrap() {
+            packets.push(packet.data);
+        }
+        packets
+    }
+
+    #[test]
+    fn ogg_rewrites_only_the_comment_packet() {
+        let comment = [
+            &b"OpusTags"[..],
+            &comments("libopus", &["TITLE=Old", "ARTIST=A"], &[]),
+        ]
+        .concat();
+        let packets: [&[u8]; 4] = [b"OpusHead\x01\x02", &comment, b"audio 1", b"audio 2"];
+        let data = ogg(&[
+            (packets[0], PacketWriteEndInfo::EndPage),
+            (packets[1], PacketWriteEndInfo::EndPage),
+            (packets[2], PacketWriteEndInfo::NormalPacket),
+            (packets[3], PacketWriteEndInfo::EndStream),
+        ]);
+
+        let updated = update_ogg(&data, &[set(b"TIT2", "TITLE", "New")]).unwrap();
+        let updated = ogg_packets(&updated);
+        assert_eq!(updated.len(), 4);
+        assert_eq!(updated[0], packets[0]);
+        assert_eq!(
+            updated[1],
+            [
+                &b"OpusTags"[..],
+                &comments("libopus", &["ARTIST=A", "TITLE=New"], &[])
+            ]
+            .concat()
+        );
+        assert_eq!(updated[2..], [packets[2].to_vec(), packets[3].to_vec()]);
+    }
+}
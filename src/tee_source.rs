@@ -1,19 +1,107 @@
 use rodio::Source;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 use std::time::Duration;
 
+/// Channels kept apart in a [`SampleRing`]; any beyond these are dropped.
+pub const MAX_CHANNELS: usize = 8;
+/// Frames each channel of a [`SampleRing`] holds, twice the longest window
+/// anything reads so the writer can't lap a reader part way through a copy.
+const RING_FRAMES: usize = 4096;
+
+/// The most recent frames of audio, one ring per channel, shared between
+/// the audio thread writing them and whatever analyzes them. Neither side
+/// ever waits for the other: samples are stored as atomic bit patterns and
+/// only the count of frames written is synchronized.
+pub struct SampleRing {
+    channels: [Box<[AtomicU32]>; MAX_CHANNELS],
+    /// Frames written so far. A frame is complete once this counts it.
+    written: AtomicUsize,
+    channel_count: AtomicU32,
+    sample_rate: AtomicU32,
+}
+
+impl SampleRing {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            channels: std::array::from_fn(|_| {
+                (0..RING_FRAMES).map(|_| AtomicU32::new(0)).collect()
+            }),
+            written: AtomicUsize::new(0),
+            channel_count: AtomicU32::new(1),
+            sample_rate: AtomicU32::new(44100),
+        })
+    }
+
+    /// Records the layout of the frames that follow.
+    pub fn set_format(&self, channels: u16, sample_rate: u32) {
+        let channels = (channels as u32).clamp(1, MAX_CHANNELS as u32);
+        self.channel_count.store(channels, Ordering::Relaxed);
+        self.sample_rate
+            .store(sample_rate.max(1), Ordering::Relaxed);
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channel_count.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// Frames written so far, which changes whenever there's new audio.
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Acquire)
+    }
+
+    /// Adds one sample per channel. Only one thread may write at a time.
+    pub fn push(&self, frame: &[f32]) {
+        let index = self.written.load(Ordering::Relaxed);
+        for (ring, &sample) in self.channels.iter().zip(frame) {
+            ring[index % RING_FRAMES].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.written.store(index + 1, Ordering::Release);
+    }
+
+    /// Copies the latest `out.len()` samples of `channel` into `out`, oldest
+    /// first. Returns false, leaving `out` alone, until that many have been
+    /// written.
+    pub fn latest(&self, channel: usize, out: &mut [f32]) -> bool {
+        let written = self.written.load(Ordering::Acquire);
+        let Some(ring) = self.channels.get(channel) else {
+            return false;
+        };
+        if out.len() > written || out.len() > RING_FRAMES / 2 {
+            return false;
+        }
+        let start = written - out.len();
+        for (offset, sample) in out.iter_mut().enumerate() {
+            let bits = ring[(start + offset) % RING_FRAMES].load(Ordering::Relaxed);
+            *sample = f32::from_bits(bits);
+        }
+        true
+    }
+}
+
+/// Passes samples through unchanged while copying each frame, channels kept
+/// apart, into a [`SampleRing`] for analysis.
 pub struct TeeSource<I> {
     input: I,
-    sample_buffer: Arc<Mutex<Vec<f32>>>,
-    buffer_size: usize,
+    ring: Arc<SampleRing>,
+    frame: [f32; MAX_CHANNELS],
+    frame_position: usize,
+    /// Layout last given to the ring, so it's only updated on a change.
+    format: (u16, u32),
 }
 
 impl<I> TeeSource<I> {
-    pub fn new(input: I, sample_buffer: Arc<Mutex<Vec<f32>>>) -> Self {
+    pub fn new(input: I, ring: Arc<SampleRing>) -> Self {
         Self {
             input,
-            sample_buffer,
-            buffer_size: 2048,
+            ring,
+            frame: [0.0; MAX_CHANNELS],
+            frame_position: 0,
+            format: (0, 0),
         }
     }
 }
@@ -25,17 +113,26 @@ where
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(sample) = self.input.next() {
-            let mut buffer = self.sample_buffer.lock().unwrap();
-            buffer.push(sample);
-            let len = buffer.len();
-            if len > self.buffer_size {
-                buffer.drain(0..len - self.buffer_size);
+        let channels = self.input.channels().max(1);
+        if self.frame_position == 0 {
+            let format = (channels, self.input.sample_rate());
+            if format != self.format {
+                self.format = format;
+                self.ring.set_format(format.0, format.1);
             }
-            Some(sample)
-        } else {
-            None
         }
+
+        let sample = self.input.next()?;
+        if let Some(slot) = self.frame.get_mut(self.frame_position) {
+            *slot = sample;
+        }
+        self.frame_position += 1;
+        if self.frame_position >= channels as usize {
+            let kept = self.frame_position.min(MAX_CHANNELS);
+            self.ring.push(&self.frame[..kept]);
+            self.frame_position = 0;
+        }
+        Some(sample)
     }
 }
 
@@ -0,0 +1,65 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::player::PlaybackState;
+
+/// Keeps the terminal window title in sync with what's playing. The title is
+/// set with OSC 0 (window title and icon name), which tmux and most status
+/// bars pick up as the pane/window title, and the user's previous title is
+/// saved on the xterm title stack and restored on drop.
+pub struct TerminalTitle {
+    last: String,
+}
+
+impl TerminalTitle {
+    pub fn new() -> Self {
+        // XTWINOPS: push the current window and icon titles.
+        print!("\x1b[22;0t");
+        io::stdout().flush().ok();
+        Self {
+            last: String::new(),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        title: &str,
+        state: PlaybackState,
+        position: Duration,
+        duration: Duration,
+    ) {
+        let symbol = match state {
+            PlaybackState::Playing => "▶",
+            PlaybackState::Paused => "⏸",
+            PlaybackState::Stopped => "■",
+        };
+        let text = format!(
+            "{} {} ({}/{})",
+            symbol,
+            title,
+            format_time(position),
+            format_time(duration)
+        );
+
+        if text != self.last {
+            // Strip control characters so tags can't terminate the sequence early.
+            let sanitized: String = text.chars().filter(|c| !c.is_control()).collect();
+            print!("\x1b]0;{}\x07", sanitized);
+            io::stdout().flush().ok();
+            self.last = text;
+        }
+    }
+}
+
+impl Drop for TerminalTitle {
+    fn drop(&mut self) {
+        // XTWINOPS: pop the titles saved in `new`.
+        print!("\x1b[23;0t");
+        io::stdout().flush().ok();
+    }
+}
+
+fn format_time(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
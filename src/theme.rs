@@ -0,0 +1,290 @@
+use ratatui::style::Color;
+
+pub const PRESETS: [&str; 5] = ["default", "nord", "gruvbox", "dracula", "monochrome"];
+
+/// Every color the UI draws with, by role.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// Block title of the main window.
+    pub title: Color,
+    /// Track name, progress bar.
+    pub track: Color,
+    /// Chapter names and other secondary text.
+    pub secondary: Color,
+    /// Gauge backgrounds, unplayed waveform, separators.
+    pub muted: Color,
+    /// Visualizer while playing.
+    pub playing: Color,
+    /// Visualizer and status symbol while paused.
+    pub paused: Color,
+    /// Key hints, bookmark labels, the prompt cursor.
+    pub highlight: Color,
+    /// Chapter marks on the waveform.
+    pub marker: Color,
+    /// Spectrum tint for the low and high ends of the band.
+    pub bass: Color,
+    pub treble: Color,
+    /// Status symbol while playing, high volume.
+    pub good: Color,
+    /// Medium volume.
+    pub warning: Color,
+    /// Spectrum peaks, low volume.
+    pub danger: Color,
+    /// Bottom and top of the spectrum bars on truecolor terminals.
+    pub gradient_start: Color,
+    pub gradient_end: Color,
+    /// Whether the terminal renders 24-bit color, set by [`Theme::for_terminal`].
+    pub truecolor: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            title: Color::Magenta,
+            track: Color::Cyan,
+            secondary: Color::Gray,
+            muted: Color::DarkGray,
+            playing: Color::Cyan,
+            paused: Color::Yellow,
+            highlight: Color::Yellow,
+            marker: Color::Magenta,
+            bass: Color::Magenta,
+            treble: Color::Green,
+            good: Color::Green,
+            warning: Color::Yellow,
+            danger: Color::Red,
+            gradient_start: rgb(0x00afd7),
+            gradient_end: rgb(0xff5f87),
+            truecolor: false,
+        }
+    }
+}
+
+impl Theme {
+    pub fn named(name: &str) -> Option<Self> {
+        let theme = match name {
+            "default" => Self::default(),
+            "nord" => Self {
+                title: rgb(0xb48ead),
+                track: rgb(0x88c0d0),
+                secondary: rgb(0xd8dee9),
+                muted: rgb(0x4c566a),
+                playing: rgb(0x88c0d0),
+                paused: rgb(0xebcb8b),
+                highlight: rgb(0xebcb8b),
+                marker: rgb(0xb48ead),
+                bass: rgb(0x5e81ac),
+                treble: rgb(0xa3be8c),
+                good: rgb(0xa3be8c),
+                warning: rgb(0xebcb8b),
+                danger: rgb(0xbf616a),
+                gradient_start: rgb(0x5e81ac),
+                gradient_end: rgb(0x88c0d0),
+                truecolor: false,
+            },
+            "gruvbox" => Self {
+                title: rgb(0xd3869b),
+                track: rgb(0x83a598),
+                secondary: rgb(0xa89984),
+                muted: rgb(0x504945),
+                playing: rgb(0x8ec07c),
+                paused: rgb(0xfabd2f),
+                highlight: rgb(0xfabd2f),
+                marker: rgb(0xd3869b),
+                bass: rgb(0xfe8019),
+                treble: rgb(0xb8bb26),
+                good: rgb(0xb8bb26),
+                warning: rgb(0xfabd2f),
+                danger: rgb(0xfb4934),
+                gradient_start: rgb(0x8ec07c),
+                gradient_end: rgb(0xfe8019),
+                truecolor: false,
+            },
+            "dracula" => Self {
+                title: rgb(0xff79c6),
+                track: rgb(0x8be9fd),
+                secondary: rgb(0xf8f8f2),
+                muted: rgb(0x44475a),
+                playing: rgb(0xbd93f9),
+                paused: rgb(0xf1fa8c),
+                highlight: rgb(0xf1fa8c),
+                marker: rgb(0xff79c6),
+                bass: rgb(0xff79c6),
+                treble: rgb(0x50fa7b),
+                good: rgb(0x50fa7b),
+                warning: rgb(0xffb86c),
+                danger: rgb(0xff5555),
+                gradient_start: rgb(0xbd93f9),
+                gradient_end: rgb(0xff79c6),
+                truecolor: false,
+            },
+            "monochrome" => Self {
+                title: Color::White,
+                track: Color::White,
+                secondary: Color::Gray,
+                muted: Color::DarkGray,
+                playing: Color::White,
+                paused: Color::Gray,
+                highlight: Color::White,
+                marker: Color::Gray,
+                bass: Color::White,
+                treble: Color::White,
+                good: Color::White,
+                warning: Color::Gray,
+                danger: Color::White,
+                gradient_start: rgb(0x4e4e4e),
+                gradient_end: rgb(0xffffff),
+                truecolor: false,
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// Overrides a single role, e.g. `set("track", "#88c0d0")`.
+    pub fn set(&mut self, role: &str, color: &str) -> Result<(), String> {
+        let color: Color = color
+            .parse()
+            .map_err(|_| format!("invalid color '{}'", color))?;
+        let slot = match role {
+            "title" => &mut self.title,
+            "track" => &mut self.track,
+            "secondary" => &mut self.secondary,
+            "muted" => &mut self.muted,
+            "playing" => &mut self.playing,
+            "paused" => &mut self.paused,
+            "highlight" => &mut self.highlight,
+            "marker" => &mut self.marker,
+            "bass" => &mut self.bass,
+            "treble" => &mut self.treble,
+            "good" => &mut self.good,
+            "warning" => &mut self.warning,
+            "danger" => &mut self.danger,
+            "gradient-start" => &mut self.gradient_start,
+            "gradient-end" => &mut self.gradient_end,
+            _ => return Err(format!("unknown color role '{}'", role)),
+        };
+        *slot = color;
+        Ok(())
+    }
+
+    /// Replaces 24-bit colors with their closest 256-color equivalent unless
+    /// the terminal advertises truecolor support through `COLORTERM`.
+    pub fn for_terminal(self) -> Self {
+        if truecolor_supported() {
+            return Self {
+                truecolor: true,
+                ..self
+            };
+        }
+
+        Self {
+            title: downsample(self.title),
+            track: downsample(self.track),
+            secondary: downsample(self.secondary),
+            muted: downsample(self.muted),
+            playing: downsample(self.playing),
+            paused: downsample(self.paused),
+            highlight: downsample(self.highlight),
+            marker: downsample(self.marker),
+            bass: downsample(self.bass),
+            treble: downsample(self.treble),
+            good: downsample(self.good),
+            warning: downsample(self.warning),
+            danger: downsample(self.danger),
+            gradient_start: downsample(self.gradient_start),
+            gradient_end: downsample(self.gradient_end),
+            truecolor: false,
+        }
+    }
+}
+
+pub fn truecolor_supported() -> bool {
+    std::env::var("COLORTERM").is_ok_and(|value| value == "truecolor" || value == "24bit")
+}
+
+fn rgb(hex: u32) -> Color {
+    Color::Rgb((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+}
+
+/// Linear interpolation between two colors, `t` running from 0.0 (`from`)
+/// to 1.0 (`to`). Named colors are blended using their usual xterm values.
+pub fn blend(from: Color, to: Color, t: f32) -> Color {
+    let (Some((r1, g1, b1)), Some((r2, g2, b2))) = (to_rgb(from), to_rgb(to)) else {
+        return if t < 0.5 { from } else { to };
+    };
+
+    let t = t.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color::Rgb(mix(r1, r2), mix(g1, g2), mix(b1, b2))
+}
+
+fn to_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    let rgb = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Black => (0, 0, 0),
+        Color::Red => (205, 0, 0),
+        Color::Green => (0, 205, 0),
+        Color::Yellow => (205, 205, 0),
+        Color::Blue => (0, 0, 238),
+        Color::Magenta => (205, 0, 205),
+        Color::Cyan => (0, 205, 205),
+        Color::Gray => (229, 229, 229),
+        Color::DarkGray => (127, 127, 127),
+        Color::LightRed => (255, 0, 0),
+        Color::LightGreen => (0, 255, 0),
+        Color::LightYellow => (255, 255, 0),
+        Color::LightBlue => (92, 92, 255),
+        Color::LightMagenta => (255, 0, 255),
+        Color::LightCyan => (0, 255, 255),
+        Color::White => (255, 255, 255),
+        Color::Indexed(index @ 16..=231) => {
+            let index = (index - 16) as usize;
+            (
+                CUBE_LEVELS[index / 36],
+                CUBE_LEVELS[index / 6 % 6],
+                CUBE_LEVELS[index % 6],
+            )
+        }
+        Color::Indexed(index @ 232..) => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+        _ => return None,
+    };
+    Some(rgb)
+}
+
+/// Channel levels of the xterm 6x6x6 color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Maps a 24-bit color onto the xterm 256-color palette: the 6x6x6 cube or
+/// the grayscale ramp, whichever is closer.
+pub fn downsample(color: Color) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(c))
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    let average = (r as u16 + g as u16 + b as u16) / 3;
+    let gray_index = ((average.saturating_sub(8)) / 10).min(23) as u8;
+    let gray = 8 + gray_index * 10;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(cr, r) + d(cg, g) + d(cb, b)
+    };
+
+    if distance((gray, gray, gray)) < distance(cube) {
+        Color::Indexed(232 + gray_index)
+    } else {
+        Color::Indexed(16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8)
+    }
+}
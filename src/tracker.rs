@@ -0,0 +1,213 @@
+//! Tracker module playback (MOD, XM, S3M, IT) through xmrsplayer.
+
+use rodio::Source;
+use rodio::source::SeekError;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use xmrs::prelude::Module;
+use xmrsplayer::prelude::XmrsPlayer;
+
+pub const EXTENSIONS: [&str; 4] = ["mod", "xm", "s3m", "it"];
+
+const SAMPLE_RATE: u32 = 48_000;
+/// Stereo frames rendered per chunk: about 20 ms.
+const CHUNK_FRAMES: usize = 1024;
+
+/// Where the song is in its pattern sequence, for the title line.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrackerPosition {
+    /// Index into the order list, and its length.
+    pub order: usize,
+    pub orders: usize,
+    pub pattern: usize,
+    pub row: usize,
+}
+
+pub type SharedPosition = Arc<Mutex<TrackerPosition>>;
+
+/// The module parsed last, with the file it came from as it was then. The
+/// player, the title, the waveform and the silence scan all read a track
+/// as it loads, and share the one parse.
+static PARSED: Mutex<Option<(PathBuf, fs::Metadata, Arc<Module>)>> = Mutex::new(None);
+
+pub fn is_module(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Parses the module at `path`, unless it was the last one parsed and
+/// hasn't changed since. `None` if it isn't one xmrs understands.
+pub fn load(path: &Path) -> io::Result<Option<Arc<Module>>> {
+    let metadata = fs::metadata(path)?;
+    // Held while parsing, so a second reader waits for the first's module.
+    let mut parsed = PARSED.lock().unwrap();
+    if let Some((_, _, module)) = parsed.as_ref().filter(|(parsed_path, parsed_metadata, _)| {
+        parsed_path == path
+            && parsed_metadata.len() == metadata.len()
+            && parsed_metadata.modified().ok() == metadata.modified().ok()
+    }) {
+        return Ok(Some(Arc::clone(module)));
+    }
+    let Ok(module) = Module::load(&fs::read(path)?) else {
+        return Ok(None);
+    };
+    let module = Arc::new(module);
+    *parsed = Some((path.to_path_buf(), metadata, Arc::clone(&module)));
+    Ok(Some(module))
+}
+
+/// The song name stored in a module file, if it has one.
+pub fn title(path: &Path) -> Option<String> {
+    if !is_module(path) {
+        return None;
+    }
+    let module = load(path).ok()??;
+    let name = module.name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+struct Chunk {
+    /// Bumped on every seek, so chunks rendered before it can be dropped.
+    generation: u64,
+    position: TrackerPosition,
+    samples: Vec<i16>,
+}
+
+/// Renders a module on its own thread. The player borrows the module it
+/// plays, so both live on that thread and hand finished audio over a
+/// channel; seeks go the other way.
+pub struct TrackerSource {
+    chunks: Receiver<Chunk>,
+    seeks: Sender<Duration>,
+    generation: u64,
+    samples: Vec<i16>,
+    cursor: usize,
+    position: SharedPosition,
+    duration: Duration,
+}
+
+impl TrackerSource {
+    pub fn new(module: Arc<Module>) -> Self {
+        let duration = Duration::from_secs_f64(
+            XmrsPlayer::new(&module, SAMPLE_RATE, 0)
+                .duration_seconds()
+                .max(0.0),
+        );
+        let position = TrackerPosition {
+            orders: module.song_length(0),
+            ..TrackerPosition::default()
+        };
+        let (chunk_sender, chunks) = mpsc::sync_channel(4);
+        let (seeks, seek_receiver) = mpsc::channel();
+        thread::spawn(move || render(module, chunk_sender, seek_receiver));
+
+        Self {
+            chunks,
+            seeks,
+            generation: 0,
+            samples: Vec::new(),
+            cursor: 0,
+            position: Arc::new(Mutex::new(position)),
+            duration,
+        }
+    }
+
+    /// Pattern and row of the audio currently being played.
+    pub fn position(&self) -> SharedPosition {
+        Arc::clone(&self.position)
+    }
+}
+
+fn render(module: Arc<Module>, chunks: SyncSender<Chunk>, seeks: Receiver<Duration>) {
+    let mut player = XmrsPlayer::new(&module, SAMPLE_RATE, 0);
+    player.set_max_loop_count(1);
+    let orders = module.song_length(0);
+    let mut generation = 0;
+
+    loop {
+        while let Ok(position) = seeks.try_recv() {
+            player.seek_seconds(position.as_secs_f64());
+            generation += 1;
+        }
+
+        let position = TrackerPosition {
+            order: player.get_current_table_index(),
+            orders,
+            pattern: player.playing_pattern(),
+            row: player.playing_row(),
+        };
+        let samples: Vec<i16> = player.by_ref().take(CHUNK_FRAMES * 2).collect();
+        if samples.is_empty() {
+            return;
+        }
+
+        let chunk = Chunk {
+            generation,
+            position,
+            samples,
+        };
+        if chunks.send(chunk).is_err() {
+            return;
+        }
+    }
+}
+
+impl Iterator for TrackerSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        while self.cursor >= self.samples.len() {
+            let chunk = self.chunks.recv().ok()?;
+            if chunk.generation != self.generation {
+                continue;
+            }
+            *self.position.lock().unwrap() = chunk.position;
+            self.samples = chunk.samples;
+            self.cursor = 0;
+        }
+        let sample = self.samples[self.cursor];
+        self.cursor += 1;
+        Some(sample)
+    }
+}
+
+impl Source for TrackerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.duration)
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        // The render thread only stops once the song has ended, at which
+        // point there's nothing left to seek in.
+        self.seeks
+            .send(pos)
+            .map_err(|e| SeekError::Other(Box::new(e)))?;
+        self.generation += 1;
+        self.samples.clear();
+        self.cursor = 0;
+        Ok(())
+    }
+}
@@ -1,317 +1,2311 @@
 use ratatui::{
     Frame,
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Layout, Position, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Gauge, Paragraph, Sparkline},
+    widgets::{Block, Borders, Clear, Gauge, List, ListState, Padding, Paragraph, Sparkline},
 };
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::player::PlaybackState;
-use crate::spectrum::SpectrumAnalyzer;
-use crate::waveform::WaveformData;
+use crate::ab::{Comparison, Side};
+use crate::analyze::Beats;
+use crate::bookmarks::Bookmarks;
+use crate::buffering::BufferStatus;
+use crate::chapters::{self, Chapter};
+use crate::compressor::Compressor;
+use crate::config;
+use crate::cover::Cover;
+use crate::cues::HotCues;
+use crate::download;
+use crate::downmix;
+use crate::envelope::Envelope;
+use crate::eq::{self, Equalizer};
+use crate::files::FileAction;
+use crate::history::{self, Play};
+use crate::library::{self, TrackStats};
+use crate::metadata::TrackInfo;
+use crate::metronome::Metronome;
+use crate::player::{PlaybackState, Player, Practice};
+use crate::podcast::{self, Episode, Feed, Progress};
+use crate::raw;
+use crate::resample::{self, Resampler};
+use crate::shuffle::ShuffleMode;
+use crate::spectrum::{FLOOR_DB, Spectrum};
+use crate::stereo::Stereo;
+use crate::stream_info::{LiveFormat, StreamInfo};
+use crate::theme::{self, Theme};
+use crate::tracker::TrackerPosition;
+use crate::visualizer::{self, Scene, Visualizer};
+use crate::waveform::{Reduction, WaveformData};
 
 pub struct UIState {
+    pub path: PathBuf,
     pub filename: String,
+    pub track: TrackInfo,
+    pub stream_info: StreamInfo,
+    pub live_format: Arc<LiveFormat>,
+    /// Set in A/B mode.
+    pub comparison: Option<Comparison>,
+    /// Pattern and row, for tracker modules.
+    pub tracker: Option<TrackerPosition>,
     pub position: Duration,
     pub duration: Duration,
+    /// Set while playing a network stream.
+    pub buffer: Option<BufferStatus>,
     pub volume: f32,
     pub state: PlaybackState,
     pub waveform: WaveformData,
-    pub spectrum: Option<Arc<Mutex<SpectrumAnalyzer>>>,
+    /// Waveform of the file compared against in A/B mode, drawn over the
+    /// track's own.
+    pub compare_waveform: Option<WaveformData>,
+    pub waveform_view: WaveformView,
+    /// Screen cell the mouse pointer is over, while it's on the waveform.
+    pub hover: Option<(u16, u16)>,
+    pub spectrum: Option<Spectrum>,
+    pub equalizer: Arc<Equalizer>,
+    pub compressor: Arc<Compressor>,
+    pub stereo: Arc<Stereo>,
+    pub metronome: Arc<Metronome>,
+    /// Volume keyframes of the current track.
+    pub envelope: Arc<Envelope>,
+    pub resampler: Arc<Resampler>,
+    /// Open while the equalizer's bands are being adjusted.
+    pub eq_editor: Option<EqEditor>,
+    /// Deck mode, with hot cues, pitch and a big countdown, for cueing up
+    /// tracks.
+    pub deck: bool,
+    pub cues: HotCues,
+    pub beats: Beats,
+    /// Length in beats of the loop roll playing.
+    pub roll: Option<u32>,
+    pub practice: Option<Practice>,
+    pub chapters: Vec<Chapter>,
+    pub bookmarks: Bookmarks,
+    /// In and out points of a clip to export, marked with `I` and `O`.
+    pub clip_in: Option<Duration>,
+    pub clip_out: Option<Duration>,
+    pub stats: TrackStats,
+    pub prompt: Option<Prompt>,
+    pub recent: Option<RecentList>,
+    pub podcasts: Option<PodcastList>,
+    /// Feed the podcast picker lists, reopened with `P`.
+    pub podcast_feed: Option<String>,
+    /// A feed being fetched in the background, and its URL, to list once it
+    /// arrives.
+    pub pending_feed: Option<(String, Receiver<Result<Feed, String>>)>,
+    /// Last recoverable error, shown until dismissed with Esc.
+    pub error: Option<String>,
+    /// Brief word of what a key did, or of a problem that was carried on
+    /// past, which goes away by itself.
+    pub toast: Option<Toast>,
+    pub queue_index: usize,
+    pub queue_len: usize,
+    pub queue: Vec<PathBuf>,
+    pub queue_panel: Option<QueuePanel>,
+    pub tag_editor: Option<TagEditor>,
+    /// Where the current track is about to be moved, until confirmed.
+    pub file_action: Option<FileAction>,
+    pub keep_dir: Option<PathBuf>,
+    pub reject_dir: Option<PathBuf>,
+    /// How the queue was shuffled, if it was.
+    pub shuffle: Option<ShuffleMode>,
+    /// Sound device, address or Chromecast being played to.
+    pub output: String,
+    /// Pause when the current track ends instead of starting the next one.
+    pub stop_after_track: bool,
+    /// Start each track when the one before it ends. When off the next
+    /// track is loaded paused, for cueing up tracks by hand.
+    pub auto_advance: bool,
+    /// Count down to the end of the track instead of up from the start.
+    pub show_remaining: bool,
+    /// Show times to the millisecond, for reference while editing audio.
+    pub precise_time: bool,
+    /// When the current track was loaded, for scrolling a title that
+    /// doesn't fit.
+    pub loaded_at: Instant,
+    pub hidden: Vec<Panel>,
+    pub spectrum_layout: SpectrumLayout,
+    /// Cover of the current track, with `--cover`, and the most columns
+    /// it may take.
+    pub cover: Option<Arc<Cover>>,
+    pub cover_width: Option<u16>,
+    /// What the visualizer panel shows, with the spectrum analyzer on.
+    pub visualizer: RefCell<Box<dyn Visualizer>>,
+    pub theme: Theme,
 }
 
-impl UIState {
-    pub fn new<P: AsRef<Path>>(
-        path: P,
-        duration: Duration,
-        waveform: WaveformData,
-        spectrum: Option<Arc<Mutex<SpectrumAnalyzer>>>,
-    ) -> Self {
-        let filename = path
-            .as_ref()
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("Unknown")
-            .to_string();
+pub const PANELS: [&str; 5] = ["title", "visualizer", "volume", "controls", "status"];
+
+/// Sections of the full layout that can be hidden to make room for the
+/// others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Panel {
+    Title,
+    Visualizer,
+    Volume,
+    Controls,
+    /// The line of badges along the bottom.
+    Status,
+}
+
+impl Panel {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "title" => Some(Self::Title),
+            "visualizer" => Some(Self::Visualizer),
+            "volume" => Some(Self::Volume),
+            "controls" => Some(Self::Controls),
+            "status" => Some(Self::Status),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Visualizer => "visualizer",
+            Self::Volume => "volume",
+            Self::Controls => "controls",
+            Self::Status => "status",
+        }
+    }
+}
+
+/// How long a toast is shown.
+const TOAST_DURATION: Duration = Duration::from_secs(2);
+/// Errors stay up longer, to give time to read them.
+const ERROR_TOAST_DURATION: Duration = Duration::from_secs(5);
+
+pub struct Toast {
+    pub text: String,
+    pub error: bool,
+    pub shown_at: Instant,
+}
+
+impl Toast {
+    pub fn is_expired(&self) -> bool {
+        let duration = if self.error {
+            ERROR_TOAST_DURATION
+        } else {
+            TOAST_DURATION
+        };
+        self.shown_at.elapsed() >= duration
+    }
+}
+
+pub const SPECTRUM_LAYOUTS: [&str; 2] = ["bars", "mirror"];
+
+/// How the spectrum bars are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SpectrumLayout {
+    /// Rising from the bottom.
+    #[default]
+    Bars,
+    /// Reaching up and down from a line across the middle.
+    Mirror,
+}
+
+impl SpectrumLayout {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "bars" => Some(Self::Bars),
+            "mirror" => Some(Self::Mirror),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bars => "bars",
+            Self::Mirror => "mirror",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Bars => Self::Mirror,
+            Self::Mirror => Self::Bars,
+        }
+    }
+}
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptKind {
+    BookmarkLabel(Duration),
+    /// A `:` command line.
+    Command,
+    /// A file to play instead of the current track.
+    OpenFile,
+    /// A file to play after the current track.
+    InsertFile,
+    /// Where to save the marked clip.
+    ExportClip,
+    /// Volume of a keyframe at this position.
+    Gain(Duration),
+}
+
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind) -> Self {
+        Self {
+            kind,
+            input: String::new(),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self.kind {
+            PromptKind::BookmarkLabel(position) => {
+                format!("Bookmark at {}", format_duration(position))
+            }
+            PromptKind::Command => "Command".to_string(),
+            PromptKind::OpenFile => "Open file, Tab to complete".to_string(),
+            PromptKind::InsertFile => "Play next, Tab to complete".to_string(),
+            PromptKind::ExportClip => "Save clip as .wav or .flac".to_string(),
+            PromptKind::Gain(position) => {
+                format!(
+                    "Volume at {} in dB, blank to remove",
+                    format_duration(position)
+                )
+            }
+        }
+    }
+}
+
+/// Tracks listed in the Recent panel.
+const RECENT_LIMIT: usize = 20;
+
+/// The Recent panel: the last tracks played, newest first, for playing one
+/// of them again.
+pub struct RecentList {
+    pub plays: Vec<Play>,
+    pub selected: usize,
+}
+
+impl RecentList {
+    pub fn load() -> Self {
         Self {
-            filename,
+            plays: history::recent(RECENT_LIMIT),
+            selected: 0,
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.plays.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn selected(&self) -> Option<&Play> {
+        self.plays.get(self.selected)
+    }
+}
+
+/// Episodes of a podcast feed, with how far into each you got, for picking
+/// one to play.
+pub struct PodcastList {
+    pub title: String,
+    pub episodes: Vec<(Episode, Progress)>,
+    pub selected: usize,
+}
+
+impl PodcastList {
+    pub fn new(feed: Feed) -> Self {
+        let episodes = feed
+            .episodes
+            .into_iter()
+            .map(|episode| {
+                let progress = episode
+                    .path()
+                    .map(|path| podcast::progress(&path))
+                    .unwrap_or_default();
+                (episode, progress)
+            })
+            .collect();
+        Self {
+            title: feed.title,
+            episodes,
+            selected: 0,
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.episodes.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn selected(&self) -> Option<&Episode> {
+        self.episodes.get(self.selected).map(|(episode, _)| episode)
+    }
+}
+
+/// The queue panel, for reordering and trimming what's coming up.
+#[derive(Debug, Default)]
+pub struct QueuePanel {
+    pub selected: usize,
+    /// Entries picked out to act on together, by index.
+    pub marked: BTreeSet<usize>,
+    /// Whether keys go to the panel. Tab hands them back to the player,
+    /// leaving the queue showing.
+    pub focused: bool,
+    /// Version of the queue the marks were made in.
+    pub version: Option<u64>,
+}
+
+impl QueuePanel {
+    pub fn new(selected: usize) -> Self {
+        Self {
+            selected,
+            marked: BTreeSet::new(),
+            focused: true,
+            version: None,
+        }
+    }
+
+    /// Marks are by index, so they're dropped once the queue changes
+    /// under them.
+    pub fn follow_queue(&mut self, version: u64) {
+        if self.version.is_some_and(|marked_in| marked_in != version) {
+            self.marked.clear();
+        }
+        self.version = Some(version);
+    }
+
+    /// What the panel's actions apply to: the marked entries, or the
+    /// selected one if none are.
+    pub fn targets(&self, len: usize) -> Vec<usize> {
+        let marked: Vec<usize> = self.marked.iter().copied().filter(|&i| i < len).collect();
+        if !marked.is_empty() || len == 0 {
+            return marked;
+        }
+        vec![self.selected(len)]
+    }
+
+    /// The selected entry, kept within a queue of `len` tracks.
+    pub fn selected(&self, len: usize) -> usize {
+        self.selected.min(len.saturating_sub(1))
+    }
+}
+
+pub struct EqEditor {
+    /// Index of the band the keys adjust.
+    pub selected: usize,
+}
+
+pub const TAG_FIELDS: [&str; 4] = ["Title", "Artist", "Album", "Track"];
+
+/// The tag editor: the current track's tags as text, one field being edited
+/// at a time, and whether the changes are waiting to be confirmed.
+pub struct TagEditor {
+    pub values: [String; 4],
+    pub focused: usize,
+    pub confirming: bool,
+}
+
+impl TagEditor {
+    pub fn new(track: &TrackInfo) -> Self {
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        Self {
+            values: [
+                text(&track.title),
+                text(&track.artist),
+                text(&track.album),
+                track
+                    .track_number
+                    .map(|number| number.to_string())
+                    .unwrap_or_default(),
+            ],
+            focused: 0,
+            confirming: false,
+        }
+    }
+
+    /// The edited tags, or an error if the track number isn't a number.
+    pub fn track_info(&self) -> Result<TrackInfo, String> {
+        let text = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        let number = self.values[3].trim();
+        let track_number = if number.is_empty() {
+            None
+        } else {
+            Some(
+                number
+                    .parse()
+                    .map_err(|_| format!("track number must be a number, got '{}'", number))?,
+            )
+        };
+        Ok(TrackInfo {
+            title: text(&self.values[0]),
+            artist: text(&self.values[1]),
+            album: text(&self.values[2]),
+            track_number,
+        })
+    }
+}
+
+/// How finely the clock in the progress bar is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    Seconds,
+    Tenths,
+    Millis,
+}
+
+impl Precision {
+    /// How often a clock at this precision changes.
+    pub fn tick(self) -> Duration {
+        match self {
+            Precision::Seconds => Duration::from_secs(1),
+            Precision::Tenths => Duration::from_millis(100),
+            Precision::Millis => Duration::from_millis(1),
+        }
+    }
+}
+
+/// Seconds shown either side of the center at each zoom level of the
+/// enhanced waveform; the first level shows the whole track.
+const ZOOM_LEVELS: [Option<f64>; 3] = [None, Some(30.0), Some(10.0)];
+
+/// Which part of the track the enhanced waveform shows. A zoomed view
+/// follows the playhead, shifted by however far it has been scrolled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WaveformView {
+    zoom: usize,
+    /// Seconds between the playhead and the center of the view.
+    offset: f64,
+}
+
+impl WaveformView {
+    pub fn zoom_in(&mut self) {
+        self.zoom = (self.zoom + 1).min(ZOOM_LEVELS.len() - 1);
+        self.offset = 0.0;
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = self.zoom.saturating_sub(1);
+        self.offset = 0.0;
+    }
+
+    /// Moves a zoomed view by half its width, towards the end of the track
+    /// if `forward`.
+    pub fn scroll(&mut self, forward: bool, position: Duration, duration: Duration) {
+        let Some(half) = ZOOM_LEVELS[self.zoom] else {
+            return;
+        };
+        let step = if forward { half } else { -half };
+        let (start, end) = self.window(position, duration);
+        let center = (start + end) / 2.0 + step;
+        self.offset = center - position.as_secs_f64();
+        // Stop at either end instead of building up an offset that would
+        // take as many presses to undo.
+        let (start, end) = self.window(position, duration);
+        self.offset = (start + end) / 2.0 - position.as_secs_f64();
+    }
+
+    pub fn is_zoomed(&self) -> bool {
+        ZOOM_LEVELS[self.zoom].is_some()
+    }
+
+    /// Start and end of the visible part of the track, in seconds.
+    pub fn window(&self, position: Duration, duration: Duration) -> (f64, f64) {
+        let duration = duration.as_secs_f64();
+        match ZOOM_LEVELS[self.zoom] {
+            Some(half) if duration > half * 2.0 => {
+                let center = (position.as_secs_f64() + self.offset).clamp(half, duration - half);
+                (center - half, center + half)
+            }
+            _ => (0.0, duration),
+        }
+    }
+}
+
+impl UIState {
+    pub fn new(player: &Player, theme: Theme) -> Self {
+        Self {
+            path: player.path().to_path_buf(),
+            filename: file_name(player.path()),
+            track: player.track_info().clone(),
+            stream_info: player.stream_info().clone(),
+            live_format: player.live_format(),
+            comparison: player.comparison().cloned(),
+            tracker: player.tracker_position(),
             position: Duration::from_secs(0),
-            duration,
+            duration: player.duration(),
+            buffer: player.buffer_status(),
             volume: 1.0,
             state: PlaybackState::Paused,
-            waveform,
-            spectrum,
+            waveform: player.waveform().clone(),
+            compare_waveform: player.compare_waveform().cloned(),
+            waveform_view: WaveformView::default(),
+            hover: None,
+            spectrum: player.spectrum(),
+            equalizer: player.equalizer(),
+            compressor: player.compressor(),
+            stereo: player.stereo(),
+            metronome: player.metronome(),
+            envelope: player.envelope(),
+            resampler: player.resampler(),
+            eq_editor: None,
+            deck: false,
+            cues: HotCues::load(player.path()),
+            beats: player.beats(),
+            roll: None,
+            practice: None,
+            chapters: player.chapters().to_vec(),
+            bookmarks: Bookmarks::load(player.path()),
+            clip_in: None,
+            clip_out: None,
+            stats: library::stats(player.path()),
+            prompt: None,
+            recent: None,
+            podcasts: None,
+            podcast_feed: None,
+            pending_feed: None,
+            error: None,
+            toast: None,
+            queue_index: 0,
+            queue_len: 1,
+            queue: Vec::new(),
+            queue_panel: None,
+            tag_editor: None,
+            file_action: None,
+            keep_dir: None,
+            reject_dir: None,
+            shuffle: None,
+            output: player.output_name().to_string(),
+            stop_after_track: false,
+            auto_advance: true,
+            show_remaining: false,
+            precise_time: false,
+            loaded_at: Instant::now(),
+            hidden: Vec::new(),
+            spectrum_layout: SpectrumLayout::default(),
+            cover: None,
+            cover_width: None,
+            visualizer: RefCell::new(visualizer::first()),
+            theme,
+        }
+    }
+
+    /// Refreshes the per-track fields after the player has loaded a new file.
+    pub fn load_track(&mut self, player: &Player) {
+        self.path = player.path().to_path_buf();
+        self.filename = file_name(player.path());
+        self.track = player.track_info().clone();
+        self.stream_info = player.stream_info().clone();
+        self.live_format = player.live_format();
+        self.comparison = player.comparison().cloned();
+        self.tracker = player.tracker_position();
+        self.position = Duration::from_secs(0);
+        self.duration = player.duration();
+        self.waveform = player.waveform().clone();
+        self.compare_waveform = player.compare_waveform().cloned();
+        self.waveform_view = WaveformView::default();
+        self.chapters = player.chapters().to_vec();
+        self.bookmarks = Bookmarks::load(player.path());
+        self.cues = HotCues::load(player.path());
+        self.clip_in = None;
+        self.clip_out = None;
+        self.stats = library::stats(player.path());
+        self.cover = self.cover_width.map(|_| Cover::load(player.path()));
+        self.loaded_at = Instant::now();
+    }
+
+    /// Marks where a clip starts, dropping an out point that's no longer
+    /// after it.
+    pub fn mark_in(&mut self, position: Duration) {
+        self.clip_in = Some(position);
+        if self.clip_out.is_some_and(|out| out <= position) {
+            self.clip_out = None;
+        }
+    }
+
+    pub fn mark_out(&mut self, position: Duration) {
+        self.clip_out = Some(position);
+        if self.clip_in.is_some_and(|start| start >= position) {
+            self.clip_in = None;
+        }
+    }
+
+    /// The marked clip, which runs from the start or end of the track where
+    /// only one of the in and out points is set.
+    pub fn clip(&self) -> Option<Range<Duration>> {
+        if self.clip_in.is_none() && self.clip_out.is_none() {
+            return None;
+        }
+        let start = self.clip_in.unwrap_or_default();
+        let end = self.clip_out.unwrap_or(self.duration);
+        (end > start).then_some(start..end)
+    }
+
+    pub fn display_title(&self) -> String {
+        self.track.display_title(&self.filename)
+    }
+
+    /// Whether the compressor's gain reduction meter is on a screen of
+    /// size `screen`.
+    pub fn shows_reduction(&self, screen: Rect) -> bool {
+        self.compressor.is_enabled() && self.is_shown(Panel::Volume) && screen.height >= MINI_HEIGHT
+    }
+
+    /// Position in the track under the screen cell (`column`, `row`), if
+    /// that's on the waveform. The minimap under a zoomed waveform covers the
+    /// whole track.
+    pub fn waveform_position(&self, screen: Rect, column: u16, row: u16) -> Option<Duration> {
+        let duration = self.duration.as_secs_f64();
+        if self.spectrum.is_some() || duration <= 0.0 || screen.height < MINI_HEIGHT {
+            return None;
+        }
+
+        let (_, area) = split_cover(layout(screen, self)[1], self);
+        let inner = Block::default().borders(Borders::ALL).inner(area);
+        let (waveform, minimap) = split_minimap(inner, self);
+        let window = if waveform.contains(Position::new(column, row)) {
+            self.visible_window()
+        } else if minimap.is_some_and(|m| m.contains(Position::new(column, row))) {
+            (0.0, duration)
+        } else {
+            return None;
+        };
+
+        let ratio = ((column - inner.x) as f64 + 0.5) / inner.width as f64;
+        Some(Duration::from_secs_f64(
+            window.0 + (window.1 - window.0) * ratio,
+        ))
+    }
+
+    /// Position of the chapter, bookmark or loop point ticked under screen
+    /// column `column` on the progress bar, or a column either side, if
+    /// `row` is on the bar.
+    pub fn progress_mark_at(&self, screen: Rect, column: u16, row: u16) -> Option<Duration> {
+        if screen.height < MINI_HEIGHT {
+            return None;
+        }
+        let area = progress_area(layout(screen, self)[2], self);
+        if !area.contains(Position::new(column, row)) {
+            return None;
+        }
+        let inner = Block::default().borders(Borders::ALL).inner(area);
+        progress_marks(self)
+            .into_iter()
+            .filter_map(|(position, _, _)| {
+                let x = mark_column(inner, position, self.duration)?;
+                (x.abs_diff(column) <= 1).then_some((position, x.abs_diff(column)))
+            })
+            .min_by_key(|&(_, distance)| distance)
+            .map(|(position, _)| position)
+    }
+
+    /// Starts fetching the feed at `url` afresh, to list its episodes once
+    /// it's arrived.
+    pub fn open_podcasts(&mut self, url: &str) {
+        if !download::is_url(url) {
+            self.error = Some(format!("Not a feed URL: {}", url));
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let fetching = url.to_string();
+        thread::spawn(move || {
+            sender.send(Feed::fetch(&fetching)).ok();
+        });
+        self.pending_feed = Some((url.to_string(), receiver));
+    }
+
+    /// Lists the episodes of a feed that's finished fetching. Returns
+    /// whether one has.
+    pub fn receive_feed(&mut self) -> bool {
+        let Some((_, receiver)) = &self.pending_feed else {
+            return false;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => Err("the fetch stopped".to_string()),
+        };
+        let Some((url, _)) = self.pending_feed.take() else {
+            return false;
+        };
+        match result {
+            Ok(feed) => {
+                self.podcasts = Some(PodcastList::new(feed));
+                self.podcast_feed = Some(url);
+            }
+            Err(e) => self.error = Some(format!("Couldn't load the feed: {}", e)),
+        }
+        true
+    }
+
+    pub fn toast(&mut self, text: impl Into<String>) {
+        self.toast = Some(Toast {
+            text: text.into(),
+            error: false,
+            shown_at: Instant::now(),
+        });
+    }
+
+    pub fn toast_error(&mut self, text: impl Into<String>) {
+        self.toast = Some(Toast {
+            text: text.into(),
+            error: true,
+            shown_at: Instant::now(),
+        });
+    }
+
+    pub fn toast_seek(&mut self, position: Duration) {
+        self.toast(format!(
+            "Seeked to {}",
+            format_time(position, Precision::Seconds)
+        ));
+    }
+
+    pub fn toast_volume(&mut self, volume: f32) {
+        self.toast(format!("Volume {:.0}%", volume * 100.0));
+    }
+
+    /// The toast to show, unless it's been up long enough.
+    pub fn current_toast(&self) -> Option<&Toast> {
+        self.toast.as_ref().filter(|toast| !toast.is_expired())
+    }
+
+    /// Gives the current track `rating` stars, or clears its rating.
+    pub fn rate(&mut self, rating: Option<u8>) {
+        library::rate(&self.path, rating);
+        self.stats = library::stats(&self.path);
+    }
+
+    /// Plays the current track `gain_db` louder from now on, or quieter if
+    /// negative.
+    pub fn set_track_gain(&mut self, gain_db: f32) {
+        library::set_gain(&self.path, gain_db);
+        self.stats = library::stats(&self.path);
+        self.envelope.set_offset(self.stats.gain_db);
+    }
+
+    pub fn is_shown(&self, panel: Panel) -> bool {
+        !self.hidden.contains(&panel)
+    }
+
+    /// Hides `panel` if it's shown and vice versa, remembering the choice in
+    /// the config file.
+    pub fn toggle_panel(&mut self, panel: Panel) {
+        if self.is_shown(panel) {
+            self.hidden.push(panel);
+        } else {
+            self.hidden.retain(|&p| p != panel);
+        }
+
+        let names: Vec<&str> = self.hidden.iter().map(|p| p.name()).collect();
+        let value = names.join(",");
+        config::save_option("hide", (!value.is_empty()).then_some(value.as_str()));
+    }
+
+    /// Closes the equalizer editor, remembering the bands in the config
+    /// file.
+    pub fn close_eq_editor(&mut self) {
+        self.eq_editor = None;
+        let bands = (!self.equalizer.is_flat()).then(|| eq::format(&self.equalizer.bands()));
+        config::save_option("eq", bands.as_deref());
+    }
+
+    /// Leaves deck mode, putting the pitch back to normal so nothing plays
+    /// at the wrong speed without saying so.
+    pub fn close_deck(&mut self) {
+        self.deck = false;
+        self.resampler.set_pitch(1.0);
+    }
+
+    pub fn set_spectrum_layout(&mut self, layout: SpectrumLayout) {
+        self.spectrum_layout = layout;
+        config::save_option("spectrum-layout", Some(layout.name()));
+    }
+
+    pub fn set_visualizer(&mut self, visualizer: Box<dyn Visualizer>) {
+        config::save_option("visualization", Some(visualizer.name()));
+        *self.visualizer.get_mut() = visualizer;
+    }
+
+    /// Short tracks get tenths of a second, where whole seconds are too
+    /// coarse.
+    pub fn clock_precision(&self) -> Precision {
+        if self.precise_time {
+            Precision::Millis
+        } else if self.deck || !self.duration.is_zero() && self.duration < Duration::from_secs(60) {
+            Precision::Tenths
+        } else {
+            Precision::Seconds
+        }
+    }
+
+    /// Start and end of the part of the track the waveform shows, in seconds.
+    fn visible_window(&self) -> (f64, f64) {
+        if self.waveform.config.enhanced {
+            self.waveform_view.window(self.position, self.duration)
+        } else {
+            (0.0, self.duration.as_secs_f64())
         }
     }
 }
 
-pub fn render(frame: &mut Frame, state: &UIState) {
-    let area = frame.area();
+fn file_name(path: &Path) -> String {
+    if raw::is_stdin(path) {
+        return "stdin".to_string();
+    }
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Unknown")
+        .to_string()
+}
+
+fn layout(area: Rect, state: &UIState) -> Rc<[Rect]> {
+    let height = |panel, rows| if state.is_shown(panel) { rows } else { 0 };
+    let title = height(Panel::Title, 3);
+    let volume = height(Panel::Volume, 3);
+    // Prompts and errors take the place of the controls, so always get room.
+    let controls = if state.prompt.is_some() || state.error.is_some() {
+        3
+    } else {
+        height(Panel::Controls, 3)
+    };
+    let status = height(Panel::Status, 1);
 
-    let viz_height = if state.spectrum.is_some() {
-        area.height.saturating_sub(12).max(10)
-    } else if state.waveform.enhanced {
-        9
+    // Rows freed up by hidden panels go to the visualizer.
+    let viz_height = if !state.is_shown(Panel::Visualizer) {
+        0
+    } else if state.spectrum.is_some() {
+        area.height
+            .saturating_sub(title + 3 + volume + controls + status)
+            .max(10)
+    } else if state.waveform.config.enhanced {
+        9 + 9 - (title + volume + controls)
     } else {
-        5
+        5 + 9 - (title + volume + controls)
     };
 
-    let chunks = Layout::vertical([
-        Constraint::Length(3),          // Title
+    Layout::vertical([
+        Constraint::Length(title),      // Title
         Constraint::Length(viz_height), // Waveform/Spectrum
         Constraint::Length(3),          // Progress
-        Constraint::Length(3),          // Volume
+        Constraint::Length(volume),     // Volume
         Constraint::Min(0),             // Spacer
-        Constraint::Length(3),          // Controls
+        Constraint::Length(controls),   // Controls
+        Constraint::Length(status),     // Status
     ])
-    .split(area);
+    .split(area)
+}
+
+/// Columns of the buffer meter beside the progress bar.
+const BUFFER_METER_WIDTH: u16 = 24;
+/// Columns of the gain reduction meter beside the volume.
+const COMPRESSOR_METER_WIDTH: u16 = 32;
+/// Gain reduction that fills the meter.
+const COMPRESSOR_METER_DB: f32 = 20.0;
+
+/// Below this many rows the bordered widgets don't fit, so everything goes
+/// on one line instead.
+const MINI_HEIGHT: u16 = 10;
 
-    render_title(frame, chunks[0], state);
-    render_visualization(frame, chunks[1], state);
+pub fn render(frame: &mut Frame, state: &UIState) {
+    if frame.area().height < MINI_HEIGHT {
+        render_mini(frame, frame.area(), state);
+    } else {
+        render_full(frame, state);
+    }
+    if let Some(panel) = &state.queue_panel {
+        render_queue(frame, frame.area(), panel, state);
+    }
+    if let Some(recent) = &state.recent {
+        render_recent(frame, frame.area(), recent, &state.theme);
+    }
+    if let Some(podcasts) = &state.podcasts {
+        render_podcasts(frame, frame.area(), podcasts, &state.theme);
+    }
+    if let Some(editor) = &state.tag_editor {
+        render_tag_editor(frame, frame.area(), editor, state);
+    }
+    if let Some(editor) = &state.eq_editor {
+        render_eq_editor(frame, frame.area(), editor, state);
+    } else if state.deck {
+        render_deck(frame, frame.area(), state);
+    }
+    if let Some(action) = &state.file_action {
+        render_file_action(frame, frame.area(), action, state);
+    }
+}
+
+fn render_full(frame: &mut Frame, state: &UIState) {
+    let chunks = layout(frame.area(), state);
+
+    if state.is_shown(Panel::Title) {
+        render_title(frame, chunks[0], state);
+    }
+    if state.is_shown(Panel::Visualizer) {
+        let (cover, area) = split_cover(chunks[1], state);
+        if let (Some(cover), Some(cover_area)) = (&state.cover, cover) {
+            cover.render(frame, cover_area, state.theme.truecolor);
+        }
+        render_visualization(frame, area, state);
+        render_hover(frame, area, state);
+    }
     render_progress(frame, chunks[2], state);
-    render_volume(frame, chunks[3], state);
-    render_controls(frame, chunks[5]);
+    if state.is_shown(Panel::Volume) {
+        render_volume(frame, chunks[3], state);
+    }
+    if let Some(prompt) = &state.prompt {
+        render_prompt(frame, chunks[5], prompt, &state.theme);
+    } else if let Some(error) = &state.error {
+        render_error(frame, chunks[5], error, &state.theme);
+    } else if state.is_shown(Panel::Controls) {
+        render_controls(frame, chunks[5], &state.theme);
+    }
+    if state.is_shown(Panel::Status) {
+        render_status(frame, chunks[6], state);
+    }
+    if let Some(toast) = state.current_toast() {
+        render_toast(frame, chunks[1].y, toast, &state.theme);
+    }
+}
+
+/// Status, title, clock and a short progress bar on one line, with a prompt
+/// or error below it if there's room.
+fn render_mini(frame: &mut Frame, area: Rect, state: &UIState) {
+    let (label, ratio) = progress(state);
+    let clock = format!(" {} ", label);
+    let bar_width = (area.width / 5).min(20) as usize;
+    let title_width = (area.width as usize).saturating_sub(2 + clock.chars().count() + bar_width);
+
+    let title = marquee(title_spans(state), title_width, state.loaded_at);
+    let padding = title_width.saturating_sub(title.iter().map(Span::width).sum());
+    let filled = (ratio * bar_width as f64).round() as usize;
+
+    let mut line = vec![status_span(state), Span::raw(" ")];
+    line.extend(title);
+    line.extend([
+        Span::raw(" ".repeat(padding)),
+        Span::styled(clock, Style::default().fg(state.theme.secondary)),
+        Span::styled("━".repeat(filled), Style::default().fg(state.theme.track)),
+        Span::styled(
+            "─".repeat(bar_width - filled),
+            Style::default().fg(state.theme.muted),
+        ),
+    ]);
+
+    let mut lines = vec![Line::from(line)];
+    if let Some(prompt) = &state.prompt {
+        lines.push(Line::from(vec![
+            Span::raw(format!("{}: {}", prompt.label(), prompt.input)),
+            Span::styled("█", Style::default().fg(state.theme.highlight)),
+        ]));
+    } else if let Some(error) = &state.error {
+        lines.push(Line::from(Span::styled(
+            error.as_str(),
+            Style::default().fg(state.theme.danger),
+        )));
+    } else if let Some(toast) = state.current_toast() {
+        let color = if toast.error {
+            state.theme.danger
+        } else {
+            state.theme.secondary
+        };
+        lines.push(Line::from(Span::styled(
+            toast.text.as_str(),
+            Style::default().fg(color),
+        )));
+    }
+
+    frame.render_widget(Paragraph::new(lines), area);
 }
 
 fn render_visualization(frame: &mut Frame, area: Rect, state: &UIState) {
     if let Some(spectrum) = &state.spectrum {
-        render_spectrum_bars(frame, area, state, spectrum);
-    } else if state.waveform.enhanced {
-        render_enhanced_waveform(frame, area, state);
+        let playing = state.state == PlaybackState::Playing;
+        let layout = state.spectrum_layout;
+        // The equalizer's curve is drawn over the spectrum, whatever else
+        // would be showing.
+        if let Some(editor) = &state.eq_editor {
+            let bars = spectrum.bars();
+            let bars =
+                render_spectrum_bars(frame, area, &state.theme, layout, playing, spectrum, &bars);
+            render_eq_curve(frame, bars, editor, state, spectrum);
+        } else {
+            let scene = Scene::capture(spectrum, &state.theme, layout, playing);
+            state.visualizer.borrow_mut().render(frame, area, &scene);
+        }
+    } else {
+        let (inner, minimap) =
+            split_minimap(Block::default().borders(Borders::ALL).inner(area), state);
+        let window = state.visible_window();
+        // Only the Braille waveform can be drawn over with another.
+        if state.waveform.config.enhanced || state.compare_waveform.is_some() {
+            render_enhanced_waveform(frame, area, inner, state, window);
+            if let Some(minimap) = minimap {
+                render_minimap(frame, minimap, state, window);
+            }
+        } else {
+            render_simple_waveform(frame, area, state);
+        }
+        render_chapter_marks(frame, inner, state, window);
+        render_clip_marks(frame, inner, state, window);
+    }
+}
+
+/// Takes room for the cover, once there's one to show, from the left of
+/// the visualizer's `area`: as wide as a square picture its height, up to
+/// the width asked for.
+fn split_cover(area: Rect, state: &UIState) -> (Option<Rect>, Rect) {
+    let (Some(cover), Some(max_width)) = (&state.cover, state.cover_width) else {
+        return (None, area);
+    };
+    // Two pixels to a cell down, one across.
+    let width = max_width.min(area.height.saturating_sub(2) * 2) + 2;
+    if !cover.is_loaded() || width >= area.width / 2 {
+        return (None, area);
+    }
+    let [cover, rest] =
+        Layout::horizontal([Constraint::Length(width), Constraint::Min(0)]).areas(area);
+    (Some(cover), rest)
+}
+
+/// Splits the inside of the waveform block into the waveform itself and,
+/// when zoomed, the minimap row below it.
+fn split_minimap(mut inner: Rect, state: &UIState) -> (Rect, Option<Rect>) {
+    if !state.waveform.config.enhanced || !state.waveform_view.is_zoomed() || inner.height < 2 {
+        return (inner, None);
+    }
+    inner.height -= 1;
+    let minimap = Rect::new(inner.x, inner.bottom(), inner.width, 1);
+    (inner, Some(minimap))
+}
+
+/// Previews a click on the waveform: a ghost cursor under the pointer and
+/// the position it would seek to on the top border.
+fn render_hover(frame: &mut Frame, area: Rect, state: &UIState) {
+    let Some((column, row)) = state.hover else {
+        return;
+    };
+    let Some(position) = state.waveform_position(frame.area(), column, row) else {
+        return;
+    };
+
+    let (waveform, minimap) =
+        split_minimap(Block::default().borders(Borders::ALL).inner(area), state);
+    let rows = match minimap {
+        Some(minimap) if row == minimap.y => minimap.y..minimap.bottom(),
+        _ => waveform.top()..waveform.bottom(),
+    };
+    for y in rows {
+        let cell = &mut frame.buffer_mut()[(column, y)];
+        if cell.symbol() == " " {
+            cell.set_symbol("┊");
+        }
+        cell.set_fg(state.theme.highlight);
+    }
+
+    let precision = if state.precise_time {
+        Precision::Millis
+    } else {
+        Precision::Seconds
+    };
+    let label = format!(" {} ", format_time(position, precision));
+    let width = label.chars().count() as u16;
+    let x = column
+        .saturating_sub(width / 2)
+        .min(area.right().saturating_sub(width + 1))
+        .max(area.x + 1);
+    frame.buffer_mut().set_string(
+        x,
+        area.y,
+        label,
+        Style::default()
+            .fg(state.theme.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+}
+
+/// Draws the chapter starts that fall within `window`, given in seconds.
+fn render_chapter_marks(frame: &mut Frame, inner: Rect, state: &UIState, window: (f64, f64)) {
+    let (start, end) = window;
+    if end <= start || inner.width == 0 {
+        return;
+    }
+
+    for chapter in state.chapters.iter().filter(|c| !c.start.is_zero()) {
+        let ratio = (chapter.start.as_secs_f64() - start) / (end - start);
+        if !(0.0..1.0).contains(&ratio) {
+            continue;
+        }
+        let x = (ratio * inner.width as f64) as u16;
+
+        for y in 0..inner.height {
+            let cell = &mut frame.buffer_mut()[(inner.x + x, inner.y + y)];
+            if cell.symbol() == " " {
+                cell.set_symbol("│");
+                cell.set_fg(state.theme.muted);
+            } else {
+                cell.set_fg(state.theme.marker);
+            }
+        }
+    }
+}
+
+/// Draws the in and out points of the marked clip, where they're in
+/// `window`.
+fn render_clip_marks(frame: &mut Frame, inner: Rect, state: &UIState, window: (f64, f64)) {
+    let (start, end) = window;
+    if end <= start || inner.width == 0 {
+        return;
+    }
+
+    for (mark, symbol) in [(state.clip_in, "["), (state.clip_out, "]")] {
+        let Some(mark) = mark else {
+            continue;
+        };
+        let ratio = (mark.as_secs_f64() - start) / (end - start);
+        if !(0.0..=1.0).contains(&ratio) {
+            continue;
+        }
+        let x = ((ratio * inner.width as f64) as u16).min(inner.width - 1);
+
+        for y in 0..inner.height {
+            let cell = &mut frame.buffer_mut()[(inner.x + x, inner.y + y)];
+            cell.set_symbol(if y == 0 { symbol } else { "│" });
+            cell.set_fg(state.theme.good);
+        }
+    }
+}
+
+const PARTIAL_BLOCKS: [&str; 7] = ["▁", "▂", "▃", "▄", "▅", "▆", "▇"];
+/// How long the spectrum lights up after each beat.
+const BEAT_PULSE: Duration = Duration::from_millis(150);
+/// Frequencies that may be labeled under the spectrum, as many as fit.
+const FREQUENCY_LABELS: [f32; 10] = [
+    20.0, 50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0, 10000.0, 20000.0,
+];
+/// Columns taken by the dB scale at the side of the spectrum.
+const DB_AXIS_WIDTH: u16 = 4;
+/// Smallest spectrum, inside its border, that's given axes.
+const AXES_MIN_SIZE: (u16, u16) = (40, 8);
+
+/// Draws `bars` from `spectrum`, in the colors for playing audio when
+/// `active` and paused audio otherwise. Returns the area the bars are drawn
+/// in.
+pub fn render_spectrum_bars(
+    frame: &mut Frame,
+    area: Rect,
+    theme: &Theme,
+    layout: SpectrumLayout,
+    active: bool,
+    spectrum: &Spectrum,
+    bars: &[f32],
+) -> Rect {
+    let mut block = Block::default().borders(Borders::ALL);
+    if !spectrum.detects_beats() {
+        block = block.title("Spectrum Analyzer");
+    } else {
+        let pulse = spectrum
+            .since_beat()
+            .is_some_and(|since| since < BEAT_PULSE);
+        let (dot, color) = if pulse {
+            ("●", theme.highlight)
+        } else {
+            ("○", theme.muted)
+        };
+        let mut title = vec![
+            Span::raw("Spectrum Analyzer "),
+            Span::styled(dot, Style::default().fg(color)),
+        ];
+        if let Some(tempo) = spectrum.tempo() {
+            title.push(Span::raw(format!(" {:.0} BPM", tempo)));
+        }
+        block = block.title(Line::from(title));
+        if pulse {
+            block = block.border_style(Style::default().fg(theme.highlight));
+        }
+    }
+    let mut inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let num_bars = bars.len().max(1);
+
+    // A dB scale doesn't read both ways, so mirrored bars go without axes.
+    if layout == SpectrumLayout::Bars
+        && inner.width >= AXES_MIN_SIZE.0
+        && inner.height >= AXES_MIN_SIZE.1
+    {
+        inner = render_spectrum_axes(frame, inner, theme, spectrum, num_bars);
+    }
+    let width = inner.width as usize;
+    let height = inner.height as usize;
+    let bar_width = (width / num_bars).max(1);
+
+    let waveform_color = if active { theme.playing } else { theme.paused };
+
+    // Paused bars fade up from the paused color instead, like the 16-color
+    // scheme does.
+    let gradient_start = if active {
+        theme.gradient_start
     } else {
-        render_simple_waveform(frame, area, state);
+        theme.paused
+    };
+
+    // Rows above the line mirrored bars reach out from, which get the odd
+    // row out.
+    let upper = match layout {
+        SpectrumLayout::Bars => height,
+        SpectrumLayout::Mirror => height - height / 2,
+    };
+
+    for (i, &amplitude) in bars.iter().enumerate() {
+        let x_pos = i * bar_width;
+        if x_pos >= width {
+            break;
+        }
+
+        // Color of the cell `h` rows along a bar `bar_height` rows long, out of
+        // the `rows` it could reach.
+        let hue_factor = i as f32 / num_bars as f32;
+        let color_at = |h: usize, bar_height: usize, rows: usize| {
+            let intensity = h as f32 / bar_height.max(1) as f32;
+            if theme.truecolor {
+                theme::blend(gradient_start, theme.gradient_end, h as f32 / rows as f32)
+            } else if intensity > 0.8 {
+                theme.danger
+            } else if intensity > 0.5 {
+                if hue_factor < 0.33 {
+                    theme.bass
+                } else if hue_factor < 0.66 {
+                    waveform_color
+                } else {
+                    theme.treble
+                }
+            } else {
+                waveform_color
+            }
+        };
+        let mut set = |y: usize, symbol: &str, color: Color| {
+            for x in x_pos..(x_pos + bar_width).min(width) {
+                let cell = &mut frame.buffer_mut()[(inner.x + x as u16, inner.y + y as u16)];
+                cell.set_symbol(symbol);
+                cell.set_fg(color);
+            }
+        };
+
+        // Bar heights are tracked in eighths of a row so the top cell can use
+        // a partial block.
+        let eighths = ((amplitude * upper as f32 * 8.0) as usize).min(upper * 8);
+        let bar_height = eighths.div_ceil(8);
+        for h in 0..bar_height {
+            let remainder = eighths % 8;
+            let symbol = if h + 1 == bar_height && remainder > 0 {
+                PARTIAL_BLOCKS[remainder - 1]
+            } else {
+                "█"
+            };
+            set(upper - h - 1, symbol, color_at(h, bar_height, upper));
+        }
+
+        if layout == SpectrumLayout::Mirror {
+            // The reflection, in half rows since only the upper half block
+            // hangs from the top of a cell.
+            let lower = height - upper;
+            let halves = ((amplitude * lower as f32 * 2.0) as usize).min(lower * 2);
+            let bar_height = halves.div_ceil(2);
+            for h in 0..bar_height {
+                let symbol = if h + 1 == bar_height && halves % 2 == 1 {
+                    "▀"
+                } else {
+                    "█"
+                };
+                set(upper + h, symbol, color_at(h, bar_height, lower));
+            }
+        }
+    }
+    inner
+}
+
+/// Labels levels down the left of `area` and frequencies along the bottom,
+/// returning what's left for the bars. Levels are as drawn before the bass
+/// boost, which raises the lower bars.
+fn render_spectrum_axes(
+    frame: &mut Frame,
+    area: Rect,
+    theme: &Theme,
+    spectrum: &Spectrum,
+    num_bars: usize,
+) -> Rect {
+    let bars = Rect {
+        x: area.x + DB_AXIS_WIDTH,
+        y: area.y,
+        width: area.width - DB_AXIS_WIDTH,
+        height: area.height - 1,
+    };
+    let style = Style::default().fg(theme.muted);
+    let buffer = frame.buffer_mut();
+
+    let height = bars.height as f32;
+    let mut next_row = 0;
+    for db in (FLOOR_DB as i32 + 10..=0).rev().step_by(10) {
+        let level = (db as f32 - FLOOR_DB) / -FLOOR_DB;
+        let row = (height - (level * height).round()).min(height - 1.0) as u16;
+        if row < next_row {
+            continue;
+        }
+        let label = format!("{:>3}", db);
+        buffer.set_string(area.x, bars.y + row, label, style);
+        next_row = row + 3;
+    }
+    buffer.set_string(area.x + 1, bars.bottom(), "dB", style);
+
+    let bar_width = (bars.width as usize / num_bars).max(1);
+    let drawn = (num_bars * bar_width).min(bars.width as usize);
+    let mut next_column = 0;
+    for frequency in FREQUENCY_LABELS {
+        let Some(position) = spectrum.position_of(frequency) else {
+            continue;
+        };
+        let label = if frequency >= 1000.0 {
+            format!("{} kHz", frequency / 1000.0)
+        } else {
+            format!("{} Hz", frequency)
+        };
+        let center = (position * (num_bars * bar_width) as f32) as usize;
+        if center >= drawn {
+            continue;
+        }
+        let start = center
+            .saturating_sub(label.len() / 2)
+            .min(drawn.saturating_sub(label.len()));
+        if start < next_column {
+            continue;
+        }
+        buffer.set_string(bars.x + start as u16, bars.bottom(), &label, style);
+        next_column = start + label.len() + 1;
     }
+    bars
 }
 
-fn render_spectrum_bars(
+/// Draws the equalizer's response across the spectrum, 0 dB along the
+/// middle, with a mark at the center of each band.
+fn render_eq_curve(
     frame: &mut Frame,
     area: Rect,
+    editor: &EqEditor,
     state: &UIState,
-    spectrum: &Arc<Mutex<SpectrumAnalyzer>>,
+    spectrum: &Spectrum,
 ) {
+    if area.width == 0 || area.height < 3 {
+        return;
+    }
+    let num_bars = spectrum.num_bars().max(1);
+    let span = (num_bars * (area.width as usize / num_bars).max(1)) as f32;
+    let sample_rate = spectrum.ring().sample_rate();
+    let half = (area.height - 1) as f32 / 2.0;
+    let row_of = |db: f32| {
+        let offset = (db / eq::MAX_GAIN_DB).clamp(-1.0, 1.0) * half;
+        area.y + (half - offset).round() as u16
+    };
+
+    let buffer = frame.buffer_mut();
+    let line = Style::default().fg(state.theme.highlight);
+    for x in 0..area.width.min(span as u16) {
+        let frequency = spectrum.frequency_at((x as f32 + 0.5) / span);
+        let db = state.equalizer.response_db(frequency, sample_rate);
+        buffer.set_string(area.x + x, row_of(db), "•", line);
+    }
+
+    for (index, band) in state.equalizer.bands().iter().enumerate() {
+        let Some(position) = spectrum.position_of(band.frequency) else {
+            continue;
+        };
+        let x = ((position * span) as u16).min(area.width - 1);
+        let (mark, color) = if index == editor.selected {
+            ("◆", state.theme.good)
+        } else {
+            ("◇", state.theme.highlight)
+        };
+        let style = Style::default().fg(color).add_modifier(Modifier::BOLD);
+        buffer.set_string(area.x + x, row_of(band.gain_db), mark, style);
+    }
+}
+
+/// The equalizer's bands along the bottom of the screen, out of the way of
+/// the curve drawn on the spectrum.
+fn render_eq_editor(frame: &mut Frame, area: Rect, editor: &EqEditor, state: &UIState) {
+    let mut rect = popup(area, eq::BANDS);
+    rect.y = area.bottom().saturating_sub(rect.height);
     let block = Block::default()
         .borders(Borders::ALL)
-        .title("Spectrum Analyzer");
-    let inner = block.inner(area);
-    frame.render_widget(block, area);
+        .border_style(Style::default().fg(state.theme.highlight))
+        .title("Equalizer (Tab band, ←/→ freq, ↑/↓ gain, -/+ width, 0 flat, Esc close)");
+    frame.render_widget(Clear, rect);
+
+    let lines: Vec<Line> = state
+        .equalizer
+        .bands()
+        .iter()
+        .enumerate()
+        .map(|(index, band)| {
+            let selected = index == editor.selected;
+            let marker = if selected { "▶ " } else { "  " };
+            let style = if selected {
+                Style::default()
+                    .fg(state.theme.track)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let frequency = if band.frequency >= 1000.0 {
+                format!("{:.1} kHz", band.frequency / 1000.0)
+            } else {
+                format!("{:.0} Hz", band.frequency)
+            };
+            Line::from(vec![
+                Span::styled(marker, Style::default().fg(state.theme.good)),
+                Span::styled(
+                    format!("{} ", index + 1),
+                    Style::default().fg(state.theme.muted),
+                ),
+                Span::styled(
+                    format!(
+                        "{:>9}  {:>+5.1} dB  Q {:.2}",
+                        frequency, band.gain_db, band.q
+                    ),
+                    style,
+                ),
+            ])
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines).block(block), rect);
+}
+
+/// Rows of the deck's big clock.
+const DIGIT_ROWS: usize = 5;
+
+/// `text` in block characters [`DIGIT_ROWS`] high, for reading at a glance.
+/// Only digits, `:`, `.` and `-` are drawn.
+fn big_text(text: &str) -> Vec<String> {
+    let mut rows = vec![String::new(); DIGIT_ROWS];
+    for c in text.chars() {
+        let glyph: [&str; DIGIT_ROWS] = match c {
+            '0' => ["███", "█ █", "█ █", "█ █", "███"],
+            '1' => [" ██", "  █", "  █", "  █", "  █"],
+            '2' => ["███", "  █", "███", "█  ", "███"],
+            '3' => ["███", "  █", "███", "  █", "███"],
+            '4' => ["█ █", "█ █", "███", "  █", "  █"],
+            '5' => ["███", "█  ", "███", "  █", "███"],
+            '6' => ["███", "█  ", "███", "█ █", "███"],
+            '7' => ["███", "  █", "  █", "  █", "  █"],
+            '8' => ["███", "█ █", "███", "█ █", "███"],
+            '9' => ["███", "█ █", "███", "  █", "███"],
+            ':' => [" ", "█", " ", "█", " "],
+            '.' => [" ", " ", " ", " ", "█"],
+            '-' => ["   ", "   ", "███", "   ", "   "],
+            _ => continue,
+        };
+        for (row, part) in rows.iter_mut().zip(glyph) {
+            if !row.is_empty() {
+                row.push(' ');
+            }
+            row.push_str(part);
+        }
+    }
+    rows
+}
+
+/// The tempo, and the loop roll playing if there is one.
+fn beats_span(state: &UIState) -> Span<'static> {
+    let muted = Style::default().fg(state.theme.muted);
+    match (state.beats, state.roll) {
+        (Beats::Found(grid), Some(beats)) => Span::styled(
+            format!(
+                "{:.1} BPM  ⟲ {} beat{}",
+                grid.bpm(),
+                beats,
+                if beats == 1 { "" } else { "s" }
+            ),
+            Style::default().fg(state.theme.highlight),
+        ),
+        (Beats::Found(grid), None) => Span::styled(
+            format!("{:.1} BPM", grid.bpm()),
+            Style::default().fg(state.theme.secondary),
+        ),
+        (Beats::NotFound, _) => Span::styled("No steady beat", muted),
+        (Beats::Unknown | Beats::Finding, _) => Span::styled("Finding the beat…", muted),
+    }
+}
+
+/// Deck mode: time left at the current pitch in big digits, the pitch, and
+/// the hot cues.
+fn render_deck(frame: &mut Frame, area: Rect, state: &UIState) {
+    let mut rect = popup(area, DIGIT_ROWS + 3);
+    rect.y = area.bottom().saturating_sub(rect.height);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(state.theme.highlight))
+        .title("Deck (1-4 cue, Shift+1-4 set, 5-8 roll, -/=/_/+ pitch, 0 reset, d close)");
+    frame.render_widget(Clear, rect);
+
+    let pitch = state.resampler.pitch();
+    let (clock, color) = if state.duration.is_zero() {
+        (
+            format_time(state.position, Precision::Tenths),
+            state.theme.track,
+        )
+    } else {
+        let left = state.duration.saturating_sub(state.position).div_f32(pitch);
+        // Time to get the next track ready.
+        let color = if left < Duration::from_secs(30) {
+            state.theme.danger
+        } else {
+            state.theme.track
+        };
+        (format!("-{}", format_time(left, Precision::Tenths)), color)
+    };
+    let mut lines: Vec<Line> = big_text(&clock)
+        .into_iter()
+        .map(|row| Line::from(Span::styled(row, Style::default().fg(color))).centered())
+        .collect();
+
+    let offset = (pitch - 1.0) * 100.0;
+    let pitch_color = if offset == 0.0 {
+        state.theme.secondary
+    } else {
+        state.theme.warning
+    };
+    lines.push(Line::default());
+    lines.push(
+        Line::from(vec![
+            Span::styled("Pitch ", Style::default().fg(state.theme.muted)),
+            Span::styled(format!("{:+.1}%", offset), Style::default().fg(pitch_color)),
+            Span::styled(
+                format!("  (±{:.0}%)   ", resample::MAX_PITCH * 100.0),
+                Style::default().fg(state.theme.muted),
+            ),
+            beats_span(state),
+        ])
+        .centered(),
+    );
+
+    let mut cue_spans = Vec::new();
+    for (slot, cue) in state.cues.slots().iter().enumerate() {
+        if slot > 0 {
+            cue_spans.push(Span::raw("   "));
+        }
+        cue_spans.push(Span::styled(
+            format!("{} ", slot + 1),
+            Style::default().fg(state.theme.muted),
+        ));
+        cue_spans.push(match cue {
+            Some(position) => Span::styled(
+                format_time(*position, Precision::Tenths),
+                Style::default().fg(state.theme.good),
+            ),
+            None => Span::styled("--:--.-", Style::default().fg(state.theme.muted)),
+        });
+    }
+    lines.push(Line::from(cue_spans).centered());
+
+    frame.render_widget(Paragraph::new(lines).block(block), rect);
+}
+
+fn render_simple_waveform(frame: &mut Frame, area: Rect, state: &UIState) {
+    let width = area.width.saturating_sub(2) as usize;
+    let waveform = &state.waveform;
+    let waveform_data: Vec<u64> = (0..width)
+        .map(|i| {
+            let (from, to) = (i as f64 / width as f64, (i + 1) as f64 / width as f64);
+            (waveform.scaled(waveform.mix.level_between(from, to)) * 100.0) as u64
+        })
+        .collect();
+
+    let waveform_color = match state.state {
+        PlaybackState::Playing => state.theme.playing,
+        PlaybackState::Paused | PlaybackState::Stopped => state.theme.paused,
+    };
+
+    let sparkline = Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title("Waveform"))
+        .data(&waveform_data)
+        .style(Style::default().fg(waveform_color));
+
+    frame.render_widget(sparkline, area);
+}
 
-    let mut analyzer = spectrum.lock().unwrap();
-    analyzer.update();
-    let bars = analyzer.bars();
-    let num_bars = analyzer.num_bars();
+/// Draws the part of the waveform within `window` (in seconds) into
+/// `inner`, mirrored around the center line using Braille patterns, which
+/// give each cell a 2x4 grid of dots.
+fn render_enhanced_waveform(
+    frame: &mut Frame,
+    area: Rect,
+    inner: Rect,
+    state: &UIState,
+    window: (f64, f64),
+) {
+    let waveform_color = match state.state {
+        PlaybackState::Playing => state.theme.playing,
+        PlaybackState::Paused | PlaybackState::Stopped => state.theme.paused,
+    };
+
+    let mut title = vec![Span::raw(if state.waveform_view.is_zoomed() {
+        format!(
+            "Waveform {} – {}",
+            format_duration(Duration::from_secs_f64(window.0)),
+            format_duration(Duration::from_secs_f64(window.1))
+        )
+    } else {
+        "Waveform".to_string()
+    })];
+    let compare = state.compare_waveform.as_ref();
+    if compare.is_some() {
+        title.extend([
+            Span::raw(" "),
+            Span::styled("A", Style::default().fg(state.theme.bass)),
+            Span::raw(" "),
+            Span::styled("B", Style::default().fg(state.theme.treble)),
+        ]);
+    }
+    frame.render_widget(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(Line::from(title)),
+        area,
+    );
 
     let width = inner.width as usize;
     let height = inner.height as usize;
-    let bar_width = (width / num_bars).max(1);
+    let duration = state.duration.as_secs_f64();
+    if width == 0 || height == 0 || state.waveform.mix.levels.is_empty() || duration <= 0.0 {
+        return;
+    }
 
-    let waveform_color = match state.state {
-        PlaybackState::Playing => Color::Cyan,
-        PlaybackState::Paused => Color::Yellow,
+    let dot_columns = width * 2;
+    let dot_rows = height * 4;
+    let center = dot_rows / 2;
+    let (start, end) = (window.0 / duration, window.1 / duration);
+    let column_fraction =
+        |column: usize| start + (end - start) * column as f64 / dot_columns as f64;
+
+    // Dots inside the RMS envelope are tracked separately; cells made up of
+    // nothing else form the darker inner band.
+    // Compared waveforms share a scale, so a difference in level shows.
+    let loudest = compare.map_or(0.0, |compare| compare.peak.max(state.waveform.peak));
+    let plot = |waveform: &WaveformData| {
+        let gain = if loudest > 0.0 {
+            waveform.peak / loudest
+        } else {
+            1.0
+        };
+        // Tracks being compared can differ in length, so each is placed by
+        // time rather than by how far through it is.
+        let scale = if waveform.duration > 0.0 {
+            duration / waveform.duration
+        } else {
+            1.0
+        };
+        // The stereo view puts the left channel above the center line and
+        // the right below; otherwise the mix is mirrored.
+        let (upper, lower) = if waveform.config.stereo {
+            (&waveform.left, &waveform.right)
+        } else {
+            (&waveform.mix, &waveform.mix)
+        };
+
+        let mut cells = vec![0u8; width * height];
+        let mut rms_cells = vec![0u8; width * height];
+        for column in 0..dot_columns {
+            let from = column_fraction(column) * scale;
+            let to = column_fraction(column + 1) * scale;
+            if from >= 1.0 {
+                break;
+            }
+            // Keep at least one dot above and below the center so silence
+            // still shows as a line.
+            let extent = |level: f32| {
+                ((waveform.scaled(level * gain) * center as f32) as usize).clamp(1, center)
+            };
+            let levels = center - extent(upper.level_between(from, to))
+                ..center + extent(lower.level_between(from, to));
+            // Mean and RMS outlines already sit at or inside the RMS
+            // envelope.
+            let rms = if waveform.config.reduction() == Reduction::Peak {
+                center - extent(upper.rms_between(from, to))
+                    ..center + extent(lower.rms_between(from, to))
+            } else {
+                0..0
+            };
+
+            for row in levels {
+                let cell = (row / 4) * width + column / 2;
+                let dot = braille_dot(column % 2, row % 4);
+                cells[cell] |= dot;
+                if rms.contains(&row) {
+                    rms_cells[cell] |= dot;
+                }
+            }
+        }
+        (cells, rms_cells)
+    };
+    let (cells, rms_cells) = plot(&state.waveform);
+    let compared = compare.map(plot);
+
+    let progress = state.position.as_secs_f64() / duration;
+    let cursor_pos = ((progress - start) / (end - start) * width as f64).floor() as isize;
+
+    for (index, &dots) in cells.iter().enumerate() {
+        // Where the two differ, the cell takes the color of whichever
+        // reaches further.
+        let (dots, only) = match &compared {
+            Some((other, _)) if other[index] & !dots != 0 => {
+                (dots | other[index], Some(state.theme.treble))
+            }
+            Some((other, _)) if dots & !other[index] != 0 => (dots, Some(state.theme.bass)),
+            _ => (dots, None),
+        };
+        if dots == 0 {
+            continue;
+        }
+
+        let (x, y) = (index % width, index / width);
+        let mut color = if x as isize <= cursor_pos {
+            only.unwrap_or(waveform_color)
+        } else {
+            only.map_or(state.theme.muted, |color| darker(color, &state.theme))
+        };
+        if only.is_none() && dots == rms_cells[index] {
+            color = darker(color, &state.theme);
+        }
+
+        let symbol = char::from_u32(0x2800 + dots as u32).unwrap_or(' ');
+        let cell = &mut frame.buffer_mut()[(inner.x + x as u16, inner.y + y as u16)];
+        cell.set_char(symbol);
+        cell.set_fg(color);
+    }
+}
+
+fn darker(color: Color, theme: &Theme) -> Color {
+    let color = theme::blend(color, Color::Black, 0.45);
+    if theme.truecolor {
+        color
+    } else {
+        theme::downsample(color)
+    }
+}
+
+/// One row under a zoomed waveform showing the whole track, with the part
+/// in view highlighted.
+fn render_minimap(frame: &mut Frame, area: Rect, state: &UIState, window: (f64, f64)) {
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let duration = state.duration.as_secs_f64();
+    let width = area.width as usize;
+    if width == 0 || duration <= 0.0 {
+        return;
+    }
+
+    let playhead = (state.position.as_secs_f64() / duration * width as f64) as usize;
+    for x in 0..width {
+        let (from, to) = (x as f64 / width as f64, (x + 1) as f64 / width as f64);
+        let amplitude = state
+            .waveform
+            .scaled(state.waveform.mix.level_between(from, to));
+        let level = ((amplitude * LEVELS.len() as f32) as usize).min(LEVELS.len() - 1);
+
+        let in_view = to * duration > window.0 && from * duration < window.1;
+        let color = if x == playhead {
+            state.theme.marker
+        } else if in_view {
+            state.theme.highlight
+        } else {
+            state.theme.muted
+        };
+
+        let cell = &mut frame.buffer_mut()[(area.x + x as u16, area.y)];
+        cell.set_char(LEVELS[level]);
+        cell.set_fg(color);
+    }
+}
+
+/// Bit for the dot at (`column`, `row`) within a Braille cell; dots 7 and 8
+/// on the bottom row were added to the pattern later, hence the jump.
+pub fn braille_dot(column: usize, row: usize) -> u8 {
+    const DOTS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+    DOTS[column][row]
+}
+
+fn render_title(frame: &mut Frame, area: Rect, state: &UIState) {
+    let block_title = if state.queue_len > 1 {
+        format!("apz {}/{}", state.queue_index + 1, state.queue_len)
+    } else {
+        "apz".to_string()
     };
 
-    for (i, &amplitude) in bars.iter().enumerate() {
-        let x_pos = i * bar_width;
-        if x_pos >= width {
-            break;
-        }
+    // The status symbol stays put while the rest scrolls.
+    let width = area.width.saturating_sub(4) as usize;
+    let mut line = vec![status_span(state), Span::raw(" ")];
+    line.extend(marquee(title_spans(state), width, state.loaded_at));
 
-        let bar_height = (amplitude * height as f32 * 0.5) as usize;
-        let bar_height = bar_height.min(height);
+    let title = Paragraph::new(Line::from(line)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled(
+                block_title,
+                Style::default()
+                    .fg(state.theme.title)
+                    .add_modifier(Modifier::BOLD),
+            ))
+            .title_bottom(
+                Line::from(Span::styled(
+                    stream_summary(state),
+                    Style::default().fg(state.theme.secondary),
+                ))
+                .right_aligned(),
+            ),
+    );
 
-        for h in 0..bar_height {
-            let y = height.saturating_sub(h + 1);
+    frame.render_widget(title, area);
+}
 
-            let hue_factor = i as f32 / num_bars as f32;
-            let intensity = h as f32 / bar_height.max(1) as f32;
+/// Codec, sample rate, bit depth or bitrate, and channel layout, e.g.
+/// "FLAC 96 kHz 24-bit stereo", with the rate the device is converted to
+/// where it differs.
+fn stream_summary(state: &UIState) -> String {
+    let info = &state.stream_info;
+    let format = &state.live_format;
+    let mut parts: Vec<String> = info.codec.iter().cloned().collect();
+    let sample_rate = format.sample_rate();
+    if sample_rate == 0 {
+        // Nothing decoded yet.
+        return String::new();
+    }
+    parts.push(format_rate(sample_rate));
+    if let Some(bits) = info.bits_per_sample {
+        parts.push(format!("{}-bit", bits));
+    }
+    parts.push(downmix::layout_name(format.channels()));
+    if let Some(bitrate) = info.bitrate {
+        parts.push(format!("{} kbps", bitrate));
+    }
+    if let Some(device_rate) = state.resampler.device_rate()
+        && device_rate != sample_rate
+    {
+        parts.push(format!(
+            "→ {} {}",
+            format_rate(device_rate),
+            state.resampler.quality().name()
+        ));
+    }
+    format!(" {} ", parts.join(" · "))
+}
 
-            let color = if intensity > 0.8 {
-                Color::Red
-            } else if intensity > 0.5 {
-                if hue_factor < 0.33 {
-                    Color::Magenta
-                } else if hue_factor < 0.66 {
-                    waveform_color
-                } else {
-                    Color::Green
-                }
-            } else {
-                waveform_color
-            };
+/// A sample rate in kHz, e.g. "22.05 kHz" or "48 kHz".
+fn format_rate(sample_rate: u32) -> String {
+    let khz = format!("{:.2}", sample_rate as f64 / 1000.0);
+    format!("{} kHz", khz.trim_end_matches('0').trim_end_matches('.'))
+}
 
-            for w in 0..bar_width {
-                let x = x_pos + w;
-                if x < width {
-                    let cell = &mut frame.buffer_mut()[(inner.x + x as u16, inner.y + y as u16)];
-                    cell.set_symbol("█");
-                    cell.set_fg(color);
-                }
+/// Which side of an A/B comparison is playing, the file when it's B, and
+/// how far the louder side has been turned down.
+fn comparison_spans(comparison: &Comparison, theme: Theme) -> Vec<Span<'static>> {
+    let switch = &comparison.switch;
+    let side = switch.side();
+    let label = |label: &'static str, shown: Side| {
+        if side == shown {
+            Span::styled(
+                label,
+                Style::default()
+                    .fg(theme.highlight)
+                    .add_modifier(Modifier::BOLD),
+            )
+        } else {
+            Span::styled(label, Style::default().fg(theme.muted))
+        }
+    };
+    let mut spans = vec![Span::raw("  "), label("A", Side::A), label("B", Side::B)];
+    if side == Side::Diff {
+        spans.push(label(" A−B", Side::Diff));
+    } else if side == Side::B {
+        spans.push(Span::styled(
+            format!(" {}", file_name(&comparison.b)),
+            Style::default().fg(theme.secondary),
+        ));
+    }
+    if switch.is_matched() {
+        for (name, side) in [("A", Side::A), ("B", Side::B)] {
+            let gain = switch.gain(side);
+            if gain < 1.0 {
+                spans.push(Span::styled(
+                    format!("  {} {:+.1} dB", name, 20.0 * gain.log10()),
+                    Style::default().fg(theme.secondary),
+                ));
             }
         }
     }
+    spans
 }
 
-fn render_simple_waveform(frame: &mut Frame, area: Rect, state: &UIState) {
-    let width = area.width.saturating_sub(2) as usize;
-    let waveform_data: Vec<u64> = if state.waveform.samples.len() >= width {
-        state.waveform.samples[..width]
-            .iter()
-            .map(|&v| (v * 100.0) as u64)
-            .collect()
-    } else {
-        let scale = width as f32 / state.waveform.samples.len() as f32;
-        (0..width)
-            .map(|i| {
-                let idx = (i as f32 / scale) as usize;
-                if idx < state.waveform.samples.len() {
-                    (state.waveform.samples[idx] * 100.0) as u64
-                } else {
-                    0
-                }
-            })
-            .collect()
+fn status_span(state: &UIState) -> Span<'static> {
+    let (symbol, color) = match state.state {
+        PlaybackState::Playing => ("▶", state.theme.good),
+        PlaybackState::Paused => ("⏸", state.theme.paused),
+        PlaybackState::Stopped => ("■", state.theme.paused),
     };
+    Span::styled(
+        symbol,
+        Style::default().fg(color).add_modifier(Modifier::BOLD),
+    )
+}
 
-    let waveform_color = match state.state {
-        PlaybackState::Playing => Color::Cyan,
-        PlaybackState::Paused => Color::Yellow,
-    };
+/// The track's title followed by its rating and play count, and whatever
+/// else is worth knowing about the current position: tracker pattern,
+/// chapter, bookmark.
+fn title_spans(state: &UIState) -> Vec<Span<'static>> {
+    let mut spans = vec![Span::styled(
+        state.display_title(),
+        Style::default()
+            .fg(state.theme.track)
+            .add_modifier(Modifier::BOLD),
+    )];
 
-    let sparkline = Sparkline::default()
-        .block(Block::default().borders(Borders::ALL).title("Waveform"))
-        .data(&waveform_data)
-        .style(Style::default().fg(waveform_color));
+    if let Some(comparison) = &state.comparison {
+        spans.extend(comparison_spans(comparison, state.theme));
+    }
 
-    frame.render_widget(sparkline, area);
-}
+    if let Some(rating) = state.stats.rating {
+        let stars = "★".repeat(rating as usize);
+        let unfilled = "☆".repeat((library::MAX_RATING - rating) as usize);
+        spans.push(Span::styled(
+            format!("  {}{}", stars, unfilled),
+            Style::default().fg(state.theme.highlight),
+        ));
+    }
+    if state.stats.plays > 0 {
+        let plural = if state.stats.plays == 1 { "" } else { "s" };
+        spans.push(Span::styled(
+            format!("  {} play{}", state.stats.plays, plural),
+            Style::default().fg(state.theme.muted),
+        ));
+    }
+    if state.stats.gain_db != 0.0 {
+        spans.push(Span::styled(
+            format!("  track {:+} dB", state.stats.gain_db),
+            Style::default().fg(state.theme.secondary),
+        ));
+    }
 
-fn render_enhanced_waveform(frame: &mut Frame, area: Rect, state: &UIState) {
-    let waveform_color = match state.state {
-        PlaybackState::Playing => Color::Cyan,
-        PlaybackState::Paused => Color::Yellow,
-    };
+    if let Some(tracker) = state.tracker {
+        spans.push(Span::styled(
+            format!(
+                "  Order {}/{} · Pattern {} · Row {}",
+                tracker.order + 1,
+                tracker.orders,
+                tracker.pattern,
+                tracker.row
+            ),
+            Style::default().fg(state.theme.secondary),
+        ));
+    }
 
-    let position_secs = state.position.as_secs();
-    let duration_secs = state.duration.as_secs().max(1);
-    let progress_ratio = position_secs as f64 / duration_secs as f64;
+    if let Some(index) = chapters::chapter_at(&state.chapters, state.position) {
+        spans.push(Span::styled(
+            format!(
+                "  [{}/{}] {}",
+                index + 1,
+                state.chapters.len(),
+                state.chapters[index].title
+            ),
+            Style::default().fg(state.theme.secondary),
+        ));
+    }
 
-    let block = Block::default().borders(Borders::ALL).title("Waveform");
-    let inner = block.inner(area);
-    frame.render_widget(block, area);
+    if let Some(bookmark) = state.bookmarks.entries().iter().rev().find(|b| {
+        b.position <= state.position && state.position < b.position + Duration::from_secs(3)
+    }) {
+        spans.push(Span::styled(
+            format!("  ◆ {}", bookmark.label),
+            Style::default().fg(state.theme.highlight),
+        ));
+    }
 
-    let width = inner.width as usize;
-    let height = inner.height as usize;
-    let center = height / 2;
+    // Found while working out the waveform, so they show up once it's done.
+    let waveform = &state.waveform;
+    if waveform.has_dc_offset() {
+        spans.push(Span::styled(
+            format!("  ⚠ DC offset {:.0} dB", 20.0 * waveform.dc_offset.log10()),
+            Style::default().fg(state.theme.warning),
+        ));
+    }
+    if waveform.is_out_of_phase() {
+        spans.push(Span::styled(
+            "  ⚠ out of phase",
+            Style::default().fg(state.theme.warning),
+        ));
+    }
 
-    let waveform_data: Vec<f32> = if state.waveform.samples.len() >= width {
-        state.waveform.samples[..width].to_vec()
-    } else {
-        let scale = width as f32 / state.waveform.samples.len() as f32;
-        (0..width)
-            .map(|i| {
-                let idx = (i as f32 / scale) as usize;
-                if idx < state.waveform.samples.len() {
-                    state.waveform.samples[idx]
-                } else {
-                    0.0
-                }
-            })
-            .collect()
-    };
+    if let Some(solo) = state.stereo.solo() {
+        spans.push(Span::styled(
+            format!("  {} only", solo.name()),
+            Style::default().fg(state.theme.warning),
+        ));
+    } else if state.stereo.is_mono() {
+        spans.push(Span::styled(
+            "  mono",
+            Style::default().fg(state.theme.warning),
+        ));
+    } else if state.stereo.width() != 1.0 {
+        spans.push(Span::styled(
+            format!("  width {:.0}%", state.stereo.width() * 100.0),
+            Style::default().fg(state.theme.secondary),
+        ));
+    }
 
-    let cursor_pos = (progress_ratio * width as f64) as usize;
+    if let Some(gain_db) = state.envelope.gain_db_at(state.position) {
+        spans.push(Span::styled(
+            format!("  gain {:+.1} dB", gain_db),
+            Style::default().fg(state.theme.secondary),
+        ));
+    }
 
-    for (x, &amplitude) in waveform_data.iter().enumerate() {
-        let bar_height = (amplitude * center as f32) as usize;
-        let color = if x <= cursor_pos {
-            waveform_color
-        } else {
-            Color::DarkGray
+    if state.metronome.is_enabled() {
+        let (text, color) = match state.beats {
+            Beats::Found(grid) => (format!("  ♩ {:.1} BPM", grid.bpm()), state.theme.secondary),
+            Beats::NotFound => ("  ♩ no beat, tap ;".to_string(), state.theme.warning),
+            Beats::Unknown | Beats::Finding => {
+                ("  ♩ finding the beat…".to_string(), state.theme.muted)
+            }
         };
+        spans.push(Span::styled(text, Style::default().fg(color)));
+    }
 
-        for y in 0..bar_height.min(center) {
-            let top_y = center.saturating_sub(y + 1);
-            let bottom_y = center + y;
+    let transpose = state.resampler.transpose();
+    if transpose != 0 {
+        spans.push(Span::styled(
+            format!("  key {:+}", transpose),
+            Style::default().fg(state.theme.secondary),
+        ));
+    }
 
-            if top_y < height {
-                let cell = &mut frame.buffer_mut()[(inner.x + x as u16, inner.y + top_y as u16)];
-                cell.set_symbol("█");
-                cell.set_fg(color);
-            }
-            if bottom_y < height {
-                let cell = &mut frame.buffer_mut()[(inner.x + x as u16, inner.y + bottom_y as u16)];
-                cell.set_symbol("█");
-                cell.set_fg(color);
-            }
-        }
+    if !state.auto_advance {
+        spans.push(Span::styled(
+            "  manual advance",
+            Style::default().fg(state.theme.secondary),
+        ));
+    }
+
+    if state.stop_after_track {
+        spans.push(Span::styled(
+            "  ■ stop after this track",
+            Style::default().fg(state.theme.highlight),
+        ));
     }
 
-    if center < height {
-        for x in 0..width {
-            let cell = &mut frame.buffer_mut()[(inner.x + x as u16, inner.y + center as u16)];
-            cell.set_symbol("─");
-            cell.set_fg(Color::DarkGray);
+    spans
+}
+
+/// How long a line that doesn't fit waits before scrolling by another
+/// character. Matches the slowest redraw rate, so it moves steadily even
+/// while paused.
+const MARQUEE_STEP: Duration = Duration::from_millis(500);
+const MARQUEE_GAP: &str = "   ";
+
+/// Scrolls `spans` one character per [`MARQUEE_STEP`] since `since` if
+/// they're wider than `width`, wrapping around after a gap.
+fn marquee(spans: Vec<Span<'static>>, width: usize, since: Instant) -> Vec<Span<'static>> {
+    if spans.iter().map(Span::width).sum::<usize>() <= width {
+        return spans;
+    }
+    if width == 0 {
+        return Vec::new();
+    }
+
+    let mut chars: Vec<(char, Style)> = spans
+        .iter()
+        .flat_map(|span| span.content.chars().map(move |c| (c, span.style)))
+        .collect();
+    chars.extend(MARQUEE_GAP.chars().map(|c| (c, Style::default())));
+    let offset = (since.elapsed().as_millis() / MARQUEE_STEP.as_millis()) as usize % chars.len();
+
+    let mut scrolled = Vec::new();
+    let mut used = 0;
+    for &(c, style) in chars.iter().cycle().skip(offset) {
+        let span = Span::styled(c.to_string(), style);
+        used += span.width();
+        if used > width {
+            break;
         }
+        scrolled.push(span);
     }
+    scrolled
 }
 
-fn render_title(frame: &mut Frame, area: Rect, state: &UIState) {
-    let status_symbol = match state.state {
-        PlaybackState::Playing => "▶",
-        PlaybackState::Paused => "⏸",
-    };
+fn render_progress(frame: &mut Frame, area: Rect, state: &UIState) {
+    let (label, ratio) = progress(state);
+    let gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title("Progress"))
+        .gauge_style(Style::default().fg(state.theme.track).bg(state.theme.muted))
+        .label(label)
+        .ratio(ratio)
+        .use_unicode(true);
 
-    let status_color = match state.state {
-        PlaybackState::Playing => Color::Green,
-        PlaybackState::Paused => Color::Yellow,
+    let progress = progress_area(area, state);
+    frame.render_widget(gauge, progress);
+    render_progress_marks(frame, progress, state);
+
+    let Some(buffer) = state.buffer else {
+        return;
+    };
+    let meter = Rect {
+        x: progress.right(),
+        width: area.width - progress.width,
+        ..area
     };
 
-    let title = Paragraph::new(Line::from(vec![
-        Span::styled(
-            status_symbol,
-            Style::default()
-                .fg(status_color)
-                .add_modifier(Modifier::BOLD),
-        ),
-        Span::raw(" "),
+    let block = Block::default().borders(Borders::ALL).title("Buffer");
+    let inner = block.inner(meter);
+    frame.render_widget(block, meter);
+    let label = format!("{:>3.0}% ", buffer.fill * 100.0);
+    let width = (inner.width as usize).saturating_sub(label.len());
+    let filled = (buffer.fill * width as f32).round() as usize;
+    let color = if buffer.waiting {
+        state.theme.warning
+    } else {
+        state.theme.good
+    };
+    let meter = Line::from(vec![
+        Span::styled(label, Style::default().fg(state.theme.secondary)),
+        Span::styled("█".repeat(filled), Style::default().fg(color)),
         Span::styled(
-            &state.filename,
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
+            "·".repeat(width - filled),
+            Style::default().fg(state.theme.muted),
         ),
-    ]))
-    .block(
-        Block::default().borders(Borders::ALL).title(Span::styled(
-            "apz",
-            Style::default()
-                .fg(Color::Magenta)
-                .add_modifier(Modifier::BOLD),
-        )),
-    );
+    ]);
+    frame.render_widget(Paragraph::new(meter), inner);
+}
 
-    frame.render_widget(title, area);
+/// The part of `area` the progress bar takes, leaving room for a stream's
+/// buffer meter.
+fn progress_area(area: Rect, state: &UIState) -> Rect {
+    if state.buffer.is_none() {
+        return area;
+    }
+    let [progress, _] =
+        Layout::horizontal([Constraint::Min(10), Constraint::Length(BUFFER_METER_WIDTH)])
+            .areas(area);
+    progress
 }
 
-fn render_progress(frame: &mut Frame, area: Rect, state: &UIState) {
-    let position_secs = state.position.as_secs();
-    let duration_secs = state.duration.as_secs().max(1);
-    let ratio = (position_secs as f64 / duration_secs as f64).min(1.0);
+/// Chapter starts, bookmarks and the loop's in and out points, with how
+/// each is ticked under the progress bar. Later ones are drawn over
+/// earlier ones in the same column.
+fn progress_marks(state: &UIState) -> Vec<(Duration, &'static str, Color)> {
+    let mut marks: Vec<_> = state
+        .chapters
+        .iter()
+        .filter(|chapter| !chapter.start.is_zero())
+        .map(|chapter| (chapter.start, "┴", state.theme.secondary))
+        .collect();
+    marks.extend(
+        state
+            .bookmarks
+            .entries()
+            .iter()
+            .map(|bookmark| (bookmark.position, "▲", state.theme.marker)),
+    );
+    for (mark, symbol) in [(state.clip_in, "["), (state.clip_out, "]")] {
+        if let Some(mark) = mark {
+            marks.push((mark, symbol, state.theme.good));
+        }
+    }
+    marks
+}
 
-    let position_str = format_duration(state.position);
-    let duration_str = format_duration(state.duration);
-    let label = format!("{} / {}", position_str, duration_str);
+/// Screen column of `position` along the progress bar inside `inner`.
+fn mark_column(inner: Rect, position: Duration, duration: Duration) -> Option<u16> {
+    if duration.is_zero() || inner.width == 0 || position > duration {
+        return None;
+    }
+    let ratio = position.as_secs_f64() / duration.as_secs_f64();
+    Some(inner.x + ((ratio * inner.width as f64) as u16).min(inner.width - 1))
+}
 
-    let gauge = Gauge::default()
-        .block(Block::default().borders(Borders::ALL).title("Progress"))
-        .gauge_style(Style::default().fg(Color::Cyan).bg(Color::DarkGray))
-        .label(label)
-        .ratio(ratio);
+/// Ticks the chapters, bookmarks and loop points into the bottom border of
+/// the progress bar in `area`.
+fn render_progress_marks(frame: &mut Frame, area: Rect, state: &UIState) {
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    if inner.height == 0 {
+        return;
+    }
+    for (position, symbol, color) in progress_marks(state) {
+        if let Some(x) = mark_column(inner, position, state.duration) {
+            let cell = &mut frame.buffer_mut()[(x, area.bottom() - 1)];
+            cell.set_symbol(symbol);
+            cell.set_fg(color);
+        }
+    }
+}
+
+/// The clock shown on the progress bar, and how far through the track
+/// playback is.
+fn progress(state: &UIState) -> (String, f64) {
+    let ratio = (state.position.as_secs_f64() / state.duration.as_secs_f64().max(1.0)).min(1.0);
 
-    frame.render_widget(gauge, area);
+    let precision = state.clock_precision();
+    let position_str = if state.show_remaining && !state.duration.is_zero() {
+        format!(
+            "-{}",
+            format_time(state.duration.saturating_sub(state.position), precision)
+        )
+    } else {
+        format_time(state.position, precision)
+    };
+    // Streams of unknown length, such as stdin, only show elapsed time.
+    let mut label = if state.duration.is_zero() {
+        position_str
+    } else {
+        format!(
+            "{} / {}",
+            position_str,
+            format_time(state.duration, precision)
+        )
+    };
+    if state.buffer.is_some_and(|buffer| buffer.waiting) {
+        label.push_str("  Buffering…");
+    }
+    if let Some(clip) = state.clip() {
+        label.push_str(&format!(
+            "  ✂ {}–{}",
+            format_time(clip.start, precision),
+            format_time(clip.end, precision)
+        ));
+    }
+    if let Some(practice) = state.practice {
+        label.push_str(&format!(
+            "  ⟲ {:.0}% ×{}{}",
+            practice.tempo * 100.0,
+            practice.repetitions,
+            if practice.clean { "" } else { " (again)" }
+        ));
+    }
+    let ratio = if state.duration.is_zero() { 0.0 } else { ratio };
+    (label, ratio)
 }
 
 fn render_volume(frame: &mut Frame, area: Rect, state: &UIState) {
@@ -319,61 +2313,66 @@ fn render_volume(frame: &mut Frame, area: Rect, state: &UIState) {
     let label = format!("{}%", volume_percent);
 
     let volume_color = if state.volume > 0.7 {
-        Color::Green
+        state.theme.good
     } else if state.volume > 0.3 {
-        Color::Yellow
+        state.theme.warning
     } else {
-        Color::Red
+        state.theme.danger
     };
 
     let gauge = Gauge::default()
         .block(Block::default().borders(Borders::ALL).title("Volume"))
-        .gauge_style(Style::default().fg(volume_color).bg(Color::DarkGray))
+        .gauge_style(Style::default().fg(volume_color).bg(state.theme.muted))
         .label(label)
         .ratio(state.volume as f64);
 
-    frame.render_widget(gauge, area);
+    if !state.compressor.is_enabled() {
+        frame.render_widget(gauge, area);
+        return;
+    }
+    let [volume, compressor] = Layout::horizontal([
+        Constraint::Min(10),
+        Constraint::Length(COMPRESSOR_METER_WIDTH),
+    ])
+    .areas(area);
+    frame.render_widget(gauge, volume);
+
+    // Gain reduction, growing from the right the way the gain goes down.
+    let reduction = state.compressor.reduction_db();
+    let block = Block::default().borders(Borders::ALL).title("Compressor");
+    let inner = block.inner(compressor);
+    frame.render_widget(block, compressor);
+    let label = format!("-{:.1} dB ", reduction);
+    let width = (inner.width as usize).saturating_sub(label.len());
+    let filled = ((reduction / COMPRESSOR_METER_DB).min(1.0) * width as f32).round() as usize;
+    let meter = Line::from(vec![
+        Span::styled(label, Style::default().fg(state.theme.secondary)),
+        Span::styled(
+            "·".repeat(width - filled),
+            Style::default().fg(state.theme.muted),
+        ),
+        Span::styled("█".repeat(filled), Style::default().fg(state.theme.warning)),
+    ]);
+    frame.render_widget(Paragraph::new(meter), inner);
 }
 
-fn render_controls(frame: &mut Frame, area: Rect) {
+fn render_controls(frame: &mut Frame, area: Rect, theme: &Theme) {
+    let key = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
     let controls = Paragraph::new(vec![
         Line::from(vec![
-            Span::styled(
-                "[Space]",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            ),
+            Span::styled("[Space]", key),
             Span::raw(" play/pause  "),
-            Span::styled(
-                "[Q]",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            ),
+            Span::styled("[Q]", key),
             Span::raw(" quit  "),
-            Span::styled(
-                "[R]",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            ),
+            Span::styled("[R]", key),
             Span::raw(" restart"),
         ]),
         Line::from(vec![
-            Span::styled(
-                "[←/→]",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            ),
+            Span::styled("[←/→]", key),
             Span::raw(" seek ±5s  "),
-            Span::styled(
-                "[↑/↓]",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            ),
+            Span::styled("[↑/↓]", key),
             Span::raw(" volume ±5%"),
         ]),
     ])
@@ -382,9 +2381,407 @@ fn render_controls(frame: &mut Frame, area: Rect) {
     frame.render_widget(controls, area);
 }
 
+/// Badges for the modes that change what's heard, lit up when they're on,
+/// then where the audio is going.
+fn render_status(frame: &mut Frame, area: Rect, state: &UIState) {
+    let theme = &state.theme;
+    let badge = |label: String, on: bool| {
+        if on {
+            Span::styled(
+                format!(" {} ", label),
+                Style::default()
+                    .fg(theme.highlight)
+                    .add_modifier(Modifier::REVERSED),
+            )
+        } else {
+            Span::styled(format!(" {} ", label), Style::default().fg(theme.muted))
+        }
+    };
+
+    let mut badges = vec![badge(
+        "loop".to_string(),
+        state.practice.is_some() || state.roll.is_some(),
+    )];
+    badges.push(match state.shuffle {
+        Some(ShuffleMode::Random) => badge("shuffle".to_string(), true),
+        Some(ShuffleMode::Smart) => badge("smart shuffle".to_string(), true),
+        None => badge("shuffle".to_string(), false),
+    });
+    let gain_db = state.stats.gain_db;
+    badges.push(if gain_db != 0.0 {
+        badge(format!("gain {:+.1} dB", gain_db), true)
+    } else {
+        badge("gain".to_string(), false)
+    });
+    let speed = state.resampler.pitch() * state.resampler.tempo();
+    badges.push(badge(format!("{:.2}×", speed), (speed - 1.0).abs() > 0.001));
+    badges.push(badge("eq".to_string(), !state.equalizer.is_flat()));
+    if state.stop_after_track {
+        badges.push(badge("stop after".to_string(), true));
+    } else if !state.auto_advance {
+        badges.push(badge("manual".to_string(), true));
+    }
+    if let Some(buffer) = state.buffer {
+        let color = if buffer.waiting {
+            theme.danger
+        } else if buffer.fill < 0.25 {
+            theme.warning
+        } else {
+            theme.good
+        };
+        badges.push(Span::styled(
+            format!(" buffer {:.0}% ", buffer.fill * 100.0),
+            Style::default().fg(color).add_modifier(Modifier::REVERSED),
+        ));
+    }
+
+    let mut line = Vec::new();
+    for badge in badges {
+        line.push(badge);
+        line.push(Span::raw(" "));
+    }
+    line.push(Span::styled(
+        format!("→ {}", state.output),
+        Style::default().fg(theme.secondary),
+    ));
+    frame.render_widget(Paragraph::new(Line::from(line)), area);
+}
+
+fn render_error(frame: &mut Frame, area: Rect, error: &str, theme: &Theme) {
+    let message = Paragraph::new(Line::from(Span::styled(
+        error,
+        Style::default().fg(theme.danger),
+    )))
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.danger))
+            .title("Error (Esc to dismiss)"),
+    );
+
+    frame.render_widget(message, area);
+}
+
+/// A toast in the top right corner of the screen, just under row `top`.
+fn render_toast(frame: &mut Frame, top: u16, toast: &Toast, theme: &Theme) {
+    let screen = frame.area();
+    let width = (toast.text.chars().count() as u16 + 4).min(screen.width);
+    let area = Rect::new(screen.right() - width, top, width, 3).intersection(screen);
+    let color = if toast.error {
+        theme.danger
+    } else {
+        theme.highlight
+    };
+    let message = Paragraph::new(Line::from(toast.text.as_str())).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(color))
+            .padding(Padding::horizontal(1)),
+    );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(message, area);
+}
+
+fn render_prompt(frame: &mut Frame, area: Rect, prompt: &Prompt, theme: &Theme) {
+    let input = Paragraph::new(Line::from(vec![
+        Span::raw(&prompt.input),
+        Span::styled("█", Style::default().fg(theme.highlight)),
+    ]))
+    .block(Block::default().borders(Borders::ALL).title(format!(
+        "{} (Enter to confirm, Esc to cancel)",
+        prompt.label()
+    )));
+
+    frame.render_widget(input, area);
+}
+
+/// A box over the middle of `area`, tall enough for `rows` lines if there's
+/// room for them.
+fn popup(area: Rect, rows: usize) -> Rect {
+    let width = area.width.saturating_sub(8).min(80);
+    let height = (rows as u16)
+        .saturating_add(2)
+        .min(area.height)
+        .max(area.height.min(3));
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// The queue, with the current track marked, over the middle of the screen.
+fn render_queue(frame: &mut Frame, area: Rect, panel: &QueuePanel, state: &UIState) {
+    let area = popup(area, state.queue.len());
+    let title = if panel.marked.is_empty() {
+        "Queue".to_string()
+    } else {
+        format!("Queue · {} marked", panel.marked.len())
+    };
+    let (border, keys) = if panel.focused {
+        (
+            state.theme.highlight,
+            " Space mark · n play next · t top · d remove · J/K move · Tab player ",
+        )
+    } else {
+        (state.theme.muted, " Tab queue · Esc close ")
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(border))
+        .title(title)
+        .title_bottom(Line::from(keys).right_aligned());
+    frame.render_widget(Clear, area);
+
+    let number_width = state.queue.len().to_string().len();
+    let items: Vec<Line> = state
+        .queue
+        .iter()
+        .enumerate()
+        .map(|(index, path)| {
+            let current = index == state.queue_index;
+            let marker = if current { "▶ " } else { "  " };
+            let mark = if panel.marked.contains(&index) {
+                "● "
+            } else {
+                "  "
+            };
+            let style = if current {
+                Style::default()
+                    .fg(state.theme.track)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(marker, Style::default().fg(state.theme.good)),
+                Span::styled(mark, Style::default().fg(state.theme.highlight)),
+                Span::styled(
+                    format!("{:>width$} ", index + 1, width = number_width),
+                    Style::default().fg(state.theme.muted),
+                ),
+                Span::styled(file_name(path), style),
+            ])
+        })
+        .collect();
+    let list = List::new(items).block(block).highlight_style(
+        Style::default()
+            .fg(state.theme.highlight)
+            .add_modifier(Modifier::REVERSED),
+    );
+
+    // The selection only shows while the keys move it.
+    let selected = panel.focused.then(|| panel.selected(state.queue.len()));
+    let mut list_state = ListState::default().with_selected(selected);
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+/// The question whether to move the current track, over the middle of the
+/// screen.
+fn render_file_action(frame: &mut Frame, area: Rect, action: &FileAction, state: &UIState) {
+    let area = popup(area, 1);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(state.theme.warning))
+        .title("Move file? (y/n)");
+    let question = Line::from(vec![
+        Span::raw(format!("{} to ", state.filename)),
+        Span::styled(
+            action.describe(),
+            Style::default().fg(state.theme.highlight),
+        ),
+    ]);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(question).block(block), area);
+}
+
+/// The tag fields over the middle of the screen, with a cursor in the one
+/// being edited, or the question whether to write them.
+fn render_tag_editor(frame: &mut Frame, area: Rect, editor: &TagEditor, state: &UIState) {
+    let area = popup(area, TAG_FIELDS.len() + 2);
+    let title = if editor.confirming {
+        format!("Write tags to {}? (y/n)", state.filename)
+    } else {
+        "Edit tags (Tab next field, Enter save, Esc cancel)".to_string()
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(if editor.confirming {
+            state.theme.warning
+        } else {
+            state.theme.highlight
+        }))
+        .title(title);
+    frame.render_widget(Clear, area);
+
+    let mut lines: Vec<Line> = TAG_FIELDS
+        .iter()
+        .zip(&editor.values)
+        .enumerate()
+        .map(|(index, (name, value))| {
+            let mut line = vec![
+                Span::styled(
+                    format!("{:>7}: ", name),
+                    Style::default().fg(state.theme.secondary),
+                ),
+                Span::raw(value.clone()),
+            ];
+            if index == editor.focused && !editor.confirming {
+                line.push(Span::styled(
+                    "█",
+                    Style::default().fg(state.theme.highlight),
+                ));
+            }
+            Line::from(line)
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "  ID3v2 for MP3, Vorbis comments for FLAC, Ogg and Opus",
+        Style::default().fg(state.theme.muted),
+    )));
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// A box over the middle of the screen listing recently played tracks.
+fn render_recent(frame: &mut Frame, area: Rect, recent: &RecentList, theme: &Theme) {
+    let area = popup(area, recent.plays.len());
+    let width = area.width;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.highlight))
+        .title("Recent (Enter to play, Esc to close)");
+    frame.render_widget(Clear, area);
+
+    if recent.plays.is_empty() {
+        let empty = Paragraph::new(Span::styled(
+            "Nothing played yet",
+            Style::default().fg(theme.muted),
+        ));
+        frame.render_widget(empty.block(block), area);
+        return;
+    }
+
+    let name_width = (width as usize).saturating_sub(2 + 17);
+    let items: Vec<Line> = recent
+        .plays
+        .iter()
+        .map(|play| {
+            let name: String = file_name(&play.path).chars().take(name_width).collect();
+            Line::from(vec![
+                Span::raw(format!("{:<width$}", name, width = name_width)),
+                Span::styled(
+                    format!(" {:>10}", ago(play.played_at)),
+                    Style::default().fg(theme.secondary),
+                ),
+                Span::styled(
+                    format!(" {:>4.0}%", play.completion * 100.0),
+                    Style::default().fg(theme.muted),
+                ),
+            ])
+        })
+        .collect();
+    let list = List::new(items).block(block).highlight_style(
+        Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::REVERSED),
+    );
+
+    let mut list_state = ListState::default().with_selected(Some(recent.selected));
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+/// How long ago `time` was, roughly, e.g. "5 min ago".
+fn ago(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs();
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} d ago", seconds / 86400),
+    }
+}
+
+fn render_podcasts(frame: &mut Frame, area: Rect, podcasts: &PodcastList, theme: &Theme) {
+    let area = popup(area, podcasts.episodes.len());
+    let width = area.width;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.highlight))
+        .title(format!("{} (Enter to play, Esc to close)", podcasts.title));
+    frame.render_widget(Clear, area);
+
+    if podcasts.episodes.is_empty() {
+        let empty = Paragraph::new(Span::styled(
+            "No episodes in this feed",
+            Style::default().fg(theme.muted),
+        ));
+        frame.render_widget(empty.block(block), area);
+        return;
+    }
+
+    let title_width = (width as usize).saturating_sub(2 + 2 + 12 + 7);
+    let items: Vec<Line> = podcasts
+        .episodes
+        .iter()
+        .map(|(episode, progress)| {
+            let title: String = episode.title.chars().take(title_width).collect();
+            let (mark, title_style) = if progress.listened {
+                ("✓ ", Style::default().fg(theme.muted))
+            } else {
+                ("  ", Style::default())
+            };
+            let position = if progress.listened || progress.position.is_zero() {
+                String::new()
+            } else {
+                format_duration(progress.position)
+            };
+            Line::from(vec![
+                Span::styled(mark, Style::default().fg(theme.secondary)),
+                Span::styled(
+                    format!("{:<width$}", title, width = title_width),
+                    title_style,
+                ),
+                Span::styled(
+                    format!(" {:>11}", episode.published.as_deref().unwrap_or("")),
+                    Style::default().fg(theme.secondary),
+                ),
+                Span::styled(
+                    format!(" {:>6}", position),
+                    Style::default().fg(theme.muted),
+                ),
+            ])
+        })
+        .collect();
+    let list = List::new(items).block(block).highlight_style(
+        Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::REVERSED),
+    );
+
+    let mut list_state = ListState::default().with_selected(Some(podcasts.selected));
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
 fn format_duration(duration: Duration) -> String {
+    format_time(duration, Precision::Seconds)
+}
+
+pub fn format_time(duration: Duration, precision: Precision) -> String {
     let secs = duration.as_secs();
     let minutes = secs / 60;
     let seconds = secs % 60;
-    format!("{:02}:{:02}", minutes, seconds)
+    let millis = duration.subsec_millis();
+    match precision {
+        Precision::Seconds => format!("{:02}:{:02}", minutes, seconds),
+        Precision::Tenths => format!("{:02}:{:02}.{}", minutes, seconds, millis / 100),
+        Precision::Millis => format!("{:02}:{:02}.{:03}", minutes, seconds, millis),
+    }
 }
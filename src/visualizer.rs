@@ -0,0 +1,127 @@
+//! What the visualizer panel shows while the spectrum analyzer runs. Each
+//! visualization implements [`Visualizer`] and is listed in [`REGISTRY`],
+//! which `W` and `:visualizer` cycle through; adding one takes a module
+//! and a line there.
+
+use ratatui::{Frame, layout::Rect};
+
+use crate::fire::Fire;
+use crate::rain::Rain;
+use crate::scope::Scope;
+use crate::spectrum::Spectrum;
+use crate::theme::Theme;
+use crate::ui::{self, SpectrumLayout};
+
+/// Audio frames mixed into [`Scene::samples`], about 20 ms at 48 kHz.
+const SCENE_SAMPLES: usize = 1024;
+
+/// What a visualizer is given to draw each frame.
+pub struct Scene<'a> {
+    pub spectrum: &'a Spectrum,
+    /// Levels from the latest analysis, low frequencies first, each from
+    /// 0.0 to 1.0.
+    pub bars: Vec<f32>,
+    /// The latest audio mixed to mono, oldest first, or empty before there's
+    /// been enough.
+    pub samples: Vec<f32>,
+    pub theme: &'a Theme,
+    pub layout: SpectrumLayout,
+    /// Whether audio is playing, rather than paused.
+    pub active: bool,
+}
+
+impl<'a> Scene<'a> {
+    pub fn capture(
+        spectrum: &'a Spectrum,
+        theme: &'a Theme,
+        layout: SpectrumLayout,
+        active: bool,
+    ) -> Self {
+        let ring = spectrum.ring();
+        let channels = ring.channels().max(1);
+        let mut samples = vec![0.0; SCENE_SAMPLES];
+        let mut channel_samples = vec![0.0; SCENE_SAMPLES];
+        for channel in 0..channels {
+            if !ring.latest(channel, &mut channel_samples) {
+                samples.clear();
+                break;
+            }
+            for (sample, &channel_sample) in samples.iter_mut().zip(&channel_samples) {
+                *sample += channel_sample / channels as f32;
+            }
+        }
+        Self {
+            spectrum,
+            bars: spectrum.bars(),
+            samples,
+            theme,
+            layout,
+            active,
+        }
+    }
+}
+
+pub trait Visualizer {
+    /// Name it's picked by, in `--visualization` and `:visualizer`.
+    fn name(&self) -> &'static str;
+
+    /// Draws the latest frame into `area`, border and all. Called once per
+    /// frame drawn, so anything animated can step on from the last.
+    fn render(&mut self, frame: &mut Frame, area: Rect, scene: &Scene);
+}
+
+type Create = fn() -> Box<dyn Visualizer>;
+
+/// Every visualization, in the order they're cycled through.
+const REGISTRY: &[(&str, Create)] = &[
+    ("spectrum", || Box::new(Bars)),
+    ("scope", || Box::new(Scope)),
+    ("rain", || Box::new(Rain::new())),
+    ("fire", || Box::new(Fire::new())),
+];
+
+pub fn names() -> Vec<&'static str> {
+    REGISTRY.iter().map(|(name, _)| *name).collect()
+}
+
+pub fn named(name: &str) -> Option<Box<dyn Visualizer>> {
+    REGISTRY
+        .iter()
+        .find(|(registered, _)| *registered == name)
+        .map(|(_, create)| create())
+}
+
+/// The first registered, shown unless another's picked.
+pub fn first() -> Box<dyn Visualizer> {
+    (REGISTRY[0].1)()
+}
+
+/// The one after `name`, going back round to the first.
+pub fn next(name: &str) -> Box<dyn Visualizer> {
+    let index = REGISTRY
+        .iter()
+        .position(|(registered, _)| *registered == name)
+        .map_or(0, |index| (index + 1) % REGISTRY.len());
+    (REGISTRY[index].1)()
+}
+
+/// The spectrum analyzer's bars.
+struct Bars;
+
+impl Visualizer for Bars {
+    fn name(&self) -> &'static str {
+        "spectrum"
+    }
+
+    fn render(&mut self, frame: &mut Frame, area: Rect, scene: &Scene) {
+        ui::render_spectrum_bars(
+            frame,
+            area,
+            scene.theme,
+            scene.layout,
+            scene.active,
+            scene.spectrum,
+            &scene.bars,
+        );
+    }
+}
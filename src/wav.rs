@@ -0,0 +1,61 @@
+//! Writes 16-bit PCM WAV files, for exporting audio.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Bytes before the samples: the RIFF header, `fmt ` chunk and `data`
+/// chunk header.
+const HEADER_LENGTH: u32 = 44;
+
+/// Streams samples to a file. The sizes in the header are filled in by
+/// [`WavWriter::finish`], once they're known.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    data_length: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        Self::new(File::create(path)?, sample_rate, channels)
+    }
+
+    /// Writes to `file`, already opened.
+    pub fn new(file: File, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let mut file = BufWriter::new(file);
+        let block_align = channels * 2;
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            file,
+            data_length: 0,
+        })
+    }
+
+    pub fn write(&mut self, sample: i16) -> io::Result<()> {
+        if self.data_length > u32::MAX - HEADER_LENGTH - 2 {
+            return Err(io::Error::other("too long for a WAV file"));
+        }
+        self.data_length += 2;
+        self.file.write_all(&sample.to_le_bytes())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(HEADER_LENGTH - 8 + self.data_length).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_length.to_le_bytes())?;
+        self.file.flush()
+    }
+}
@@ -1,96 +1,314 @@
-use rodio::{Decoder, Source};
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use rodio::Source;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
+use crate::decoder;
+use crate::error::ApzError;
+
+/// Buckets computed for the sparkline unless configured otherwise.
+const DEFAULT_RESOLUTION: usize = 100;
+/// Buckets computed for the enhanced waveform: far more than fit on screen,
+/// so that zooming in still shows detail.
+const ENHANCED_RESOLUTION: usize = 4000;
+pub const REDUCTIONS: [&str; 3] = ["peak", "mean", "rms"];
+/// Quietest level shown on the dB scale, relative to the loudest peak.
+const DB_FLOOR: f32 = -60.0;
+/// Average level of a channel, as a fraction of full scale, past which it's
+/// reported as having a DC offset: about -46 dBFS.
+const DC_OFFSET_WARNING: f32 = 0.005;
+/// Correlation between the channels below which they're reported as out of
+/// phase: at -1 they cancel out completely when played in mono.
+const PHASE_WARNING: f32 = -0.5;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WaveformConfig {
+    /// Taller Braille waveform with a playhead instead of a sparkline.
+    pub enhanced: bool,
+    /// Left channel above the center line and right below it, in the
+    /// enhanced view.
+    pub stereo: bool,
+    /// Show levels in decibels, so quiet passages aren't flattened.
+    pub db_scale: bool,
+    /// Buckets the track is split into, overriding the default for the view.
+    pub resolution: Option<usize>,
+    /// How buckets are reduced, overriding the default for the view.
+    pub reduction: Option<Reduction>,
+}
+
+impl WaveformConfig {
+    /// The sparkline shows the average level, as it always has; the
+    /// enhanced view outlines the peaks, with the RMS band inside.
+    pub fn reduction(&self) -> Reduction {
+        self.reduction.unwrap_or(if self.enhanced {
+            Reduction::Peak
+        } else {
+            Reduction::Mean
+        })
+    }
+}
+
+/// How the samples in a bucket are reduced to a single level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reduction {
+    /// Loudest sample.
+    Peak,
+    /// Average absolute level.
+    Mean,
+    Rms,
+}
+
+impl Reduction {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "peak" => Some(Self::Peak),
+            "mean" => Some(Self::Mean),
+            "rms" => Some(Self::Rms),
+            _ => None,
+        }
+    }
+}
+
+/// Levels over successive stretches of a track.
+#[derive(Clone, Default)]
+pub struct Envelope {
+    /// Each bucket reduced with the configured [`Reduction`].
+    pub levels: Vec<f32>,
+    pub rms: Vec<f32>,
+}
+
+impl Envelope {
+    fn flat(width: usize) -> Self {
+        Self {
+            levels: vec![0.0; width],
+            rms: vec![0.0; width],
+        }
+    }
+
+    /// Highest level between two points in the track, given as fractions of
+    /// its length.
+    pub fn level_between(&self, from: f64, to: f64) -> f32 {
+        max_between(&self.levels, from, to)
+    }
+
+    /// Highest RMS level between two points in the track.
+    pub fn rms_between(&self, from: f64, to: f64) -> f32 {
+        max_between(&self.rms, from, to)
+    }
+
+    fn scale(&mut self, factor: f32) {
+        for level in self.levels.iter_mut().chain(self.rms.iter_mut()) {
+            *level *= factor;
+        }
+    }
+}
+
+fn max_between(levels: &[f32], from: f64, to: f64) -> f32 {
+    let len = levels.len();
+    if len == 0 {
+        return 0.0;
+    }
+    let start = ((from * len as f64) as usize).min(len - 1);
+    let end = ((to * len as f64).ceil() as usize).clamp(start + 1, len);
+    levels[start..end].iter().copied().fold(0.0, f32::max)
+}
+
+/// Envelopes of a track, all scaled so the loudest level is 1.0.
 #[derive(Clone)]
 pub struct WaveformData {
-    pub samples: Vec<f32>,
-    pub enhanced: bool,
+    /// All channels together.
+    pub mix: Envelope,
+    /// The first two channels; both are the mix for mono tracks.
+    pub left: Envelope,
+    pub right: Envelope,
+    pub config: WaveformConfig,
+    /// Length of the track in seconds, or 0 for a placeholder.
+    pub duration: f64,
+    /// Level the envelopes were scaled down from, so waveforms can be
+    /// brought back to a common scale.
+    pub peak: f32,
+    /// Largest average level of any channel, which should be close to 0.
+    pub dc_offset: f32,
+    /// Correlation between the first two channels over the whole track,
+    /// from -1 to 1; None for mono or silent tracks.
+    pub correlation: Option<f32>,
 }
 
 impl WaveformData {
-    pub fn new(samples: Vec<f32>, enhanced: bool) -> Self {
-        Self { samples, enhanced }
+    /// A silent placeholder, for when there's nothing to show yet.
+    pub fn flat(width: usize, config: WaveformConfig) -> Self {
+        Self {
+            mix: Envelope::flat(width),
+            left: Envelope::flat(width),
+            right: Envelope::flat(width),
+            config,
+            duration: 0.0,
+            peak: 0.0,
+            dc_offset: 0.0,
+            correlation: None,
+        }
+    }
+
+    pub fn has_dc_offset(&self) -> bool {
+        self.dc_offset > DC_OFFSET_WARNING
+    }
+
+    pub fn is_out_of_phase(&self) -> bool {
+        self.correlation
+            .is_some_and(|correlation| correlation < PHASE_WARNING)
+    }
+
+    /// Height to draw `level` at, from 0.0 to 1.0.
+    pub fn scaled(&self, level: f32) -> f32 {
+        if !self.config.db_scale {
+            return level;
+        }
+        if level <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - 20.0 * level.log10() / DB_FLOOR).clamp(0.0, 1.0)
     }
 }
 
+/// Generates the waveform on a background thread, since decoding a whole
+/// file takes a while. A file that can't be decoded gets a flat waveform.
+pub fn generate_in_background(path: PathBuf, config: WaveformConfig) -> Receiver<WaveformData> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let waveform = generate_waveform(&path, config)
+            .unwrap_or_else(|_| WaveformData::flat(DEFAULT_RESOLUTION, config));
+        sender.send(waveform).ok();
+    });
+    receiver
+}
+
 pub fn generate_waveform<P: AsRef<Path>>(
     path: P,
-    target_width: usize,
-    enhanced: bool,
-) -> Result<WaveformData, Box<dyn std::error::Error>> {
-    let width = if enhanced {
-        target_width * 2
+    config: WaveformConfig,
+) -> Result<WaveformData, ApzError> {
+    let width = config.resolution.unwrap_or(if config.enhanced {
+        ENHANCED_RESOLUTION
     } else {
-        target_width
-    };
-    generate_waveform_internal(path, width, enhanced)
+        DEFAULT_RESOLUTION
+    });
+    generate_waveform_internal(path, width.max(1), config)
 }
 
 fn generate_waveform_internal<P: AsRef<Path>>(
     path: P,
     target_width: usize,
-    enhanced: bool,
-) -> Result<WaveformData, Box<dyn std::error::Error>> {
-    let file = File::open(path)?;
-    let source = Decoder::new(BufReader::new(file))?;
+    config: WaveformConfig,
+) -> Result<WaveformData, ApzError> {
+    let path = path.as_ref();
+    let source = decoder::open(path)?.source;
 
-    let channels = source.channels();
+    let channels = source.channels().max(1) as usize;
+    let sample_rate = source.sample_rate().max(1);
     let samples: Vec<i16> = source.convert_samples().collect();
 
     if samples.is_empty() {
-        return Ok(WaveformData::new(vec![0.0; target_width], enhanced));
+        return Ok(WaveformData::flat(target_width, config));
     }
 
-    let total_samples = samples.len() / channels as usize;
-    let samples_per_bar = (total_samples / target_width).max(1);
-
-    let mut waveform = Vec::with_capacity(target_width);
+    // Buckets hold whole frames, so every channel counts towards the mix.
+    let total_frames = samples.len() / channels;
+    let frames_per_bar = (total_frames / target_width).max(1);
+    let bucket = |i: usize| {
+        let start = (i * frames_per_bar * channels).min(samples.len());
+        let end = ((i + 1) * frames_per_bar * channels).min(samples.len());
+        &samples[start..end]
+    };
 
+    let reduction = config.reduction();
+    let mut mix = Envelope::default();
+    let mut left = Envelope::default();
+    let mut right = Envelope::default();
     for i in 0..target_width {
-        let start_idx = i * samples_per_bar * channels as usize;
-        let end_idx = ((i + 1) * samples_per_bar * channels as usize).min(samples.len());
+        let bucket = bucket(i);
+        push_levels(&mut mix, reduction, bucket.iter());
+        push_levels(&mut left, reduction, bucket.iter().step_by(channels));
+        push_levels(
+            &mut right,
+            reduction,
+            bucket.iter().skip(1.min(channels - 1)).step_by(channels),
+        );
+    }
 
-        if start_idx >= samples.len() {
-            waveform.push(0.0);
-            continue;
+    // Everything is scaled by the same amount, keeping the RMS band in
+    // proportion and the channels comparable.
+    let max = mix.levels.iter().copied().fold(0.0, f32::max);
+    if max > 0.0 {
+        for envelope in [&mut mix, &mut left, &mut right] {
+            envelope.scale(max.recip());
         }
+    }
 
-        let mut sum = 0.0;
-        let mut count = 0;
+    let (dc_offset, correlation) = channel_statistics(&samples, channels);
+    Ok(WaveformData {
+        mix,
+        left,
+        right,
+        config,
+        duration: total_frames as f64 / sample_rate as f64,
+        peak: max,
+        dc_offset,
+        correlation,
+    })
+}
 
-        for idx in (start_idx..end_idx).step_by(channels as usize) {
-            if idx < samples.len() {
-                let sample = samples[idx] as f32 / i16::MAX as f32;
-                sum += sample.abs();
-                count += 1;
-            }
+/// Largest average level of any channel, and the correlation between the
+/// first two once those averages are taken out.
+fn channel_statistics(samples: &[i16], channels: usize) -> (f32, Option<f32>) {
+    let frames = (samples.len() / channels).max(1) as f64;
+    let mut sums = vec![0.0f64; channels];
+    for frame in samples.chunks_exact(channels) {
+        for (sum, &sample) in sums.iter_mut().zip(frame) {
+            *sum += sample as f64;
         }
-
-        let avg = if count > 0 { sum / count as f32 } else { 0.0 };
-        waveform.push(avg);
+    }
+    let means: Vec<f64> = sums.iter().map(|sum| sum / frames).collect();
+    let dc_offset = means.iter().fold(0.0f64, |max, mean| max.max(mean.abs()));
+    let dc_offset = (dc_offset / i16::MAX as f64) as f32;
+    if channels < 2 {
+        return (dc_offset, None);
     }
 
-    normalize_waveform(&mut waveform);
-
-    Ok(WaveformData::new(waveform, enhanced))
+    let (mut product, mut left_squares, mut right_squares) = (0.0f64, 0.0f64, 0.0f64);
+    for frame in samples.chunks_exact(channels) {
+        let left = frame[0] as f64 - means[0];
+        let right = frame[1] as f64 - means[1];
+        product += left * right;
+        left_squares += left * left;
+        right_squares += right * right;
+    }
+    let energy = (left_squares * right_squares).sqrt();
+    let correlation = (energy > 0.0).then(|| (product / energy) as f32);
+    (dc_offset, correlation)
 }
 
-fn normalize_waveform(waveform: &mut [f32]) {
-    if waveform.is_empty() {
-        return;
+fn push_levels<'a>(
+    envelope: &mut Envelope,
+    reduction: Reduction,
+    samples: impl Iterator<Item = &'a i16>,
+) {
+    let mut peak = 0.0f32;
+    let mut sum = 0.0;
+    let mut sum_squares = 0.0;
+    let mut count = 0;
+    for &sample in samples {
+        let sample = sample as f32 / i16::MAX as f32;
+        peak = peak.max(sample.abs());
+        sum += sample.abs();
+        sum_squares += sample * sample;
+        count += 1;
     }
 
-    let max = waveform
-        .iter()
-        .copied()
-        .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-        .unwrap_or(1.0);
-
-    if max > 0.0 {
-        for sample in waveform.iter_mut() {
-            *sample /= max;
-        }
-    }
+    let count = count.max(1) as f32;
+    let rms = (sum_squares / count).sqrt();
+    envelope.levels.push(match reduction {
+        Reduction::Peak => peak,
+        Reduction::Mean => sum / count,
+        Reduction::Rms => rms,
+    });
+    envelope.rms.push(rms);
 }
@@ -0,0 +1,210 @@
+//! Playing audio from sites like YouTube, SoundCloud and Bandcamp, whose
+//! pages don't link the audio directly. yt-dlp, if it's installed, finds the
+//! stream, which is then downloaded and played as it arrives like a podcast
+//! episode, or streamed if it's HLS.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::metadata::TrackInfo;
+use crate::{download, hls, paths};
+
+/// Hosts whose pages are handed to yt-dlp. Any subdomain counts too, such as
+/// `music.youtube.com` or `artist.bandcamp.com`.
+const SITES: [&str; 4] = ["youtube.com", "youtu.be", "soundcloud.com", "bandcamp.com"];
+/// Formats to ask for, best first: ones apz can decode while they're still
+/// downloading, then HLS.
+const FORMAT: &str = "bestaudio[ext=m4a]/bestaudio[ext=mp3]/bestaudio[ext=ogg]/bestaudio[ext=flac]/bestaudio[protocol^=m3u8]";
+const STREAMS_DIR: &str = "streams";
+const INFO_FILE: &str = "streams.tsv";
+
+/// Title and uploader of the streams found this session, by where they're
+/// played from. Downloads are also kept in `streams.tsv`, for next time.
+static FOUND: Mutex<Vec<(PathBuf, TrackInfo)>> = Mutex::new(Vec::new());
+
+/// Whether `path` is the URL of a page on one of the sites yt-dlp is used
+/// for.
+pub fn is_page(path: &Path) -> bool {
+    let url = path.to_string_lossy();
+    if !download::is_url(&url) {
+        return false;
+    }
+    let host = url
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .unwrap_or_default();
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host
+        .split(':')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    SITES
+        .iter()
+        .any(|site| host == *site || host.ends_with(&format!(".{}", site)))
+}
+
+/// The stream yt-dlp found on a page.
+struct Found {
+    url: String,
+    title: Option<String>,
+    uploader: Option<String>,
+    extension: String,
+    /// Delivered as HLS rather than as one file.
+    hls: bool,
+}
+
+fn find(page: &str) -> Result<Found, String> {
+    let output = Command::new("yt-dlp")
+        .args(["--no-playlist", "--no-warnings", "-f", FORMAT])
+        .args(["--print", "title", "--print", "uploader"])
+        .args(["--print", "ext", "--print", "protocol", "--print", "url"])
+        .args(["--", page])
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => "yt-dlp isn't installed".to_string(),
+            _ => format!("couldn't run yt-dlp: {}", e),
+        })?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        let message = message.lines().last().unwrap_or_default();
+        return Err(message.trim_start_matches("ERROR: ").to_string());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(str::trim);
+    // yt-dlp prints "NA" for anything the site doesn't say.
+    let mut field = || {
+        lines
+            .next()
+            .filter(|value| !value.is_empty() && *value != "NA")
+            .map(str::to_string)
+    };
+    let title = field();
+    let uploader = field();
+    let extension = field().unwrap_or_else(|| "m4a".to_string());
+    let hls = field().is_some_and(|protocol| protocol.starts_with("m3u8"));
+    let url = field().ok_or("yt-dlp found no audio")?;
+    Ok(Found {
+        url,
+        title,
+        uploader,
+        extension,
+        hls,
+    })
+}
+
+/// Has yt-dlp find the audio on `page`, and starts downloading it unless
+/// it's an HLS stream. Returns where to play it from: the download, or the
+/// stream's playlist URL.
+pub fn open(page: &str) -> Result<PathBuf, String> {
+    let found = find(page)?;
+    let info = TrackInfo {
+        title: found.title.clone(),
+        artist: found.uploader.clone(),
+        ..TrackInfo::default()
+    };
+
+    let path = if found.hls {
+        let path = PathBuf::from(&found.url);
+        if !hls::is_playlist(&path) {
+            return Err("the stream isn't in a format apz can play".to_string());
+        }
+        path
+    } else {
+        let title = found.title.as_deref().unwrap_or("stream");
+        let path = download::cache_path(STREAMS_DIR, title, page, &found.extension)
+            .ok_or("no cache directory")?;
+        download::start(&found.url, &path).map_err(|e| e.to_string())?;
+        save(&path, &info);
+        path
+    };
+    let mut found = FOUND.lock().unwrap();
+    found.retain(|(known, _)| *known != path);
+    found.push((path.clone(), info));
+    Ok(path)
+}
+
+/// Title and uploader of a stream found with yt-dlp, for `path` as
+/// returned by [`open`].
+pub fn track_info(path: &Path) -> Option<TrackInfo> {
+    let found = FOUND
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(known, _)| known == path)
+        .map(|(_, info)| info.clone());
+    found.or_else(|| {
+        if !is_download(path) {
+            return None;
+        }
+        let key = path.to_string_lossy();
+        read_store()
+            .lines()
+            .filter_map(parse_line)
+            .find(|(entry, _)| *entry == key)
+            .map(|(_, info)| info)
+    })
+}
+
+/// Whether `path` is a stream downloaded with yt-dlp's help.
+fn is_download(path: &Path) -> bool {
+    paths::cache_dir().is_some_and(|dir| path.starts_with(dir.join(STREAMS_DIR)))
+}
+
+/// Remembers a download's title and uploader, as `path<TAB>title<TAB>uploader`.
+fn save(path: &Path, info: &TrackInfo) {
+    let Some(store) = store_path() else {
+        return;
+    };
+    let key = path.to_string_lossy();
+    let clean = |value: &Option<String>| value.as_deref().unwrap_or_default().replace('\t', " ");
+    let contents = read_store();
+    let mut lines: Vec<String> = contents
+        .lines()
+        .filter(|line| parse_line(line).is_none_or(|(entry, _)| entry != key))
+        .map(str::to_string)
+        .collect();
+    lines.push(format!(
+        "{}\t{}\t{}",
+        key,
+        clean(&info.title),
+        clean(&info.artist)
+    ));
+
+    if let Some(dir) = store.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    let contents: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    fs::write(store, contents).ok();
+}
+
+fn store_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(INFO_FILE))
+}
+
+fn read_store() -> String {
+    store_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default()
+}
+
+fn parse_line(line: &str) -> Option<(&str, TrackInfo)> {
+    let mut fields = line.splitn(3, '\t');
+    let path = fields.next()?;
+    let value = |field: Option<&str>| field.filter(|value| !value.is_empty()).map(str::to_string);
+    let title = value(fields.next());
+    let artist = value(fields.next());
+    Some((
+        path,
+        TrackInfo {
+            title,
+            artist,
+            ..TrackInfo::default()
+        },
+    ))
+}